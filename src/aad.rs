@@ -0,0 +1,576 @@
+//! Azure AD access tokens for Azure DevOps
+//!
+//! People sign in with the device code flow: the first run prints a code to
+//! enter in a browser. The tokens are kept in a file, and the access token is
+//! renewed with the refresh token without asking again, until the refresh
+//! token runs out too. Unattended runs sign in as a service principal or a
+//! managed identity instead, which get a new token every run.
+
+use crate::{
+    client::{self, SendRetrying},
+    ClientOptions, Error, VERBOSE,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::atomic::Ordering};
+
+const AUTHORITY: &str = "https://login.microsoftonline.com";
+
+/// Azure DevOps' application id
+const AZURE_DEVOPS: &str = "499b84ac-1321-427f-aa17-267ca6975798";
+
+/// Azure DevOps itself, with a refresh token
+const SCOPE: &str = "499b84ac-1321-427f-aa17-267ca6975798/.default offline_access";
+
+/// The instance metadata service VMs get managed identity tokens from
+const IMDS: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// Visual Studio's public client, which Azure DevOps accepts without an app
+/// registration of our own
+pub const DEFAULT_CLIENT_ID: &str = "872cd9fa-d31f-45e0-9eab-6e460a02d1f1";
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// A tenant id or domain, or `organizations` for the account's home tenant
+    pub tenant: String,
+    pub client_id: String,
+    /// Where the tokens are kept between runs
+    pub cache: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Cached {
+    tenant: String,
+    client_id: String,
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    /// Tells where to go and which code to enter
+    message: String,
+    expires_in: i64,
+    interval: Option<u64>,
+}
+
+/// A token, or why there isn't one (yet)
+#[derive(Debug, Deserialize)]
+struct TokenReply {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+impl TokenReply {
+    fn refused(&self) -> String {
+        format!(
+            "Azure AD sign-in failed: {}",
+            self.error_description
+                .as_deref()
+                .or(self.error.as_deref())
+                .unwrap_or("no token in the reply")
+        )
+    }
+}
+
+/// An access token for Azure DevOps: the cached one while it's good, a
+/// refreshed one, or else one from signing in with a device code
+pub async fn access_token(options: &ClientOptions, settings: &Settings) -> Result<String, Error> {
+    signed_in(&client::build(options)?, AUTHORITY, settings).await
+}
+
+/// [`access_token`], from Azure AD at `authority`
+async fn signed_in(
+    http: &reqwest::Client,
+    authority: &str,
+    settings: &Settings,
+) -> Result<String, Error> {
+    let cached = std::fs::read_to_string(&settings.cache)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<Cached>(&contents).ok())
+        .filter(|cached| {
+            cached.tenant == settings.tenant && cached.client_id == settings.client_id
+        });
+
+    if let Some(cached) = &cached {
+        // Some slack so it doesn't run out halfway through the report
+        if cached.expires_at > Utc::now() + Duration::minutes(5) {
+            return Ok(cached.access_token.clone());
+        }
+        if let Some(refresh_token) = &cached.refresh_token {
+            let reply = request_token(
+                http,
+                authority,
+                settings,
+                &[
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", refresh_token),
+                ],
+            )
+            .await?;
+            match reply.access_token {
+                Some(_) => return save(settings, reply),
+                None if VERBOSE.load(Ordering::Relaxed) => {
+                    eprintln!("{}, signing in again", reply.refused());
+                }
+                None => {}
+            }
+        }
+    }
+
+    let device_code: DeviceCode = http
+        .post(endpoint(authority, settings, "devicecode"))
+        .form(&[("client_id", settings.client_id.as_str()), ("scope", SCOPE)])
+        .send_retrying()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(aad_error)?
+        .json()
+        .await
+        .map_err(aad_error)?;
+    eprintln!("{}", device_code.message);
+
+    let mut interval = device_code.interval.unwrap_or(5);
+    let give_up = Utc::now() + Duration::seconds(device_code.expires_in);
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+        let reply = request_token(
+            http,
+            authority,
+            settings,
+            &[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ("device_code", &device_code.device_code),
+            ],
+        )
+        .await?;
+        match reply.error.as_deref() {
+            None => return save(settings, reply),
+            Some("authorization_pending") if Utc::now() < give_up => {}
+            Some("slow_down") => interval += 5,
+            _ => return Err(reply.refused().into()),
+        }
+    }
+}
+
+fn endpoint(authority: &str, settings: &Settings, name: &str) -> String {
+    format!("{}/{}/oauth2/v2.0/{}", authority, settings.tenant, name)
+}
+
+/// How a service principal proves who it is
+#[derive(Debug, Clone)]
+pub enum Credential {
+    /// A client secret of the app registration
+    Secret(String),
+    /// A file with a token from another identity provider the app trusts, as
+    /// workload identity federation in pipelines and Kubernetes writes
+    FederatedToken(PathBuf),
+}
+
+/// An access token for a service principal, from the client credentials flow
+pub async fn service_principal_token(
+    options: &ClientOptions,
+    tenant: &str,
+    client_id: &str,
+    credential: &Credential,
+) -> Result<String, Error> {
+    let http = client::build(options)?;
+    client_credentials(&http, AUTHORITY, tenant, client_id, credential).await
+}
+
+/// [`service_principal_token`], from Azure AD at `authority`
+async fn client_credentials(
+    http: &reqwest::Client,
+    authority: &str,
+    tenant: &str,
+    client_id: &str,
+    credential: &Credential,
+) -> Result<String, Error> {
+    // Read every time, as federated tokens are short-lived and rotated
+    let assertion = match credential {
+        Credential::Secret(_) => String::new(),
+        Credential::FederatedToken(path) => std::fs::read_to_string(path)
+            .map_err(|error| format!("Can't read {}: {}", path.display(), error))?
+            .trim()
+            .to_string(),
+    };
+    let scope = format!("{}/.default", AZURE_DEVOPS);
+    let mut form = vec![
+        ("grant_type", "client_credentials"),
+        ("client_id", client_id),
+        ("scope", &scope),
+    ];
+    match credential {
+        Credential::Secret(secret) => form.push(("client_secret", secret)),
+        Credential::FederatedToken(_) => {
+            form.push((
+                "client_assertion_type",
+                "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+            ));
+            form.push(("client_assertion", &assertion));
+        }
+    }
+    let reply: TokenReply = http
+        .post(format!("{}/{}/oauth2/v2.0/token", authority, tenant))
+        .form(&form)
+        // Asking for another token does no harm
+        .send_retrying_idempotent()
+        .await
+        .map_err(aad_error)?
+        .json()
+        .await
+        .map_err(aad_error)?;
+    reply
+        .access_token
+        .clone()
+        .ok_or_else(|| reply.refused().into())
+}
+
+#[derive(Debug, Deserialize)]
+struct ManagedIdentityToken {
+    access_token: String,
+}
+
+/// An access token for the managed identity of the VM, App Service or
+/// container the run is on, or the user-assigned one with `client_id`
+pub async fn managed_identity_token(
+    options: &ClientOptions,
+    client_id: Option<&str>,
+) -> Result<String, Error> {
+    // The endpoint is local to the host, so never through the proxy
+    let http = client::build(&ClientOptions {
+        max_redirects: options.max_redirects,
+        max_retries: options.max_retries,
+        ..ClientOptions::default()
+    })?;
+    // App Service and Container Apps say where their endpoint is, VMs have IMDS
+    let request = match (
+        std::env::var("IDENTITY_ENDPOINT"),
+        std::env::var("IDENTITY_HEADER"),
+    ) {
+        (Ok(endpoint), Ok(header)) => http
+            .get(endpoint)
+            .query(&[("api-version", "2019-08-01")])
+            .header("X-IDENTITY-HEADER", header),
+        _ => http
+            .get(IMDS)
+            .query(&[("api-version", "2018-02-01")])
+            .header("Metadata", "true"),
+    };
+    identity_token(request, client_id).await
+}
+
+/// Ask the endpoint [`managed_identity_token`] found for a token
+async fn identity_token(
+    request: reqwest::RequestBuilder,
+    client_id: Option<&str>,
+) -> Result<String, Error> {
+    let mut query = vec![("resource", AZURE_DEVOPS)];
+    query.extend(client_id.map(|id| ("client_id", id)));
+    let token: ManagedIdentityToken = request
+        .query(&query)
+        .send_retrying()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|error| format!("No managed identity token: {}", error))?
+        .json()
+        .await
+        .map_err(|error| format!("No managed identity token: {}", error))?;
+    Ok(token.access_token)
+}
+
+/// Ask the token endpoint, which answers errors such as a pending sign-in
+/// with a 400 and a reason
+async fn request_token(
+    http: &reqwest::Client,
+    authority: &str,
+    settings: &Settings,
+    grant: &[(&str, &str)],
+) -> Result<TokenReply, Error> {
+    let mut form = vec![("client_id", settings.client_id.as_str()), ("scope", SCOPE)];
+    form.extend_from_slice(grant);
+    http.post(endpoint(authority, settings, "token"))
+        .form(&form)
+        .send_retrying()
+        .await
+        .map_err(aad_error)?
+        .json()
+        .await
+        .map_err(aad_error)
+}
+
+/// Keep the tokens for the next run, and hand out the access token
+fn save(settings: &Settings, reply: TokenReply) -> Result<String, Error> {
+    let Some(access_token) = reply.access_token.clone() else {
+        return Err(reply.refused().into());
+    };
+    let cached = Cached {
+        tenant: settings.tenant.clone(),
+        client_id: settings.client_id.clone(),
+        access_token: access_token.clone(),
+        refresh_token: reply.refresh_token,
+        expires_at: Utc::now() + Duration::seconds(reply.expires_in.unwrap_or(3600)),
+    };
+    if let Some(dir) = settings.cache.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let contents = serde_json::to_string(&cached).map_err(|error| error.to_string())?;
+    write_private(&settings.cache, &contents)?;
+    Ok(access_token)
+}
+
+/// Write a file only its owner can read, as it holds credentials
+fn write_private(path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, contents.as_bytes())
+}
+
+/// Errors from Azure AD, which [`Error`]'s own messages would blame on the PAT
+fn aad_error(error: reqwest::Error) -> Error {
+    format!("Azure AD sign-in failed: {}", error).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_server, response};
+    use serde_json::json;
+
+    fn json(status: &str, body: serde_json::Value) -> String {
+        response(
+            status,
+            &[("Content-Type", "application/json")],
+            &body.to_string(),
+        )
+    }
+
+    /// The form a request sent, by name
+    fn form(request: &str) -> std::collections::HashMap<String, String> {
+        let body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+        // Encoded as a query string is
+        let url = reqwest::Url::parse(&format!("http://localhost/?{}", body)).unwrap();
+        url.query_pairs().into_owned().collect()
+    }
+
+    fn settings(name: &str) -> Settings {
+        Settings {
+            tenant: "contoso.com".to_string(),
+            client_id: DEFAULT_CLIENT_ID.to_string(),
+            cache: std::env::temp_dir()
+                .join(format!("aad-{}-{}", name, std::process::id()))
+                .join("token.json"),
+        }
+    }
+
+    #[tokio::test]
+    async fn device_code_sign_in_is_kept_and_refreshed() {
+        let (server, requests) = mock_server(vec![
+            json(
+                "200 OK",
+                json!({
+                    "device_code": "device",
+                    "message": "Enter ABC at https://microsoft.com/devicelogin",
+                    "expires_in": 900,
+                    "interval": 0,
+                }),
+            ),
+            json("400 Bad Request", json!({ "error": "authorization_pending" })),
+            json(
+                "200 OK",
+                json!({ "access_token": "first", "refresh_token": "refresh", "expires_in": 3600 }),
+            ),
+            json(
+                "200 OK",
+                json!({ "access_token": "second", "refresh_token": "refreshed", "expires_in": 3600 }),
+            ),
+        ])
+        .await;
+        let authority = format!("http://{}", server);
+        let http = reqwest::Client::new();
+        let settings = settings("device-code");
+
+        let signed_in_first = signed_in(&http, &authority, &settings).await.unwrap();
+        // Good for a while yet, so not asked for again
+        let kept = signed_in(&http, &authority, &settings).await.unwrap();
+        let saved = std::fs::read_to_string(&settings.cache).unwrap();
+        #[cfg(unix)]
+        let mode = std::os::unix::fs::PermissionsExt::mode(
+            &std::fs::metadata(&settings.cache).unwrap().permissions(),
+        );
+        let mut cached: Cached = serde_json::from_str(&saved).unwrap();
+        cached.expires_at = Utc::now() + Duration::minutes(4);
+        std::fs::write(&settings.cache, serde_json::to_string(&cached).unwrap()).unwrap();
+        let refreshed = signed_in(&http, &authority, &settings).await;
+        let saved_again = std::fs::read_to_string(&settings.cache);
+        let _ = std::fs::remove_dir_all(settings.cache.parent().unwrap());
+
+        assert_eq!(signed_in_first, "first");
+        assert_eq!(kept, "first");
+        assert_eq!(cached.refresh_token.as_deref(), Some("refresh"));
+        #[cfg(unix)]
+        assert_eq!(mode & 0o777, 0o600);
+        // About to run out, so renewed without signing in again
+        assert_eq!(refreshed.unwrap(), "second");
+        let cached: Cached = serde_json::from_str(&saved_again.unwrap()).unwrap();
+        assert_eq!(cached.refresh_token.as_deref(), Some("refreshed"));
+        assert!(cached.expires_at > Utc::now() + Duration::minutes(59));
+
+        let requests = requests.await.unwrap();
+        assert!(
+            requests[0].starts_with("POST /contoso.com/oauth2/v2.0/devicecode "),
+            "{}",
+            requests[0]
+        );
+        assert_eq!(form(&requests[0])["scope"], SCOPE);
+        let grants: Vec<(String, String)> = requests[1..]
+            .iter()
+            .map(|request| {
+                assert!(request.starts_with("POST /contoso.com/oauth2/v2.0/token "));
+                let form = form(request);
+                let code = form.get("device_code").or(form.get("refresh_token"));
+                (form["grant_type"].clone(), code.unwrap().clone())
+            })
+            .collect();
+        let device_code = "urn:ietf:params:oauth:grant-type:device_code".to_string();
+        assert_eq!(
+            grants,
+            [
+                (device_code.clone(), "device".to_string()),
+                (device_code, "device".to_string()),
+                ("refresh_token".to_string(), "refresh".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_refused_sign_in_says_why() {
+        let (server, _) = mock_server(vec![
+            json(
+                "200 OK",
+                json!({ "device_code": "device", "message": "", "expires_in": 900, "interval": 0 }),
+            ),
+            json(
+                "400 Bad Request",
+                json!({ "error": "expired_token", "error_description": "AADSTS70020: The code expired." }),
+            ),
+        ])
+        .await;
+        let settings = settings("refused");
+        let error = signed_in(
+            &reqwest::Client::new(),
+            &format!("http://{}", server),
+            &settings,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Azure AD sign-in failed: AADSTS70020: The code expired."
+        );
+        assert!(!settings.cache.exists());
+    }
+
+    #[tokio::test]
+    async fn service_principals_use_a_secret_or_a_federated_token() {
+        let token = |token: &str| json("200 OK", json!({ "access_token": token }));
+        let (server, requests) = mock_server(vec![
+            token("with-secret"),
+            token("federated"),
+            json(
+                "401 Unauthorized",
+                json!({ "error": "invalid_client", "error_description": "AADSTS7000215: Invalid client secret." }),
+            ),
+        ])
+        .await;
+        let authority = format!("http://{}", server);
+        let http = reqwest::Client::new();
+        let path = std::env::temp_dir().join(format!("federated-{}.jwt", std::process::id()));
+        std::fs::write(&path, "header.payload.signature\n").unwrap();
+        let token = |credential| {
+            let (http, authority) = (&http, &authority);
+            async move { client_credentials(http, authority, "tenant-id", "app-id", &credential).await }
+        };
+
+        let with_secret = token(Credential::Secret("s3cret".to_string())).await;
+        let federated = token(Credential::FederatedToken(path.clone())).await;
+        let refused = token(Credential::Secret("wrong".to_string())).await;
+        let _ = std::fs::remove_file(&path);
+        let unreadable = token(Credential::FederatedToken(path.clone())).await;
+
+        assert_eq!(with_secret.unwrap(), "with-secret");
+        assert_eq!(federated.unwrap(), "federated");
+        assert_eq!(
+            refused.unwrap_err().to_string(),
+            "Azure AD sign-in failed: AADSTS7000215: Invalid client secret."
+        );
+        assert!(unreadable
+            .unwrap_err()
+            .to_string()
+            .starts_with(&format!("Can't read {}: ", path.display())));
+
+        let requests = requests.await.unwrap();
+        assert!(requests[0].starts_with("POST /tenant-id/oauth2/v2.0/token "));
+        let secret = form(&requests[0]);
+        assert_eq!(secret["grant_type"], "client_credentials");
+        assert_eq!(secret["client_id"], "app-id");
+        assert_eq!(secret["client_secret"], "s3cret");
+        assert_eq!(secret["scope"], format!("{}/.default", AZURE_DEVOPS));
+        let federated = form(&requests[1]);
+        assert_eq!(federated.get("client_secret"), None);
+        assert_eq!(
+            federated["client_assertion_type"],
+            "urn:ietf:params:oauth:client-assertion-type:jwt-bearer"
+        );
+        assert_eq!(federated["client_assertion"], "header.payload.signature");
+    }
+
+    #[tokio::test]
+    async fn managed_identities_ask_for_azure_devops() {
+        let (server, requests) = mock_server(vec![
+            json(
+                "200 OK",
+                json!({ "access_token": "identity", "expires_in": "3599" }),
+            ),
+            response("400 Bad Request", &[], "Identity not found"),
+        ])
+        .await;
+        let http = reqwest::Client::new();
+        let endpoint = format!("http://{}/metadata/identity/oauth2/token", server);
+        let request = || http.get(&endpoint).header("Metadata", "true");
+
+        let token = identity_token(request(), Some("user-assigned")).await;
+        let missing = identity_token(request(), None).await;
+
+        assert_eq!(token.unwrap(), "identity");
+        assert!(missing
+            .unwrap_err()
+            .to_string()
+            .starts_with("No managed identity token: "));
+        let requests = requests.await.unwrap();
+        assert!(
+            requests[0].starts_with(&format!(
+                "GET /metadata/identity/oauth2/token?resource={}&client_id=user-assigned ",
+                AZURE_DEVOPS
+            )),
+            "{}",
+            requests[0]
+        );
+        assert!(
+            requests[0].contains("metadata: true\r\n"),
+            "{}",
+            requests[0]
+        );
+        assert!(requests[1].starts_with(&format!(
+            "GET /metadata/identity/oauth2/token?resource={} ",
+            AZURE_DEVOPS
+        )));
+    }
+}
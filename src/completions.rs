@@ -0,0 +1,295 @@
+//! Shell completion scripts, generated from the clap definition of the command
+//! line so they can't drift from it
+
+use clap::{Arg, Command};
+use std::io::{self, Write};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+pub fn write(out: &mut dyn Write, shell: Shell, command: &Command) -> io::Result<()> {
+    match shell {
+        Shell::Bash => bash(out, command),
+        Shell::Zsh => zsh(out, command),
+        Shell::Fish => fish(out, command),
+    }
+}
+
+/// The visible arguments that are given by their long name
+fn flags(command: &Command) -> impl Iterator<Item = &Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set() && arg.get_long().is_some())
+}
+
+fn subcommands(command: &Command) -> impl Iterator<Item = &Command> {
+    command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+}
+
+/// The values positional arguments can take, such as the shell here
+fn positional_values(command: &Command) -> Vec<String> {
+    command
+        .get_positionals()
+        .filter(|arg| !arg.is_hide_set())
+        .flat_map(possible_values)
+        .collect()
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_num_args().is_some_and(|range| range.takes_values())
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .into_iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
+
+/// The first line of the help, as a short description
+fn about(help: Option<&clap::builder::StyledStr>) -> String {
+    help.map(|help| help.to_string())
+        .unwrap_or_default()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn bash(out: &mut dyn Write, command: &Command) -> io::Result<()> {
+    let name = command.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    let words = |command: &Command| {
+        flags(command)
+            .map(|arg| format!("--{}", arg.get_long().unwrap()))
+            .chain(subcommands(command).map(|subcommand| subcommand.get_name().to_string()))
+            .chain(positional_values(command))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    // Values of the flags given before the word being completed
+    let cases = |command: &Command, indent: &str| {
+        let mut cases = String::new();
+        for arg in flags(command).filter(|arg| takes_value(arg)) {
+            let values = possible_values(arg);
+            let reply = if values.is_empty() {
+                "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string()
+            } else {
+                format!(
+                    "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                    values.join(" ")
+                )
+            };
+            cases.push_str(&format!(
+                "{indent}--{})\n{indent}    {}\n{indent}    return\n{indent}    ;;\n",
+                arg.get_long().unwrap(),
+                reply
+            ));
+        }
+        cases
+    };
+
+    writeln!(out, "{}() {{", function)?;
+    writeln!(out, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"")?;
+    writeln!(out, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"")?;
+    writeln!(out, "    local word subcommand=\"\"")?;
+    writeln!(
+        out,
+        "    for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do"
+    )?;
+    writeln!(out, "        case \"$word\" in")?;
+    for subcommand in subcommands(command) {
+        writeln!(
+            out,
+            "            {}) subcommand=\"$word\" ;;",
+            subcommand.get_name()
+        )?;
+    }
+    writeln!(out, "        esac")?;
+    writeln!(out, "    done")?;
+    writeln!(out, "    case \"$subcommand\" in")?;
+    for subcommand in subcommands(command) {
+        writeln!(out, "    {})", subcommand.get_name())?;
+        writeln!(out, "        case \"$prev\" in")?;
+        write!(out, "{}", cases(subcommand, "            "))?;
+        writeln!(out, "        esac")?;
+        writeln!(
+            out,
+            "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+            words(subcommand)
+        )?;
+        writeln!(out, "        return")?;
+        writeln!(out, "        ;;")?;
+    }
+    writeln!(out, "    esac")?;
+    writeln!(out, "    case \"$prev\" in")?;
+    write!(out, "{}", cases(command, "        "))?;
+    writeln!(out, "    esac")?;
+    writeln!(
+        out,
+        "    COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+        words(command)
+    )?;
+    writeln!(out, "}}")?;
+    writeln!(out, "complete -F {} {}", function, name)
+}
+
+/// An `_arguments` spec per flag
+fn zsh_specs(command: &Command) -> Vec<String> {
+    flags(command)
+        .map(|arg| {
+            let help = about(arg.get_help())
+                .replace('\'', "'\\''")
+                .replace('[', "\\[")
+                .replace(']', "\\]")
+                .replace(':', "\\:");
+            let repeat = if matches!(arg.get_action(), clap::ArgAction::Append) {
+                "*"
+            } else {
+                ""
+            };
+            let value = if takes_value(arg) {
+                let values = possible_values(arg);
+                let name = arg
+                    .get_value_names()
+                    .and_then(|names| names.first())
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| "VALUE".to_string());
+                if values.is_empty() {
+                    format!(":{}:_files", name)
+                } else {
+                    format!(":{}:({})", name, values.join(" "))
+                }
+            } else {
+                String::new()
+            };
+            format!(
+                "'{}--{}[{}]{}'",
+                repeat,
+                arg.get_long().unwrap(),
+                help,
+                value
+            )
+        })
+        .collect()
+}
+
+fn zsh(out: &mut dyn Write, command: &Command) -> io::Result<()> {
+    let name = command.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    writeln!(out, "#compdef {}", name)?;
+    writeln!(out)?;
+    writeln!(out, "{}() {{", function)?;
+    writeln!(out, "    local state line")?;
+    writeln!(out, "    _arguments -C \\")?;
+    for spec in zsh_specs(command) {
+        writeln!(out, "        {} \\", spec)?;
+    }
+    writeln!(out, "        '1: :->subcommand' \\")?;
+    writeln!(out, "        '*:: :->arguments'")?;
+    writeln!(out, "    case $state in")?;
+    writeln!(out, "    subcommand)")?;
+    writeln!(out, "        local subcommands=(")?;
+    for subcommand in subcommands(command) {
+        writeln!(
+            out,
+            "            '{}:{}'",
+            subcommand.get_name(),
+            about(subcommand.get_about())
+                .replace('\'', "'\\''")
+                .replace(':', "\\:")
+        )?;
+    }
+    writeln!(out, "        )")?;
+    writeln!(out, "        _describe subcommand subcommands")?;
+    writeln!(out, "        ;;")?;
+    writeln!(out, "    arguments)")?;
+    writeln!(out, "        case $line[1] in")?;
+    for subcommand in subcommands(command) {
+        let mut specs = zsh_specs(subcommand);
+        let values = positional_values(subcommand);
+        if !values.is_empty() {
+            specs.push(format!("'1: :({})'", values.join(" ")));
+        }
+        writeln!(out, "        {})", subcommand.get_name())?;
+        writeln!(out, "            _arguments \\")?;
+        writeln!(
+            out,
+            "                {}",
+            specs.join(" \\\n                ")
+        )?;
+        writeln!(out, "            ;;")?;
+    }
+    writeln!(out, "        esac")?;
+    writeln!(out, "        ;;")?;
+    writeln!(out, "    esac")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "{} \"$@\"", function)
+}
+
+fn fish_flags(
+    out: &mut dyn Write,
+    name: &str,
+    command: &Command,
+    condition: &str,
+) -> io::Result<()> {
+    for arg in flags(command) {
+        let mut line = format!(
+            "complete -c {} -n '{}' -l {}",
+            name,
+            condition,
+            arg.get_long().unwrap()
+        );
+        if takes_value(arg) {
+            let values = possible_values(arg);
+            if values.is_empty() {
+                line.push_str(" -r -F");
+            } else {
+                line.push_str(&format!(" -x -a '{}'", values.join(" ")));
+            }
+        }
+        let help = about(arg.get_help());
+        if !help.is_empty() {
+            line.push_str(&format!(" -d '{}'", help.replace('\'', "\\'")));
+        }
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+fn fish(out: &mut dyn Write, command: &Command) -> io::Result<()> {
+    let name = command.get_name();
+    fish_flags(out, name, command, "__fish_use_subcommand")?;
+    for subcommand in subcommands(command) {
+        writeln!(
+            out,
+            "complete -c {} -n '__fish_use_subcommand' -f -a {} -d '{}'",
+            name,
+            subcommand.get_name(),
+            about(subcommand.get_about()).replace('\'', "\\'")
+        )?;
+    }
+    for subcommand in subcommands(command) {
+        let condition = format!("__fish_seen_subcommand_from {}", subcommand.get_name());
+        fish_flags(out, name, subcommand, &condition)?;
+        let values = positional_values(subcommand);
+        if !values.is_empty() {
+            writeln!(
+                out,
+                "complete -c {} -n '{}' -f -a '{}'",
+                name,
+                condition,
+                values.join(" ")
+            )?;
+        }
+    }
+    Ok(())
+}
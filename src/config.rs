@@ -0,0 +1,388 @@
+//! Settings from `~/.config/azure-devops-time-used/config.toml`
+//!
+//! Keys are the long names of the command line flags, so anything that can be
+//! passed as a flag can live in the config file:
+//!
+//! ```toml
+//! organization = "adaptdk"
+//! project = "Internal"
+//! user = "me@adapt.dk"
+//! format = "markdown"
+//! also = ["json:report.json"]
+//! ```
+//!
+//! Only the subset of TOML such files need is understood: tables, bare and
+//! quoted keys, strings, numbers, booleans and arrays of those.
+
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    /// Numbers are kept as written, clap parses them like any flag value
+    Number(String),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(text) | Value::Number(text) => f.write_str(text),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Array(values) => {
+                let values: Vec<String> = values.iter().map(Value::to_string).collect();
+                write!(f, "{}", values.join(","))
+            }
+        }
+    }
+}
+
+pub type Table = BTreeMap<String, Value>;
+
+/// A parsed config file: the top-level keys and every `[table]` by its name
+#[derive(Debug, Default)]
+pub struct Config {
+    pub path: PathBuf,
+    pub root: Table,
+    pub tables: BTreeMap<String, Table>,
+}
+
+/// Where the config file is looked for when `--config` isn't given
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join("azure-devops-time-used").join("config.toml"))
+}
+
+/// Read the config file given, or the default one if it exists
+pub fn load(path: Option<&Path>) -> Result<Option<Config>, String> {
+    let (path, explicit) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(None),
+        },
+    };
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound && !explicit => return Ok(None),
+        Err(error) => return Err(format!("Can't read {}: {}", path.display(), error)),
+    };
+    let mut config = parse(&contents).map_err(|error| format!("{}: {}", path.display(), error))?;
+    config.path = path;
+    Ok(Some(config))
+}
+
+pub fn parse(contents: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut table: Option<String> = None;
+
+    for (number, line) in contents.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+        let mut rest = Cursor(line.trim());
+        if rest.done() {
+            continue;
+        }
+
+        if rest.eat('[') {
+            let name = rest.key().map_err(|message| error(&message))?;
+            if !rest.eat(']') || !rest.done() {
+                return Err(error("expected ] after table name"));
+            }
+            if config.tables.contains_key(&name) {
+                return Err(error(&format!("table [{}] defined twice", name)));
+            }
+            config.tables.insert(name.clone(), Table::new());
+            table = Some(name);
+            continue;
+        }
+
+        let key = rest.key().map_err(|message| error(&message))?;
+        if !rest.eat('=') {
+            return Err(error("expected = after key"));
+        }
+        let value = rest.value().map_err(|message| error(&message))?;
+        if !rest.done() {
+            return Err(error("unexpected text after value"));
+        }
+        let target = match &table {
+            Some(name) => config.tables.get_mut(name).unwrap(),
+            None => &mut config.root,
+        };
+        if target.insert(key.clone(), value).is_some() {
+            return Err(error(&format!("{} given twice", key)));
+        }
+    }
+    Ok(config)
+}
+
+/// The unparsed rest of a line
+struct Cursor<'a>(&'a str);
+
+impl Cursor<'_> {
+    fn skip_space(&mut self) {
+        self.0 = self.0.trim_start();
+    }
+
+    /// Whether only whitespace or a comment is left
+    fn done(&mut self) -> bool {
+        self.skip_space();
+        self.0.is_empty() || self.0.starts_with('#')
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_space();
+        match self.0.strip_prefix(c) {
+            Some(rest) => {
+                self.0 = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// A possibly dotted key, as in `profiles.client-a` or `"odd key"`
+    fn key(&mut self) -> Result<String, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_space();
+            let part = if self.0.starts_with(['"', '\'']) {
+                self.string()?
+            } else {
+                let end = self
+                    .0
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+                    .unwrap_or(self.0.len());
+                if end == 0 {
+                    return Err("expected a key".to_string());
+                }
+                let (part, rest) = self.0.split_at(end);
+                self.0 = rest;
+                part.to_string()
+            };
+            parts.push(part);
+            if !self.eat('.') {
+                return Ok(parts.join("."));
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_space();
+        if self.0.starts_with(['"', '\'']) {
+            return self.string().map(Value::String);
+        }
+        if self.eat('[') {
+            let mut values = Vec::new();
+            loop {
+                if self.eat(']') {
+                    return Ok(Value::Array(values));
+                }
+                values.push(self.value()?);
+                if !self.eat(',') {
+                    return if self.eat(']') {
+                        Ok(Value::Array(values))
+                    } else {
+                        Err("expected , or ] in array".to_string())
+                    };
+                }
+            }
+        }
+
+        let end = self
+            .0
+            .find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+            .unwrap_or(self.0.len());
+        let (word, rest) = self.0.split_at(end);
+        self.0 = rest;
+        match word {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ if word.replace('_', "").parse::<f64>().is_ok() => {
+                Ok(Value::Number(word.replace('_', "")))
+            }
+            "" => Err("expected a value".to_string()),
+            _ => Err(format!("unsupported value {}, strings need quotes", word)),
+        }
+    }
+
+    /// A basic `"..."` string with escapes, or a literal `'...'` one
+    fn string(&mut self) -> Result<String, String> {
+        let line = self.0;
+        let quote = line.chars().next().unwrap();
+        let mut chars = line[1..].char_indices();
+        let mut text = String::new();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                _ if c == quote => {
+                    self.0 = &line[index + 2..];
+                    return Ok(text);
+                }
+                '\\' if quote == '"' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('r') => text.push('\r'),
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid escape \\u{}", hex))?;
+                        text.push(c);
+                    }
+                    other => return Err(format!("unsupported escape \\{}", other.unwrap_or(' '))),
+                },
+                _ => text.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+
+    fn number(text: &str) -> Value {
+        Value::Number(text.to_string())
+    }
+
+    #[test]
+    fn values_of_every_kind() {
+        let config = parse(
+            r#"
+# Who I am
+organization = "adaptdk"   # after a value
+"odd key" = 'C:\no\escapes'
+escaped = "tab\there \"quoted\" \\ \u00e6 # not a comment"
+hours = 37.5
+big = 1_000
+negative = -2
+yes = true
+no = false
+also = ["json:report.json", 'csv:report.csv', ] # trailing comma
+nested = [[1, 2], []]
+
+[profiles.client-a]
+project = "Client A"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.root,
+            Table::from([
+                ("organization".to_string(), string("adaptdk")),
+                ("odd key".to_string(), string(r"C:\no\escapes")),
+                (
+                    "escaped".to_string(),
+                    string("tab\there \"quoted\" \\ æ # not a comment")
+                ),
+                ("hours".to_string(), number("37.5")),
+                ("big".to_string(), number("1000")),
+                ("negative".to_string(), number("-2")),
+                ("yes".to_string(), Value::Boolean(true)),
+                ("no".to_string(), Value::Boolean(false)),
+                (
+                    "also".to_string(),
+                    Value::Array(vec![string("json:report.json"), string("csv:report.csv")])
+                ),
+                (
+                    "nested".to_string(),
+                    Value::Array(vec![
+                        Value::Array(vec![number("1"), number("2")]),
+                        Value::Array(Vec::new())
+                    ])
+                ),
+            ])
+        );
+        assert_eq!(
+            config.tables,
+            BTreeMap::from([(
+                "profiles.client-a".to_string(),
+                Table::from([("project".to_string(), string("Client A"))])
+            )])
+        );
+        // As handed to clap
+        assert_eq!(
+            config.root["also"].to_string(),
+            "json:report.json,csv:report.csv"
+        );
+    }
+
+    #[test]
+    fn dotted_keys_may_be_quoted() {
+        let config = parse("[rates]\n\"a.b@c.d\" = 100\ntag.\"Support\" = 80\n").unwrap();
+        assert_eq!(
+            config.tables["rates"].keys().collect::<Vec<_>>(),
+            ["a.b@c.d", "tag.Support"]
+        );
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        for (contents, message) in [
+            ("[table", "line 1: expected ] after table name"),
+            ("[table] x", "line 1: expected ] after table name"),
+            ("[a]\n[b]\n[a]", "line 3: table [a] defined twice"),
+            ("= 1", "line 1: expected a key"),
+            ("a.= 1", "line 1: expected a key"),
+            ("\nkey 1", "line 2: expected = after key"),
+            ("key = 1 2", "line 1: unexpected text after value"),
+            ("key = 1\n\nkey = 2", "line 3: key given twice"),
+            ("[t]\nkey = 1\nkey = 2", "line 3: key given twice"),
+            ("key = [1 2]", "line 1: expected , or ] in array"),
+            ("key = [1,", "line 1: expected a value"),
+            ("key =", "line 1: expected a value"),
+            ("key = # nothing", "line 1: expected a value"),
+            (
+                "key = adaptdk",
+                "line 1: unsupported value adaptdk, strings need quotes",
+            ),
+            (r#"key = "\u00zz""#, r"line 1: invalid escape \u00zz"),
+            (r#"key = "\ud800""#, r"line 1: invalid escape \ud800"),
+            (r#"key = "\x""#, r"line 1: unsupported escape \x"),
+            (r#"key = "open"#, "line 1: unterminated string"),
+            ("key = 'open", "line 1: unterminated string"),
+        ] {
+            assert_eq!(parse(contents).unwrap_err(), message, "{}", contents);
+        }
+    }
+
+    #[test]
+    fn only_a_missing_default_file_is_no_config() {
+        let path = env::temp_dir().join(format!("config-{}.toml", std::process::id()));
+        let missing = load(Some(&path));
+        fs::write(&path, "format = markdown\n").unwrap();
+        let broken = load(Some(&path));
+        fs::write(&path, "format = \"markdown\"\n").unwrap();
+        let loaded = load(Some(&path));
+        let _ = fs::remove_file(&path);
+
+        assert!(missing.unwrap_err().starts_with("Can't read "));
+        assert_eq!(
+            broken.unwrap_err(),
+            format!(
+                "{}: line 1: unsupported value markdown, strings need quotes",
+                path.display()
+            )
+        );
+        let config = loaded.unwrap().unwrap();
+        assert_eq!(config.path, path);
+        assert_eq!(config.root["format"], string("markdown"));
+    }
+}
@@ -0,0 +1,328 @@
+//! Working out the date range to report on
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Utc, Weekday};
+use std::{env, path::Path, str::FromStr};
+
+/// The first day of the week, for the default range and weekly sums
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WeekStart {
+    Sun,
+    #[default]
+    Mon,
+    Sat,
+}
+
+impl WeekStart {
+    pub fn weekday(self) -> Weekday {
+        match self {
+            WeekStart::Sun => Weekday::Sun,
+            WeekStart::Mon => Weekday::Mon,
+            WeekStart::Sat => Weekday::Sat,
+        }
+    }
+
+    /// The week a day is in, named as the ISO week of its Monday, e.g. `2023-W12`
+    pub fn week_label(self, day: NaiveDate) -> String {
+        let first = day.week(self.weekday()).first_day();
+        let to_monday = (7 - self.weekday().num_days_from_monday()) % 7;
+        (first + Duration::days(i64::from(to_monday)))
+            .format("%G-W%V")
+            .to_string()
+    }
+}
+
+/// Named ranges relative to today
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Today,
+    Yesterday,
+    ThisWeek,
+    LastWeek,
+    ThisMonth,
+    LastMonth,
+    ThisYear,
+    LastYear,
+}
+
+impl Period {
+    /// First and last day of the period, for the given today
+    pub fn range(self, today: NaiveDate, week_start: WeekStart) -> (NaiveDate, NaiveDate) {
+        let week = |day: NaiveDate| {
+            let week = day.week(week_start.weekday());
+            (week.first_day(), week.last_day())
+        };
+        let year = |year: i32| {
+            (
+                NaiveDate::from_ymd_opt(year, 1, 1).unwrap(),
+                NaiveDate::from_ymd_opt(year, 12, 31).unwrap(),
+            )
+        };
+
+        match self {
+            Period::Today => (today, today),
+            Period::Yesterday => {
+                let yesterday = today - Duration::days(1);
+                (yesterday, yesterday)
+            }
+            Period::ThisWeek => week(today),
+            Period::LastWeek => week(today - Duration::days(7)),
+            Period::ThisMonth => month_range(today.year(), today.month()),
+            Period::LastMonth => match today.month() {
+                1 => month_range(today.year() - 1, 12),
+                m => month_range(today.year(), m - 1),
+            },
+            Period::ThisYear => year(today.year()),
+            Period::LastYear => year(today.year() - 1),
+        }
+    }
+}
+
+/// First and last day of a month
+fn month_range(year: i32, month: u32) -> (NaiveDate, NaiveDate) {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let next = match month {
+        12 => NaiveDate::from_ymd_opt(year + 1, 1, 1),
+        _ => NaiveDate::from_ymd_opt(year, month + 1, 1),
+    };
+    (first, next.unwrap().pred_opt().unwrap())
+}
+
+/// The current week
+pub fn default_range(week_start: WeekStart) -> (NaiveDate, NaiveDate) {
+    Period::ThisWeek.range(today(), week_start)
+}
+
+/// Today in the reporting time zone
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// The hour of a day in the reporting time zone
+pub fn at_hour(date: NaiveDate, hour: u32) -> DateTime<Utc> {
+    date.and_hms_opt(hour, 0, 0)
+        .unwrap()
+        .and_local_timezone(Local)
+        .earliest()
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// Report in the given IANA time zone, e.g. `Europe/Copenhagen`, rather than
+/// the system one
+///
+/// chrono's `Local` follows `TZ`, so setting it moves every day boundary.
+/// Call it before starting any threads, which may be reading the environment.
+pub fn set_timezone(name: &str) -> Result<(), String> {
+    let directory = env::var_os("TZDIR").unwrap_or_else(|| "/usr/share/zoneinfo".into());
+    let known = name == "UTC"
+        || (!name.starts_with('/')
+            && !name.split('/').any(|part| part == "..")
+            && Path::new(&directory).join(name).is_file());
+    if !known {
+        return Err(format!("unknown time zone {}", name));
+    }
+    env::set_var("TZ", name);
+    Ok(())
+}
+
+/// A `--from`/`--to` date, possibly relative to today
+///
+/// Weeks and months stand for their first day in `--from` and their last day
+/// in `--to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateSpec {
+    Date(NaiveDate),
+    /// Days from today, e.g. `-7d`, `today` or `yesterday`
+    Days(i64),
+    /// The latest such weekday, today included, e.g. `monday`
+    Weekday(Weekday),
+    /// An ISO week, e.g. `2024-W12`
+    Week(i32, u32),
+    /// A calendar month, e.g. `2024-03`
+    Month(i32, u32),
+}
+
+/// The furthest a relative date may be from today, well within the dates
+/// chrono can hold
+const MAX_DAYS: i64 = 10_000 * 366;
+
+impl FromStr for DateSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_lowercase();
+        let invalid = || {
+            format!(
+                "invalid date {:?}, expected e.g. 2024-03-18, -7d, -2w, today, monday, 2024-W12 or 2024-03",
+                s
+            )
+        };
+
+        match lower.as_str() {
+            "today" => return Ok(DateSpec::Days(0)),
+            "yesterday" => return Ok(DateSpec::Days(-1)),
+            _ => {}
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(&lower, "%Y-%m-%d") {
+            return Ok(DateSpec::Date(date));
+        }
+        if let Ok(weekday) = lower.parse::<Weekday>() {
+            return Ok(DateSpec::Weekday(weekday));
+        }
+        if let Some((year, week)) = lower.split_once("-w") {
+            let (year, week) = (
+                year.parse().map_err(|_| invalid())?,
+                week.parse().map_err(|_| invalid())?,
+            );
+            NaiveDate::from_isoywd_opt(year, week, Weekday::Mon)
+                .and(NaiveDate::from_isoywd_opt(year, week, Weekday::Sun))
+                .ok_or_else(invalid)?;
+            return Ok(DateSpec::Week(year, week));
+        }
+        if lower.starts_with(['-', '+']) {
+            // The unit is the last character, whatever its length in bytes
+            let unit = lower.chars().next_back().ok_or_else(invalid)?;
+            let number = &lower[..lower.len() - unit.len_utf8()];
+            let number: i64 = number.parse().map_err(|_| invalid())?;
+            let days = match unit {
+                'd' => Some(number),
+                'w' => number.checked_mul(7),
+                _ => None,
+            };
+            return days
+                .filter(|days| days.abs() <= MAX_DAYS)
+                .map(DateSpec::Days)
+                .ok_or_else(invalid);
+        }
+        if let Some((year, month)) = lower.split_once('-') {
+            let (year, month) = (
+                year.parse().map_err(|_| invalid())?,
+                month.parse().map_err(|_| invalid())?,
+            );
+            // With a first day of the month after it, which the last is worked out from
+            NaiveDate::from_ymd_opt(year, month, 1)
+                .filter(|_| (year, month) < (NaiveDate::MAX.year(), 12))
+                .ok_or_else(invalid)?;
+            return Ok(DateSpec::Month(year, month));
+        }
+        Err(invalid())
+    }
+}
+
+impl DateSpec {
+    pub fn first_day(self, today: NaiveDate) -> NaiveDate {
+        match self {
+            DateSpec::Week(year, week) => {
+                NaiveDate::from_isoywd_opt(year, week, Weekday::Mon).unwrap()
+            }
+            DateSpec::Month(year, month) => NaiveDate::from_ymd_opt(year, month, 1).unwrap(),
+            spec => spec.day(today),
+        }
+    }
+
+    pub fn last_day(self, today: NaiveDate) -> NaiveDate {
+        match self {
+            DateSpec::Week(year, week) => {
+                NaiveDate::from_isoywd_opt(year, week, Weekday::Sun).unwrap()
+            }
+            DateSpec::Month(year, month) => month_range(year, month).1,
+            spec => spec.day(today),
+        }
+    }
+
+    /// The day of a spec that isn't a span
+    fn day(self, today: NaiveDate) -> NaiveDate {
+        match self {
+            DateSpec::Date(date) => date,
+            DateSpec::Days(days) => today + Duration::days(days),
+            DateSpec::Weekday(weekday) => {
+                let back = (7 + today.weekday().num_days_from_monday()
+                    - weekday.num_days_from_monday())
+                    % 7;
+                today - Duration::days(i64::from(back))
+            }
+            DateSpec::Week(..) | DateSpec::Month(..) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    /// The first and last day a spec stands for, on a Wednesday
+    fn days(spec: &str) -> (NaiveDate, NaiveDate) {
+        let today = date("2024-03-20");
+        let spec: DateSpec = spec.parse().unwrap();
+        (spec.first_day(today), spec.last_day(today))
+    }
+
+    #[test]
+    fn dates_relative_to_today() {
+        assert_eq!(days("2024-03-18"), (date("2024-03-18"), date("2024-03-18")));
+        assert_eq!(days("-7d"), (date("2024-03-13"), date("2024-03-13")));
+        assert_eq!(days("+1D"), (date("2024-03-21"), date("2024-03-21")));
+        assert_eq!(days("-2w"), (date("2024-03-06"), date("2024-03-06")));
+        assert_eq!(days(" today "), (date("2024-03-20"), date("2024-03-20")));
+        assert_eq!(days("yesterday"), (date("2024-03-19"), date("2024-03-19")));
+        // The latest one, today included
+        assert_eq!(days("monday"), (date("2024-03-18"), date("2024-03-18")));
+        assert_eq!(days("Wed"), (date("2024-03-20"), date("2024-03-20")));
+        assert_eq!(days("thursday"), (date("2024-03-14"), date("2024-03-14")));
+    }
+
+    #[test]
+    fn weeks_and_months_span_their_days() {
+        assert_eq!(days("2024-W12"), (date("2024-03-18"), date("2024-03-24")));
+        // ISO week 1 of 2025 starts in 2024
+        assert_eq!(days("2025-w01"), (date("2024-12-30"), date("2025-01-05")));
+        assert_eq!(days("2024-03"), (date("2024-03-01"), date("2024-03-31")));
+        assert_eq!(days("2024-02"), (date("2024-02-01"), date("2024-02-29")));
+        assert_eq!(days("2023-12"), (date("2023-12-01"), date("2023-12-31")));
+    }
+
+    #[test]
+    fn invalid_dates_are_refused() {
+        for spec in [
+            "",
+            "-é",
+            "+",
+            "-",
+            "-7",
+            "-7x",
+            "-d",
+            "--7d",
+            "1.5d",
+            "2024-W54",
+            "2024-13",
+            "2024-02-30",
+            "someday",
+            "-9223372036854775807w",
+            "-9999999d",
+            "262142-12",
+        ] {
+            assert_eq!(
+                spec.parse::<DateSpec>().unwrap_err(),
+                format!(
+                    "invalid date {:?}, expected e.g. 2024-03-18, -7d, -2w, today, monday, 2024-W12 or 2024-03",
+                    spec
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn only_known_time_zones_are_set() {
+        // Turned down before TZ is touched, TZ being shared by every test
+        for name in ["Nowhere/Atlantis", "/etc/passwd", "../../etc/passwd", ""] {
+            assert_eq!(
+                set_timezone(name).unwrap_err(),
+                format!("unknown time zone {}", name)
+            );
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! What a run can fail with, worded so the user knows what to fix
+
+use reqwest::{StatusCode, Url};
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Azure DevOps turned the token down, 401 or 403
+    Auth(reqwest::Error),
+    /// Azure DevOps answered with its sign-in page, as for an expired token
+    SignIn(Url),
+    /// The organization, project or work item doesn't exist, or isn't visible
+    NotFound(reqwest::Error),
+    /// The proxy turned its credentials down
+    ProxyAuth,
+    /// Any other status Azure DevOps answered with
+    Status(reqwest::Error),
+    /// The response wasn't the JSON expected
+    Decode(reqwest::Error),
+    /// Azure DevOps couldn't be reached
+    Network(reqwest::Error),
+    Io(io::Error),
+    /// Bad input, with the message to show
+    Message(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Credentials never end up in URLs, so they're safe to show
+        let url = |error: &reqwest::Error| {
+            error
+                .url()
+                .map(|url| format!(" for {}", url))
+                .unwrap_or_default()
+        };
+        match self {
+            Error::Auth(error) => write!(
+                f,
+                "Azure DevOps refused the token ({}){}; check that --token (ACCESS_TOKEN) is a \
+                 personal access token that hasn't expired, has the Work Items (Read) scope and \
+                 belongs to --user",
+                error.status().unwrap_or(StatusCode::UNAUTHORIZED),
+                url(error)
+            ),
+            Error::SignIn(url) => write!(
+                f,
+                "Azure DevOps wants to sign in for {}; check that --token (ACCESS_TOKEN) is a \
+                 personal access token that hasn't expired or been revoked",
+                url
+            ),
+            Error::NotFound(error) => write!(
+                f,
+                "Not found{}; check --organization and --project, and that the token's user can \
+                 see them",
+                url(error)
+            ),
+            Error::ProxyAuth => write!(
+                f,
+                "the proxy rejected the credentials, check --proxy-user/--proxy-pass"
+            ),
+            Error::Status(error) => write!(f, "Azure DevOps answered {}", error),
+            Error::Decode(error) => write!(
+                f,
+                "Unexpected response from Azure DevOps{}: {}; the API may have changed or a \
+                 proxy or login page answered instead",
+                url(error),
+                error
+            ),
+            Error::Network(error) if untrusted(error) => write!(
+                f,
+                "Can't reach Azure DevOps: {}; the certificate isn't trusted, give the root \
+                 certificate with --ca-cert",
+                error
+            ),
+            Error::Network(error) => write!(
+                f,
+                "Can't reach Azure DevOps: {}; check the network connection and --proxy",
+                error
+            ),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Message(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Whether the TLS handshake failed on the certificate, as behind a proxy
+/// with a private root CA
+fn untrusted(error: &reqwest::Error) -> bool {
+    let mut source: Option<&dyn std::error::Error> = Some(error);
+    while let Some(error) = source {
+        if error.to_string().to_lowercase().contains("certificate") {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        if crate::client::proxy_rejected(&error) {
+            return Error::ProxyAuth;
+        }
+        match error.status() {
+            Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => Error::Auth(error),
+            Some(StatusCode::NOT_FOUND) => Error::NotFound(error),
+            Some(_) => Error::Status(error),
+            None if error.is_decode() => Error::Decode(error),
+            None => Error::Network(error),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Message(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Message(message.to_string())
+    }
+}
@@ -0,0 +1,324 @@
+//! Flow metrics from the same revisions as the time: how long items spent in
+//! each state, and their lead and cycle times
+//!
+//! An item's lead time runs from its creation to when it last entered one of
+//! the done states, its cycle time from when it first left the state it was
+//! created in. Both are reported for the items finished in the range. Time in
+//! state only counts what falls in the range, and not the done states, which
+//! are where the flow ends.
+
+use crate::{
+    cache::Cache, fetch_histories, query_work_items, Connection, Error, ReportQuery, Revision,
+};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::Serialize;
+
+/// Days spent in a state
+#[derive(Debug, Clone, Serialize)]
+pub struct StateTime {
+    pub state: String,
+    pub days: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ItemFlow {
+    pub id: u64,
+    pub title: String,
+    pub organization: String,
+    pub project: String,
+    pub url: String,
+    /// The state the item is in now
+    pub state: Option<String>,
+    /// In the order the item first entered the states
+    pub time_in_state: Vec<StateTime>,
+    pub created: DateTime<Utc>,
+    /// When the item first left the state it was created in
+    pub started: Option<DateTime<Utc>>,
+    /// When the item entered the done state it's in, if it is in one
+    pub finished: Option<DateTime<Utc>>,
+    /// Days from created to finished, for items finished in the range
+    pub lead_time: Option<f64>,
+    /// Days from started to finished, for items finished in the range
+    pub cycle_time: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlowReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub items: Vec<ItemFlow>,
+    /// Days of all the items in each state
+    pub time_in_state: Vec<StateTime>,
+    /// Items finished in the range
+    pub finished: usize,
+    /// Average lead time of the items finished in the range
+    pub lead_time: Option<f64>,
+    /// Average cycle time of the items finished in the range
+    pub cycle_time: Option<f64>,
+}
+
+/// The start of a day in the local time zone
+fn start_of(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_local_timezone(Local)
+        .earliest()
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+fn days(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_seconds() as f64 / 86_400.0
+}
+
+fn add(times: &mut Vec<StateTime>, state: &str, days: f64) {
+    match times.iter_mut().find(|time| time.state == state) {
+        Some(time) => time.days += days,
+        None => times.push(StateTime {
+            state: state.to_string(),
+            days,
+        }),
+    }
+}
+
+/// The flow of the items changed in the range, everyone's, as the states
+/// aren't anyone's
+pub(crate) async fn collect(
+    client: &reqwest::Client,
+    connection: &Connection,
+    query: &ReportQuery,
+    done_states: &[String],
+) -> Result<Vec<ItemFlow>, Error> {
+    let work_items = match query.cache.as_deref().filter(|_| query.offline) {
+        Some(path) => Cache::load(path)
+            .map_err(|error| format!("Can't read the cache {}: {}", path.display(), error))?
+            .work_items(connection, connection.project.as_deref()),
+        None => query_work_items(client, connection, query).await?,
+    };
+    let histories = fetch_histories(client, connection, query, &work_items).await?;
+
+    let now = Utc::now();
+    Ok(work_items
+        .into_iter()
+        .zip(histories)
+        .filter_map(|(work_item, revisions)| {
+            item_flow(connection, query, done_states, work_item.id, revisions, now)
+        })
+        .collect())
+}
+
+/// The flow of an item from its revisions, as of `now`, or nothing if it has
+/// none
+fn item_flow(
+    connection: &Connection,
+    query: &ReportQuery,
+    done_states: &[String],
+    id: u64,
+    mut revisions: Vec<Revision>,
+    now: DateTime<Utc>,
+) -> Option<ItemFlow> {
+    let done = |state: &Option<String>| {
+        state.as_ref().is_some_and(|state| {
+            done_states
+                .iter()
+                .any(|done| done.eq_ignore_ascii_case(state))
+        })
+    };
+    let (range_start, range_end) = (start_of(query.from), start_of(query.to.succ_opt().unwrap()));
+    revisions.sort_by_key(|revision| (revision.fields.changed_date, revision.rev));
+    let (first, last) = (revisions.first()?, revisions.last()?);
+    let created = first.fields.changed_date;
+    let mut time_in_state = Vec::new();
+    let mut started = None;
+    let mut finished = None;
+    for (i, revision) in revisions.iter().enumerate() {
+        let state = &revision.fields.state;
+        if started.is_none() && state.is_some() && *state != first.fields.state {
+            started = Some(revision.fields.changed_date);
+        }
+        if done(state) && (i == 0 || !done(&revisions[i - 1].fields.state)) {
+            finished = Some(revision.fields.changed_date);
+        }
+        let (Some(state), false) = (state, done(state)) else {
+            continue;
+        };
+        let until = revisions
+            .get(i + 1)
+            .map_or(now, |next| next.fields.changed_date);
+        let (from, to) = (
+            revision.fields.changed_date.max(range_start),
+            until.min(range_end),
+        );
+        if from < to {
+            add(&mut time_in_state, state, days(from, to));
+        }
+    }
+    // Reopened items aren't finished
+    let finished = finished.filter(|_| done(&last.fields.state));
+    let in_range = finished.filter(|finished| (range_start..range_end).contains(finished));
+    let project = last
+        .fields
+        .team_project
+        .clone()
+        .or_else(|| connection.project.clone())
+        .unwrap_or_default();
+    Some(ItemFlow {
+        id,
+        title: last.fields.title.clone().unwrap_or_default(),
+        organization: connection.organization.clone(),
+        url: connection.work_item_url(&project, id).to_string(),
+        project,
+        state: last.fields.state.clone(),
+        time_in_state,
+        created,
+        started,
+        finished,
+        lead_time: in_range.map(|finished| days(created, finished)),
+        cycle_time: in_range.and_then(|finished| {
+            Some(days(
+                started.filter(|started| *started <= finished)?,
+                finished,
+            ))
+        }),
+    })
+}
+
+impl FlowReport {
+    pub(crate) fn new(query: &ReportQuery, items: Vec<ItemFlow>) -> FlowReport {
+        let mut time_in_state = Vec::new();
+        for item in &items {
+            for time in &item.time_in_state {
+                add(&mut time_in_state, &time.state, time.days);
+            }
+        }
+        let average = |times: Vec<f64>| {
+            (!times.is_empty()).then(|| times.iter().sum::<f64>() / times.len() as f64)
+        };
+        FlowReport {
+            from: query.from,
+            to: query.to,
+            finished: items.iter().filter(|item| item.lead_time.is_some()).count(),
+            lead_time: average(items.iter().filter_map(|item| item.lead_time).collect()),
+            cycle_time: average(items.iter().filter_map(|item| item.cycle_time).collect()),
+            time_in_state,
+            items,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{connection, query, revision};
+    use serde_json::json;
+
+    fn states(states: &[(&str, &str)]) -> Vec<Revision> {
+        states
+            .iter()
+            .enumerate()
+            .map(|(i, (date, state))| {
+                revision(
+                    i as u32 + 1,
+                    date,
+                    "a@b.c",
+                    json!({ "System.State": state }),
+                )
+            })
+            .collect()
+    }
+
+    /// The flows of the items over March 2024, as of mid-April
+    fn flows(histories: Vec<Vec<Revision>>) -> Vec<ItemFlow> {
+        let now = "2024-04-15T12:00:00Z".parse().unwrap();
+        let done = ["Closed".to_string(), "Removed".to_string()];
+        histories
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, revisions)| {
+                item_flow(&connection(), &query(), &done, i as u64 + 1, revisions, now)
+            })
+            .collect()
+    }
+
+    fn time_in_state(times: &[StateTime]) -> Vec<(&str, f64)> {
+        times
+            .iter()
+            .map(|time| (time.state.as_str(), time.days))
+            .collect()
+    }
+
+    #[test]
+    fn lead_and_cycle_times_run_to_the_last_time_it_was_done() {
+        let items = flows(vec![
+            // Created before the range, reopened and closed again in it
+            states(&[
+                ("2024-02-20", "New"),
+                ("2024-03-04", "Active"),
+                ("2024-03-08", "Closed"),
+                ("2024-03-11", "Active"),
+                ("2024-03-15", "closed"),
+            ]),
+            Vec::new(),
+        ]);
+        assert_eq!(items.len(), 1);
+        let item = &items[0];
+        assert_eq!(item.started, Some("2024-03-04T12:00:00Z".parse().unwrap()));
+        assert_eq!(item.finished, Some("2024-03-15T12:00:00Z".parse().unwrap()));
+        assert_eq!(item.lead_time, Some(24.0));
+        assert_eq!(item.cycle_time, Some(11.0));
+        // Only from the start of the range, and not while it was closed
+        assert_eq!(
+            time_in_state(&item.time_in_state),
+            [("New", 3.5), ("Active", 8.0)]
+        );
+        assert_eq!(item.url, "https://dev.azure.com/org/Proj/_workitems/edit/1");
+    }
+
+    #[test]
+    fn only_items_done_in_the_range_are_finished() {
+        let items = flows(vec![
+            // Reopened, and still open
+            states(&[
+                ("2024-03-05", "New"),
+                ("2024-03-06", "Active"),
+                ("2024-03-07", "Closed"),
+                ("2024-03-20", "Active"),
+            ]),
+            // Done after the range
+            states(&[("2024-03-25", "New"), ("2024-04-03", "Closed")]),
+            // Done before it
+            states(&[("2024-01-10", "New"), ("2024-02-10", "Removed")]),
+            // Created done
+            states(&[("2024-03-12", "Closed")]),
+        ]);
+        let finished: Vec<_> = items
+            .iter()
+            .map(|item| (item.finished.is_some(), item.lead_time, item.cycle_time))
+            .collect();
+        assert_eq!(
+            finished,
+            [
+                (false, None, None),
+                (true, None, None),
+                (true, None, None),
+                (true, Some(0.0), None),
+            ]
+        );
+        // Time in a state still open runs to the end of the range
+        assert_eq!(
+            time_in_state(&items[0].time_in_state),
+            [("New", 1.0), ("Active", 12.5)]
+        );
+        assert_eq!(time_in_state(&items[1].time_in_state), [("New", 6.5)]);
+        assert_eq!(time_in_state(&items[2].time_in_state), []);
+
+        let report = FlowReport::new(&query(), items);
+        assert_eq!(report.finished, 1);
+        assert_eq!(report.lead_time, Some(0.0));
+        assert_eq!(report.cycle_time, None);
+        assert_eq!(
+            time_in_state(&report.time_in_state),
+            [("New", 7.5), ("Active", 12.5)]
+        );
+    }
+}
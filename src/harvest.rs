@@ -0,0 +1,393 @@
+//! Pushing the report's time to Harvest as time entries
+//!
+//! Every work item and day the user logged time on becomes one Harvest time
+//! entry, in the [`Task`] the item is mapped to. Entries carry
+//! an external reference naming the item and day, so pushing the same range
+//! again updates or removes what was pushed before instead of adding it twice.
+
+use crate::{
+    client::{self, SendRetrying},
+    mapping::{self, Mapping},
+    ClientOptions, Error, Report, ReportItem,
+};
+use chrono::NaiveDate;
+use reqwest::{
+    header::{AUTHORIZATION, USER_AGENT},
+    Method, RequestBuilder, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+const API: &str = "https://api.harvestapp.com/v2";
+
+/// The Harvest project and task the time on a work item goes to, given as
+/// `PROJECT_ID/TASK_ID`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub project_id: u64,
+    pub task_id: u64,
+}
+
+impl FromStr for Task {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expected = || format!("expected PROJECT_ID/TASK_ID, got {:?}", s);
+        let (project_id, task_id) = s.split_once('/').ok_or_else(expected)?;
+        Ok(Task {
+            project_id: project_id.trim().parse().map_err(|_| expected())?,
+            task_id: task_id.trim().parse().map_err(|_| expected())?,
+        })
+    }
+}
+
+/// What pushing did, or would do, for a work item and day
+#[derive(Debug, Serialize)]
+pub struct Pushed {
+    pub id: u64,
+    pub date: NaiveDate,
+    pub hours: f64,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Action {
+    Created,
+    /// From the hours given
+    Updated(f64),
+    /// The time was taken off again since it was pushed
+    Deleted,
+    Unchanged,
+    /// The item isn't mapped to a Harvest project
+    Unmapped,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Created => f.write_str("created"),
+            Action::Updated(hours) => write!(f, "updated from {}", hours),
+            Action::Deleted => f.write_str("deleted"),
+            Action::Unchanged => f.write_str("unchanged"),
+            Action::Unmapped => f.write_str("not mapped to a Harvest project"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Me {
+    id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TimeEntries {
+    time_entries: Vec<TimeEntry>,
+    next_page: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TimeEntry {
+    id: u64,
+    hours: f64,
+    external_reference: Option<ExternalReference>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExternalReference {
+    id: String,
+}
+
+pub struct Harvest {
+    http: reqwest::Client,
+    api: String,
+    account_id: String,
+    token: String,
+}
+
+impl Harvest {
+    pub fn new(options: &ClientOptions, account_id: &str, token: &str) -> Result<Self, Error> {
+        Ok(Harvest {
+            http: client::build(options)?,
+            api: API.to_string(),
+            account_id: account_id.to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http
+            .request(method, format!("{}/{}", self.api, path))
+            .header(AUTHORIZATION, format!("Bearer {}", self.token))
+            .header("Harvest-Account-Id", &self.account_id)
+            // Harvest turns away requests that don't say who's asking
+            .header(
+                USER_AGENT,
+                concat!("azure-devops-time-used/", env!("CARGO_PKG_VERSION")),
+            )
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response, Error> {
+        let response = request.send_retrying().await.map_err(harvest_error)?;
+        response.error_for_status().map_err(harvest_error)
+    }
+
+    /// Make the Harvest time entries of the report's user match its time,
+    /// only telling what would change with `dry_run`
+    pub async fn push(
+        &self,
+        report: &Report,
+        tasks: &[Mapping<Task>],
+        dry_run: bool,
+    ) -> Result<Vec<Pushed>, Error> {
+        // Hours per item and day, by the key the entry is pushed under;
+        // Harvest books them on the token's user, so only theirs go
+        let mut logged: BTreeMap<String, (&ReportItem, NaiveDate, f64)> = BTreeMap::new();
+        for item in &report.items {
+            for entry in &item.entries {
+                if entry.changed_by.email.eq_ignore_ascii_case(&report.user) {
+                    logged
+                        .entry(reference(item, entry.date))
+                        .or_insert((item, entry.date, 0.0))
+                        .2 += entry.hours;
+                }
+            }
+        }
+
+        let me: Me = self
+            .send(self.request(Method::GET, "users/me"))
+            .await?
+            .json()
+            .await
+            .map_err(harvest_error)?;
+        let mut pushed_before: BTreeMap<String, TimeEntry> = BTreeMap::new();
+        let mut page = Some(1);
+        while let Some(number) = page {
+            let entries: TimeEntries = self
+                .send(self.request(Method::GET, "time_entries").query(&[
+                    ("user_id", me.id.to_string()),
+                    ("from", report.from.to_string()),
+                    ("to", report.to.to_string()),
+                    ("page", number.to_string()),
+                    ("per_page", "2000".to_string()),
+                ]))
+                .await?
+                .json()
+                .await
+                .map_err(harvest_error)?;
+            for entry in entries.time_entries {
+                if let Some(reference) = &entry.external_reference {
+                    if reference.id.starts_with(REFERENCE_PREFIX) {
+                        pushed_before.insert(reference.id.clone(), entry);
+                    }
+                }
+            }
+            page = entries.next_page;
+        }
+
+        let mut pushed = Vec::new();
+        for (key, (item, date, hours)) in &logged {
+            let hours = (hours * 100.0).round() / 100.0;
+            let before = pushed_before.remove(key);
+            let action = match (&before, mapping::lookup(tasks, item)) {
+                (Some(entry), _) if hours <= 0.0 => {
+                    if !dry_run {
+                        self.delete(entry.id).await?;
+                    }
+                    Action::Deleted
+                }
+                (_, _) if hours <= 0.0 => continue,
+                (Some(entry), _) if (entry.hours - hours).abs() < 0.005 => Action::Unchanged,
+                (Some(entry), _) => {
+                    if !dry_run {
+                        self.send(
+                            self.request(Method::PATCH, &format!("time_entries/{}", entry.id))
+                                .json(&json!({ "hours": hours })),
+                        )
+                        .await?;
+                    }
+                    Action::Updated(entry.hours)
+                }
+                (None, None) => Action::Unmapped,
+                (None, Some(task)) => {
+                    if !dry_run {
+                        self.send(self.request(Method::POST, "time_entries").json(&json!({
+                            "project_id": task.project_id,
+                            "task_id": task.task_id,
+                            "spent_date": date.to_string(),
+                            "hours": hours,
+                            "notes": format!("#{} {}", item.id, item.title),
+                            "external_reference": {
+                                "id": key,
+                                "group_id": item.organization,
+                                "permalink": item.url,
+                            },
+                        })))
+                        .await?;
+                    }
+                    Action::Created
+                }
+            };
+            pushed.push(Pushed {
+                id: item.id,
+                date: *date,
+                hours,
+                action,
+            });
+        }
+
+        // Pushed before, but no time is logged on the item that day anymore;
+        // entries of items this report didn't look at, pushed from another
+        // project or organization, are left alone
+        for (key, entry) in pushed_before {
+            let Some((organization, id, date)) = parse_reference(&key) else {
+                continue;
+            };
+            if !report.queried.contains(&(organization.to_string(), id)) {
+                continue;
+            }
+            if !dry_run {
+                self.delete(entry.id).await?;
+            }
+            pushed.push(Pushed {
+                id,
+                date,
+                hours: 0.0,
+                action: Action::Deleted,
+            });
+        }
+        Ok(pushed)
+    }
+
+    async fn delete(&self, entry: u64) -> Result<(), Error> {
+        self.send(self.request(Method::DELETE, &format!("time_entries/{}", entry)))
+            .await?;
+        Ok(())
+    }
+}
+
+const REFERENCE_PREFIX: &str = "azure-devops:";
+
+/// The external reference of the entry for a work item and day
+fn reference(item: &ReportItem, date: NaiveDate) -> String {
+    format!(
+        "{}{}:{}:{}",
+        REFERENCE_PREFIX, item.organization, item.id, date
+    )
+}
+
+/// The organization, item and day of an entry's external reference
+fn parse_reference(reference: &str) -> Option<(&str, u64, NaiveDate)> {
+    let mut parts = reference.strip_prefix(REFERENCE_PREFIX)?.rsplitn(3, ':');
+    let date = parts.next()?.parse().ok()?;
+    let id = parts.next()?.parse().ok()?;
+    Some((parts.next()?, id, date))
+}
+
+/// Errors from Harvest, which [`Error`]'s own messages would blame on Azure DevOps
+fn harvest_error(error: reqwest::Error) -> Error {
+    match error.status() {
+        Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => format!(
+            "Harvest refused the token ({}); check --harvest-token and --harvest-account-id",
+            error.status().unwrap()
+        )
+        .into(),
+        _ => format!("Harvest: {}", error).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_server, report, response, revision};
+
+    #[tokio::test]
+    async fn only_entries_of_the_items_looked_at_are_deleted() {
+        let entry = |id: u64, reference: &str| json!({ "id": id, "hours": 2.0, "external_reference": { "id": reference } });
+        let pushed_before = json!({
+            "time_entries": [
+                entry(100, "azure-devops:org:1:2024-03-04"),
+                entry(101, "azure-devops:org:1:2024-03-05"),
+                entry(105, "azure-devops:org:2:2024-03-06"),
+                // Pushed from another project and another organization
+                entry(102, "azure-devops:org:9:2024-03-05"),
+                entry(103, "azure-devops:other:1:2024-03-04"),
+                { "id": 104, "hours": 1.0, "external_reference": null },
+            ],
+            "next_page": null,
+        });
+        let ok = || response("200 OK", &[], "{}");
+        let (server, requests) = mock_server(vec![
+            response("200 OK", &[], r#"{"id": 7}"#),
+            response("200 OK", &[], &pushed_before.to_string()),
+            ok(),
+            ok(),
+            ok(),
+            ok(),
+        ])
+        .await;
+        let harvest = Harvest {
+            api: format!("http://{}/v2", server),
+            ..Harvest::new(&ClientOptions::default(), "1234", "token").unwrap()
+        };
+        let report = report(vec![
+            vec![
+                revision(
+                    1,
+                    "2024-03-04",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 1.0 }),
+                ),
+                revision(
+                    2,
+                    "2024-03-06",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 3.0 }),
+                ),
+            ],
+            // Looked at, but its time is gone
+            vec![revision(1, "2024-03-06", "a@b.c", json!({}))],
+        ]);
+        let tasks = vec!["*=11/22".parse().unwrap()];
+
+        let pushed = harvest.push(&report, &tasks, false).await.unwrap();
+        let actions: Vec<(u64, String, Action)> = pushed
+            .iter()
+            .map(|pushed| (pushed.id, pushed.date.to_string(), pushed.action))
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                (1, "2024-03-04".to_string(), Action::Updated(2.0)),
+                (1, "2024-03-06".to_string(), Action::Created),
+                (1, "2024-03-05".to_string(), Action::Deleted),
+                (2, "2024-03-06".to_string(), Action::Deleted),
+            ]
+        );
+        let requests: Vec<String> = requests
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| request.lines().next().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            requests[2..],
+            [
+                "PATCH /v2/time_entries/100 HTTP/1.1",
+                "POST /v2/time_entries HTTP/1.1",
+                "DELETE /v2/time_entries/101 HTTP/1.1",
+                "DELETE /v2/time_entries/105 HTTP/1.1",
+            ]
+        );
+    }
+
+    #[test]
+    fn references_name_the_organization_item_and_day() {
+        assert_eq!(
+            parse_reference("azure-devops:org:1:2024-03-04"),
+            Some(("org", 1, NaiveDate::from_ymd_opt(2024, 3, 4).unwrap()))
+        );
+        assert_eq!(parse_reference("toggl:org:1:2024-03-04"), None);
+        assert_eq!(parse_reference("azure-devops:1:2024-03-04"), None);
+    }
+}
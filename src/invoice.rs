@@ -0,0 +1,526 @@
+//! Invoices for the billable time, as a one-file PDF
+//!
+//! The PDF is written by hand with the standard Helvetica fonts, which every
+//! viewer has, so nothing is embedded. Text is in their Latin-1 like
+//! encoding; other characters come out as `?`.
+
+use crate::rates::{format_number, Billing};
+use chrono::NaiveDate;
+
+/// A line of the invoice: a work item, or what items roll up to with `--rollup`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub description: String,
+    pub hours: f64,
+    pub amount: f64,
+}
+
+impl Line {
+    /// The hourly rate, which is an average when rates differ by user
+    pub fn rate(&self) -> f64 {
+        if self.hours == 0.0 {
+            0.0
+        } else {
+            self.amount / self.hours
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Invoice {
+    pub number: Option<String>,
+    pub date: NaiveDate,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// Lines of the sender's name and address
+    pub bill_from: Vec<String>,
+    /// Lines of the client's name and address
+    pub bill_to: Vec<String>,
+    pub currency: String,
+    pub lines: Vec<Line>,
+}
+
+/// A line per billed item, or per item they roll up to, in report order
+pub fn lines(billing: &Billing) -> Vec<Line> {
+    let mut lines: Vec<(u64, Line)> = Vec::new();
+    for item in &billing.items {
+        let (id, description) = match &item.rolls_up_to {
+            Some(ancestor) => (
+                ancestor.id,
+                format!(
+                    "{} #{} {}",
+                    ancestor.work_item_type, ancestor.id, ancestor.title
+                ),
+            ),
+            None => (item.id, format!("#{} {}", item.id, item.title)),
+        };
+        match lines.iter_mut().find(|(line_id, _)| *line_id == id) {
+            Some((_, line)) => {
+                line.hours += item.hours;
+                line.amount += item.amount;
+            }
+            None => lines.push((
+                id,
+                Line {
+                    description,
+                    hours: item.hours,
+                    amount: item.amount,
+                },
+            )),
+        }
+    }
+    lines.into_iter().map(|(_, line)| line).collect()
+}
+
+const PAGE_WIDTH: f64 = 595.0;
+const PAGE_HEIGHT: f64 = 842.0;
+const MARGIN: f64 = 50.0;
+const ROW: f64 = 16.0;
+/// Right edges of the hours, rate and amount columns
+const HOURS_RIGHT: f64 = 375.0;
+const RATE_RIGHT: f64 = 460.0;
+const AMOUNT_RIGHT: f64 = PAGE_WIDTH - MARGIN;
+const DESCRIPTION_WIDTH: f64 = 300.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn name(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+
+    /// Width of `text` in points at `size`, from the fonts' metrics
+    fn width(self, text: &str, size: f64) -> f64 {
+        let widths = match self {
+            Font::Regular => &HELVETICA,
+            Font::Bold => &HELVETICA_BOLD,
+        };
+        let units: u32 = text
+            .chars()
+            .map(|c| match c {
+                ' '..='~' => u32::from(widths[c as usize - 32]),
+                _ => 556,
+            })
+            .sum();
+        f64::from(units) * size / 1000.0
+    }
+}
+
+/// Widths of the printable ASCII characters, in thousandths of the size
+#[rustfmt::skip]
+const HELVETICA: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+#[rustfmt::skip]
+const HELVETICA_BOLD: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+/// The content streams of the pages being laid out
+struct Pages {
+    pages: Vec<String>,
+    y: f64,
+}
+
+impl Pages {
+    fn current(&mut self) -> &mut String {
+        self.pages.last_mut().unwrap()
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(String::new());
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn text(&mut self, x: f64, y: f64, font: Font, size: f64, text: &str) {
+        let command = format!(
+            "BT /{} {} Tf {:.2} {:.2} Td ({}) Tj ET\n",
+            font.name(),
+            size,
+            x,
+            y,
+            pdf_string(text)
+        );
+        self.current().push_str(&command);
+    }
+
+    fn right(&mut self, right: f64, y: f64, font: Font, size: f64, text: &str) {
+        self.text(right - font.width(text, size), y, font, size, text);
+    }
+
+    fn rule(&mut self, y: f64) {
+        let command = format!(
+            "0.5 w {:.2} {:.2} m {:.2} {:.2} l S\n",
+            MARGIN, y, AMOUNT_RIGHT, y
+        );
+        self.current().push_str(&command);
+    }
+}
+
+impl Invoice {
+    pub fn total_hours(&self) -> f64 {
+        self.lines.iter().map(|line| line.hours).sum()
+    }
+
+    pub fn total(&self) -> f64 {
+        self.lines.iter().map(|line| line.amount).sum()
+    }
+
+    pub fn pdf(&self) -> Vec<u8> {
+        let mut pages = Pages {
+            pages: Vec::new(),
+            y: 0.0,
+        };
+        pages.new_page();
+
+        // The sender on the left, the invoice's own details on the right
+        let top = pages.y;
+        let mut y = top - 20.0;
+        for (i, line) in self.bill_from.iter().enumerate() {
+            let font = if i == 0 { Font::Bold } else { Font::Regular };
+            pages.text(MARGIN, y, font, 10.0, line);
+            y -= 13.0;
+        }
+        pages.right(AMOUNT_RIGHT, top - 20.0, Font::Bold, 20.0, "INVOICE");
+        let mut details = Vec::new();
+        if let Some(number) = &self.number {
+            details.push(format!("Invoice no. {}", number));
+        }
+        details.push(format!("Date {}", self.date));
+        details.push(format!("Period {} to {}", self.from, self.to));
+        let mut details_y = top - 40.0;
+        for detail in &details {
+            pages.right(AMOUNT_RIGHT, details_y, Font::Regular, 10.0, detail);
+            details_y -= 13.0;
+        }
+
+        y = y.min(details_y) - 20.0;
+        if !self.bill_to.is_empty() {
+            pages.text(MARGIN, y, Font::Bold, 10.0, "Bill to");
+            y -= 13.0;
+            for line in &self.bill_to {
+                pages.text(MARGIN, y, Font::Regular, 10.0, line);
+                y -= 13.0;
+            }
+            y -= 20.0;
+        }
+        pages.y = y;
+
+        let header = |pages: &mut Pages| {
+            let y = pages.y;
+            pages.text(MARGIN, y, Font::Bold, 10.0, "Description");
+            pages.right(HOURS_RIGHT, y, Font::Bold, 10.0, "Hours");
+            pages.right(RATE_RIGHT, y, Font::Bold, 10.0, "Rate");
+            let amount = format!("Amount ({})", self.currency);
+            pages.right(AMOUNT_RIGHT, y, Font::Bold, 10.0, &amount);
+            pages.rule(y - 5.0);
+            pages.y -= ROW + 4.0;
+        };
+        header(&mut pages);
+        for line in &self.lines {
+            if pages.y < MARGIN + ROW {
+                pages.new_page();
+                header(&mut pages);
+            }
+            let y = pages.y;
+            let description = truncate(&line.description, DESCRIPTION_WIDTH);
+            pages.text(MARGIN, y, Font::Regular, 10.0, &description);
+            pages.right(HOURS_RIGHT, y, Font::Regular, 10.0, &hours(line.hours));
+            pages.right(
+                RATE_RIGHT,
+                y,
+                Font::Regular,
+                10.0,
+                &format_number(line.rate()),
+            );
+            pages.right(
+                AMOUNT_RIGHT,
+                y,
+                Font::Regular,
+                10.0,
+                &format_number(line.amount),
+            );
+            pages.y -= ROW;
+        }
+        if pages.y < MARGIN + ROW * 2.0 {
+            pages.new_page();
+        }
+        let y = pages.y + ROW - 5.0;
+        pages.rule(y);
+        let y = pages.y - 6.0;
+        pages.text(MARGIN, y, Font::Bold, 10.0, "Total");
+        pages.right(HOURS_RIGHT, y, Font::Bold, 10.0, &hours(self.total_hours()));
+        pages.right(
+            AMOUNT_RIGHT,
+            y,
+            Font::Bold,
+            10.0,
+            &format_number(self.total()),
+        );
+
+        write_pdf(&pages.pages)
+    }
+}
+
+fn hours(hours: f64) -> String {
+    format!("{:.2}", hours)
+}
+
+/// Cut `text` short with an ellipsis if it's wider than `width`
+fn truncate(text: &str, width: f64) -> String {
+    if Font::Regular.width(text, 10.0) <= width {
+        return text.to_string();
+    }
+    let mut truncated = String::new();
+    for c in text.chars() {
+        if Font::Regular.width(&format!("{}{}...", truncated, c), 10.0) > width {
+            break;
+        }
+        truncated.push(c);
+    }
+    format!("{}...", truncated.trim_end())
+}
+
+/// A PDF string literal's contents, in WinAnsiEncoding with anything past
+/// ASCII as octal escapes
+fn pdf_string(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            '€' => escaped.push_str("\\200"),
+            '\u{a0}'..='\u{ff}' => escaped.push_str(&format!("\\{:03o}", c as u32)),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+/// The objects of the document around the pages' content streams, and the
+/// cross-reference table pointing at each
+fn write_pdf(pages: &[String]) -> Vec<u8> {
+    let font = |name: &str| {
+        format!(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+            name
+        )
+    };
+    // Catalog, page tree and fonts, then a page and its contents for each page
+    let kids: Vec<String> = (0..pages.len())
+        .map(|i| format!("{} 0 R", 5 + i * 2))
+        .collect();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        ),
+        font("Helvetica"),
+        font("Helvetica-Bold"),
+    ];
+    for (i, content) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            PAGE_WIDTH,
+            PAGE_HEIGHT,
+            6 + i * 2
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            content.len(),
+            content
+        ));
+    }
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+    }
+    let xref = pdf.len();
+    pdf.push_str(&format!("xref\n0 {}\n", objects.len() + 1));
+    // Entries are exactly 20 bytes, with the line ending
+    pdf.push_str("0000000000 65535 f \n");
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    pdf.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    ));
+    pdf.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rates::BilledItem, Ancestor};
+    use std::collections::BTreeMap;
+
+    fn billed(id: u64, rolls_up_to: Option<u64>, hours: f64, amount: f64) -> BilledItem {
+        BilledItem {
+            id,
+            title: format!("Task {}", id),
+            project: "Proj".to_string(),
+            rolls_up_to: rolls_up_to.map(|id| Ancestor {
+                id,
+                title: format!("Epic {}", id),
+                work_item_type: "Epic".to_string(),
+            }),
+            hours,
+            amount,
+        }
+    }
+
+    fn billing(items: Vec<BilledItem>) -> Billing {
+        Billing {
+            currency: "DKK".to_string(),
+            total: items.iter().map(|item| item.amount).sum(),
+            items,
+            by_user: BTreeMap::new(),
+            by_project: BTreeMap::new(),
+            unrated_hours: 0.0,
+        }
+    }
+
+    fn invoice(lines: Vec<Line>) -> Invoice {
+        let date = |s: &str| s.parse().unwrap();
+        Invoice {
+            number: Some("2024-007".to_string()),
+            date: date("2024-04-02"),
+            from: date("2024-03-01"),
+            to: date("2024-03-31"),
+            bill_from: vec!["Jens Hansen".to_string(), "Vej 1".to_string()],
+            bill_to: vec!["Client A/S".to_string()],
+            currency: "DKK".to_string(),
+            lines,
+        }
+    }
+
+    #[test]
+    fn items_rolling_up_to_the_same_ancestor_make_one_line() {
+        let lines = lines(&billing(vec![
+            billed(3, Some(100), 2.0, 200.0),
+            billed(1, None, 1.5, 120.0),
+            billed(4, Some(200), 1.0, 100.0),
+            billed(5, Some(100), 3.0, 240.0),
+        ]));
+        assert_eq!(
+            lines,
+            [
+                Line {
+                    description: "Epic #100 Epic 100".to_string(),
+                    hours: 5.0,
+                    amount: 440.0,
+                },
+                Line {
+                    description: "#1 Task 1".to_string(),
+                    hours: 1.5,
+                    amount: 120.0,
+                },
+                Line {
+                    description: "Epic #200 Epic 200".to_string(),
+                    hours: 1.0,
+                    amount: 100.0,
+                },
+            ]
+        );
+        // The average of the rates merged
+        assert_eq!(lines[0].rate(), 88.0);
+        let unbilled = Line {
+            description: String::new(),
+            hours: 0.0,
+            amount: 0.0,
+        };
+        assert_eq!(unbilled.rate(), 0.0);
+    }
+
+    #[test]
+    fn text_is_escaped_and_cut_to_fit() {
+        assert_eq!(pdf_string(r"(a\b) æ € ✓"), r"\(a\\b\) \346 \200 ?");
+        assert_eq!(truncate("Short", DESCRIPTION_WIDTH), "Short");
+        let long = "Word ".repeat(40);
+        let cut = truncate(&long, DESCRIPTION_WIDTH);
+        assert!(cut.ends_with("Word..."), "{}", cut);
+        assert!(Font::Regular.width(&cut, 10.0) <= DESCRIPTION_WIDTH);
+        assert!(Font::Regular.width(&format!("{} W", cut), 10.0) > DESCRIPTION_WIDTH);
+    }
+
+    #[test]
+    fn the_cross_reference_table_points_at_every_object() {
+        let lines: Vec<Line> = (1..=60)
+            .map(|id| Line {
+                description: format!("#{} Task", id),
+                hours: 1.25,
+                amount: 1000.0,
+            })
+            .collect();
+        let invoice = invoice(lines);
+        assert_eq!(invoice.total_hours(), 75.0);
+        assert_eq!(invoice.total(), 60_000.0);
+
+        let pdf = String::from_utf8(invoice.pdf()).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n") && pdf.ends_with("%%EOF\n"));
+        // Too many lines for one page
+        assert!(pdf.contains("/Kids [5 0 R 7 0 R] /Count 2"), "{}", pdf);
+        assert!(pdf.contains("(Invoice no. 2024-007) Tj"));
+        assert!(pdf.contains("(Period 2024-03-01 to 2024-03-31) Tj"));
+        assert!(pdf.contains("(Amount \\(DKK\\)) Tj"));
+        assert!(pdf.contains("(60,000.00) Tj"));
+
+        let xref: usize = pdf
+            .rsplit("startxref\n")
+            .next()
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(pdf[xref..].starts_with("xref\n0 9\n"));
+        let offsets: Vec<usize> = pdf[xref..]
+            .lines()
+            .skip(3)
+            .take(8)
+            .map(|entry| entry[..10].parse().unwrap())
+            .collect();
+        for (i, offset) in offsets.iter().enumerate() {
+            assert!(
+                pdf[*offset..].starts_with(&format!("{} 0 obj\n", i + 1)),
+                "object {}",
+                i + 1
+            );
+        }
+        // Each stream is as long as it says
+        let stream = pdf.find("<< /Length ").unwrap() + "<< /Length ".len();
+        let (length, rest) = pdf[stream..].split_once(" >>\nstream\n").unwrap();
+        let length: usize = length.parse().unwrap();
+        assert!(rest[length..].starts_with("endstream"));
+    }
+}
@@ -0,0 +1,351 @@
+//! Keeping personal access tokens in the OS keyring rather than in files
+//!
+//! Each system's own tool does the storing, so nothing links against them:
+//! `security` for the macOS Keychain, `secret-tool` for the Secret Service of
+//! GNOME Keyring and KWallet, and PowerShell for the Windows Credential
+//! Manager. Secrets go to them on stdin or in the environment, never as
+//! arguments other users could see.
+
+use crate::Error;
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+/// What the tokens are filed under
+const SERVICE: &str = "azure-devops-time-used";
+
+/// Run a tool, feeding it `input`
+fn run(command: &mut Command, input: &str) -> Result<Output, Error> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| format!("Can't run {} for the keyring: {}", program, error))?;
+    child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    Ok(child.wait_with_output()?)
+}
+
+fn failed(output: &Output, what: &str) -> Error {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    format!("Can't {} in the keyring: {}", what, stderr.trim()).into()
+}
+
+/// What to do with the token of an account
+#[derive(Debug, Clone, Copy)]
+enum Operation<'a> {
+    /// Store this token
+    Set(&'a str),
+    Get,
+    Delete,
+}
+
+/// Do it in the keyring of this system
+fn keyring(operation: Operation, account: &str) -> Result<Output, Error> {
+    if cfg!(target_os = "macos") {
+        let (mut command, input) = security(operation, account);
+        run(&mut command, &input)
+    } else if cfg!(windows) {
+        let (statement, secret) = credential_manager(operation, account);
+        powershell(&statement, secret)
+    } else {
+        let (mut command, input) = secret_tool(operation, account);
+        run(&mut command, &input)
+    }
+}
+
+/// The `security` command for the macOS Keychain, and its stdin
+fn security(operation: Operation, account: &str) -> (Command, String) {
+    let mut command = Command::new("security");
+    match operation {
+        Operation::Set(token) => {
+            // Interactive mode reads the command from stdin, token and all
+            command.arg("-i");
+            let input = format!(
+                "add-generic-password -U -s {} -a {} -w {}\n",
+                quote(SERVICE),
+                quote(account),
+                quote(token)
+            );
+            return (command, input);
+        }
+        Operation::Get => {
+            command.args(["find-generic-password", "-s", SERVICE, "-a", account, "-w"])
+        }
+        Operation::Delete => {
+            command.args(["delete-generic-password", "-s", SERVICE, "-a", account])
+        }
+    };
+    (command, String::new())
+}
+
+/// The PowerShell statement for the Windows Credential Manager, and the secret
+/// it reads
+fn credential_manager<'a>(operation: Operation<'a>, account: &str) -> (String, Option<&'a str>) {
+    match operation {
+        Operation::Set(token) => (
+            format!(
+                "[Keyring]::Write('{}', '{}', $env:KEYRING_SECRET)",
+                target(account),
+                account.replace('\'', "''")
+            ),
+            Some(token),
+        ),
+        Operation::Get => (format!("[Keyring]::Read('{}')", target(account)), None),
+        Operation::Delete => (format!("[Keyring]::Delete('{}')", target(account)), None),
+    }
+}
+
+/// The `secret-tool` command for the Secret Service, and its stdin
+fn secret_tool(operation: Operation, account: &str) -> (Command, String) {
+    let mut command = Command::new("secret-tool");
+    let attributes = ["service", SERVICE, "account", account];
+    match operation {
+        Operation::Set(token) => {
+            command
+                .arg("store")
+                .arg(format!("--label=Azure DevOps token for {}", account))
+                .args(attributes);
+            return (command, token.to_string());
+        }
+        Operation::Get => command.arg("lookup").args(attributes),
+        Operation::Delete => command.arg("clear").args(attributes),
+    };
+    (command, String::new())
+}
+
+/// Store the token for an account, replacing any there was
+pub fn set(account: &str, token: &str) -> Result<(), Error> {
+    let output = keyring(Operation::Set(token), account)?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(failed(&output, "store the token"))
+    }
+}
+
+/// The token stored for an account, if there is one
+pub fn get(account: &str) -> Result<Option<String>, Error> {
+    let output = keyring(Operation::Get, account)?;
+    // Every tool fails the same way for a missing entry as for other trouble
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((output.status.success() && !token.is_empty()).then_some(token))
+}
+
+/// Remove the token for an account, telling whether there was one
+pub fn delete(account: &str) -> Result<bool, Error> {
+    if get(account)?.is_none() {
+        return Ok(false);
+    }
+    let output = keyring(Operation::Delete, account)?;
+    if output.status.success() {
+        Ok(true)
+    } else {
+        Err(failed(&output, "remove the token"))
+    }
+}
+
+/// Double quotes for a `security -i` argument
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The Credential Manager's name for an account's token
+fn target(account: &str) -> String {
+    format!("{}:{}", SERVICE, account).replace('\'', "''")
+}
+
+/// The Credential Manager API, which PowerShell only reaches through C#
+const CREDENTIALS: &str = r#"
+Add-Type -TypeDefinition @'
+using System;
+using System.Runtime.InteropServices;
+using FILETIME = System.Runtime.InteropServices.ComTypes.FILETIME;
+
+public static class Keyring {
+    [StructLayout(LayoutKind.Sequential, CharSet = CharSet.Unicode)]
+    struct Credential {
+        public int Flags;
+        public int Type;
+        public string TargetName;
+        public string Comment;
+        public FILETIME LastWritten;
+        public int CredentialBlobSize;
+        public IntPtr CredentialBlob;
+        public int Persist;
+        public int AttributeCount;
+        public IntPtr Attributes;
+        public string TargetAlias;
+        public string UserName;
+    }
+
+    const int Generic = 1;
+    const int LocalMachine = 2;
+
+    [DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+    static extern bool CredRead(string target, int type, int flags, out IntPtr credential);
+    [DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+    static extern bool CredWrite(ref Credential credential, int flags);
+    [DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+    static extern bool CredDelete(string target, int type, int flags);
+    [DllImport("advapi32.dll")]
+    static extern void CredFree(IntPtr credential);
+
+    public static void Read(string target) {
+        IntPtr pointer;
+        if (!CredRead(target, Generic, 0, out pointer)) { Environment.Exit(1); }
+        var credential = (Credential)Marshal.PtrToStructure(pointer, typeof(Credential));
+        Console.Out.Write(Marshal.PtrToStringUni(credential.CredentialBlob, credential.CredentialBlobSize / 2));
+        CredFree(pointer);
+    }
+
+    public static void Write(string target, string user, string secret) {
+        var credential = new Credential();
+        credential.Type = Generic;
+        credential.TargetName = target;
+        credential.UserName = user;
+        credential.CredentialBlobSize = secret.Length * 2;
+        credential.CredentialBlob = Marshal.StringToCoTaskMemUni(secret);
+        credential.Persist = LocalMachine;
+        var written = CredWrite(ref credential, 0);
+        Marshal.FreeCoTaskMem(credential.CredentialBlob);
+        if (!written) { throw new System.ComponentModel.Win32Exception(); }
+    }
+
+    public static void Delete(string target) {
+        if (!CredDelete(target, Generic, 0)) { throw new System.ComponentModel.Win32Exception(); }
+    }
+}
+'@
+"#;
+
+/// Run a PowerShell statement with the Credential Manager API at hand, the
+/// secret in `$env:KEYRING_SECRET`
+///
+/// The script goes in a file, as PowerShell runs what it reads from stdin a
+/// line at a time.
+fn powershell(statement: &str, secret: Option<&str>) -> Result<Output, Error> {
+    let script = std::env::temp_dir().join(format!("{}-{}.ps1", SERVICE, std::process::id()));
+    std::fs::write(
+        &script,
+        format!(
+            "$ErrorActionPreference = 'Stop'\n{}\n{}\n",
+            CREDENTIALS, statement
+        ),
+    )?;
+    let mut command = Command::new("powershell");
+    command
+        .args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-ExecutionPolicy",
+            "Bypass",
+            "-File",
+        ])
+        .arg(&script);
+    if let Some(secret) = secret {
+        command.env("KEYRING_SECRET", secret);
+    }
+    let output = run(&mut command, "");
+    let _ = std::fs::remove_file(&script);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn secrets_never_go_in_the_arguments() {
+        let set = Operation::Set("s3cret");
+        let (command, input) = secret_tool(set, "org");
+        assert_eq!(
+            args(&command),
+            [
+                "store",
+                "--label=Azure DevOps token for org",
+                "service",
+                SERVICE,
+                "account",
+                "org"
+            ]
+        );
+        assert_eq!(input, "s3cret");
+
+        let (command, input) = security(set, r#"say "hi"\"#);
+        assert_eq!(args(&command), ["-i"]);
+        assert_eq!(
+            input,
+            r#"add-generic-password -U -s "azure-devops-time-used" -a "say \"hi\"\\" -w "s3cret""#
+                .to_string()
+                + "\n"
+        );
+
+        let (statement, secret) = credential_manager(set, "Bob's org");
+        assert_eq!(
+            statement,
+            "[Keyring]::Write('azure-devops-time-used:Bob''s org', 'Bob''s org', $env:KEYRING_SECRET)"
+        );
+        assert_eq!(secret, Some("s3cret"));
+    }
+
+    #[test]
+    fn tokens_are_found_and_removed_by_account() {
+        for (operation, secret_tool_args, security_args, statement) in [
+            (
+                Operation::Get,
+                ["lookup", "service", SERVICE, "account", "org"],
+                vec!["find-generic-password", "-s", SERVICE, "-a", "org", "-w"],
+                "[Keyring]::Read('azure-devops-time-used:org')",
+            ),
+            (
+                Operation::Delete,
+                ["clear", "service", SERVICE, "account", "org"],
+                vec!["delete-generic-password", "-s", SERVICE, "-a", "org"],
+                "[Keyring]::Delete('azure-devops-time-used:org')",
+            ),
+        ] {
+            let (command, input) = secret_tool(operation, "org");
+            assert_eq!(args(&command), secret_tool_args);
+            assert_eq!(input, "");
+            let (command, input) = security(operation, "org");
+            assert_eq!(args(&command), security_args);
+            assert_eq!(input, "");
+            assert_eq!(
+                credential_manager(operation, "org"),
+                (statement.to_string(), None)
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tools_are_fed_on_stdin() {
+        let output = run(&mut Command::new("cat"), "s3cret").unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"s3cret");
+
+        let output = run(
+            Command::new("sh").args(["-c", "echo locked >&2; exit 1"]),
+            "",
+        )
+        .unwrap();
+        assert_eq!(
+            failed(&output, "store the token").to_string(),
+            "Can't store the token in the keyring: locked"
+        );
+        let error = run(&mut Command::new("no-such-keyring-tool"), "").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Can't run no-such-keyring-tool for the keyring: "));
+    }
+}
@@ -0,0 +1,3574 @@
+//! Hours logged in Azure DevOps, from the history of each work item
+//!
+//! Azure DevOps only keeps the CompletedWork of a work item, so the time logged
+//! is the difference between successive revisions of it. [`AzdoClient`] finds
+//! the work items that may have time logged on them and reads their history,
+//! and [`Report`] holds the hours that history adds up to, as [`TimeEntry`]s
+//! per item and totals per day, user, project and so on.
+
+use cache::Cache;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use client::{Authorize, SendRetrying};
+use dates::WeekStart;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use rates::Billing;
+use reqwest::Url;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+use targets::TargetComparison;
+use uuid::Uuid;
+
+pub mod aad;
+mod cache;
+mod client;
+pub mod dates;
+pub mod error;
+pub mod flow;
+pub mod harvest;
+pub mod invoice;
+pub mod keyring;
+#[cfg(feature = "email")]
+pub mod mail;
+pub mod mapping;
+pub mod notify;
+mod odata;
+pub mod output;
+pub mod rates;
+#[cfg(feature = "serve")]
+pub mod serve;
+#[cfg(feature = "store")]
+pub mod store;
+pub mod targets;
+mod write;
+
+pub use client::ClientOptions;
+pub use error::Error;
+pub use write::Logged;
+
+#[derive(Debug, Deserialize)]
+pub struct WorkItem {
+    pub id: u64,
+    // url: String,
+    /// When the item was last changed, once the batch request has looked it up
+    #[serde(skip)]
+    pub changed_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkItemQueryResult {
+    #[serde(default)]
+    work_items: Vec<WorkItem>,
+    /// What tree and direct links queries answer with instead of `work_items`
+    #[serde(default)]
+    work_item_relations: Vec<WorkItemLink>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkItemLink {
+    source: Option<WorkItem>,
+    target: Option<WorkItem>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct User {
+    pub id: Uuid,
+    #[serde(rename(deserialize = "displayName"))]
+    pub display_name: String,
+    #[serde(rename(deserialize = "uniqueName"))]
+    pub email: String,
+}
+
+impl fmt::Display for User {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} <{}>", self.display_name, self.email)
+    }
+}
+
+impl fmt::Debug for User {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("User")
+            .field("id", &self.id)
+            .field("display_name", &self.display_name)
+            .field("email", &self.email)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Fields {
+    #[serde(rename = "System.ChangedDate")]
+    // changed_date: Option<DateTime<Utc>>,
+    changed_date: DateTime<Utc>,
+    #[serde(rename = "System.ChangedBy", serialize_with = "serialize_identity")]
+    changed_by: User,
+    #[serde(
+        rename = "Microsoft.VSTS.Scheduling.CompletedWork",
+        default,
+        deserialize_with = "deserialize_hours"
+    )]
+    completed_work: Option<f64>,
+    #[serde(
+        rename = "Microsoft.VSTS.Scheduling.RemainingWork",
+        default,
+        deserialize_with = "deserialize_hours"
+    )]
+    remaining_work: Option<f64>,
+    #[serde(
+        rename = "Microsoft.VSTS.Scheduling.OriginalEstimate",
+        default,
+        deserialize_with = "deserialize_hours"
+    )]
+    original_estimate: Option<f64>,
+    #[serde(rename = "System.Title")]
+    title: Option<String>,
+    #[serde(rename = "System.State")]
+    state: Option<String>,
+    #[serde(rename = "System.Reason")]
+    reason: Option<String>,
+    /// Semicolon separated
+    #[serde(rename = "System.Tags")]
+    tags: Option<String>,
+    #[serde(rename = "System.TeamProject")]
+    team_project: Option<String>,
+    #[serde(rename = "System.IterationPath")]
+    iteration_path: Option<String>,
+    #[serde(rename = "System.AreaPath")]
+    area_path: Option<String>,
+    /// Everything else, for fields picked at runtime
+    #[serde(flatten)]
+    other: HashMap<String, Value>,
+}
+
+pub const CHANGED_BY: &str = "System.ChangedBy";
+
+pub const COMPLETED_WORK: &str = "Microsoft.VSTS.Scheduling.CompletedWork";
+
+pub const REMAINING_WORK: &str = "Microsoft.VSTS.Scheduling.RemainingWork";
+
+impl Fields {
+    /// The hours held by the given field, if it's set
+    fn hours(&self, field: &str) -> Option<f64> {
+        match field {
+            COMPLETED_WORK => return self.completed_work,
+            REMAINING_WORK => return self.remaining_work,
+            _ => {}
+        }
+        deserialize_hours(self.other.get(field)?).ok().flatten()
+    }
+
+    /// The identity held by the given field, if it holds one
+    fn identity(&self, field: &str) -> Option<User> {
+        if field == CHANGED_BY {
+            return Some(self.changed_by.clone());
+        }
+        serde_json::from_value(self.other.get(field)?.clone()).ok()
+    }
+}
+
+/// Write an identity the way Azure DevOps does, so cached revisions read back
+fn serialize_identity<S: Serializer>(user: &User, serializer: S) -> Result<S::Ok, S::Error> {
+    serde_json::json!({
+        "id": user.id,
+        "displayName": user.display_name,
+        "uniqueName": user.email,
+    })
+    .serialize(serializer)
+}
+
+/// Tell what's going on on stderr, as `-v` does
+pub static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Accept hours as a number, a numeric string or null
+///
+/// Some tenants hand back CompletedWork as `"8"` rather than `8`.
+fn deserialize_hours<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Hours {
+        Number(f64),
+        Text(String),
+    }
+
+    match Option::<Hours>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Hours::Number(hours)) => Ok(Some(hours)),
+        Some(Hours::Text(text)) if text.trim().is_empty() => Ok(None),
+        Some(Hours::Text(text)) => {
+            let hours = text.trim().parse().map_err(|_| {
+                de::Error::invalid_value(de::Unexpected::Str(&text), &"a number of hours")
+            })?;
+            if VERBOSE.load(Ordering::Relaxed) {
+                eprintln!("Coerced CompletedWork {:?} to {}", text, hours);
+            }
+            Ok(Some(hours))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Revision {
+    // id: u32,
+    rev: u32,
+    fields: Fields,
+}
+
+#[derive(Debug, Deserialize)]
+struct Revisions {
+    #[allow(dead_code)]
+    count: u32,
+    value: Vec<Revision>,
+}
+
+/// Azure DevOps Services, with the organizations below it
+pub const CLOUD: &str = "https://dev.azure.com/";
+
+/// The api-version every request asks for
+pub const API_VERSION: &str = "7.1";
+
+/// Where and as whom to talk to Azure DevOps
+#[derive(Debug, Clone)]
+pub struct Connection {
+    /// [`CLOUD`], or the URL of an Azure DevOps Server, ending in a slash
+    pub server: Url,
+    /// The organization, or the collection on a server
+    pub organization: String,
+    /// `None` queries every project in the organization
+    pub project: Option<String>,
+    pub user: String,
+    pub token: String,
+    /// The token is an Azure AD access token rather than a PAT
+    pub bearer: bool,
+    /// strftime format for the date literals in the WIQL query
+    pub wiql_date_format: String,
+    /// The api-version to ask for instead of [`API_VERSION`], for servers that
+    /// don't have it
+    pub api_version: Option<String>,
+}
+
+impl Connection {
+    /// URL of an API below the organization, with every segment
+    /// percent-encoded
+    fn url(&self, path: &[&str]) -> Url {
+        let mut url = self.server.clone();
+        url.path_segments_mut()
+            .unwrap()
+            .pop_if_empty()
+            .push(&self.organization)
+            .extend(path);
+        url
+    }
+
+    /// URL of an API below the project, or the organization when querying all
+    /// of it, with every segment percent-encoded
+    fn project_url(&self, path: &[&str]) -> Url {
+        let mut url = self.url(&[]);
+        url.path_segments_mut()
+            .unwrap()
+            .extend(&self.project)
+            .extend(path);
+        url
+    }
+
+    /// Where the cloud keeps a service on a host of its own, a server has it
+    /// below its collections like the rest
+    fn service_url(&self, host: &str, path: &[&str]) -> Url {
+        let mut url = self.url(path);
+        if self.server.as_str() == CLOUD {
+            url.set_host(Some(host)).unwrap();
+        }
+        url
+    }
+
+    /// [`API_VERSION`], unless the connection asks for another
+    fn api_version(&self) -> &str {
+        self.api_version.as_deref().unwrap_or(API_VERSION)
+    }
+
+    /// URL of an Analytics OData entity set
+    fn analytics_url(&self, entity: &str) -> Url {
+        let mut url = self.service_url("analytics.dev.azure.com", &[]);
+        url.path_segments_mut()
+            .unwrap()
+            .extend(&self.project)
+            .extend(["_odata", "v3.0-preview", entity]);
+        url
+    }
+
+    fn work_item_url(&self, project: &str, id: u64) -> Url {
+        self.url(&[project, "_workitems", "edit", &id.to_string()])
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    name: String,
+}
+
+/// Look up the canonical name of the project, which may be given by GUID or in another case
+async fn resolve_project(
+    client: &reqwest::Client,
+    connection: &Connection,
+    project: &str,
+) -> Result<String, reqwest::Error> {
+    let url = connection.url(&["_apis", "projects", project]);
+    let project: Project = client
+        .get(url)
+        .query(&[("api-version", connection.api_version())])
+        .authorize(connection)
+        .send_retrying()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(project.name)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConnectionData {
+    /// The organization's id, which PATs name the organizations they're for by
+    instance_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionToken {
+    valid_to: DateTime<Utc>,
+    is_valid: bool,
+    /// Empty for tokens good for every organization
+    #[serde(default)]
+    target_accounts: Vec<Uuid>,
+}
+
+/// Make a request as the connection, telling when its PAT runs out if Azure
+/// DevOps says
+///
+/// A token that has expired or been revoked is sometimes answered with the
+/// sign-in page rather than a 401, which the JSON commands would otherwise
+/// fail to read.
+async fn validate_token(
+    client: &reqwest::Client,
+    connection: &Connection,
+) -> Result<Option<DateTime<Utc>>, Error> {
+    let url = connection.url(&["_apis", "connectionData"]);
+    let response = client
+        .get(url)
+        .authorize(connection)
+        .send_retrying()
+        .await?
+        .error_for_status()?;
+    let json = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if response.status() == reqwest::StatusCode::NON_AUTHORITATIVE_INFORMATION || !json {
+        return Err(Error::SignIn(response.url().clone()));
+    }
+    let data: ConnectionData = response.json().await?;
+    if connection.bearer {
+        return Ok(None);
+    }
+
+    // PATs can't be told apart in the list, so the one that lasts the longest
+    // of those that could be this one
+    let url = connection.service_url("vssps.dev.azure.com", &["_apis", "token", "sessiontokens"]);
+    let tokens = client
+        .get(url)
+        .query(&[("api-version", "5.0-preview.1"), ("isPublic", "true")])
+        .authorize(connection)
+        .send_retrying()
+        .await
+        .and_then(|response| response.error_for_status());
+    let tokens: Vec<SessionToken> = match tokens {
+        Ok(response) => response.json().await.unwrap_or_default(),
+        // Only some scopes may list tokens
+        Err(error) => {
+            if VERBOSE.load(Ordering::Relaxed) {
+                eprintln!("Can't tell when the token expires: {}", error);
+            }
+            return Ok(None);
+        }
+    };
+    Ok(tokens
+        .iter()
+        .filter(|token| {
+            token.is_valid
+                && (token.target_accounts.is_empty()
+                    || token.target_accounts.contains(&data.instance_id))
+        })
+        .map(|token| token.valid_to)
+        .max())
+}
+
+#[derive(Debug, Deserialize)]
+struct TeamMember {
+    identity: TeamIdentity,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TeamIdentity {
+    unique_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TeamMembers {
+    value: Vec<TeamMember>,
+}
+
+/// Emails of the members of a team of the connection's project
+async fn fetch_team_members(
+    client: &reqwest::Client,
+    connection: &Connection,
+    project: &str,
+    team: &str,
+) -> Result<Vec<String>, reqwest::Error> {
+    let url = connection.url(&["_apis", "projects", project, "teams", team, "members"]);
+    let members: TeamMembers = client
+        .get(url)
+        .query(&[("api-version", connection.api_version())])
+        .authorize(connection)
+        .send_retrying()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(members
+        .value
+        .into_iter()
+        .map(|member| member.identity.unique_name)
+        .collect())
+}
+
+/// Quote a WIQL string literal
+fn wiql_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// A WIQL condition matching any of the values, if there are any
+pub fn wiql_any(field: &str, operator: &str, values: &[String]) -> Option<String> {
+    let conditions: Vec<String> = values
+        .iter()
+        .map(|value| format!("[{}] {} {}", field, operator, wiql_string(value)))
+        .collect();
+    match conditions.len() {
+        0 => None,
+        _ => Some(format!("({})", conditions.join(" OR "))),
+    }
+}
+
+/// A WIQL condition for the field being one of the values, if there are any
+pub fn wiql_in(field: &str, values: &[String]) -> Option<String> {
+    let values: Vec<String> = values.iter().map(|value| wiql_string(value)).collect();
+    match values.len() {
+        0 => None,
+        _ => Some(format!("[{}] IN ({})", field, values.join(", "))),
+    }
+}
+
+/// Format the first and last instant of the range as WIQL date literals
+fn wiql_date_literals(from: NaiveDate, to: NaiveDate, format: &str) -> (String, String) {
+    let start = from.and_hms_opt(0, 0, 0).unwrap();
+    let end = to.and_hms_opt(23, 59, 59).unwrap();
+    (
+        start.format(format).to_string(),
+        end.format(format).to_string(),
+    )
+}
+
+/// Order of revisions saved at the exact same time
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tiebreak {
+    /// By revision number
+    Rev,
+    /// As returned by Azure DevOps
+    Order,
+}
+
+/// What to report on
+#[derive(Debug, Clone)]
+pub struct ReportQuery {
+    pub user: String,
+    /// Whose time is counted, everyone's when `None`
+    pub users: Option<Vec<String>>,
+    /// Lowercased emails and whose they are, so their time rolls up into one user
+    pub aliases: HashMap<String, String>,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub since_state: Option<String>,
+    /// Identity field time is attributed to, `System.ChangedBy` by default
+    pub attribute_field: String,
+    /// Numeric field whose increases are the time logged, [`COMPLETED_WORK`]
+    /// unless the process tracks time in one of its own
+    pub field: String,
+    /// Further numeric fields whose changes are summed up alongside the
+    /// time, such as RemainingWork for a burn-up
+    pub track: Vec<String>,
+    /// Ask WIQL for the range exactly, without the day either side
+    pub strict_range_server: bool,
+    /// WIQL condition replacing the date range, with `{from}` and `{to}` placeholders
+    pub wiql: Option<String>,
+    /// Further WIQL conditions the work items must meet
+    pub conditions: Vec<String>,
+    /// Saved query to run instead of building WIQL
+    pub query_id: Option<Uuid>,
+    /// Look up the ancestor each item's hours roll up to
+    pub rollup: Option<Rollup>,
+    /// Known completed work per item at the start of the range
+    pub baselines: HashMap<u64, f64>,
+    /// How revisions sharing a timestamp are ordered
+    pub tiebreak: Tiebreak,
+    /// Only report on this many randomly picked work items, with the seed to pick them by
+    pub sample: Option<(usize, u64)>,
+    /// Where weeks start, for the weekly sums
+    pub week_start: WeekStart,
+    /// Work items whose revisions are fetched at the same time
+    pub concurrency: usize,
+    pub history: HistorySource,
+    pub backend: Backend,
+    /// File of revisions fetched before, only fetching items changed since
+    pub cache: Option<PathBuf>,
+    /// Take the work items and their revisions from the cache only
+    pub offline: bool,
+}
+
+impl ReportQuery {
+    /// The email time logged by this identity is reported under
+    fn owner(&self, mut identity: User) -> User {
+        if let Some(owner) = self.aliases.get(&identity.email.to_lowercase()) {
+            identity.email = owner.clone();
+        }
+        identity
+    }
+
+    fn counts(&self, email: &str) -> bool {
+        self.users
+            .as_ref()
+            .is_none_or(|users| users.iter().any(|user| user == email))
+    }
+}
+
+/// Hours logged by one revision, the change in CompletedWork it made
+#[derive(Debug, Serialize)]
+pub struct TimeEntry {
+    pub rev: u32,
+    pub date: NaiveDate,
+    /// Who the time is attributed to, see [`ReportQuery::attribute_field`]
+    pub changed_by: User,
+    pub completed_work: f64,
+    pub hours: f64,
+    /// System.Reason as of the revision
+    pub reason: Option<String>,
+}
+
+/// A work item with time logged on it in the range
+#[derive(Debug, Serialize)]
+pub struct ReportItem {
+    pub id: u64,
+    pub title: String,
+    pub organization: String,
+    /// The project the item belongs to
+    pub project: String,
+    /// The item in the Azure DevOps web UI
+    pub url: String,
+    /// Tags as of the item's latest revision
+    pub tags: Vec<String>,
+    /// Iteration path as of the item's latest revision
+    pub iteration: Option<String>,
+    /// Area path as of the item's latest revision
+    pub area: Option<String>,
+    /// The client the item's time is for, with `--client`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    /// The ancestor the item's hours roll up to with `--rollup`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rolls_up_to: Option<Ancestor>,
+    pub entries: Vec<TimeEntry>,
+    /// Hours of all the entries
+    pub total: f64,
+    pub contributors: Vec<Contributor>,
+    /// Why the item entered the `--since-state-change` state
+    pub state_reason: Option<String>,
+    /// Several users changed completed work in the range, so the carry-over
+    /// attribution of the hours may be off
+    pub ambiguous: bool,
+    /// Completed / (completed + remaining) work as of the end of the range,
+    /// left out when both are zero
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent_complete: Option<f64>,
+    /// How much each `--track` field changed per day
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tracked: BTreeMap<String, BTreeMap<NaiveDate, f64>>,
+    /// Estimates as of the item's latest revision, left out when it has none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<Estimate>,
+}
+
+/// Work estimated against work done, in hours
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Estimate {
+    pub original_estimate: f64,
+    pub remaining_work: f64,
+    /// All the time logged, not only in the range
+    pub completed_work: f64,
+    /// Completed plus remaining work over the original estimate, negative
+    /// when it's taking less
+    pub variance: f64,
+}
+
+impl Estimate {
+    fn add(&mut self, other: &Estimate) {
+        self.original_estimate += other.original_estimate;
+        self.remaining_work += other.remaining_work;
+        self.completed_work += other.completed_work;
+        self.variance += other.variance;
+    }
+}
+
+impl ReportItem {
+    /// An item with nothing logged on it yet, the rest filled in later
+    fn new(
+        id: u64,
+        title: Option<String>,
+        organization: &str,
+        state_reason: Option<String>,
+    ) -> ReportItem {
+        ReportItem {
+            id,
+            title: title.unwrap_or_default(),
+            organization: organization.to_string(),
+            project: String::new(),
+            url: String::new(),
+            tags: Vec::new(),
+            iteration: None,
+            area: None,
+            client: None,
+            rolls_up_to: None,
+            entries: Vec::new(),
+            total: 0.0,
+            contributors: Vec::new(),
+            state_reason,
+            ambiguous: false,
+            percent_complete: None,
+            tracked: BTreeMap::new(),
+            estimate: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Contributor {
+    pub display_name: String,
+    pub email: String,
+    pub hours: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Sampled {
+    pub items: usize,
+    pub of: usize,
+}
+
+/// Hours per user, most first
+fn rank_contributors<'a>(entries: impl IntoIterator<Item = &'a TimeEntry>) -> Vec<Contributor> {
+    let mut contributors: Vec<Contributor> = Vec::new();
+    for entry in entries {
+        match contributors
+            .iter_mut()
+            .find(|contributor| contributor.email == entry.changed_by.email)
+        {
+            Some(contributor) => contributor.hours += entry.hours,
+            None => contributors.push(Contributor {
+                display_name: entry.changed_by.display_name.clone(),
+                email: entry.changed_by.email.clone(),
+                hours: entry.hours,
+            }),
+        }
+    }
+    // Stable sort, ties keep the order users first appeared in
+    contributors.sort_by(|a, b| b.hours.total_cmp(&a.hours));
+    contributors
+}
+
+/// The hours logged in the range, per item and summed up
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// The user reported on
+    pub user: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub items: Vec<ReportItem>,
+    pub sums: BTreeMap<NaiveDate, f64>,
+    pub contributors: Vec<Contributor>,
+    /// Set when only a sample of the work items was looked at, making totals partial
+    pub sampled: Option<Sampled>,
+    /// Totals per ISO week, e.g. `2023-W12`
+    pub weekly: BTreeMap<String, f64>,
+    /// Totals per month, e.g. `2023-03`
+    pub monthly: BTreeMap<String, f64>,
+    pub by_organization: BTreeMap<String, f64>,
+    pub by_project: BTreeMap<String, f64>,
+    /// Totals per user and ISO week
+    pub by_user: BTreeMap<String, BTreeMap<String, f64>>,
+    pub total: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buckets: Option<Vec<Bucket>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by_tag: Option<ByTag>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by_iteration: Option<BTreeMap<String, f64>>,
+    /// Totals per `--client`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by_client: Option<BTreeMap<String, f64>>,
+    /// Totals along the `--group-by` axes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grouped: Option<Grouped>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rolled_up: Option<RolledUp>,
+    /// Logged against expected hours, with --target-hours-per-day
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<TargetComparison>,
+    /// Billable amounts, with --rate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub billing: Option<Billing>,
+    /// How much each `--track` field changed per day
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tracked: BTreeMap<String, BTreeMap<NaiveDate, f64>>,
+    /// The estimates of the items that have one, summed up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimates: Option<Estimate>,
+    /// Every work item looked at, by organization and id, time or not; what
+    /// another run with the same filters would look at again
+    #[serde(skip)]
+    pub queried: BTreeSet<(String, u64)>,
+    /// Bumped when fields change meaning or go away; new fields may appear any time
+    pub version: u32,
+}
+
+const REPORT_VERSION: u32 = 1;
+
+impl Report {
+    /// Work out the totals of the items
+    fn new(query: &ReportQuery, items: Vec<ReportItem>, sampled: Option<Sampled>) -> Report {
+        let mut sums: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+        let mut by_organization: BTreeMap<String, f64> = BTreeMap::new();
+        let mut by_project: BTreeMap<String, f64> = BTreeMap::new();
+        let mut by_user: BTreeMap<String, BTreeMap<String, f64>> = BTreeMap::new();
+        let mut tracked: BTreeMap<String, BTreeMap<NaiveDate, f64>> = BTreeMap::new();
+        let mut estimates: Option<Estimate> = None;
+        for item in &items {
+            if let Some(estimate) = &item.estimate {
+                estimates
+                    .get_or_insert_with(Estimate::default)
+                    .add(estimate);
+            }
+            for (field, changes) in &item.tracked {
+                let sums = tracked.entry(field.clone()).or_default();
+                for (date, change) in changes {
+                    *sums.entry(*date).or_default() += change;
+                }
+            }
+            for entry in &item.entries {
+                *by_user
+                    .entry(entry.changed_by.email.clone())
+                    .or_default()
+                    .entry(query.week_start.week_label(entry.date))
+                    .or_default() += entry.hours;
+                *sums.entry(entry.date).or_default() += entry.hours;
+                *by_organization
+                    .entry(item.organization.clone())
+                    .or_default() += entry.hours;
+                *by_project.entry(item.project.clone()).or_default() += entry.hours;
+            }
+        }
+        let mut weekly: BTreeMap<String, f64> = BTreeMap::new();
+        let mut monthly: BTreeMap<String, f64> = BTreeMap::new();
+        for (date, hours) in &sums {
+            *weekly
+                .entry(query.week_start.week_label(*date))
+                .or_default() += hours;
+            *monthly.entry(date.format("%Y-%m").to_string()).or_default() += hours;
+        }
+        Report {
+            user: query.user.clone(),
+            from: query.from,
+            to: query.to,
+            contributors: rank_contributors(items.iter().flat_map(|item| &item.entries)),
+            rolled_up: query.rollup.map(|to| rollup_totals(&items, to)),
+            total: sums.values().sum(),
+            items,
+            sums,
+            sampled,
+            weekly,
+            monthly,
+            by_organization,
+            by_project,
+            by_user,
+            buckets: None,
+            by_tag: None,
+            by_iteration: None,
+            by_client: None,
+            grouped: None,
+            target: None,
+            billing: None,
+            tracked,
+            estimates,
+            queried: BTreeSet::new(),
+            version: REPORT_VERSION,
+        }
+    }
+
+    /// One report out of the reports on several projects
+    pub fn combine(query: &ReportQuery, reports: Vec<Report>) -> Report {
+        let mut items = Vec::new();
+        let mut sampled: Option<Sampled> = None;
+        let mut queried = BTreeSet::new();
+        for report in reports {
+            items.extend(report.items);
+            queried.extend(report.queried);
+            if let Some(part) = report.sampled {
+                let sampled = sampled.get_or_insert(Sampled { items: 0, of: 0 });
+                sampled.items += part.items;
+                sampled.of += part.of;
+            }
+        }
+        let mut report = Report::new(query, items, sampled);
+        report.queried = queried;
+        report
+    }
+}
+
+/// How an item's hours are spread over its tags
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagSplit {
+    /// Every tag gets all of the item's hours, so tags add up to more than the total
+    Duplicate,
+    /// The hours are divided evenly between the tags
+    Even,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    Date,
+    /// Work item, as `id title`
+    Item,
+    User,
+    Tag,
+    /// The iteration (sprint) items are in at the end of the range
+    Iteration,
+    Project,
+}
+
+/// Hours under one key of a `--group-by` axis, broken down by the next axis
+#[derive(Debug, Serialize)]
+pub struct Group {
+    pub key: String,
+    pub hours: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Group>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Grouped {
+    pub by: Vec<GroupBy>,
+    pub groups: Vec<Group>,
+}
+
+#[derive(Default)]
+struct GroupNode {
+    hours: f64,
+    children: BTreeMap<String, GroupNode>,
+}
+
+impl GroupNode {
+    /// Add hours along the keys of each remaining axis
+    fn add(&mut self, axes: &[Vec<(String, f64)>], hours: f64) {
+        self.hours += hours;
+        if let Some((keys, rest)) = axes.split_first() {
+            for (key, share) in keys {
+                let child = self.children.entry(key.clone()).or_default();
+                child.add(rest, hours * share);
+            }
+        }
+    }
+
+    fn into_groups(self) -> Vec<Group> {
+        self.children
+            .into_iter()
+            .map(|(key, node)| Group {
+                key,
+                hours: node.hours,
+                groups: node.into_groups(),
+            })
+            .collect()
+    }
+}
+
+/// The keys an entry falls under on an axis, each with its share of the hours
+fn group_keys(
+    axis: GroupBy,
+    item: &ReportItem,
+    entry: &TimeEntry,
+    split: TagSplit,
+) -> Vec<(String, f64)> {
+    match axis {
+        GroupBy::Date => vec![(entry.date.to_string(), 1.0)],
+        GroupBy::Item => vec![(format!("{} {}", item.id, item.title), 1.0)],
+        GroupBy::User => vec![(entry.changed_by.email.clone(), 1.0)],
+        GroupBy::Tag if item.tags.is_empty() => vec![(UNTAGGED.to_string(), 1.0)],
+        GroupBy::Tag => {
+            let share = match split {
+                TagSplit::Duplicate => 1.0,
+                TagSplit::Even => 1.0 / item.tags.len() as f64,
+            };
+            item.tags.iter().map(|tag| (tag.clone(), share)).collect()
+        }
+        GroupBy::Iteration => vec![(item.iteration.clone().unwrap_or_default(), 1.0)],
+        GroupBy::Project => vec![(item.project.clone(), 1.0)],
+    }
+}
+
+/// Nested totals along the axes, in order
+pub fn group_totals(items: &[ReportItem], axes: &[GroupBy], split: TagSplit) -> Grouped {
+    let mut root = GroupNode::default();
+    for item in items {
+        for entry in &item.entries {
+            let keys: Vec<_> = axes
+                .iter()
+                .map(|axis| group_keys(*axis, item, entry, split))
+                .collect();
+            root.add(&keys, entry.hours);
+        }
+    }
+    Grouped {
+        by: axes.to_vec(),
+        groups: root.into_groups(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ByTag {
+    pub split: TagSplit,
+    pub totals: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Bucket {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub hours: f64,
+}
+
+/// Split `from..=to` into `n` spans of (nearly) equal length.
+///
+/// When the days don't divide evenly the first buckets get one extra day each.
+fn bucket_spans(from: NaiveDate, to: NaiveDate, n: u32) -> Vec<(NaiveDate, NaiveDate)> {
+    let days = (to - from).num_days() + 1;
+    let n = i64::from(n).min(days.max(1));
+    let base = days / n;
+    let remainder = days % n;
+
+    let mut spans = Vec::new();
+    let mut start = from;
+    for i in 0..n {
+        let len = base + i64::from(i < remainder);
+        let end = start + chrono::Duration::days(len - 1);
+        spans.push((start, end));
+        start = end + chrono::Duration::days(1);
+    }
+    spans
+}
+
+pub fn bucket_totals(
+    sums: &BTreeMap<NaiveDate, f64>,
+    from: NaiveDate,
+    to: NaiveDate,
+    n: u32,
+) -> Vec<Bucket> {
+    let spans = bucket_spans(from, to, n);
+    let totals = sums
+        .iter()
+        .fold(vec![0.0; spans.len()], |mut totals, (date, hours)| {
+            if let Some(index) = spans
+                .iter()
+                .position(|(start, end)| start <= date && date <= end)
+            {
+                totals[index] += hours;
+            }
+            totals
+        });
+
+    spans
+        .into_iter()
+        .zip(totals)
+        .map(|((from, to), hours)| Bucket { from, to, hours })
+        .collect()
+}
+
+const UNTAGGED: &str = "(untagged)";
+
+fn split_tags(tags: Option<&str>) -> Vec<String> {
+    tags.unwrap_or("")
+        .split(';')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+pub fn tag_totals(items: &[ReportItem], split: TagSplit) -> ByTag {
+    let mut totals: BTreeMap<String, f64> = BTreeMap::new();
+    for item in items {
+        let hours = item.total;
+        if item.tags.is_empty() {
+            *totals.entry(UNTAGGED.to_string()).or_default() += hours;
+            continue;
+        }
+        let share = match split {
+            TagSplit::Duplicate => hours,
+            TagSplit::Even => hours / item.tags.len() as f64,
+        };
+        for tag in &item.tags {
+            *totals.entry(tag.clone()).or_default() += share;
+        }
+    }
+    ByTag { split, totals }
+}
+
+pub fn iteration_totals(items: &[ReportItem]) -> BTreeMap<String, f64> {
+    let mut totals: BTreeMap<String, f64> = BTreeMap::new();
+    for item in items {
+        let hours = item.total;
+        let iteration = item.iteration.clone().unwrap_or_default();
+        *totals.entry(iteration).or_default() += hours;
+    }
+    totals
+}
+
+/// Hours per client of the items, those without one under "(no client)"
+pub fn client_totals(items: &[ReportItem]) -> BTreeMap<String, f64> {
+    let mut totals: BTreeMap<String, f64> = BTreeMap::new();
+    for item in items {
+        let client = item.client.as_deref().unwrap_or(NO_CLIENT);
+        *totals.entry(client.to_string()).or_default() += item.total;
+    }
+    totals
+}
+
+pub const NO_CLIENT: &str = "(no client)";
+
+/// A condition on the iteration path, leaving macros like @CurrentIteration unquoted
+pub fn iteration_condition(iterations: &[String]) -> Option<String> {
+    let conditions: Vec<String> = iterations
+        .iter()
+        .map(|iteration| {
+            if iteration.starts_with('@') {
+                format!("[System.IterationPath] = {}", iteration)
+            } else {
+                format!("[System.IterationPath] UNDER {}", wiql_string(iteration))
+            }
+        })
+        .collect();
+    match conditions.len() {
+        0 => None,
+        _ => Some(format!("({})", conditions.join(" OR "))),
+    }
+}
+
+/// Ask WIQL for the work items changed in the range
+async fn query_work_items(
+    client: &reqwest::Client,
+    connection: &Connection,
+    query: &ReportQuery,
+) -> Result<Vec<WorkItem>, reqwest::Error> {
+    let ReportQuery { from, to, .. } = query;
+    if let Some(id) = query.query_id {
+        return run_saved_query(client, connection, id).await;
+    }
+
+    // Azure DevOps compares dates in the account's time zone, which needn't be
+    // the one reported in, so ask for a day more either side and filter here
+    let (first, last) = if query.strict_range_server {
+        (*from, *to)
+    } else {
+        (from.pred_opt().unwrap(), to.succ_opt().unwrap())
+    };
+    // A custom selection only narrows down with the range if it uses it
+    let splittable = query
+        .wiql
+        .as_ref()
+        .is_none_or(|wiql| wiql.contains("{from}") || wiql.contains("{to}"));
+
+    // WIQL refuses queries matching more than 20,000 items, so ranges that do
+    // are halved until they don't; later halves go first to keep the newest
+    // changes on top
+    let mut ranges = vec![(first, last)];
+    let mut seen = HashSet::new();
+    let mut work_items = Vec::new();
+    while let Some((first, last)) = ranges.pop() {
+        let split = splittable && first < last;
+        match run_wiql(client, connection, query, first, last, split).await? {
+            Some(found) => work_items.extend(found.into_iter().filter(|item| seen.insert(item.id))),
+            None => {
+                let middle = first + chrono::Duration::days((last - first).num_days() / 2);
+                if VERBOSE.load(Ordering::Relaxed) {
+                    eprintln!(
+                        "Too many results from {} to {}, splitting at {}",
+                        first, last, middle
+                    );
+                }
+                ranges.push((first, middle));
+                ranges.push((middle.succ_opt().unwrap(), last));
+            }
+        }
+    }
+    Ok(work_items)
+}
+
+/// Run the WIQL query for changes from `first` to `last`
+///
+/// With `split`, a query over the result size limit gives `None` rather than
+/// an error, for the caller to try smaller ranges.
+async fn run_wiql(
+    client: &reqwest::Client,
+    connection: &Connection,
+    query: &ReportQuery,
+    first: NaiveDate,
+    last: NaiveDate,
+    split: bool,
+) -> Result<Option<Vec<WorkItem>>, reqwest::Error> {
+    let (statement, time_precision) = wiql_statement(connection, query, first, last);
+    let mut map = HashMap::new();
+    map.insert("query".to_string(), statement);
+    let response = client
+        .post(connection.project_url(&["_apis", "wit", "wiql"]))
+        .query(&[("api-version", connection.api_version())])
+        .query(&[("timePrecision", time_precision)])
+        .authorize(connection)
+        .json(&map)
+        .send_retrying_idempotent()
+        .await?;
+    if let Err(error) = response.error_for_status_ref() {
+        // VS402337: the number of work items returned exceeds the size limit
+        if split
+            && response.status() == reqwest::StatusCode::BAD_REQUEST
+            && response.text().await?.contains("VS402337")
+        {
+            return Ok(None);
+        }
+        return Err(error);
+    }
+    let query_result: WorkItemQueryResult = response.json().await?;
+
+    Ok(Some(query_result.work_items))
+}
+
+/// The WIQL for the changes from `first` to `last`, and whether the date
+/// literals have a time of day
+fn wiql_statement(
+    connection: &Connection,
+    query: &ReportQuery,
+    first: NaiveDate,
+    last: NaiveDate,
+) -> (String, bool) {
+    let (from_literal, to_literal) = wiql_date_literals(first, last, &connection.wiql_date_format);
+    // Azure DevOps rejects literals with a time of day unless asked to keep it
+    let time_precision = from_literal.contains(':');
+    let selection = match &query.wiql {
+        Some(wiql) => format!(
+            "({})",
+            wiql.replace("{from}", &format!("'{from_literal}'"))
+                .replace("{to}", &format!("'{to_literal}'"))
+        ),
+        None => format!(
+            "[System.ChangedDate] >= '{from_literal}' AND [System.ChangedDate] <= '{to_literal}'"
+        ),
+    };
+    let conditions: String = query
+        .conditions
+        .iter()
+        .map(|condition| format!(" AND {}", condition))
+        .collect();
+
+    (
+        format!("SELECT [System.Id] FROM workitems WHERE {selection}{conditions} ORDER BY [System.ChangedDate] DESC"),
+        time_precision,
+    )
+}
+
+/// Work items selected by a query saved in Azure DevOps
+async fn run_saved_query(
+    client: &reqwest::Client,
+    connection: &Connection,
+    id: Uuid,
+) -> Result<Vec<WorkItem>, reqwest::Error> {
+    let result: WorkItemQueryResult = client
+        .get(connection.project_url(&["_apis", "wit", "wiql", &id.to_string()]))
+        .query(&[("api-version", connection.api_version())])
+        .authorize(connection)
+        .send_retrying()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    // Links list an item once per link, so keep the first of each
+    let links = result
+        .work_item_relations
+        .into_iter()
+        .flat_map(|link| [link.source, link.target])
+        .flatten();
+    let mut work_items: Vec<WorkItem> = Vec::new();
+    for work_item in result.work_items.into_iter().chain(links) {
+        if !work_items.iter().any(|seen| seen.id == work_item.id) {
+            work_items.push(work_item);
+        }
+    }
+    Ok(work_items)
+}
+
+/// Talks to Azure DevOps for the report, retrying and pacing requests as the
+/// [`ClientOptions`] it was built with say
+#[derive(Debug, Clone)]
+pub struct AzdoClient {
+    http: reqwest::Client,
+}
+
+impl AzdoClient {
+    pub fn new(options: &ClientOptions) -> Result<AzdoClient, Error> {
+        Ok(AzdoClient {
+            http: client::build(options)?,
+        })
+    }
+
+    /// The canonical name of a project, which may be given by GUID or in another case
+    pub async fn resolve_project(
+        &self,
+        connection: &Connection,
+        project: &str,
+    ) -> Result<String, Error> {
+        Ok(resolve_project(&self.http, connection, project).await?)
+    }
+
+    /// Make a request as the connection, failing when the token isn't let in
+    /// or the organization or project can't be found
+    pub async fn check(&self, connection: &Connection) -> Result<(), Error> {
+        if let Some(project) = &connection.project {
+            resolve_project(&self.http, connection, project).await?;
+            return Ok(());
+        }
+        let url = connection.url(&["_apis", "projects"]);
+        self.http
+            .get(url)
+            .query(&[("api-version", connection.api_version()), ("$top", "1")])
+            .authorize(connection)
+            .send_retrying()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Make sure Azure DevOps takes the connection's token, see
+    /// [`validate_token`]: when a PAT expires, if that can be found out
+    pub async fn validate_token(
+        &self,
+        connection: &Connection,
+    ) -> Result<Option<DateTime<Utc>>, Error> {
+        validate_token(&self.http, connection).await
+    }
+
+    /// Emails of the members of a team in the project
+    pub async fn team_members(
+        &self,
+        connection: &Connection,
+        project: &str,
+        team: &str,
+    ) -> Result<Vec<String>, Error> {
+        Ok(fetch_team_members(&self.http, connection, project, team).await?)
+    }
+
+    /// The work items that may have time logged on them in the range
+    pub async fn find_work_items(
+        &self,
+        connection: &Connection,
+        query: &ReportQuery,
+    ) -> Result<Vec<WorkItem>, Error> {
+        find_work_items(&self.http, connection, query).await
+    }
+
+    /// Fetch the history of the work items and sum up the completed work
+    pub async fn collect_report(
+        &self,
+        connection: &Connection,
+        query: &ReportQuery,
+        work_items: Vec<WorkItem>,
+    ) -> Result<Report, Error> {
+        Ok(collect_report(&self.http, connection, query, work_items).await?)
+    }
+
+    /// Add `hours` to the `field` of a work item, its CompletedWork unless the
+    /// process tracks time in another, see [`Logged`]
+    ///
+    /// The revision is dated now, or `at`, which needs the permission to
+    /// bypass rules and can't be before the item's last change.
+    pub async fn log_time(
+        &self,
+        connection: &Connection,
+        field: &str,
+        id: u64,
+        hours: f64,
+        at: Option<DateTime<Utc>>,
+        decrement_remaining: bool,
+    ) -> Result<Logged, Error> {
+        write::log_time(
+            &self.http,
+            connection,
+            field,
+            id,
+            hours,
+            at,
+            decrement_remaining,
+        )
+        .await
+    }
+
+    /// Log what a day of the report is off by, so item `id` has `hours` on
+    /// `date` where it has `logged`; a past day needs the permission to
+    /// bypass rules
+    pub async fn correct_day(
+        &self,
+        connection: &Connection,
+        field: &str,
+        id: u64,
+        date: NaiveDate,
+        logged: f64,
+        hours: f64,
+    ) -> Result<Logged, Error> {
+        write::correct_day(&self.http, connection, field, id, date, logged, hours).await
+    }
+
+    /// The report over every connection, with the totals added up
+    pub async fn report(
+        &self,
+        connections: &[Connection],
+        query: &ReportQuery,
+    ) -> Result<Report, Error> {
+        let mut reports = Vec::new();
+        for connection in connections {
+            let work_items = self.find_work_items(connection, query).await?;
+            reports.push(self.collect_report(connection, query, work_items).await?);
+        }
+        Ok(Report::combine(query, reports))
+    }
+
+    /// What the report will take, from the work item query alone and one
+    /// sample request, see [`Plan`]
+    pub async fn plan(
+        &self,
+        connections: &[Connection],
+        query: &ReportQuery,
+    ) -> Result<Plan, Error> {
+        let mut plan = Plan::default();
+        let mut samples = Vec::new();
+        for connection in connections {
+            let started = Instant::now();
+            match query.backend {
+                Backend::Rest => {
+                    // Only ids, without the batch requests checking CompletedWork
+                    let work_items = query_work_items(&self.http, connection, query).await?;
+                    let n = work_items.len();
+                    plan.work_items += n;
+                    plan.batch_requests += n.div_ceil(BATCH_SIZE);
+                    plan.history_requests += n;
+                    if let Some(item) = work_items.first() {
+                        let started = Instant::now();
+                        self.http
+                            .get(connection.project_url(&[
+                                "_apis",
+                                "wit",
+                                "workItems",
+                                &item.id.to_string(),
+                                "revisions",
+                            ]))
+                            .query(&[("api-version", connection.api_version())])
+                            .query(&[("$top", "1")])
+                            .authorize(connection)
+                            .send_retrying()
+                            .await?
+                            .error_for_status()?;
+                        samples.push(started.elapsed());
+                    }
+                }
+                Backend::Odata => {
+                    let n = odata::work_items(&self.http, connection, query)
+                        .await?
+                        .len();
+                    plan.work_items += n;
+                    plan.history_requests += n.div_ceil(odata::IDS_PER_REQUEST);
+                    samples.push(started.elapsed());
+                }
+            }
+        }
+        plan.request_time = samples.iter().sum::<Duration>() / samples.len().max(1) as u32;
+        plan.concurrency = match query.backend {
+            Backend::Rest => query.concurrency,
+            // One after another
+            Backend::Odata => 1,
+        };
+        Ok(plan)
+    }
+
+    /// Time in state, lead and cycle time of the items changed in the range
+    /// over every connection, see [`flow`]
+    pub async fn cycle_times(
+        &self,
+        connections: &[Connection],
+        query: &ReportQuery,
+        done_states: &[String],
+    ) -> Result<flow::FlowReport, Error> {
+        let mut items = Vec::new();
+        for connection in connections {
+            items.extend(flow::collect(&self.http, connection, query, done_states).await?);
+        }
+        Ok(flow::FlowReport::new(query, items))
+    }
+}
+
+/// What a report will take, for `--plan` to ask before starting on it
+#[derive(Debug, Default)]
+pub struct Plan {
+    /// Work items matching the query, some of which may turn out to hold no
+    /// time and cost no history request
+    pub work_items: usize,
+    /// Requests checking items for CompletedWork, [`BATCH_SIZE`] at a time
+    pub batch_requests: usize,
+    /// History requests, at most one per item unless a history is longer than
+    /// a page, or its updates can't be read and the revisions are fetched
+    pub history_requests: usize,
+    /// History requests made at the same time
+    pub concurrency: usize,
+    /// How long one request took
+    pub request_time: Duration,
+}
+
+impl Plan {
+    /// The rough time the requests take, the batches one after another and
+    /// the histories `concurrency` at a time
+    pub fn estimate(&self) -> Duration {
+        let rounds = self.batch_requests + self.history_requests.div_ceil(self.concurrency.max(1));
+        self.request_time * rounds as u32
+    }
+}
+
+/// Where the work items and their history come from
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// A WIQL query and the REST API per work item
+    #[default]
+    Rest,
+    /// The Analytics OData service, in a handful of requests
+    Odata,
+}
+
+/// The work items that may have time logged on them in the range
+async fn find_work_items(
+    client: &reqwest::Client,
+    connection: &Connection,
+    query: &ReportQuery,
+) -> Result<Vec<WorkItem>, Error> {
+    if let Some(path) = query.cache.as_deref().filter(|_| query.offline) {
+        let cache = Cache::load(path)
+            .map_err(|error| format!("Can't read the cache {}: {}", path.display(), error))?;
+        return Ok(cache.work_items(connection, connection.project.as_deref()));
+    }
+    match query.backend {
+        Backend::Rest => {
+            let work_items = query_work_items(client, connection, query).await?;
+            let fields: Vec<&str> = std::iter::once(&query.field)
+                .chain(&query.track)
+                .map(String::as_str)
+                .collect();
+            Ok(with_completed_work(client, connection, &fields, query.from, work_items).await?)
+        }
+        Backend::Odata => Ok(odata::work_items(client, connection, query).await?),
+    }
+}
+
+/// Revisions per request, the most Azure DevOps hands out in one page
+const REVISION_PAGE: usize = 200;
+
+/// Every revision of a work item, oldest first
+///
+/// Long histories span several pages, and a missing early page would throw
+/// the baseline of the diffs off, so this pages until a short page comes back.
+/// The endpoint takes no `fields`, so every revision comes with all of its
+/// fields; [`fetch_updates`] is the smaller answer.
+async fn fetch_revisions(
+    client: &reqwest::Client,
+    connection: &Connection,
+    id: u64,
+) -> Result<Vec<Revision>, reqwest::Error> {
+    let url = connection.project_url(&["_apis", "wit", "workItems", &id.to_string(), "revisions"]);
+    let mut all = Vec::new();
+    loop {
+        let page: Revisions = client
+            .get(url.clone())
+            .query(&[("api-version", connection.api_version())])
+            .query(&[("$top", REVISION_PAGE), ("$skip", all.len())])
+            .authorize(connection)
+            .send_retrying()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let done = page.value.len() < REVISION_PAGE;
+        all.extend(page.value);
+        if done {
+            return Ok(all);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Updates {
+    value: Vec<Update>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Update {
+    rev: u32,
+    /// The fields this update changed, left out when it only changed links
+    #[serde(default)]
+    fields: HashMap<String, FieldChange>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FieldChange {
+    /// Missing when the update cleared the field
+    new_value: Option<Value>,
+}
+
+/// Where the history of a work item is read from
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HistorySource {
+    /// Only the fields each revision changed, falling back to revisions
+    #[default]
+    Updates,
+    /// Every field of every revision
+    Revisions,
+}
+
+/// Revisions of a work item rebuilt from its updates, oldest first
+///
+/// Each update only holds the fields it changed, so a revision is the fields
+/// so far with the update applied. `None` when the server has no updates
+/// endpoint or the updates don't add up to revisions. Like the revisions
+/// endpoint, it takes no `fields` to narrow the updates down with.
+async fn fetch_updates(
+    client: &reqwest::Client,
+    connection: &Connection,
+    id: u64,
+) -> Result<Option<Vec<Revision>>, reqwest::Error> {
+    let url = connection.project_url(&["_apis", "wit", "workItems", &id.to_string(), "updates"]);
+    let mut updates = Vec::new();
+    loop {
+        let response = client
+            .get(url.clone())
+            .query(&[("api-version", connection.api_version())])
+            .query(&[("$top", REVISION_PAGE), ("$skip", updates.len())])
+            .authorize(connection)
+            .send_retrying()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let page: Updates = response.error_for_status()?.json().await?;
+
+        let done = page.value.len() < REVISION_PAGE;
+        updates.extend(page.value);
+        if done {
+            break;
+        }
+    }
+
+    let mut fields = serde_json::Map::new();
+    let mut revisions = Vec::new();
+    for update in updates {
+        // Link changes can't touch completed work
+        if update.fields.is_empty() {
+            continue;
+        }
+        for (name, change) in update.fields {
+            match change.new_value {
+                Some(value) => fields.insert(name, value),
+                None => fields.remove(&name),
+            };
+        }
+        match serde_json::from_value(Value::Object(fields.clone())) {
+            Ok(fields) => revisions.push(Revision {
+                rev: update.rev,
+                fields,
+            }),
+            Err(_) => return Ok(None),
+        }
+    }
+    Ok(Some(revisions))
+}
+
+/// The history of a work item from the given source, oldest first
+async fn fetch_history(
+    client: &reqwest::Client,
+    connection: &Connection,
+    id: u64,
+    source: HistorySource,
+) -> Result<Vec<Revision>, reqwest::Error> {
+    if source == HistorySource::Updates {
+        if let Some(revisions) = fetch_updates(client, connection, id).await? {
+            return Ok(revisions);
+        }
+        if VERBOSE.load(Ordering::Relaxed) {
+            eprintln!("Falling back to the revisions of {}", id);
+        }
+    }
+    fetch_revisions(client, connection, id).await
+}
+
+/// The revisions of the work items, in their order, from the cache where
+/// they haven't changed since, which is then updated
+async fn fetch_histories(
+    client: &reqwest::Client,
+    connection: &Connection,
+    query: &ReportQuery,
+    work_items: &[WorkItem],
+) -> Result<Vec<Vec<Revision>>, reqwest::Error> {
+    let mut cache = query.cache.as_deref().map(|path| {
+        Cache::load(path).unwrap_or_else(|error| {
+            eprintln!("Warning: ignoring the cache {}: {}", path.display(), error);
+            Cache::default()
+        })
+    });
+    let cached: Vec<Option<Vec<Revision>>> = work_items
+        .iter()
+        .map(|item| {
+            let cache = cache.as_mut()?;
+            cache.take(connection, item)
+        })
+        .collect();
+    if cache.is_some() && VERBOSE.load(Ordering::Relaxed) {
+        eprintln!(
+            "{} of {} work items unchanged since cached",
+            cached.iter().flatten().count(),
+            work_items.len()
+        );
+    }
+
+    // Analytics brings every history anyway
+    let fetched = query.backend == Backend::Odata || cached.iter().any(Option::is_none);
+
+    // Fetched several at a time, but kept in query order so the output is stable
+    let ids: Vec<u64> = work_items.iter().map(|item| item.id).collect();
+    let histories: Vec<Vec<Revision>> = match query.backend {
+        Backend::Rest => {
+            stream::iter(ids.into_iter().zip(cached))
+                .map(|(id, cached)| async move {
+                    match cached {
+                        Some(revisions) => Ok(revisions),
+                        None => fetch_history(client, connection, id, query.history).await,
+                    }
+                })
+                .buffered(query.concurrency)
+                .try_collect()
+                .await?
+        }
+        Backend::Odata => odata::histories(client, connection, &ids).await?,
+    };
+
+    // Nothing new is nothing to write
+    let path = query.cache.as_ref().filter(|_| fetched && !query.offline);
+    if let (Some(cache), Some(path)) = (&mut cache, path) {
+        for (item, revisions) in work_items.iter().zip(&histories) {
+            if let Some(changed_date) = item.changed_date {
+                cache.insert(connection, item.id, changed_date, revisions.clone());
+            }
+        }
+        if let Err(error) = cache.save(path) {
+            eprintln!(
+                "Warning: can't write the cache {}: {}",
+                path.display(),
+                error
+            );
+        }
+    }
+    Ok(histories)
+}
+
+/// Fetch the revisions of the work items and sum up the completed work
+async fn collect_report(
+    client: &reqwest::Client,
+    connection: &Connection,
+    query: &ReportQuery,
+    work_items: Vec<WorkItem>,
+) -> Result<Report, reqwest::Error> {
+    let total_items = work_items.len();
+    let work_items = match query.sample {
+        Some((n, seed)) => sample(work_items, n, seed),
+        None => work_items,
+    };
+    let sampled = query.sample.map(|_| Sampled {
+        items: work_items.len(),
+        of: total_items,
+    });
+
+    let queried = work_items
+        .iter()
+        .map(|item| (connection.organization.clone(), item.id))
+        .collect();
+    let histories = fetch_histories(client, connection, query, &work_items).await?;
+    let mut items = collect_items(connection, query, work_items, histories);
+
+    if let Some(to) = query.rollup.filter(|_| !items.is_empty()) {
+        let ids: Vec<u64> = items.iter().map(|item| item.id).collect();
+        let mut ancestors = fetch_ancestors(client, connection, &ids, to).await?;
+        for item in &mut items {
+            item.rolls_up_to = ancestors.remove(&item.id);
+        }
+    }
+
+    let mut report = Report::new(query, items, sampled);
+    report.queried = queried;
+    Ok(report)
+}
+
+/// Sum up the completed work of each item from its revisions, as
+/// [`collect_report`] does once they're fetched
+fn collect_items(
+    connection: &Connection,
+    query: &ReportQuery,
+    work_items: Vec<WorkItem>,
+    histories: Vec<Vec<Revision>>,
+) -> Vec<ReportItem> {
+    let ReportQuery {
+        from,
+        to,
+        since_state,
+        ..
+    } = query;
+
+    let mut found_identity = false;
+    let mut items = Vec::new();
+    for (work_item, revisions) in work_items.into_iter().zip(histories) {
+        let mut item: Option<ReportItem> = None;
+        // Everyone who changed completed work in the range
+        let mut editors: Vec<String> = Vec::new();
+        let mut last_completed_work: f64 = 0.0;
+        let mut last_tracked = vec![0.0; query.track.len()];
+        let mut tracked: BTreeMap<String, BTreeMap<NaiveDate, f64>> = BTreeMap::new();
+        let mut title = None;
+        let mut estimate = None;
+        let mut baseline = query.baselines.get(&work_item.id).copied();
+        // Items that never reach the state are left out entirely
+        let mut reached_state = since_state.is_none();
+        let mut state_reason = None;
+        // Completed and remaining work as of the last revision in the range
+        let mut latest_work = None;
+        let mut tags = None;
+        let mut iteration = None;
+        let mut area = None;
+        let mut project = connection.project.clone();
+        let mut revisions = revisions;
+        // Stable sorts, so equal keys keep the order the API returned them in
+        match query.tiebreak {
+            Tiebreak::Rev => {
+                revisions.sort_by_key(|revision| (revision.fields.changed_date, revision.rev))
+            }
+            Tiebreak::Order => revisions.sort_by_key(|revision| revision.fields.changed_date),
+        }
+        for revision in revisions.into_iter() {
+            title = revision.fields.title.clone();
+            estimate = Some((
+                revision.fields.original_estimate,
+                revision.fields.remaining_work,
+                revision.fields.hours(&query.field),
+            ));
+            tags = revision.fields.tags.clone();
+            iteration = revision.fields.iteration_path.clone();
+            area = revision.fields.area_path.clone();
+            if revision.fields.team_project.is_some() {
+                project = revision.fields.team_project.clone();
+            }
+
+            if !reached_state && revision.fields.state == *since_state {
+                reached_state = true;
+                state_reason = revision.fields.reason.clone();
+            }
+
+            let date = revision
+                .fields
+                .changed_date
+                .with_timezone(&Local)
+                .date_naive();
+            if date >= *from && date <= *to {
+                latest_work = Some((
+                    revision.fields.hours(&query.field).unwrap_or(0.0),
+                    revision.fields.remaining_work.unwrap_or(0.0),
+                ));
+            }
+
+            // By the same rules as the time
+            for (field, last) in query.track.iter().zip(&mut last_tracked) {
+                let Some(value) = revision.fields.hours(field) else {
+                    continue;
+                };
+                let change = value - *last;
+                *last = value;
+                if change == 0.0 || !reached_state || date < *from || date > *to {
+                    continue;
+                }
+                let Some(attributed) = revision.fields.identity(&query.attribute_field) else {
+                    continue;
+                };
+                if query.counts(&query.owner(attributed).email) {
+                    *tracked
+                        .entry(field.clone())
+                        .or_default()
+                        .entry(date)
+                        .or_default() += change;
+                }
+            }
+
+            if let Some(completed_work) = revision.fields.hours(&query.field) {
+                if date >= *from {
+                    if let Some(baseline) = baseline.take() {
+                        last_completed_work = baseline;
+                    }
+                }
+
+                let diff = completed_work - last_completed_work;
+                last_completed_work = completed_work;
+
+                if diff == 0.0 {
+                    continue;
+                };
+
+                if !reached_state {
+                    continue;
+                }
+
+                let Some(attributed) = revision.fields.identity(&query.attribute_field) else {
+                    continue;
+                };
+                let attributed = query.owner(attributed);
+                found_identity = true;
+
+                if date < *from || date > *to {
+                    continue;
+                }
+
+                if !editors.contains(&attributed.email) {
+                    editors.push(attributed.email.clone());
+                }
+                if !query.counts(&attributed.email) {
+                    continue;
+                }
+
+                item.get_or_insert_with(|| {
+                    ReportItem::new(
+                        work_item.id,
+                        revision.fields.title,
+                        &connection.organization,
+                        state_reason.clone(),
+                    )
+                })
+                .entries
+                .push(TimeEntry {
+                    rev: revision.rev,
+                    date,
+                    changed_by: attributed,
+                    completed_work,
+                    hours: diff,
+                    reason: revision.fields.reason,
+                });
+            }
+        }
+        // Items whose tracked fields changed are reported without time too
+        if item.is_none() && !tracked.is_empty() {
+            item = Some(ReportItem::new(
+                work_item.id,
+                title,
+                &connection.organization,
+                state_reason,
+            ));
+        }
+        if let Some(mut item) = item {
+            item.tracked = tracked;
+            item.estimate = match estimate {
+                Some((None, None, _)) | None => None,
+                Some((original, remaining, completed)) => {
+                    let (original, remaining) = (original.unwrap_or(0.0), remaining.unwrap_or(0.0));
+                    let completed = completed.unwrap_or(0.0);
+                    Some(Estimate {
+                        original_estimate: original,
+                        remaining_work: remaining,
+                        completed_work: completed,
+                        variance: completed + remaining - original,
+                    })
+                }
+            };
+            item.total = item.entries.iter().map(|entry| entry.hours).sum();
+            item.contributors = rank_contributors(&item.entries);
+            item.ambiguous = editors.len() > 1;
+            item.tags = split_tags(tags.as_deref());
+            item.iteration = iteration;
+            item.area = area;
+            // Items may have moved, so the project is the one they're in now
+            item.project = project.unwrap_or_default();
+            item.url = connection.work_item_url(&item.project, item.id).to_string();
+            item.percent_complete = latest_work
+                .filter(|(completed, remaining)| completed + remaining > 0.0)
+                .map(|(completed, remaining)| completed / (completed + remaining) * 100.0);
+            items.push(item);
+        }
+    }
+
+    if !found_identity && query.attribute_field != CHANGED_BY {
+        eprintln!(
+            "Warning: {} did not hold an identity on any revision with completed work",
+            query.attribute_field
+        );
+    }
+    items
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchWorkItem {
+    id: u64,
+    /// Left out when none of the fields asked for are set
+    #[serde(default)]
+    fields: BatchFields,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct BatchFields {
+    #[serde(rename = "System.ChangedDate")]
+    changed_date: Option<DateTime<Utc>>,
+    /// The field time is tracked in, under its own name
+    #[serde(flatten)]
+    other: HashMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchResult {
+    value: Vec<BatchWorkItem>,
+}
+
+/// Work items per `workitemsbatch` request, the most it takes
+const BATCH_SIZE: usize = 200;
+
+/// The work items that have any of the `fields` set, or were changed since
+/// `from`, 200 per batch request
+///
+/// Only their revisions can hold logged time or tracked changes, so the
+/// others needn't be fetched. An item whose fields are empty now may still
+/// have had them set in the range and cleared since, which changed it after
+/// the range started; one last changed before then had nothing to log in it.
+/// The items kept come with when they were last changed, for the cache.
+async fn with_completed_work(
+    client: &reqwest::Client,
+    connection: &Connection,
+    fields: &[&str],
+    from: NaiveDate,
+    work_items: Vec<WorkItem>,
+) -> Result<Vec<WorkItem>, reqwest::Error> {
+    let ids: Vec<u64> = work_items.iter().map(|item| item.id).collect();
+    let mut logged = HashMap::new();
+    for chunk in ids.chunks(BATCH_SIZE) {
+        let batch: BatchResult = client
+            .post(connection.project_url(&["_apis", "wit", "workitemsbatch"]))
+            .query(&[("api-version", connection.api_version())])
+            .authorize(connection)
+            .json(&serde_json::json!({
+                "ids": chunk,
+                "fields": ([fields, &["System.ChangedDate"]].concat()),
+            }))
+            .send_retrying_idempotent()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        logged.extend(
+            batch
+                .value
+                .into_iter()
+                .filter(|item| {
+                    let changed_since = item
+                        .fields
+                        .changed_date
+                        .is_none_or(|changed| changed.with_timezone(&Local).date_naive() >= from);
+                    changed_since
+                        || fields.iter().any(|field| {
+                            item.fields
+                                .other
+                                .get(*field)
+                                .is_some_and(|value| !value.is_null())
+                        })
+                })
+                .map(|item| (item.id, item.fields.changed_date)),
+        );
+    }
+    Ok(work_items
+        .into_iter()
+        .filter_map(|item| {
+            let changed_date = logged.get(&item.id)?;
+            Some(WorkItem {
+                changed_date: *changed_date,
+                ..item
+            })
+        })
+        .collect())
+}
+
+/// Which ancestor an item's hours roll up to
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rollup {
+    /// The direct parent, whatever its type
+    Parent,
+    /// The nearest Feature above the item
+    Feature,
+    /// The nearest Epic above the item
+    Epic,
+}
+
+impl Rollup {
+    fn work_item_type(self) -> Option<&'static str> {
+        match self {
+            Rollup::Parent => None,
+            Rollup::Feature => Some("Feature"),
+            Rollup::Epic => Some("Epic"),
+        }
+    }
+}
+
+/// The work item an item's hours roll up to
+#[derive(Debug, Clone, Serialize)]
+pub struct Ancestor {
+    pub id: u64,
+    pub title: String,
+    pub work_item_type: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RolledUp {
+    pub to: Rollup,
+    /// Hours per ancestor, most first, with items without one last
+    pub totals: Vec<RollupTotal>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RollupTotal {
+    pub ancestor: Option<Ancestor>,
+    pub hours: f64,
+}
+
+fn rollup_totals(items: &[ReportItem], to: Rollup) -> RolledUp {
+    let mut totals: Vec<RollupTotal> = Vec::new();
+    for item in items {
+        let hours = item.total;
+        let id = item.rolls_up_to.as_ref().map(|ancestor| ancestor.id);
+        match totals
+            .iter_mut()
+            .find(|total| total.ancestor.as_ref().map(|ancestor| ancestor.id) == id)
+        {
+            Some(total) => total.hours += hours,
+            None => totals.push(RollupTotal {
+                ancestor: item.rolls_up_to.clone(),
+                hours,
+            }),
+        }
+    }
+    totals.sort_by(|a, b| {
+        (a.ancestor.is_none().cmp(&b.ancestor.is_none())).then(b.hours.total_cmp(&a.hours))
+    });
+    RolledUp { to, totals }
+}
+
+#[derive(Debug, Deserialize)]
+struct LinkedWorkItem {
+    id: u64,
+    fields: LinkedFields,
+    #[serde(default)]
+    relations: Vec<Relation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinkedFields {
+    #[serde(rename = "System.Title")]
+    title: Option<String>,
+    #[serde(rename = "System.WorkItemType")]
+    work_item_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Relation {
+    rel: String,
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinkedBatch {
+    value: Vec<LinkedWorkItem>,
+}
+
+/// Deepest a work item hierarchy is followed looking for an ancestor
+const MAX_ROLLUP_DEPTH: usize = 10;
+
+/// The ancestor each of the work items rolls up to, when it has one
+///
+/// Parents are looked up a level at a time with batch requests, so a deep
+/// hierarchy costs a request per level rather than per item.
+async fn fetch_ancestors(
+    client: &reqwest::Client,
+    connection: &Connection,
+    ids: &[u64],
+    to: Rollup,
+) -> Result<HashMap<u64, Ancestor>, reqwest::Error> {
+    // Type, title and parent of every work item seen so far
+    let mut known: HashMap<u64, (Ancestor, Option<u64>)> = HashMap::new();
+    let mut wanted: Vec<u64> = ids.to_vec();
+    // The items themselves, then their parents and so on
+    let levels = match to {
+        Rollup::Parent => 2,
+        _ => MAX_ROLLUP_DEPTH + 1,
+    };
+    for _ in 0..levels {
+        wanted.retain(|id| !known.contains_key(id));
+        wanted.sort_unstable();
+        wanted.dedup();
+        if wanted.is_empty() {
+            break;
+        }
+        let mut parents = Vec::new();
+        for chunk in wanted.chunks(BATCH_SIZE) {
+            let batch: LinkedBatch = client
+                .post(connection.project_url(&["_apis", "wit", "workitemsbatch"]))
+                .query(&[("api-version", connection.api_version())])
+                .authorize(connection)
+                .json(&serde_json::json!({ "ids": chunk, "$expand": "relations" }))
+                .send_retrying_idempotent()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            for item in batch.value {
+                let parent = item
+                    .relations
+                    .iter()
+                    .find(|relation| relation.rel == "System.LinkTypes.Hierarchy-Reverse")
+                    .and_then(|relation| relation.url.rsplit('/').next()?.parse().ok());
+                parents.extend(parent);
+                let ancestor = Ancestor {
+                    id: item.id,
+                    title: item.fields.title.unwrap_or_default(),
+                    work_item_type: item.fields.work_item_type.unwrap_or_default(),
+                };
+                known.insert(item.id, (ancestor, parent));
+            }
+        }
+        wanted = parents;
+    }
+
+    let mut ancestors = HashMap::new();
+    for id in ids {
+        let mut current = known.get(id).and_then(|(_, parent)| *parent);
+        for _ in 0..MAX_ROLLUP_DEPTH {
+            let Some((ancestor, parent)) = current.and_then(|id| known.get(&id)) else {
+                break;
+            };
+            let found = to
+                .work_item_type()
+                .is_none_or(|wanted| ancestor.work_item_type.eq_ignore_ascii_case(wanted));
+            if found {
+                ancestors.insert(*id, ancestor.clone());
+                break;
+            }
+            current = *parent;
+        }
+    }
+    Ok(ancestors)
+}
+
+/// SplitMix64, plenty for picking a reproducible sample
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Pick `n` work items at random, keeping them in query order
+fn sample(mut work_items: Vec<WorkItem>, n: usize, seed: u64) -> Vec<WorkItem> {
+    let mut rng = Rng(seed);
+    let n = n.min(work_items.len());
+    // Partial Fisher-Yates over the order the items came in
+    let mut order: Vec<usize> = (0..work_items.len()).collect();
+    for i in 0..n {
+        let j = i + (rng.next() % (order.len() - i) as u64) as usize;
+        order.swap(i, j);
+    }
+    let mut picked = order[..n].to_vec();
+    picked.sort_unstable();
+
+    let mut index = 0;
+    work_items.retain(|_| {
+        let keep = picked.binary_search(&index).is_ok();
+        index += 1;
+        keep
+    });
+    work_items
+}
+
+/// Building blocks for the tests of the modules
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use serde_json::json;
+
+    pub fn connection() -> Connection {
+        Connection {
+            server: Url::parse(CLOUD).unwrap(),
+            organization: "org".to_string(),
+            project: Some("Proj".to_string()),
+            user: "a@b.c".to_string(),
+            token: "token".to_string(),
+            bearer: false,
+            wiql_date_format: "%Y-%m-%dT%H:%M:%SZ".to_string(),
+            api_version: None,
+        }
+    }
+
+    pub fn query() -> ReportQuery {
+        ReportQuery {
+            user: "a@b.c".to_string(),
+            users: None,
+            aliases: HashMap::new(),
+            from: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            to: NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+            since_state: None,
+            attribute_field: CHANGED_BY.to_string(),
+            field: COMPLETED_WORK.to_string(),
+            track: Vec::new(),
+            strict_range_server: false,
+            wiql: None,
+            conditions: Vec::new(),
+            query_id: None,
+            rollup: None,
+            baselines: HashMap::new(),
+            tiebreak: Tiebreak::Rev,
+            sample: None,
+            week_start: WeekStart::Mon,
+            concurrency: 1,
+            history: HistorySource::Revisions,
+            backend: Backend::Rest,
+            cache: None,
+            offline: false,
+        }
+    }
+
+    /// A revision saved at noon UTC, which is the same day in most zones
+    pub fn revision(rev: u32, date: &str, email: &str, fields: Value) -> Revision {
+        let mut all = json!({
+            "System.ChangedDate": format!("{}T12:00:00Z", date),
+            "System.ChangedBy": {
+                "id": Uuid::nil(),
+                "displayName": email,
+                "uniqueName": email,
+            },
+            "System.Title": "Item",
+        });
+        all.as_object_mut()
+            .unwrap()
+            .extend(fields.as_object().unwrap().clone());
+        serde_json::from_value(json!({ "rev": rev, "fields": all })).unwrap()
+    }
+
+    pub fn work_item(id: u64) -> WorkItem {
+        WorkItem {
+            id,
+            changed_date: None,
+        }
+    }
+
+    /// The report on the items with these histories, as [`query`] asks for it
+    pub fn report(histories: Vec<Vec<Revision>>) -> Report {
+        let query = query();
+        let ids = 1..=histories.len() as u64;
+        let items = collect_items(
+            &connection(),
+            &query,
+            ids.clone().map(work_item).collect(),
+            histories,
+        );
+        let mut report = Report::new(&query, items, None);
+        report.queried = ids.map(|id| ("org".to_string(), id)).collect();
+        report
+    }
+
+    /// An HTTP server on localhost answering one connection after another
+    /// with `responses`, handing back the requests it got
+    pub async fn mock_server(
+        responses: Vec<String>,
+    ) -> (std::net::SocketAddr, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                // The head, then as much body as it says there is
+                loop {
+                    let n = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length = text[..end]
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length")
+                                    .then(|| value.trim().parse::<usize>().ok())?
+                            })
+                            .unwrap_or(0);
+                        if request.len() >= end + 4 + length {
+                            break;
+                        }
+                    }
+                    if n == 0 {
+                        break;
+                    }
+                }
+                requests.push(String::from_utf8_lossy(&request).into_owned());
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+            requests
+        });
+        (address, server)
+    }
+
+    /// A response for [`mock_server`], closing the connection after it
+    pub fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect();
+        format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            headers,
+            body.len(),
+            body
+        )
+    }
+
+    /// What [`output::render`] writes
+    pub fn rendered(
+        format: output::Format,
+        report: &Report,
+        options: &output::RenderOptions,
+    ) -> String {
+        let mut out = Vec::new();
+        output::render(&mut out, format, report, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use serde_json::json;
+
+    fn hours(item: &ReportItem) -> Vec<(u32, f64)> {
+        item.entries
+            .iter()
+            .map(|entry| (entry.rev, entry.hours))
+            .collect()
+    }
+
+    #[test]
+    fn revisions_saved_at_once_are_ordered_by_the_tiebreak() {
+        // Same timestamp, returned out of rev order
+        let history = || {
+            vec![
+                revision(2, "2024-03-04", "a@b.c", json!({ COMPLETED_WORK: 3.0 })),
+                revision(1, "2024-03-04", "a@b.c", json!({ COMPLETED_WORK: 1.0 })),
+            ]
+        };
+        let items = |tiebreak| {
+            let query = ReportQuery {
+                tiebreak,
+                ..query()
+            };
+            collect_items(&connection(), &query, vec![work_item(1)], vec![history()])
+        };
+
+        let by_rev = items(Tiebreak::Rev);
+        assert_eq!(hours(&by_rev[0]), [(1, 1.0), (2, 2.0)]);
+        let in_order = items(Tiebreak::Order);
+        assert_eq!(hours(&in_order[0]), [(2, 3.0), (1, -2.0)]);
+
+        // And the same every time, down to the merged day
+        let options = output::RenderOptions {
+            merge_same_day: true,
+            ..Default::default()
+        };
+        let text = rendered(output::Format::Text, &report(vec![history()]), &options);
+        assert!(text.contains(" 3 3 #1@rev1,2\n"), "{}", text);
+        assert_eq!(
+            text,
+            rendered(output::Format::Text, &report(vec![history()]), &options)
+        );
+    }
+
+    #[test]
+    fn completed_work_as_strings_and_null() {
+        let revisions: Revisions = serde_json::from_str(include_str!(
+            "../tests/fixtures/completed-work-strings.json"
+        ))
+        .unwrap();
+        let completed: Vec<_> = revisions
+            .value
+            .iter()
+            .map(|revision| revision.fields.completed_work)
+            .collect();
+        assert_eq!(completed, [Some(8.0), None, Some(10.5), Some(12.0)]);
+
+        // A null in between leaves the last value as the baseline
+        let items = collect_items(
+            &connection(),
+            &query(),
+            vec![work_item(42)],
+            vec![revisions.value],
+        );
+        assert_eq!(hours(&items[0]), [(1, 8.0), (3, 2.5), (4, 1.5)]);
+    }
+
+    #[test]
+    fn percent_complete_as_of_the_end_of_the_range() {
+        let revisions: Revisions =
+            serde_json::from_str(include_str!("../tests/fixtures/remaining-work.json")).unwrap();
+        let items = collect_items(
+            &connection(),
+            &query(),
+            vec![work_item(9)],
+            vec![revisions.value],
+        );
+        // 3 done and 1 to go on 2024-03-06, the April revision is after the range
+        assert_eq!(items[0].percent_complete, Some(75.0));
+        let json = serde_json::to_value(&items[0]).unwrap();
+        assert_eq!(json["percent_complete"], json!(75.0));
+
+        // Nothing done nor left, after a correction
+        let items = collect_items(
+            &connection(),
+            &query(),
+            vec![work_item(1)],
+            vec![vec![
+                revision(
+                    1,
+                    "2024-03-04",
+                    "a@b.c",
+                    json!({ COMPLETED_WORK: 2.0, REMAINING_WORK: 0.0 }),
+                ),
+                revision(2, "2024-03-05", "a@b.c", json!({ COMPLETED_WORK: 0.0 })),
+            ]],
+        );
+        assert_eq!(items[0].percent_complete, None);
+        let json = serde_json::to_value(&items[0]).unwrap();
+        assert!(json.get("percent_complete").is_none(), "{}", json);
+    }
+
+    #[test]
+    fn tag_hours_duplicated_or_split_evenly() {
+        let tagged = |tags: Option<&str>, hours: f64| {
+            let mut fields = json!({ COMPLETED_WORK: hours });
+            if let Some(tags) = tags {
+                fields["System.Tags"] = json!(tags);
+            }
+            vec![revision(1, "2024-03-04", "a@b.c", fields)]
+        };
+        let report = report(vec![
+            tagged(Some("Alpha; Beta"), 4.0),
+            tagged(Some("Beta"), 2.0),
+            tagged(None, 1.0),
+        ]);
+        let totals = |split| {
+            let by_tag = tag_totals(&report.items, split);
+            let grouped = group_totals(&report.items, &[GroupBy::Tag], split);
+            let groups: Vec<(String, f64)> = grouped
+                .groups
+                .iter()
+                .map(|group| (group.key.clone(), group.hours))
+                .collect();
+            // Grouping by tag spreads the hours the same way
+            assert_eq!(groups, Vec::from_iter(by_tag.totals.clone()));
+            by_tag
+        };
+
+        let duplicate = totals(TagSplit::Duplicate);
+        assert_eq!(
+            duplicate.totals,
+            BTreeMap::from([
+                ("(untagged)".to_string(), 1.0),
+                ("Alpha".to_string(), 4.0),
+                ("Beta".to_string(), 6.0),
+            ])
+        );
+        let even = totals(TagSplit::Even);
+        assert_eq!(
+            even.totals,
+            BTreeMap::from([
+                ("(untagged)".to_string(), 1.0),
+                ("Alpha".to_string(), 2.0),
+                ("Beta".to_string(), 4.0),
+            ])
+        );
+        // Only split evenly do the tags add up to the total
+        assert_eq!(even.totals.values().sum::<f64>(), report.total);
+        assert_eq!(serde_json::to_value(&even).unwrap()["split"], json!("even"));
+    }
+
+    #[test]
+    fn completed_work_that_isnt_a_number_fails() {
+        let error = serde_json::from_value::<Fields>(json!({
+            "System.ChangedDate": "2024-03-04T12:00:00Z",
+            "System.ChangedBy": { "id": Uuid::nil(), "displayName": "A", "uniqueName": "a@b.c" },
+            COMPLETED_WORK: "eight",
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("a number of hours"), "{}", error);
+    }
+
+    #[test]
+    fn wiql_date_literals_cover_the_whole_days() {
+        let (first, last) = (query().from, query().to);
+        let (statement, time_precision) = wiql_statement(&connection(), &query(), first, last);
+        assert_eq!(
+            statement,
+            "SELECT [System.Id] FROM workitems WHERE [System.ChangedDate] >= '2024-03-01T00:00:00Z' \
+             AND [System.ChangedDate] <= '2024-03-31T23:59:59Z' ORDER BY [System.ChangedDate] DESC"
+        );
+        assert!(time_precision);
+
+        // Servers that only take dates get them without timePrecision
+        let connection = Connection {
+            wiql_date_format: "%Y-%m-%d".to_string(),
+            ..connection()
+        };
+        let (statement, time_precision) = wiql_statement(&connection, &query(), first, last);
+        assert!(statement.contains(">= '2024-03-01' AND [System.ChangedDate] <= '2024-03-31'"));
+        assert!(!time_precision);
+
+        let query = ReportQuery {
+            wiql: Some("[System.CreatedDate] >= {from}".to_string()),
+            ..query()
+        };
+        let (statement, _) = wiql_statement(&connection, &query, first, last);
+        assert!(statement.contains("WHERE ([System.CreatedDate] >= '2024-03-01') ORDER BY"));
+    }
+
+    /// The WIQL asked for with these conditions, between the dates and the order
+    fn wiql_conditions(conditions: impl IntoIterator<Item = Option<String>>) -> String {
+        let query = ReportQuery {
+            conditions: conditions.into_iter().flatten().collect(),
+            ..query()
+        };
+        let (statement, _) = wiql_statement(&connection(), &query, query.from, query.to);
+        let conditions = statement
+            .strip_prefix(
+                "SELECT [System.Id] FROM workitems WHERE [System.ChangedDate] >= '2024-03-01T00:00:00Z' \
+                 AND [System.ChangedDate] <= '2024-03-31T23:59:59Z'",
+            )
+            .and_then(|rest| rest.strip_suffix(" ORDER BY [System.ChangedDate] DESC"))
+            .unwrap_or_else(|| panic!("{}", statement));
+        conditions.to_string()
+    }
+
+    #[test]
+    fn area_paths_narrow_the_query() {
+        let paths = ["Proj\\Web".to_string(), "Proj\\Bob's".to_string()];
+        assert_eq!(
+            wiql_conditions([wiql_any("System.AreaPath", "UNDER", &paths)]),
+            " AND ([System.AreaPath] UNDER 'Proj\\Web' OR [System.AreaPath] UNDER 'Proj\\Bob''s')"
+        );
+        assert_eq!(wiql_any("System.AreaPath", "UNDER", &[]), None);
+        assert_eq!(wiql_conditions([None]), "");
+    }
+
+    #[test]
+    fn iterations_narrow_the_query_and_sum_up() {
+        let iterations = [
+            "@CurrentIteration".to_string(),
+            "Proj\\Sprint 1".to_string(),
+        ];
+        assert_eq!(
+            wiql_conditions([iteration_condition(&iterations)]),
+            " AND ([System.IterationPath] = @CurrentIteration \
+             OR [System.IterationPath] UNDER 'Proj\\Sprint 1')"
+        );
+        assert_eq!(iteration_condition(&[]), None);
+
+        // Items count towards the iteration they're in last
+        let report = report(vec![
+            vec![
+                revision(
+                    1,
+                    "2024-03-04",
+                    "a@b.c",
+                    json!({ COMPLETED_WORK: 2.0, "System.IterationPath": "Proj\\Sprint 1" }),
+                ),
+                revision(
+                    2,
+                    "2024-03-18",
+                    "a@b.c",
+                    json!({ COMPLETED_WORK: 5.0, "System.IterationPath": "Proj\\Sprint 2" }),
+                ),
+            ],
+            vec![revision(
+                1,
+                "2024-03-05",
+                "a@b.c",
+                json!({ COMPLETED_WORK: 1.5, "System.IterationPath": "Proj\\Sprint 1" }),
+            )],
+            vec![revision(
+                1,
+                "2024-03-06",
+                "a@b.c",
+                json!({ COMPLETED_WORK: 1.0 }),
+            )],
+        ]);
+        assert_eq!(
+            iteration_totals(&report.items),
+            BTreeMap::from([
+                (String::new(), 1.0),
+                ("Proj\\Sprint 1".to_string(), 1.5),
+                ("Proj\\Sprint 2".to_string(), 5.0),
+            ])
+        );
+    }
+
+    #[test]
+    fn tags_narrow_the_query_and_show_with_the_items() {
+        let tags = ["billing".to_string(), "O'Neil".to_string()];
+        assert_eq!(
+            wiql_conditions([wiql_any("System.Tags", "CONTAINS", &tags)]),
+            " AND ([System.Tags] CONTAINS 'billing' OR [System.Tags] CONTAINS 'O''Neil')"
+        );
+
+        let report = report(vec![
+            vec![revision(
+                1,
+                "2024-03-04",
+                "a@b.c",
+                json!({ COMPLETED_WORK: 2.0, "System.Tags": "billing; internal" }),
+            )],
+            vec![revision(
+                1,
+                "2024-03-05",
+                "a@b.c",
+                json!({ COMPLETED_WORK: 1.0 }),
+            )],
+        ]);
+        assert_eq!(report.items[0].tags, ["billing", "internal"]);
+        let headings = |show_tags| {
+            let options = output::RenderOptions {
+                show_tags,
+                ..Default::default()
+            };
+            rendered(output::Format::Text, &report, &options)
+                .lines()
+                .filter(|line| !line.starts_with('\t'))
+                .take(2)
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            headings(true),
+            ["1 Item (tags: billing, internal)", "2 Item"]
+        );
+        assert_eq!(headings(false), ["1 Item", "2 Item"]);
+    }
+
+    #[test]
+    fn work_item_types_narrow_the_query() {
+        let types = ["Task".to_string(), "Bug".to_string()];
+        assert_eq!(
+            wiql_conditions([wiql_in("System.WorkItemType", &types)]),
+            " AND [System.WorkItemType] IN ('Task', 'Bug')"
+        );
+        assert_eq!(wiql_in("System.WorkItemType", &[]), None);
+
+        // Along with the other filters, all of which must hold
+        let area = ["Proj".to_string()];
+        assert_eq!(
+            wiql_conditions([
+                wiql_any("System.AreaPath", "UNDER", &area),
+                wiql_in("System.WorkItemType", &types[..1]),
+            ]),
+            " AND ([System.AreaPath] UNDER 'Proj') AND [System.WorkItemType] IN ('Task')"
+        );
+    }
+
+    #[test]
+    fn attribute_field_reports_another_identity() {
+        let activated_by = json!({
+            "id": Uuid::nil(),
+            "displayName": "Dev",
+            "uniqueName": "dev@b.c",
+        });
+        let query = ReportQuery {
+            attribute_field: "Microsoft.VSTS.Common.ActivatedBy".to_string(),
+            users: Some(vec!["dev@b.c".to_string()]),
+            ..query()
+        };
+        let revisions = vec![
+            revision(1, "2024-03-04", "pm@b.c", json!({ COMPLETED_WORK: 1.0 })),
+            revision(
+                2,
+                "2024-03-05",
+                "pm@b.c",
+                json!({ COMPLETED_WORK: 3.0, "Microsoft.VSTS.Common.ActivatedBy": activated_by }),
+            ),
+        ];
+        let items = collect_items(&connection(), &query, vec![work_item(1)], vec![revisions]);
+        // The first revision has no activator, so nobody's time
+        assert_eq!(hours(&items[0]), [(2, 2.0)]);
+        assert_eq!(items[0].entries[0].changed_by.email, "dev@b.c");
+
+        // Saved by the PM, but that's not who the time is attributed to
+        let query = ReportQuery {
+            users: Some(vec!["pm@b.c".to_string()]),
+            ..query
+        };
+        let revisions = vec![revision(
+            1,
+            "2024-03-05",
+            "pm@b.c",
+            json!({ COMPLETED_WORK: 3.0, "Microsoft.VSTS.Common.ActivatedBy": activated_by }),
+        )];
+        assert!(
+            collect_items(&connection(), &query, vec![work_item(1)], vec![revisions]).is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn strict_range_server_only_narrows_the_query() {
+        let wiql = |strict_range_server| async move {
+            let (server, requests) = mock_server(vec![response(
+                "200 OK",
+                &[("Content-Type", "application/json")],
+                r#"{"workItems": [{"id": 1}]}"#,
+            )])
+            .await;
+            let connection = Connection {
+                server: Url::parse(&format!("http://{}/", server)).unwrap(),
+                ..connection()
+            };
+            let query = ReportQuery {
+                strict_range_server,
+                ..query()
+            };
+            let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+            query_work_items(&client.http, &connection, &query)
+                .await
+                .unwrap();
+            requests.await.unwrap().remove(0)
+        };
+        let widened = wiql(false).await;
+        assert!(widened.contains(">= '2024-02-29T00:00:00Z'"), "{}", widened);
+        assert!(widened.contains("<= '2024-04-01T23:59:59Z'"), "{}", widened);
+        let strict = wiql(true).await;
+        assert!(strict.contains(">= '2024-03-01T00:00:00Z'"), "{}", strict);
+        assert!(strict.contains("<= '2024-03-31T23:59:59Z'"), "{}", strict);
+
+        // WIQL picks the item, not its revisions, so those weeks before the
+        // range are still left out
+        let history = vec![
+            revision(1, "2024-02-01", "a@b.c", json!({ COMPLETED_WORK: 4.0 })),
+            revision(2, "2024-02-12", "a@b.c", json!({ COMPLETED_WORK: 6.0 })),
+            revision(3, "2024-03-04", "a@b.c", json!({ COMPLETED_WORK: 7.5 })),
+            revision(4, "2024-04-08", "a@b.c", json!({ COMPLETED_WORK: 9.0 })),
+        ];
+        let query = ReportQuery {
+            strict_range_server: true,
+            ..query()
+        };
+        let items = collect_items(&connection(), &query, vec![work_item(1)], vec![history]);
+        assert_eq!(hours(&items[0]), [(3, 1.5)]);
+        assert_eq!(items[0].total, 1.5);
+    }
+
+    #[tokio::test]
+    async fn items_whose_work_was_cleared_since_are_kept() {
+        let (server, requests) = mock_server(vec![response(
+            "200 OK",
+            &[("Content-Type", "application/json")],
+            &json!({ "value": [
+                { "id": 1, "fields": {
+                    "System.ChangedDate": "2024-01-10T12:00:00Z",
+                    COMPLETED_WORK: 3.0,
+                }},
+                // Cleared in the range, or after it
+                { "id": 2, "fields": { "System.ChangedDate": "2024-03-10T12:00:00Z" }},
+                { "id": 4, "fields": { "System.ChangedDate": "2024-04-15T12:00:00Z" }},
+                // Empty since before the range
+                { "id": 3, "fields": { "System.ChangedDate": "2024-02-01T12:00:00Z" }},
+            ]})
+            .to_string(),
+        )])
+        .await;
+        let connection = Connection {
+            server: Url::parse(&format!("http://{}/", server)).unwrap(),
+            ..connection()
+        };
+        let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+        let work_items = (1..=4).map(work_item).collect();
+        let query = query();
+        let kept = with_completed_work(
+            &client.http,
+            &connection,
+            &[COMPLETED_WORK],
+            query.from,
+            work_items,
+        )
+        .await
+        .unwrap();
+
+        let kept: Vec<(u64, String)> = kept
+            .iter()
+            .map(|item| (item.id, item.changed_date.unwrap().to_rfc3339()))
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                (1, "2024-01-10T12:00:00+00:00".to_string()),
+                (2, "2024-03-10T12:00:00+00:00".to_string()),
+                (4, "2024-04-15T12:00:00+00:00".to_string()),
+            ]
+        );
+        let requests = requests.await.unwrap();
+        assert_eq!(requests.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn plan_only_runs_the_query_and_one_request() {
+        let (server, requests) = mock_server(vec![
+            response(
+                "200 OK",
+                &[("Content-Type", "application/json")],
+                r#"{"workItems": [{"id": 1}, {"id": 2}, {"id": 3}]}"#,
+            ),
+            response(
+                "200 OK",
+                &[("Content-Type", "application/json")],
+                r#"{"count": 0, "value": []}"#,
+            ),
+        ])
+        .await;
+        let connection = Connection {
+            server: Url::parse(&format!("http://{}/", server)).unwrap(),
+            ..connection()
+        };
+        let query = ReportQuery {
+            concurrency: 2,
+            ..query()
+        };
+        let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+        let plan = client.plan(&[connection], &query).await.unwrap();
+        assert_eq!(
+            (plan.work_items, plan.history_requests, plan.batch_requests),
+            (3, 3, 1)
+        );
+        assert_eq!(plan.concurrency, 2);
+
+        let requests = requests.await.unwrap();
+        assert!(
+            requests[0].starts_with("POST /org/Proj/_apis/wit/wiql?"),
+            "{}",
+            requests[0]
+        );
+        assert!(
+            requests[1].starts_with(
+                "GET /org/Proj/_apis/wit/workItems/1/revisions?api-version=7.1&%24top=1 "
+            ),
+            "{}",
+            requests[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn project_names_are_resolved_and_percent_encoded() {
+        let (server, requests) = mock_server(vec![
+            response(
+                "200 OK",
+                &[("Content-Type", "application/json")],
+                r#"{"name": "My Project"}"#,
+            ),
+            response(
+                "200 OK",
+                &[("Content-Type", "application/json")],
+                r#"{"workItems": [{"id": 1}]}"#,
+            ),
+        ])
+        .await;
+        let connection = Connection {
+            server: Url::parse(&format!("http://{}/", server)).unwrap(),
+            ..connection()
+        };
+        let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+        let project = client
+            .resolve_project(&connection, "my project")
+            .await
+            .unwrap();
+        assert_eq!(project, "My Project");
+        let connection = Connection {
+            project: Some(project),
+            ..connection
+        };
+        let work_items = query_work_items(&client.http, &connection, &query())
+            .await
+            .unwrap();
+        assert_eq!(work_items.len(), 1);
+
+        let requests = requests.await.unwrap();
+        assert!(
+            requests[0].starts_with("GET /org/_apis/projects/my%20project?"),
+            "{}",
+            requests[0]
+        );
+        assert!(
+            requests[1].starts_with("POST /org/My%20Project/_apis/wit/wiql?"),
+            "{}",
+            requests[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn long_histories_are_read_to_the_end() {
+        // Revisions two hours apart through February, the last one in the range
+        let february = "2024-02-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let last = REVISION_PAGE as u32 + 50;
+        let history: Vec<Value> = (1..=last)
+            .map(|rev| {
+                let date = if rev == last {
+                    "2024-03-04T12:00:00Z".parse().unwrap()
+                } else {
+                    february + chrono::Duration::hours(i64::from(rev) * 2)
+                };
+                json!({
+                    "rev": rev,
+                    "fields": {
+                        "System.ChangedDate": date,
+                        "System.ChangedBy": {
+                            "id": Uuid::nil(),
+                            "displayName": "a@b.c",
+                            "uniqueName": "a@b.c",
+                        },
+                        COMPLETED_WORK: f64::from(rev),
+                    },
+                })
+            })
+            .collect();
+        let page = |revisions: &[Value]| {
+            json!({ "count": revisions.len(), "value": revisions }).to_string()
+        };
+        let json = [("Content-Type", "application/json")];
+        let (server, requests) = mock_server(vec![
+            response("200 OK", &json, &page(&history[..REVISION_PAGE])),
+            response("200 OK", &json, &page(&history[REVISION_PAGE..])),
+        ])
+        .await;
+        let connection = Connection {
+            server: Url::parse(&format!("http://{}/", server)).unwrap(),
+            ..connection()
+        };
+        let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+        let revisions = fetch_revisions(&client.http, &connection, 5).await.unwrap();
+        assert_eq!(revisions.len(), REVISION_PAGE + 50);
+        let requests = requests.await.unwrap();
+        assert!(requests[1].contains("%24skip=200 "), "{}", requests[1]);
+
+        // Read to the end, the baseline is the revision before the range
+        let full = collect_items(&connection, &query(), vec![work_item(5)], vec![revisions]);
+        assert_eq!(hours(&full[0]), [(250, 1.0)]);
+        // Cut off at the range, everything before it would count as logged in it
+        let cut: Vec<Revision> =
+            serde_json::from_value(json!(history[REVISION_PAGE + 49..])).unwrap();
+        let cut = collect_items(&connection, &query(), vec![work_item(5)], vec![cut]);
+        assert_eq!(hours(&cut[0]), [(250, 250.0)]);
+    }
+
+    #[tokio::test]
+    async fn older_servers_get_the_api_version_they_ask_for() {
+        let (server, requests) = mock_server(vec![response(
+            "200 OK",
+            &[("Content-Type", "application/json")],
+            r#"{"count": 0, "value": []}"#,
+        )])
+        .await;
+        let connection = Connection {
+            server: Url::parse(&format!("http://{}/", server)).unwrap(),
+            api_version: Some("6.0".to_string()),
+            ..connection()
+        };
+        let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+        fetch_revisions(&client.http, &connection, 1).await.unwrap();
+
+        let request = requests.await.unwrap().remove(0);
+        assert!(
+            request.starts_with(
+                "GET /org/Proj/_apis/wit/workItems/1/revisions?api-version=6.0&%24top=200&%24skip=0 "
+            ),
+            "{}",
+            request
+        );
+        assert_eq!(crate::testing::connection().api_version(), "7.1");
+    }
+
+    #[tokio::test]
+    async fn the_cache_is_only_written_when_something_was_fetched() {
+        let path = std::env::temp_dir().join(format!("cache-{}.json", std::process::id()));
+        let (server, requests) = mock_server(vec![response(
+            "200 OK",
+            &[],
+            r#"{"count": 0, "value": []}"#,
+        )])
+        .await;
+        let connection = Connection {
+            server: Url::parse(&format!("http://{}/", server)).unwrap(),
+            ..connection()
+        };
+        let changed = "2024-03-04T12:00:00Z".parse().unwrap();
+        let item = |id: u64| WorkItem {
+            id,
+            changed_date: Some(changed),
+        };
+        let history = vec![revision(
+            1,
+            "2024-03-04",
+            "a@b.c",
+            json!({ COMPLETED_WORK: 1.0 }),
+        )];
+        let mut cache = Cache::default();
+        cache.insert(&connection, 1, changed, history);
+        cache.save(&path).unwrap();
+        // Laid out unlike a save, to tell whether it was saved again
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let laid_out = serde_json::to_string_pretty(&saved).unwrap();
+        std::fs::write(&path, &laid_out).unwrap();
+        let query = ReportQuery {
+            cache: Some(path.clone()),
+            ..query()
+        };
+        let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+
+        let all_cached = fetch_histories(&client.http, &connection, &query, &[item(1)]).await;
+        let untouched = std::fs::read_to_string(&path).unwrap();
+        let one_fetched =
+            fetch_histories(&client.http, &connection, &query, &[item(1), item(2)]).await;
+        let rewritten = std::fs::read_to_string(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(all_cached.unwrap()[0].len(), 1);
+        assert_eq!(untouched, laid_out);
+        assert_eq!(
+            one_fetched
+                .unwrap()
+                .iter()
+                .map(Vec::len)
+                .collect::<Vec<_>>(),
+            [1, 0]
+        );
+        // Only the item not cached was asked for
+        assert_eq!(requests.await.unwrap().len(), 1);
+        assert_ne!(rewritten.unwrap(), laid_out);
+    }
+
+    /// What a server answers a report with item `id` on, `hours` logged on it
+    /// in one revision in `project`, if it says
+    fn one_item_answers(id: u64, hours: f64, project: Option<&str>) -> Vec<String> {
+        let json = |body: Value| {
+            response(
+                "200 OK",
+                &[("Content-Type", "application/json")],
+                &body.to_string(),
+            )
+        };
+        let mut fields = json!({
+            "System.ChangedDate": "2024-03-04T12:00:00Z",
+            "System.ChangedBy": { "id": Uuid::nil(), "displayName": "A", "uniqueName": "a@b.c" },
+            "System.Title": format!("Item {}", id),
+            COMPLETED_WORK: hours,
+        });
+        if let Some(project) = project {
+            fields["System.TeamProject"] = json!(project);
+        }
+        vec![
+            json(json!({ "workItems": [{ "id": id }] })),
+            json(json!({ "value": [{ "id": id, "fields": {
+                "System.ChangedDate": "2024-03-04T12:00:00Z",
+                COMPLETED_WORK: hours,
+            }}]})),
+            json(json!({ "count": 1, "value": [{ "rev": 1, "fields": fields }] })),
+        ]
+    }
+
+    #[tokio::test]
+    async fn every_project_goes_into_one_report() {
+        let (server, requests) = mock_server(
+            [
+                one_item_answers(1, 2.0, None),
+                one_item_answers(2, 3.0, Some("B")),
+            ]
+            .concat(),
+        )
+        .await;
+        let server = Url::parse(&format!("http://{}/", server)).unwrap();
+        let connections = ["A", "B"].map(|project| Connection {
+            server: server.clone(),
+            project: Some(project.to_string()),
+            ..connection()
+        });
+        let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+        let report = client.report(&connections, &query()).await.unwrap();
+
+        let items: Vec<(u64, &str, &str)> = report
+            .items
+            .iter()
+            .map(|item| (item.id, item.project.as_str(), item.url.as_str()))
+            .collect();
+        let url = |path: &str| format!("{}org/{}", server, path);
+        assert_eq!(
+            items,
+            [
+                (1, "A", url("A/_workitems/edit/1").as_str()),
+                (2, "B", url("B/_workitems/edit/2").as_str()),
+            ]
+        );
+        assert_eq!(
+            report.by_project,
+            BTreeMap::from([("A".to_string(), 2.0), ("B".to_string(), 3.0)])
+        );
+        assert_eq!(report.total, 5.0);
+        assert_eq!(
+            report.queried,
+            BTreeSet::from([("org".to_string(), 1), ("org".to_string(), 2)])
+        );
+
+        // One project after the other
+        let requests = requests.await.unwrap();
+        let paths: Vec<&str> = requests
+            .iter()
+            .map(|request| request.split(['?', ' ']).nth(1).unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "/org/A/_apis/wit/wiql",
+                "/org/A/_apis/wit/workitemsbatch",
+                "/org/A/_apis/wit/workItems/1/revisions",
+                "/org/B/_apis/wit/wiql",
+                "/org/B/_apis/wit/workitemsbatch",
+                "/org/B/_apis/wit/workItems/2/revisions",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn organizations_are_added_up_with_their_own_tokens() {
+        // The same id in both, which are different items
+        let (a, a_requests) = mock_server(one_item_answers(1, 2.0, None)).await;
+        let (b, b_requests) = mock_server(one_item_answers(1, 3.0, None)).await;
+        let connections = [(a, "a-org", "token-a"), (b, "b-org", "token-b")].map(
+            |(server, organization, token)| Connection {
+                server: Url::parse(&format!("http://{}/", server)).unwrap(),
+                organization: organization.to_string(),
+                token: token.to_string(),
+                ..connection()
+            },
+        );
+        let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+        let report = client.report(&connections, &query()).await.unwrap();
+
+        let items: Vec<(&str, u64, f64)> = report
+            .items
+            .iter()
+            .map(|item| (item.organization.as_str(), item.id, item.total))
+            .collect();
+        assert_eq!(items, [("a-org", 1, 2.0), ("b-org", 1, 3.0)]);
+        assert_eq!(
+            report.by_organization,
+            BTreeMap::from([("a-org".to_string(), 2.0), ("b-org".to_string(), 3.0)])
+        );
+        assert_eq!(report.total, 5.0);
+        assert_eq!(
+            report.queried,
+            BTreeSet::from([("a-org".to_string(), 1), ("b-org".to_string(), 1)])
+        );
+
+        for (requests, path, authorization) in [
+            (a_requests, "/a-org/Proj/", "YUBiLmM6dG9rZW4tYQ=="),
+            (b_requests, "/b-org/Proj/", "YUBiLmM6dG9rZW4tYg=="),
+        ] {
+            for request in requests.await.unwrap() {
+                assert!(request.contains(path), "{}", request);
+                assert!(
+                    request.contains(&format!("authorization: Basic {}\r\n", authorization)),
+                    "{}",
+                    request
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn hours_roll_up_a_level_of_the_hierarchy_at_a_time() {
+        let linked = |id: u64, work_item_type: &str, parent: Option<u64>| {
+            let relations: Vec<Value> = parent
+                .into_iter()
+                .map(|parent| {
+                    json!({
+                        "rel": "System.LinkTypes.Hierarchy-Reverse",
+                        "url": format!("https://dev.azure.com/org/_apis/wit/workItems/{}", parent),
+                    })
+                })
+                .chain([json!({ "rel": "System.LinkTypes.Related", "url": ".../workItems/8" })])
+                .collect();
+            json!({
+                "id": id,
+                "fields": { "System.Title": format!("{} {}", work_item_type, id), "System.WorkItemType": work_item_type },
+                "relations": relations,
+            })
+        };
+        let batch = |items: Vec<Value>| {
+            response(
+                "200 OK",
+                &[("Content-Type", "application/json")],
+                &json!({ "value": items }).to_string(),
+            )
+        };
+        // Tasks 1 and 2 under story 5, under feature 7 under epic 9; task 3
+        // under story 6, which has no parent
+        let tasks = || {
+            batch(vec![
+                linked(1, "Task", Some(5)),
+                linked(2, "Task", Some(5)),
+                linked(3, "Task", Some(6)),
+            ])
+        };
+        let stories = || {
+            batch(vec![
+                linked(5, "User Story", Some(7)),
+                linked(6, "User Story", None),
+            ])
+        };
+        let (server, requests) = mock_server(vec![
+            tasks(),
+            stories(),
+            batch(vec![linked(7, "Feature", Some(9))]),
+            batch(vec![linked(9, "Epic", None)]),
+            tasks(),
+            stories(),
+        ])
+        .await;
+        let connection = Connection {
+            server: Url::parse(&format!("http://{}/", server)).unwrap(),
+            ..connection()
+        };
+        let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+        let ancestors = |to| {
+            let (client, connection) = (&client, &connection);
+            async move {
+                let ancestors = fetch_ancestors(&client.http, connection, &[1, 2, 3], to)
+                    .await
+                    .unwrap();
+                let mut ancestors: Vec<(u64, u64, String)> = ancestors
+                    .into_iter()
+                    .map(|(id, ancestor)| (id, ancestor.id, ancestor.title))
+                    .collect();
+                ancestors.sort();
+                ancestors
+            }
+        };
+
+        assert_eq!(
+            ancestors(Rollup::Epic).await,
+            [(1, 9, "Epic 9".to_string()), (2, 9, "Epic 9".to_string())]
+        );
+        // Only as far up as the parents
+        assert_eq!(
+            ancestors(Rollup::Parent).await,
+            [
+                (1, 5, "User Story 5".to_string()),
+                (2, 5, "User Story 5".to_string()),
+                (3, 6, "User Story 6".to_string()),
+            ]
+        );
+        let asked_for: Vec<Value> = requests
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| {
+                let body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+                serde_json::from_str::<Value>(body).unwrap()["ids"].clone()
+            })
+            .collect();
+        assert_eq!(
+            asked_for,
+            [
+                json!([1, 2, 3]),
+                json!([5, 6]),
+                json!([7]),
+                json!([9]),
+                json!([1, 2, 3]),
+                json!([5, 6]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rolled_up_hours_go_most_first_with_the_rest_last() {
+        let logged = |hours: f64| {
+            vec![revision(
+                1,
+                "2024-03-04",
+                "a@b.c",
+                json!({ COMPLETED_WORK: hours }),
+            )]
+        };
+        let mut report = report(vec![logged(1.0), logged(4.0), logged(2.0), logged(5.0)]);
+        let ancestor = |id: u64| Ancestor {
+            id,
+            title: format!("Epic {}", id),
+            work_item_type: "Epic".to_string(),
+        };
+        report.items[0].rolls_up_to = Some(ancestor(10));
+        report.items[2].rolls_up_to = Some(ancestor(10));
+        report.items[3].rolls_up_to = Some(ancestor(20));
+
+        let rolled_up = rollup_totals(&report.items, Rollup::Epic);
+        let totals: Vec<(Option<u64>, f64)> = rolled_up
+            .totals
+            .iter()
+            .map(|total| {
+                (
+                    total.ancestor.as_ref().map(|ancestor| ancestor.id),
+                    total.hours,
+                )
+            })
+            .collect();
+        assert_eq!(totals, [(Some(20), 5.0), (Some(10), 3.0), (None, 4.0)]);
+        assert_eq!(rolled_up.to, Rollup::Epic);
+    }
+
+    #[test]
+    fn remainder_days_go_to_the_first_buckets() {
+        let date = |s: &str| s.parse::<NaiveDate>().unwrap();
+        let lengths = |from: &str, to: &str, n: u32| -> Vec<i64> {
+            let spans = bucket_spans(date(from), date(to), n);
+            // Back to back, over the whole range
+            assert_eq!(spans.first().unwrap().0, date(from));
+            assert_eq!(spans.last().unwrap().1, date(to));
+            for pair in spans.windows(2) {
+                assert_eq!(pair[0].1.succ_opt().unwrap(), pair[1].0);
+            }
+            spans
+                .iter()
+                .map(|(start, end)| (*end - *start).num_days() + 1)
+                .collect()
+        };
+        assert_eq!(lengths("2024-03-01", "2024-03-10", 3), [4, 3, 3]);
+        assert_eq!(lengths("2024-03-01", "2024-03-11", 3), [4, 4, 3]);
+        assert_eq!(lengths("2024-03-01", "2024-03-31", 4), [8, 8, 8, 7]);
+        assert_eq!(lengths("2024-03-01", "2024-03-09", 3), [3, 3, 3]);
+        assert_eq!(lengths("2024-03-01", "2024-03-31", 1), [31]);
+    }
+
+    #[test]
+    fn buckets_are_never_shorter_than_a_day() {
+        let date = |s: &str| s.parse::<NaiveDate>().unwrap();
+        let spans = bucket_spans(date("2024-03-04"), date("2024-03-06"), 10);
+        assert_eq!(
+            spans,
+            [
+                (date("2024-03-04"), date("2024-03-04")),
+                (date("2024-03-05"), date("2024-03-05")),
+                (date("2024-03-06"), date("2024-03-06")),
+            ]
+        );
+        assert_eq!(
+            bucket_spans(date("2024-03-04"), date("2024-03-04"), 3).len(),
+            1
+        );
+
+        let sums = BTreeMap::from([
+            (date("2024-03-03"), 9.0),
+            (date("2024-03-04"), 1.0),
+            (date("2024-03-06"), 2.5),
+        ]);
+        let totals: Vec<f64> = bucket_totals(&sums, date("2024-03-04"), date("2024-03-06"), 10)
+            .iter()
+            .map(|bucket| bucket.hours)
+            .collect();
+        // The day before the range is in no bucket
+        assert_eq!(totals, [1.0, 0.0, 2.5]);
+    }
+
+    #[test]
+    fn plan_estimates_rounds_of_requests() {
+        let plan = Plan {
+            work_items: 450,
+            batch_requests: 3,
+            history_requests: 450,
+            concurrency: 8,
+            request_time: Duration::from_millis(200),
+        };
+        // 3 batches, then 57 rounds of 8 histories
+        assert_eq!(plan.estimate(), Duration::from_millis(200 * 60));
+    }
+
+    #[test]
+    fn baseline_replaces_the_carried_over_completed_work() {
+        // The history before the range is wrong or cut short
+        let histories = || {
+            vec![vec![
+                revision(1, "2024-02-20", "a@b.c", json!({ COMPLETED_WORK: 2.0 })),
+                revision(2, "2024-03-04", "a@b.c", json!({ COMPLETED_WORK: 5.0 })),
+                revision(3, "2024-03-05", "a@b.c", json!({ COMPLETED_WORK: 6.0 })),
+            ]]
+        };
+        let carried = collect_items(&connection(), &query(), vec![work_item(1)], histories());
+        assert_eq!(hours(&carried[0]), [(2, 3.0), (3, 1.0)]);
+
+        let query = ReportQuery {
+            baselines: HashMap::from([(1, 4.0)]),
+            ..query()
+        };
+        let corrected = collect_items(&connection(), &query, vec![work_item(1)], histories());
+        assert_eq!(hours(&corrected[0]), [(2, 1.0), (3, 1.0)]);
+
+        // Other items keep their own history
+        let other = collect_items(&connection(), &query, vec![work_item(2)], histories());
+        assert_eq!(hours(&other[0]), [(2, 3.0), (3, 1.0)]);
+    }
+
+    fn sampled(n: usize, seed: u64) -> Vec<u64> {
+        let work_items = (1..=100).map(work_item).collect();
+        sample(work_items, n, seed)
+            .iter()
+            .map(|item| item.id)
+            .collect()
+    }
+
+    #[test]
+    fn sample_is_the_same_for_the_same_seed() {
+        let picked = sampled(10, 42);
+        assert_eq!(picked.len(), 10);
+        assert_eq!(picked, sampled(10, 42));
+        assert_ne!(picked, sampled(10, 43));
+        // In query order, without repeats
+        assert!(picked.windows(2).all(|pair| pair[0] < pair[1]));
+        // Asking for more than there are gives them all
+        assert_eq!(sampled(500, 42), (1..=100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sampled_reports_say_so() {
+        let query = ReportQuery {
+            sample: Some((1, 7)),
+            ..query()
+        };
+        let report = Report::new(&query, Vec::new(), Some(Sampled { items: 1, of: 100 }));
+        let text = testing::rendered(output::Format::Text, &report, &Default::default());
+        assert!(text.contains("sampled: 1 of 100 items"), "{}", text);
+    }
+
+    #[test]
+    fn since_state_counts_from_the_transition() {
+        let query = ReportQuery {
+            since_state: Some("Active".to_string()),
+            ..query()
+        };
+        let revisions = vec![
+            revision(
+                1,
+                "2024-03-01",
+                "a@b.c",
+                json!({ "System.State": "New", COMPLETED_WORK: 2.0 }),
+            ),
+            revision(
+                2,
+                "2024-03-02",
+                "a@b.c",
+                json!({ "System.State": "Active", COMPLETED_WORK: 3.0 }),
+            ),
+            revision(
+                3,
+                "2024-03-03",
+                "a@b.c",
+                json!({ "System.State": "Active", COMPLETED_WORK: 7.0 }),
+            ),
+        ];
+        let items = collect_items(&connection(), &query, vec![work_item(1)], vec![revisions]);
+        assert_eq!(hours(&items[0]), [(2, 1.0), (3, 4.0)]);
+        assert_eq!(items[0].total, 5.0);
+    }
+
+    #[test]
+    fn since_state_leaves_out_items_never_in_the_state() {
+        let query = ReportQuery {
+            since_state: Some("Active".to_string()),
+            ..query()
+        };
+        let revisions = vec![
+            revision(
+                1,
+                "2024-03-01",
+                "a@b.c",
+                json!({ "System.State": "New", COMPLETED_WORK: 2.0 }),
+            ),
+            revision(
+                2,
+                "2024-03-02",
+                "a@b.c",
+                json!({ "System.State": "Closed", COMPLETED_WORK: 3.0 }),
+            ),
+        ];
+        let items = collect_items(&connection(), &query, vec![work_item(1)], vec![revisions]);
+        assert!(items.is_empty());
+    }
+}
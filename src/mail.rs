@@ -0,0 +1,498 @@
+//! Mailing the report over SMTP, as plain text with an HTML alternative
+//!
+//! Just enough SMTP for handing a message to a submission server: EHLO,
+//! STARTTLS or TLS from the start, AUTH PLAIN and one message.
+
+use crate::{Error, Rng};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{Local, Utc};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tokio_native_tls::{native_tls, TlsConnector};
+
+/// How the connection to the server is secured
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    /// Plain connection upgraded with STARTTLS, usually port 587
+    #[default]
+    Starttls,
+    /// TLS from the start, usually port 465
+    Tls,
+    /// No encryption, only for a relay on the same host or network
+    None,
+}
+
+impl Security {
+    fn default_port(self) -> u16 {
+        match self {
+            Security::Starttls => 587,
+            Security::Tls => 465,
+            Security::None => 25,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Smtp {
+    pub host: String,
+    /// The usual port for the security when left out
+    pub port: Option<u16>,
+    pub security: Security,
+    /// Credentials for AUTH, when the server wants them
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub from: String,
+    pub to: Vec<String>,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+/// Give up on a server that stops answering
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+pub async fn send(smtp: &Smtp, message: &Message) -> Result<(), Error> {
+    check_headers(message)?;
+    tokio::time::timeout(TIMEOUT, session(smtp, message))
+        .await
+        .map_err(|_| format!("{} didn't answer in time", smtp.host))?
+}
+
+/// Refuse line breaks in what goes in the headers, which would start headers
+/// or SMTP commands of their own
+fn check_headers(message: &Message) -> Result<(), Error> {
+    let headers = [("From", &message.from), ("Subject", &message.subject)]
+        .into_iter()
+        .chain(message.to.iter().map(|to| ("To", to)));
+    for (name, value) in headers {
+        if value.contains(['\r', '\n']) {
+            return Err(format!("{} {:?} can't have line breaks", name, value).into());
+        }
+    }
+    Ok(())
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+struct Connection {
+    stream: BufReader<Box<dyn Stream>>,
+    host: String,
+}
+
+impl Connection {
+    /// Read a reply, which may span several lines, and check that it's of
+    /// the kind of `code`, as 251 does for 250
+    async fn expect(&mut self, code: u16) -> Result<String, Error> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(format!("{} closed the connection", self.host).into());
+            }
+            reply.push_str(&line);
+            // The last line has a space after the code, the others a dash
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        if reply.as_bytes().first() != Some(&(b'0' + (code / 100) as u8)) {
+            return Err(format!("{} answered {}", self.host, reply.trim_end()).into());
+        }
+        Ok(reply)
+    }
+
+    async fn command(&mut self, command: &str, code: u16) -> Result<String, Error> {
+        let stream = self.stream.get_mut();
+        stream.write_all(command.as_bytes()).await?;
+        stream.write_all(b"\r\n").await?;
+        stream.flush().await?;
+        self.expect(code).await
+    }
+
+    async fn tls(self) -> Result<Connection, Error> {
+        let connector = native_tls::TlsConnector::new()
+            .map_err(|error| format!("Can't set up TLS: {}", error))?;
+        let stream = TlsConnector::from(connector)
+            .connect(&self.host, self.stream.into_inner())
+            .await
+            .map_err(|error| format!("TLS with {} failed: {}", self.host, error))?;
+        Ok(Connection {
+            stream: BufReader::new(Box::new(stream)),
+            host: self.host,
+        })
+    }
+}
+
+async fn session(smtp: &Smtp, message: &Message) -> Result<(), Error> {
+    let port = smtp.port.unwrap_or(smtp.security.default_port());
+    let tcp = TcpStream::connect((smtp.host.as_str(), port))
+        .await
+        .map_err(|error| format!("Can't reach {}:{}: {}", smtp.host, port, error))?;
+    let mut connection = Connection {
+        stream: BufReader::new(Box::new(tcp)),
+        host: smtp.host.clone(),
+    };
+    if smtp.security == Security::Tls {
+        connection = connection.tls().await?;
+    }
+    connection.expect(220).await?;
+    let mut features = connection.command("EHLO localhost", 250).await?;
+    if smtp.security == Security::Starttls {
+        connection.command("STARTTLS", 220).await?;
+        connection = connection.tls().await?;
+        features = connection.command("EHLO localhost", 250).await?;
+    }
+
+    if let Some(user) = &smtp.user {
+        if !features.lines().any(|line| {
+            line.get(4..)
+                .is_some_and(|feature| feature.to_ascii_uppercase().starts_with("AUTH"))
+        }) {
+            return Err(format!("{} doesn't take a login", smtp.host).into());
+        }
+        let credentials = format!("\0{}\0{}", user, smtp.password.as_deref().unwrap_or(""));
+        connection
+            .command(&format!("AUTH PLAIN {}", STANDARD.encode(credentials)), 235)
+            .await
+            .map_err(|error| format!("Logging in as {} failed: {}", user, error))?;
+    }
+
+    connection
+        .command(&format!("MAIL FROM:<{}>", address(&message.from)), 250)
+        .await?;
+    for to in &message.to {
+        connection
+            .command(&format!("RCPT TO:<{}>", address(to)), 250)
+            .await
+            .map_err(|error| format!("{} can't get mail: {}", to, error))?;
+    }
+    connection.command("DATA", 354).await?;
+    connection
+        .command(&dot_stuffed(&compose(message)), 250)
+        .await?;
+    // The message is on its way whatever QUIT gets
+    let _ = connection.command("QUIT", 221).await;
+    Ok(())
+}
+
+/// The message as sent after DATA, ending with the line of just a dot
+fn dot_stuffed(mail: &str) -> String {
+    let mut data = String::new();
+    for line in mail.lines() {
+        // A line of just a dot would end the message, so every leading dot
+        // is doubled
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    data.push('.');
+    data
+}
+
+/// The address in `Name <address>`, or the whole thing
+fn address(mailbox: &str) -> &str {
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+/// The message with headers, as multipart/alternative
+fn compose(message: &Message) -> String {
+    // Only has to be unique, for the boundary and the message id
+    let mut rng =
+        Rng(Utc::now().timestamp_nanos_opt().unwrap_or(0) as u64 ^ u64::from(std::process::id()));
+    let unique = format!("{:016x}{:016x}", rng.next(), rng.next());
+    let boundary = format!("=_{}", unique);
+    let domain = address(&message.from)
+        .rsplit_once('@')
+        .map_or("localhost", |(_, domain)| domain);
+    let mut mail = String::new();
+    mail.push_str(&format!("From: {}\r\n", message.from));
+    mail.push_str(&format!("To: {}\r\n", message.to.join(", ")));
+    mail.push_str(&format!("Subject: {}\r\n", encode_header(&message.subject)));
+    mail.push_str(&format!("Date: {}\r\n", Local::now().to_rfc2822()));
+    mail.push_str(&format!("Message-ID: <{}@{}>\r\n", unique, domain));
+    mail.push_str("MIME-Version: 1.0\r\n");
+    mail.push_str(&format!(
+        "Content-Type: multipart/alternative;\r\n boundary=\"{}\"\r\n\r\n",
+        boundary
+    ));
+    for (kind, body) in [("plain", &message.text), ("html", &message.html)] {
+        mail.push_str(&format!("--{}\r\n", boundary));
+        mail.push_str(&format!("Content-Type: text/{}; charset=utf-8\r\n", kind));
+        mail.push_str("Content-Transfer-Encoding: base64\r\n\r\n");
+        // Base64 keeps long lines and any dots out of the way
+        let encoded = STANDARD.encode(body);
+        for chunk in encoded.as_bytes().chunks(76) {
+            mail.push_str(std::str::from_utf8(chunk).unwrap());
+            mail.push_str("\r\n");
+        }
+    }
+    mail.push_str(&format!("--{}--\r\n", boundary));
+    mail
+}
+
+/// RFC 2047 encoding for headers that aren't plain ASCII
+///
+/// Encoded words are kept short enough for a line each, the first one after
+/// the header's name too, split between characters.
+fn encode_header(text: &str) -> String {
+    if text.is_ascii() {
+        return text.to_string();
+    }
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in text.chars() {
+        // 39 bytes are 64 characters encoded, 76 with the markers
+        if word.len() + c.len_utf8() > 39 {
+            words.push(format!("=?utf-8?B?{}?=", STANDARD.encode(&word)));
+            word.clear();
+        }
+        word.push(c);
+    }
+    words.push(format!("=?utf-8?B?{}?=", STANDARD.encode(&word)));
+    words.join("\r\n ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{io::AsyncReadExt, net::TcpListener, task::JoinHandle};
+
+    fn message() -> Message {
+        Message {
+            from: "Timesheets <reports@adapt.dk>".to_string(),
+            to: vec!["boss@adapt.dk".to_string(), "HR <hr@adapt.dk>".to_string()],
+            subject: "Hours for week 12".to_string(),
+            text: "7.5 hours\n.\n".to_string(),
+            html: "<p>7.5 hours</p>".to_string(),
+        }
+    }
+
+    /// An SMTP server on localhost greeting and then answering each command
+    /// with the next of `replies`, handing back the commands it got
+    async fn smtp_server(replies: &[&str]) -> (u16, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let replies: Vec<String> = replies.iter().map(|reply| reply.to_string()).collect();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            stream
+                .get_mut()
+                .write_all(b"220 localhost\r\n")
+                .await
+                .unwrap();
+            let mut commands = Vec::new();
+            let mut data = false;
+            for reply in replies {
+                let mut command = String::new();
+                // The message goes up to the line of just a dot
+                while {
+                    let mut line = String::new();
+                    if stream.read_line(&mut line).await.unwrap() == 0 {
+                        return commands;
+                    }
+                    command.push_str(&line);
+                    data && !command.ends_with("\r\n.\r\n")
+                } {}
+                data = reply.starts_with("354");
+                commands.push(command);
+                stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+            }
+            let mut rest = String::new();
+            stream.read_to_string(&mut rest).await.unwrap();
+            assert!(rest.is_empty(), "{}", rest);
+            commands
+        });
+        (port, server)
+    }
+
+    fn smtp(port: u16, user: Option<&str>) -> Smtp {
+        Smtp {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            security: Security::None,
+            user: user.map(str::to_string),
+            password: user.map(|_| "secret".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn the_message_is_handed_over_after_logging_in() {
+        let (port, server) = smtp_server(&[
+            "250-localhost\r\n250-SIZE 1000000\r\n250 AUTH PLAIN LOGIN\r\n",
+            "235 ok\r\n",
+            "250 ok\r\n",
+            "250 ok\r\n",
+            "251 forwarded\r\n",
+            "354 go ahead\r\n",
+            "250 queued\r\n",
+            "221 bye\r\n",
+        ])
+        .await;
+        send(&smtp(port, Some("me")), &message()).await.unwrap();
+
+        let commands = server.await.unwrap();
+        assert_eq!(
+            commands[..5],
+            [
+                "EHLO localhost\r\n",
+                &format!("AUTH PLAIN {}\r\n", STANDARD.encode("\0me\0secret")),
+                "MAIL FROM:<reports@adapt.dk>\r\n",
+                "RCPT TO:<boss@adapt.dk>\r\n",
+                "RCPT TO:<hr@adapt.dk>\r\n",
+            ]
+        );
+        assert_eq!(commands[5], "DATA\r\n");
+        assert!(commands[6].starts_with("From: Timesheets <reports@adapt.dk>\r\n"));
+        assert!(commands[6].ends_with("--\r\n.\r\n"));
+        assert_eq!(commands[7], "QUIT\r\n");
+    }
+
+    #[tokio::test]
+    async fn refusals_name_what_was_refused() {
+        let (port, server) = smtp_server(&["250 localhost\r\n"]).await;
+        let error = send(&smtp(port, Some("me")), &message()).await.unwrap_err();
+        assert_eq!(error.to_string(), "127.0.0.1 doesn't take a login");
+        server.await.unwrap();
+
+        let (port, server) = smtp_server(&[
+            "250 localhost\r\n",
+            "250 ok\r\n",
+            "550 5.1.1 no such user\r\n",
+        ])
+        .await;
+        let error = send(&smtp(port, None), &message()).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "boss@adapt.dk can't get mail: 127.0.0.1 answered 550 5.1.1 no such user"
+        );
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn line_breaks_in_headers_are_refused() {
+        for (message, error) in [
+            (
+                Message {
+                    subject: "Hours\r\nBcc: everyone@adapt.dk".to_string(),
+                    ..message()
+                },
+                r#"Subject "Hours\r\nBcc: everyone@adapt.dk" can't have line breaks"#,
+            ),
+            (
+                Message {
+                    from: "me@adapt.dk>\r\nRCPT TO:<everyone@adapt.dk".to_string(),
+                    ..message()
+                },
+                r#"From "me@adapt.dk>\r\nRCPT TO:<everyone@adapt.dk" can't have line breaks"#,
+            ),
+            (
+                Message {
+                    to: vec!["boss@adapt.dk".to_string(), "hr@adapt.dk\n".to_string()],
+                    ..message()
+                },
+                r#"To "hr@adapt.dk\n" can't have line breaks"#,
+            ),
+        ] {
+            // Refused before connecting anywhere
+            let smtp = smtp(1, None);
+            assert_eq!(send(&smtp, &message).await.unwrap_err().to_string(), error);
+        }
+    }
+
+    #[test]
+    fn messages_have_both_parts_in_base64() {
+        let mail = compose(&Message {
+            subject: "Timer for uge 12 – æøå".to_string(),
+            ..message()
+        });
+        let (headers, body) = mail.split_once("\r\n\r\n").unwrap();
+        // Unfolded
+        let headers = headers.replace("\r\n ", " ");
+        let header = |name: &str| {
+            headers
+                .split("\r\n")
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+                .unwrap()
+        };
+        assert_eq!(header("From"), "Timesheets <reports@adapt.dk>");
+        assert_eq!(header("To"), "boss@adapt.dk, HR <hr@adapt.dk>");
+        assert_eq!(
+            header("Subject"),
+            "=?utf-8?B?VGltZXIgZm9yIHVnZSAxMiDigJMgw6bDuMOl?="
+        );
+        assert!(header("Message-ID").ends_with("@adapt.dk>"));
+        let boundary = header("Content-Type")
+            .strip_prefix("multipart/alternative; boundary=\"")
+            .and_then(|rest| rest.strip_suffix('"'))
+            .unwrap();
+
+        let parts: Vec<&str> = body.split(&format!("--{}", boundary)).collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[3], "--\r\n");
+        for (part, kind, text) in [
+            (parts[1], "plain", "7.5 hours\n.\n"),
+            (parts[2], "html", "<p>7.5 hours</p>"),
+        ] {
+            let (headers, encoded) = part.split_once("\r\n\r\n").unwrap();
+            assert!(headers.contains(&format!("Content-Type: text/{}; charset=utf-8", kind)));
+            let encoded: String = encoded.split("\r\n").collect();
+            assert_eq!(STANDARD.decode(encoded).unwrap(), text.as_bytes());
+        }
+        for line in mail.split("\r\n") {
+            assert!(line.len() <= 78, "{}", line);
+        }
+    }
+
+    #[test]
+    fn headers_past_ascii_are_encoded_words_of_whole_characters() {
+        assert_eq!(encode_header("Hours for week 12"), "Hours for week 12");
+        assert_eq!(
+            encode_header("Uge 12 – æ"),
+            "=?utf-8?B?VWdlIDEyIOKAkyDDpg==?="
+        );
+
+        let subject = "æøå".repeat(20);
+        let encoded = encode_header(&subject);
+        let words: Vec<&str> = encoded.split("\r\n ").collect();
+        assert_eq!(words.len(), 4);
+        let decoded: Vec<u8> = words
+            .iter()
+            .flat_map(|word| {
+                assert!(word.len() <= 75, "{}", word);
+                let word = word
+                    .strip_prefix("=?utf-8?B?")
+                    .unwrap()
+                    .strip_suffix("?=")
+                    .unwrap();
+                let bytes = STANDARD.decode(word).unwrap();
+                // Each word is text of its own
+                String::from_utf8(bytes.clone()).unwrap();
+                bytes
+            })
+            .collect();
+        assert_eq!(String::from_utf8(decoded).unwrap(), subject);
+    }
+
+    #[test]
+    fn leading_dots_are_doubled() {
+        assert_eq!(
+            dot_stuffed("Subject: x\r\n\r\n.\r\n..two\r\nend."),
+            "Subject: x\r\n\r\n..\r\n...two\r\nend.\r\n."
+        );
+    }
+}
@@ -1,203 +1,2043 @@
-use chrono::{DateTime, NaiveDate, Utc, Weekday};
-use clap::Parser;
+#[cfg(feature = "email")]
+use azure_devops_time_used::mail;
+#[cfg(feature = "serve")]
+use azure_devops_time_used::serve;
+#[cfg(feature = "store")]
+use azure_devops_time_used::store;
+use azure_devops_time_used::{
+    aad, bucket_totals, client_totals,
+    harvest::{self, Action, Harvest},
+    invoice::{self, Invoice},
+    iteration_totals, keyring,
+    mapping::{self, Mapping},
+    notify,
+    rates::{self, Rate},
+};
+use azure_devops_time_used::{
+    dates::{self, default_range, DateSpec, Period, WeekStart},
+    group_totals, iteration_condition,
+    output::{
+        render, render_flow, write_sink, DurationFormat, Format, Interval, RenderOptions, Sink,
+    },
+    tag_totals,
+    targets::{self, Targets},
+    wiql_any, wiql_in, AzdoClient, Backend, ClientOptions, Connection, Error, GroupBy,
+    HistorySource, Logged, Report, ReportQuery, Rollup, TagSplit, Tiebreak, User, CHANGED_BY,
+    CLOUD, COMPLETED_WORK, VERBOSE,
+};
+use chrono::{NaiveDate, Utc, Weekday};
+use clap::{parser::ValueSource, CommandFactory, Parser};
+use completions::Shell;
 use dotenvy::dotenv;
-use serde::Deserialize;
+use reqwest::Url;
 use serde_json::Value;
-use std::{collections::HashMap, fmt};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsString,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::atomic::Ordering,
+};
 use uuid::Uuid;
 
-#[derive(Debug, Deserialize)]
-struct WorkItem {
-    id: u64,
-    // url: String,
+mod completions;
+mod config;
+#[cfg(feature = "tui")]
+mod tui;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+/// Naïve utility to get time logs from Azure Devops
+///
+/// Playing with way more fun Rust features than needed
+struct Args {
+    /// First date to include
+    ///
+    /// A date, or relative to today as in -7d, -2w, today, yesterday or
+    /// monday. A week (2024-W12) or month (2024-03) starts on its first day.
+    #[arg(short, long, value_name = "DATE", allow_hyphen_values = true)]
+    from: Option<DateSpec>,
+
+    /// Last date to include, like --from; a week or month ends on its last day
+    #[arg(short, long, value_name = "DATE", allow_hyphen_values = true)]
+    to: Option<DateSpec>,
+
+    /// Report on a named period instead of --from and --to
+    #[arg(long, value_enum, conflicts_with_all = ["from", "to"])]
+    period: Option<Period>,
+
+    /// First day of the week, for the default range, the week periods and weekly sums
+    #[arg(long, value_enum, default_value_t = WeekStart::Mon)]
+    week_start: WeekStart,
+
+    /// Time zone days are counted in, e.g. Europe/Copenhagen; the system one by default
+    #[arg(long, value_name = "ZONE")]
+    timezone: Option<String>,
+
+    /// Email of user
+    ///
+    /// May be given several times or as a comma-separated list when the user
+    /// has more than one email; the first one signs in and the time of all of
+    /// them is reported as theirs
+    #[arg(short, long, env = "USERNAME", value_delimiter = ',')]
+    user: Vec<String>,
+
+    /// Report time logged as OLD as OLD's owner NEW, given as OLD=NEW
+    ///
+    /// May be given several times, or as an [aliases] table in the config file
+    #[arg(long, value_name = "OLD=NEW")]
+    alias: Vec<String>,
+
+    /// Azure DevOps personal access token
+    #[arg(long, env = "ACCESS_TOKEN")]
+    token: Option<String>,
+
+    /// How to sign in to Azure DevOps when --token isn't given
+    #[arg(long, value_enum, default_value_t = Auth::Pat)]
+    auth: Auth,
+
+    /// Azure AD tenant to sign in to with --auth device or sp, by id or domain
+    #[arg(long, env = "AZURE_TENANT_ID", default_value = "organizations")]
+    tenant: String,
+
+    /// Application (client) id to sign in as
+    ///
+    /// For --auth device the app signed in with, Visual Studio's by default;
+    /// for --auth sp the service principal; for --auth managed-identity a
+    /// user-assigned identity rather than the system-assigned one.
+    #[arg(long, value_name = "ID", env = "AZURE_CLIENT_ID")]
+    aad_client_id: Option<String>,
+
+    /// Client secret of the service principal for --auth sp
+    #[arg(long, env = "AZURE_CLIENT_SECRET", hide_env_values = true)]
+    client_secret: Option<String>,
+
+    /// File with a federated token for --auth sp, instead of a client secret
+    #[arg(long, value_name = "PATH", env = "AZURE_FEDERATED_TOKEN_FILE")]
+    federated_token_file: Option<PathBuf>,
+
+    /// Where --auth device keeps its tokens, next to the config file by default
+    #[arg(long, value_name = "PATH")]
+    token_cache: Option<PathBuf>,
+
+    /// Warn when the PAT expires within DAYS, 0 for never
+    #[arg(long, value_name = "DAYS", default_value_t = 7)]
+    token_expiry_warning: i64,
+
+    /// Azuee DevOps Organization, or the collection on --server-url
+    #[arg(short, long, env = "ORG", visible_alias = "collection")]
+    organization: Option<String>,
+
+    /// URL of an Azure DevOps Server or TFS to use instead of dev.azure.com,
+    /// up to where its collections are, e.g. https://tfs.example.com/tfs
+    #[arg(long, value_name = "URL", env = "SERVER_URL", value_parser = parse_server_url)]
+    server_url: Option<Url>,
+
+    /// The api-version to ask for instead of 7.1, as older servers need, e.g.
+    /// 7.0 for Azure DevOps Server 2022, 6.0 for 2020, 5.0 for 2019 or 4.1 for
+    /// TFS 2018
+    #[arg(long, value_name = "VERSION")]
+    api_version: Option<String>,
+
+    /// Azuee DevOps Project, by name or GUID
+    ///
+    /// May be given several times or as a comma-separated list, the projects'
+    /// hours are then added up into one report
+    #[arg(short, long, env = "PROJECT", value_delimiter = ',')]
+    project: Vec<String>,
+
+    /// Report on everyone who logged time in the range, not just --user
+    #[arg(long, conflicts_with = "team")]
+    all_users: bool,
+
+    /// Report on the members of this team of the (first) project
+    #[arg(long, value_name = "NAME")]
+    team: Option<String>,
+
+    /// Only look at work items under this area path
+    ///
+    /// May be given several times
+    #[arg(long, value_name = "PATH")]
+    area_path: Vec<String>,
+
+    /// Only look at work items in this iteration, by path or as a macro
+    /// such as @CurrentIteration
+    ///
+    /// May be given several times
+    #[arg(long, value_name = "PATH")]
+    iteration: Vec<String>,
+
+    /// Only look at work items with this tag
+    ///
+    /// May be given several times, items with any of the tags are kept
+    #[arg(long, value_name = "TAG")]
+    tag: Vec<String>,
+
+    /// Only look at work items of this type, e.g. Task
+    ///
+    /// May be given several times
+    #[arg(long = "type", value_name = "TYPE")]
+    work_item_type: Vec<String>,
+
+    /// WHERE clause to select work items by instead of the date range
+    ///
+    /// {from} and {to} are replaced by the range's date literals. Other
+    /// filters such as --type still apply.
+    #[arg(long, value_name = "CLAUSE")]
+    wiql: Option<String>,
+
+    /// Read the --wiql clause from a file
+    #[arg(long, value_name = "PATH", conflicts_with = "wiql")]
+    wiql_file: Option<PathBuf>,
+
+    /// Select work items with this saved query rather than by date range
+    ///
+    /// Only time logged within the range is counted
+    #[arg(
+        long,
+        value_name = "GUID",
+        conflicts_with_all = ["wiql", "wiql_file", "area_path", "iteration", "tag", "work_item_type"]
+    )]
+    query_id: Option<Uuid>,
+
+    /// Show each item's tags in the text output
+    #[arg(long)]
+    show_tags: bool,
+
+    /// Query the whole organization at once instead of given projects
+    ///
+    /// Every item is reported with the project it's in. Any --project is
+    /// ignored, so one set in the config file doesn't get in the way.
+    #[arg(long)]
+    all_projects: bool,
+
+    /// strftime format of the date literals sent in the WIQL query
+    ///
+    /// Literals with a time of day are sent with timePrecision enabled
+    #[arg(long, default_value = "%Y-%m-%dT%H:%M:%SZ")]
+    wiql_date_format: String,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Also write the report to a file, as FORMAT:PATH (e.g. json:report.json)
+    ///
+    /// May be given several times
+    #[arg(long, value_name = "FORMAT:PATH")]
+    also: Vec<Sink>,
+
+    /// Also write an HTML report to this file, same as --also html:PATH
+    #[arg(long, value_name = "PATH")]
+    html: Option<PathBuf>,
+
+    /// Also write an Excel workbook to this file, same as --also xlsx:PATH
+    #[arg(long, value_name = "PATH")]
+    xlsx: Option<PathBuf>,
+
+    /// Hours expected on a workday, to show how far each day is off
+    #[arg(long, value_name = "HOURS")]
+    target_hours_per_day: Option<f64>,
+
+    /// Hours expected on a weekday instead, as DAY=HOURS, e.g. fri=6 or sat=4
+    ///
+    /// Saturday and Sunday expect no hours unless given here.
+    #[arg(
+        long,
+        value_name = "DAY=HOURS",
+        value_delimiter = ',',
+        value_parser = targets::parse_weekday_hours
+    )]
+    target_hours: Vec<(Weekday, f64)>,
+
+    /// Day off expecting no hours, as DATE or FIRST..LAST for a vacation
+    #[arg(
+        long,
+        value_name = "DATE",
+        value_delimiter = ',',
+        value_parser = targets::parse_days_off
+    )]
+    holiday: Vec<(NaiveDate, NaiveDate)>,
+
+    /// Calendar (.ics) whose all-day events are days off, e.g. public holidays
+    #[arg(long, value_name = "PATH")]
+    holidays_ics: Vec<PathBuf>,
+
+    /// Hours each workday needs for --format junit to pass it
+    #[arg(long, value_name = "HOURS", default_value_t = 8.0)]
+    expected_min: f64,
+
+    /// How hours are written
+    #[arg(long, value_enum, default_value_t = DurationFormat::Decimal)]
+    duration_format: DurationFormat,
+
+    /// Ask the server for items changed in the range exactly, without the day
+    /// either side
+    ///
+    /// Needs the precise WIQL date literals, see --wiql-date-format
+    #[arg(long)]
+    strict_range_server: bool,
+
+    /// Proxy for all requests, credentials may be embedded in the URL
+    #[arg(long, env = "HTTPS_PROXY", hide_env_values = true)]
+    proxy: Option<String>,
+
+    /// User name for the proxy
+    #[arg(long, env = "PROXY_USER", requires = "proxy_pass")]
+    proxy_user: Option<String>,
+
+    /// Password for the proxy
+    #[arg(long, env = "PROXY_PASS", hide_env_values = true)]
+    proxy_pass: Option<String>,
+
+    /// Hosts to reach without the proxy, comma-separated, e.g.
+    /// localhost,.internal.example.com
+    #[arg(long, value_name = "HOSTS", env = "NO_PROXY")]
+    no_proxy: Option<String>,
+
+    /// PEM file with root certificates to trust besides the system's, such as
+    /// a corporate proxy's or an on-premises server's
+    #[arg(long, value_name = "PATH")]
+    ca_cert: Option<PathBuf>,
+
+    /// Accept any TLS certificate, even invalid or self-signed ones
+    ///
+    /// Anyone in between can then read the token, so only for trying things out.
+    #[arg(long)]
+    insecure: bool,
+
+    /// Estimate the number of requests and the run time, then ask before continuing
+    ///
+    /// Only the work item query is run for it, and one request to time.
+    #[arg(long)]
+    plan: bool,
+
+    /// Print the estimate --plan makes and stop there, without fetching any history
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Don't ask for confirmation
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Exit with the rounded total hours as the exit code, 0 to 249
+    ///
+    /// More hours exit with 249, with a warning. Codes 250 to 255 are kept for
+    /// failures so they can't be taken for hours: 250 for a command line that
+    /// can't be parsed, 255 for any other error.
+    #[arg(long)]
+    exit_code_hours: bool,
+
+    /// Exit with code 3 when less than HOURS were logged in the range
+    ///
+    /// Not 2, which is what a command line that can't be parsed exits with, so
+    /// a broken job isn't taken for missing hours; errors exit with 1.
+    #[arg(long, value_name = "HOURS", conflicts_with = "exit_code_hours")]
+    fail_under: Option<f64>,
+
+    /// Keep running, and print the daily totals again whenever they change
+    ///
+    /// Polls every SECONDS, 60 when left out, until interrupted. Only the
+    /// items changed since the last poll have their history fetched again.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        num_args = 0..=1,
+        default_missing_value = "60",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["plan", "dry_run", "exit_code_hours", "fail_under", "offline"]
+    )]
+    watch: Option<u64>,
+
+    /// Times to retry a request that was throttled, hit a server error or lost
+    /// its connection, waiting longer each time
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    max_retries: u32,
+
+    /// Redirects to follow before giving up
+    #[arg(long, default_value_t = 10)]
+    max_redirects: usize,
+
+    /// Print extra diagnostics to stderr
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Read default settings from this file
+    ///
+    /// Defaults to ~/.config/azure-devops-time-used/config.toml. Flags and
+    /// environment variables override what's in the file.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Use the settings in the config file's [profiles.NAME] table
+    ///
+    /// They take precedence over the file's top-level settings. Give several
+    /// profiles to add up the hours from each of their organizations; all other
+    /// settings come from the first.
+    #[arg(long, value_name = "NAME", value_delimiter = ',')]
+    profile: Vec<String>,
+
+    /// Organizations of the profiles after the first
+    #[arg(skip)]
+    accounts: Vec<Account>,
+
+    /// Sum the hours per day, week or month in the summary
+    #[arg(long, value_enum, default_value_t = Interval::Day)]
+    bucket: Interval,
+
+    /// Where work items and their history come from
+    ///
+    /// The Analytics service can't run WIQL, so the query narrowing flags
+    /// don't go with odata.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Backend::Rest,
+        conflicts_with_all = ["wiql", "wiql_file", "query_id", "area_path", "iteration", "tag", "work_item_type"]
+    )]
+    backend: Backend,
+
+    /// Where the history of each work item is read from
+    #[arg(long, value_enum, default_value_t = HistorySource::Updates)]
+    history_source: HistorySource,
+
+    /// Keep fetched revisions in this file and only fetch items changed since
+    #[arg(long, value_name = "PATH")]
+    cache: Option<PathBuf>,
+
+    /// Report from --cache alone, without talking to Azure DevOps
+    ///
+    /// Every cached item is looked at, so the query narrowing flags don't go
+    /// with it, and neither do those needing more requests.
+    #[arg(
+        long,
+        requires = "cache",
+        conflicts_with_all = [
+            "wiql", "wiql_file", "query_id", "area_path", "iteration", "tag", "work_item_type",
+            "team", "plan", "dry_run", "rollup",
+        ]
+    )]
+    offline: bool,
+
+    /// Fetch the revisions of this many work items at a time
+    #[arg(long, value_name = "N", default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: u16,
+
+    /// Split the range into N equal buckets and report the total per bucket
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    buckets: Option<u32>,
+
+    /// Show several saves of an item on the same day as one line
+    #[arg(long)]
+    merge_same_day: bool,
+
+    /// Identity field to attribute time to and filter --user on
+    #[arg(long, value_name = "REFNAME", default_value = CHANGED_BY)]
+    attribute_field: String,
+
+    /// Numeric field the time is tracked in, for processes with one of their
+    /// own such as Custom.LoggedHours
+    ///
+    /// Its increases are the time logged, and log and correct add to it.
+    #[arg(long, value_name = "REFNAME", default_value = COMPLETED_WORK)]
+    field: String,
+
+    /// Also sum up the changes of another numeric field per item and day,
+    /// such as Microsoft.VSTS.Scheduling.RemainingWork for a burn-up
+    ///
+    /// May be given several times. Items where only these changed are
+    /// reported too, with no time.
+    #[arg(long, value_name = "REFNAME")]
+    track: Vec<String>,
+
+    /// Also total the hours along other axes
+    ///
+    /// Several axes, as in user,date, nest the totals in that order
+    #[arg(long, value_enum, value_delimiter = ',')]
+    group_by: Vec<GroupBy>,
+
+    /// Also total the hours per parent, Feature or Epic of the items
+    #[arg(long, value_enum)]
+    rollup: Option<Rollup>,
+
+    /// How --group-by tag spreads an item's hours over its tags
+    #[arg(long, value_enum, default_value_t = TagSplit::Duplicate)]
+    tag_split: TagSplit,
+
+    /// Show the top N contributors per item and overall
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "5")]
+    top_contributors: Option<usize>,
+
+    /// Sum up users under --minor-threshold hours as one "Others" entry, in the
+    /// top contributors, the by-user summary and the groups by user
+    #[arg(long)]
+    collapse_users_under_threshold: bool,
+
+    /// Hours below which a contributor counts as minor
+    #[arg(long, value_name = "HOURS", default_value_t = 1.0)]
+    minor_threshold: f64,
+
+    /// Show how far along each item is, from CompletedWork and RemainingWork
+    #[arg(long)]
+    completion_ratio: bool,
+
+    /// Show each item's original estimate, remaining and completed work, and
+    /// how far the work is off the estimate
+    #[arg(long)]
+    estimates: bool,
+
+    /// Mark hours on items that several users logged time on as approximate
+    #[arg(long)]
+    flag_ambiguous: bool,
+
+    /// CSV of work item id and known completed work at the start of the range
+    ///
+    /// Overrides the carry-over computed from the item's history
+    #[arg(long, value_name = "CSV")]
+    baseline_file: Option<PathBuf>,
+
+    /// Only look at N randomly picked work items, for spot checks
+    ///
+    /// Totals only cover the sampled items
+    #[arg(long, value_name = "N")]
+    sample: Option<usize>,
+
+    /// Seed for --sample, so the same items are picked again
+    #[arg(long, requires = "sample")]
+    seed: Option<u64>,
+
+    /// How to order revisions with identical timestamps
+    #[arg(long, value_enum, default_value_t = Tiebreak::Rev)]
+    tiebreak: Tiebreak,
+
+    /// Deprecated and ignored, titles come with the histories that are read
+    /// anyway
+    #[arg(long, hide = true)]
+    lazy_titles: bool,
+
+    /// Deprecated and ignored, every item's complete revision history is
+    /// always read
+    #[arg(long, hide = true)]
+    recompute_diffs_from_full_history: bool,
+
+    /// Only count time logged at or after the item entered this state
+    #[arg(long, value_name = "STATE")]
+    since_state_change: Option<String>,
+
+    /// Also post a summary with the daily totals to this Slack incoming webhook
+    ///
+    /// Days short of --target-hours-per-day are pointed out.
+    #[arg(
+        long,
+        value_name = "URL",
+        env = "SLACK_WEBHOOK",
+        hide_env_values = true
+    )]
+    slack_webhook: Option<String>,
+
+    /// Also post the summary, as with --slack-webhook, to this Teams webhook
+    ///
+    /// With several users, as with --team, their totals are added.
+    #[arg(
+        long,
+        value_name = "URL",
+        env = "TEAMS_WEBHOOK",
+        hide_env_values = true
+    )]
+    teams_webhook: Option<String>,
+
+    /// Also mail the report, as text and HTML, to these addresses
+    #[cfg(feature = "email")]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        value_delimiter = ',',
+        requires = "smtp_host"
+    )]
+    email_to: Vec<String>,
+
+    /// Sender of the mail, --user when left out
+    #[cfg(feature = "email")]
+    #[arg(long, value_name = "ADDRESS")]
+    email_from: Option<String>,
+
+    /// SMTP server to send the mail through
+    #[cfg(feature = "email")]
+    #[arg(long, value_name = "HOST", env = "SMTP_HOST")]
+    smtp_host: Option<String>,
+
+    /// Port of the SMTP server, 587, 465 or 25 depending on --smtp-security
+    #[cfg(feature = "email")]
+    #[arg(long, env = "SMTP_PORT")]
+    smtp_port: Option<u16>,
+
+    /// How the connection to the SMTP server is secured
+    #[cfg(feature = "email")]
+    #[arg(long, value_enum, default_value_t = mail::Security::Starttls)]
+    smtp_security: mail::Security,
+
+    /// User to log in to the SMTP server as, if it wants a login
+    #[cfg(feature = "email")]
+    #[arg(long, env = "SMTP_USER", requires = "smtp_password")]
+    smtp_user: Option<String>,
+
+    /// Password for --smtp-user
+    #[cfg(feature = "email")]
+    #[arg(long, env = "SMTP_PASSWORD", hide_env_values = true)]
+    smtp_password: Option<String>,
+
+    /// Hourly rate for some of the time, as KEY=RATE, to add billable amounts
+    ///
+    /// The key is a work item (#1234), a tag (tag:Support), a project, a user's
+    /// email or * for the rest; the most specific of those applies. May be
+    /// given several times, or as a [rates] table in the config file.
+    #[arg(long, value_name = "KEY=RATE")]
+    rate: Vec<Rate>,
+
+    /// Currency the rates are in
+    #[arg(long, value_name = "CODE", default_value = "EUR")]
+    currency: String,
+
+    /// A line of the sender's name and address on an `invoice`
+    ///
+    /// May be given several times, or as an array in the config file.
+    #[arg(long, value_name = "LINE")]
+    bill_from: Vec<String>,
+
+    /// A line of the client's name and address on an `invoice`
+    ///
+    /// May be given several times, or as an array in the config file, such as
+    /// in the client's profile.
+    #[arg(long, value_name = "LINE")]
+    bill_to: Vec<String>,
+
+    /// Client some work items are for, as KEY=NAME, to add totals per client
+    ///
+    /// The key is a work item (#1234), a tag (tag:Acme), an area path
+    /// (area:Website\Acme, taking in the areas under it), an Azure DevOps
+    /// project or * for the rest, tried in that order. May be given several
+    /// times, or as a [clients] table in the config file.
+    #[arg(long, value_name = "KEY=NAME")]
+    client: Vec<Mapping<String>>,
+
+    /// Harvest account to `push harvest` to
+    #[arg(long, env = "HARVEST_ACCOUNT_ID")]
+    harvest_account_id: Option<String>,
+
+    /// Harvest personal access token for `push harvest`
+    #[arg(long, env = "HARVEST_TOKEN", hide_env_values = true)]
+    harvest_token: Option<String>,
+
+    /// Harvest project and task for some work items, as KEY=PROJECT_ID/TASK_ID
+    ///
+    /// The key is a work item (#1234), a tag (tag:Support), an area path
+    /// (area:Website\Shop), an Azure DevOps project or * for the rest, tried
+    /// in that order. May be given several times, or as a [harvest] table in
+    /// the config file.
+    #[arg(long, value_name = "KEY=PROJECT_ID/TASK_ID")]
+    harvest_map: Vec<Mapping<harvest::Task>>,
+
+    /// Jira issue for some work items in --format tempo, as KEY=ISSUE
+    ///
+    /// The key is a work item (#1234), a tag (tag:Support), an area path
+    /// (area:Website\Shop), an Azure DevOps project or * for the rest, tried
+    /// in that order. The issue is its id, or its key (OPS-12) for
+    /// Tempo API versions before 4. May be given several times, or as a
+    /// [tempo-issues] table in the config file.
+    #[arg(long, value_name = "KEY=ISSUE")]
+    tempo_issue: Vec<Mapping<String>>,
+
+    /// Atlassian account id of a user in --format tempo, as EMAIL=ACCOUNT_ID
+    ///
+    /// May be given several times, or as a [tempo-accounts] table in the
+    /// config file.
+    #[arg(long, value_name = "EMAIL=ACCOUNT_ID")]
+    tempo_account: Vec<String>,
+
+    /// Record the reported time in a local SQLite store for `trend`
+    #[cfg(feature = "store")]
+    #[arg(long, value_name = "PATH")]
+    store: Option<PathBuf>,
+
+    /// What to do, `report` when left out
+    ///
+    /// Flags go before it, as in `--period last-week export json:week.json`
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Print the report, as running without a subcommand does
+    Report,
+
+    /// Write the report to files only, printing nothing
+    Export {
+        /// Where to write it, as with --also
+        #[arg(value_name = "FORMAT:PATH", required = true)]
+        sinks: Vec<Sink>,
+    },
+
+    /// Fetch the history of the items changed since into --cache, printing nothing
+    Sync,
+
+    /// Check that the token works for every organization and project
+    Check,
+
+    /// Log time on a work item by adding to its CompletedWork
+    Log {
+        /// Work item id
+        #[arg(long)]
+        item: u64,
+
+        /// Hours to add, negative to take some off
+        #[arg(long, allow_hyphen_values = true)]
+        hours: f64,
+
+        /// Day to log the time on, today by default
+        ///
+        /// Other days backdate the revision, which needs the "Bypass rules on
+        /// work item updates" permission
+        #[arg(long, allow_hyphen_values = true)]
+        date: Option<DateSpec>,
+
+        /// Take the hours off RemainingWork too, down to 0
+        #[arg(long)]
+        remaining: bool,
+    },
+
+    /// Print the report, then ask for days to correct and log the difference
+    ///
+    /// Each correction is a work item, a day and the hours it should have, as
+    /// in `1234 2024-05-02 1.5`. An empty line or end of input finishes.
+    Correct,
+
+    /// Print how long the items changed in the range spent in each state, and
+    /// the lead and cycle time of those finished in it
+    ///
+    /// Covers everyone's items, not only --user's. Lead time runs from
+    /// creation, cycle time from first leaving the initial state, to entering
+    /// a done state.
+    CycleTime {
+        /// States items are finished in
+        #[arg(long, value_name = "STATE", default_values = ["Closed", "Done"])]
+        done_state: Vec<String>,
+    },
+
+    /// Keep the personal access token for --organization in the OS keyring
+    ///
+    /// Runs without --token use the one kept for their organization.
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+
+    /// Copy the reported time to another time tracker, printing what changed
+    Push {
+        #[command(subcommand)]
+        service: Service,
+    },
+
+    /// Write an invoice for the billable time as a PDF, see --rate and --bill-to
+    ///
+    /// Each work item with billable time is a line, or what items roll up to
+    /// with --rollup.
+    Invoice {
+        /// Where to write the PDF
+        #[arg(long, value_name = "PATH")]
+        output: PathBuf,
+
+        /// Invoice number to print
+        #[arg(long)]
+        number: Option<String>,
+
+        /// Date of the invoice, today by default
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+
+    /// Browse the report interactively in the terminal
+    #[cfg(feature = "tui")]
+    Tui,
+
+    /// Print a completion script for the shell
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// Serve the report as JSON over HTTP
+    #[cfg(feature = "serve")]
+    Serve {
+        /// Address to listen on, only this machine by default
+        ///
+        /// The server has no authentication and answers with everyone's hours,
+        /// so 0.0.0.0 puts them on the network for anyone to read.
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: std::net::IpAddr,
+
+        /// Port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+
+        /// Seconds to keep a computed report before asking Azure DevOps again
+        #[arg(long, default_value_t = 300)]
+        cache_ttl: u64,
+    },
+
+    /// Show daily totals from a local store, without asking Azure DevOps
+    #[cfg(feature = "store")]
+    Trend {
+        /// Store written by earlier runs with --store
+        #[arg(long)]
+        store: PathBuf,
+
+        /// First date to include
+        #[arg(long)]
+        since: Option<NaiveDate>,
+    },
+}
+
+/// How the token for Azure DevOps is gotten
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Auth {
+    /// A personal access token, from --token
+    Pat,
+    /// Signing in to Azure AD with a code entered in a browser, once until the
+    /// cached tokens run out
+    Device,
+    /// As a service principal, see --aad-client-id and --client-secret
+    Sp,
+    /// As the managed identity of the Azure VM, App Service or container
+    ManagedIdentity,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum AuthAction {
+    /// Ask for the token and store it, replacing the one there was
+    Login,
+    /// Remove the stored token
+    Logout,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct WorkItemQueryResult {
-    work_items: Vec<WorkItem>,
+/// Where `push` copies the time to
+#[derive(clap::Subcommand, Debug)]
+enum Service {
+    /// Harvest, with a time entry per work item and day, see --harvest-map
+    ///
+    /// Entries pushed before are updated or removed to match, so pushing the
+    /// same range again doesn't add the time twice.
+    Harvest {
+        /// Only print what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
-#[derive(Deserialize)]
-struct User {
-    id: Uuid,
-    #[serde(rename = "displayName")]
-    display_name: String,
-    #[serde(rename = "uniqueName")]
-    email: String,
+/// Connection settings of an additional `--profile`
+#[derive(Debug, Clone, Default)]
+struct Account {
+    server_url: Option<Url>,
+    organization: Option<String>,
+    project: Vec<String>,
+    user: Option<String>,
+    token: Option<String>,
 }
 
-impl fmt::Display for User {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} <{}>", self.display_name, self.email)
+/// What the keyring files a token under: the organization, or a server's
+/// collection with the server's URL
+fn keyring_account(server: Option<&Url>, organization: &str) -> String {
+    match server {
+        Some(server) if server.as_str() != CLOUD => format!("{}{}", server, organization),
+        _ => organization.to_string(),
     }
 }
 
-impl fmt::Debug for User {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("User")
-            .field("id", &self.id)
-            .field("display_name", &self.display_name)
-            .field("email", &self.email)
-            .finish()
+/// A server URL, which the collection is added to, so with a slash at the end
+fn parse_server_url(url: &str) -> Result<Url, String> {
+    let mut url = Url::parse(url).map_err(|error| error.to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("not an http(s) URL: {}", url));
     }
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    Ok(url)
 }
 
-#[derive(Debug, Deserialize)]
-struct Fields {
-    #[serde(rename = "System.ChangedDate")]
-    // changed_date: Option<DateTime<Utc>>,
-    changed_date: DateTime<Utc>,
-    #[serde(rename = "System.ChangedBy")]
-    changed_by: User,
-    #[serde(rename = "Microsoft.VSTS.Scheduling.CompletedWork")]
-    completed_work: Option<f64>,
-    #[serde(rename = "System.Title")]
-    title: Option<String>,
+/// Read `id,completed_work` lines, skipping blank lines, `#` comments and a header
+fn read_baselines(path: &Path) -> Result<HashMap<u64, f64>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let mut baselines = HashMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = line
+            .split_once(',')
+            .and_then(|(id, hours)| Some((id.trim().parse().ok()?, hours.trim().parse().ok()?)));
+        match parsed {
+            Some((id, hours)) => {
+                baselines.insert(id, hours);
+            }
+            None if number == 0 => continue,
+            None => return Err(format!("line {}: expected id,completed_work", number + 1)),
+        }
+    }
+    Ok(baselines)
 }
 
-#[derive(Debug, Deserialize)]
-struct Revision {
-    // id: u32,
-    #[allow(dead_code)]
-    rev: u32,
-    fields: Fields,
+/// Daily totals of the stored records, narrowed by whichever of user,
+/// organization and projects are given
+#[cfg(feature = "store")]
+fn print_trend(records: &[store::Record], args: &Args) {
+    let matches = |wanted: &[String], value: &str| {
+        wanted.is_empty()
+            || wanted
+                .iter()
+                .any(|wanted| wanted.eq_ignore_ascii_case(value))
+    };
+    let mut sums: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+    for record in records.iter().filter(|record| {
+        matches(&args.user, &record.user)
+            && matches(args.organization.as_slice(), &record.organization)
+            && matches(&args.project, &record.project)
+    }) {
+        *sums.entry(record.date).or_default() += record.hours;
+    }
+    for (date, hours) in &sums {
+        println!("{} {}", date, hours);
+    }
+    println!("Total {}", sums.values().sum::<f64>());
 }
 
-#[derive(Debug, Deserialize)]
-struct Revisions {
-    #[allow(dead_code)]
-    count: u32,
-    value: Vec<Revision>,
+/// Read a line without showing it, when on a terminal that can hide it
+fn read_secret(prompt: &str) -> io::Result<String> {
+    eprint!("{}", prompt);
+    let stty = |setting: &str| {
+        std::process::Command::new("stty")
+            .arg(setting)
+            .stdin(std::process::Stdio::inherit())
+            .status()
+            .is_ok_and(|status| status.success())
+    };
+    let hidden = cfg!(unix) && io::stdin().is_terminal() && stty("-echo");
+    let mut line = String::new();
+    let read = io::stdin().read_line(&mut line);
+    if hidden {
+        stty("echo");
+        eprintln!();
+    }
+    read?;
+    Ok(line.trim().to_string())
 }
 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-/// Naïve utility to get time logs from Azure Devops
-///
-/// Playing with way more fun Rust features than needed
-struct Args {
-    /// First date to include
-    #[arg(short, long)]
-    from: Option<NaiveDate>,
+/// Ask a yes/no question on the terminal, defaulting to no
+fn confirm(question: &str) -> io::Result<bool> {
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
 
-    /// Last date to include
-    #[arg(short, long)]
-    to: Option<NaiveDate>,
+fn main() -> ExitCode {
+    // The .env file is optional, everything can come from flags, the
+    // environment or the config file; only a broken one is worth a word
+    if let Err(error) = dotenv() {
+        if !error.not_found() {
+            eprintln!("Warning: ignoring .env: {}", error);
+        }
+    }
 
-    /// Email of user
-    #[arg(short, long, env = "USERNAME")]
-    user: String,
+    let args = match parse_args(std::env::args_os().collect()) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            return if exit_code_hours_given() {
+                ExitCode::from(EXIT_FAILURE)
+            } else {
+                ExitCode::FAILURE
+            };
+        }
+    };
+    // eprintln!("{:#?}", args);
+    VERBOSE.store(args.verbose, Ordering::Relaxed);
 
-    /// Azure DevOps personal access token
-    #[arg(long, env = "ACCESS_TOKEN")]
-    token: String,
+    let failure = if args.exit_code_hours {
+        EXIT_FAILURE
+    } else {
+        1
+    };
+    // Before the runtime starts its threads, which mustn't see TZ change
+    let started = match &args.timezone {
+        Some(timezone) => dates::set_timezone(timezone).map_err(Error::from),
+        None => Ok(()),
+    };
+    let result = started.and_then(|()| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?
+            .block_on(run(args))
+    });
+    match result {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::from(failure)
+        }
+    }
+}
+
+/// With --exit-code-hours, the most hours an exit code tells; the codes above
+/// it are failures
+const MAX_EXIT_HOURS: u8 = 249;
+
+/// With --exit-code-hours, the exit code for a command line that can't be parsed
+const EXIT_USAGE: u8 = 250;
 
-    /// Azuee DevOps Organization
-    #[arg(short, long, env = "ORG")]
-    organization: String,
+/// With --exit-code-hours, the exit code for any other failure
+const EXIT_FAILURE: u8 = 255;
 
-    /// Azuee DevOps Project
-    #[arg(short, long, env = "PROJECT")]
-    project: String,
+/// With --fail-under, the exit code for less time logged than asked for; clap
+/// exits with 2 on a command line it turns down
+const EXIT_UNDER: u8 = 3;
+
+/// The exit code telling the total hours, and a warning when they don't fit
+fn hours_exit_code(total: f64) -> (u8, Option<String>) {
+    let rounded = total.round();
+    let code = rounded.clamp(0.0, f64::from(MAX_EXIT_HOURS)) as u8;
+    let warning = (rounded != f64::from(code)).then(|| {
+        format!(
+            "{} hours don't fit in an exit code, exiting with {}",
+            total, code
+        )
+    });
+    (code, warning)
 }
 
-#[tokio::main]
-async fn main() -> Result<(), reqwest::Error> {
-    dotenv().unwrap();
+/// Whether --exit-code-hours is on the command line, for failures before
+/// it's parsed
+fn exit_code_hours_given() -> bool {
+    std::env::args_os().any(|arg| arg == "--exit-code-hours")
+}
 
-    // Find dates
-    let now = Utc::now();
-    let today = now.date_naive();
-    let week = today.week(Weekday::Mon);
+/// Exit for a command line clap turns down, with a code in the reserved range
+/// under --exit-code-hours rather than clap's 2
+fn usage_error(error: clap::Error) -> ! {
+    if error.use_stderr() && exit_code_hours_given() {
+        let _ = error.print();
+        std::process::exit(EXIT_USAGE.into());
+    }
+    error.exit()
+}
 
-    let args = Args::parse();
-    // eprintln!("{:#?}", args);
+/// The command line, with settings from the config file filling in for flags
+/// that weren't given on the command line or through the environment
+fn parse_args(argv: Vec<OsString>) -> Result<Args, String> {
+    let matches = Args::command()
+        .try_get_matches_from(&argv)
+        .unwrap_or_else(|error| usage_error(error));
+    let mut profiles: Vec<String> = matches
+        .get_many::<String>("profile")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let Some(config) = config::load(matches.get_one::<PathBuf>("config").map(PathBuf::as_path))?
+    else {
+        return match profiles.first() {
+            Some(name) => Err(format!("--profile {} needs a config file", name)),
+            None => Ok(Args::try_parse_from(argv).unwrap_or_else(|error| usage_error(error))),
+        };
+    };
 
-    let from = args.from.unwrap_or(week.first_day());
-    let to = args.to.unwrap_or(week.last_day());
+    // A top-level `profile` setting picks the profiles used when none are given
+    let mut table = config.root.clone();
+    if let Some(default) = table.remove("profile") {
+        if profiles.is_empty() {
+            profiles = strings(&default);
+        }
+    }
+    let profile = |name: &String| {
+        config
+            .tables
+            .get(&format!("profiles.{}", name))
+            .ok_or_else(|| format!("{}: no [profiles.{}]", config.path.display(), name))
+    };
+    if let Some(name) = profiles.first() {
+        table.extend(profile(name)?.clone());
+    }
+    let mut accounts = Vec::new();
+    for name in profiles.iter().skip(1) {
+        // Whatever the profile leaves out comes from the top-level settings
+        let settings = profile(name)?;
+        let setting = |key: &str| settings.get(key).or_else(|| config.root.get(key));
+        let server_url = setting("server-url")
+            .or_else(|| setting("server_url"))
+            .map(|url| parse_server_url(&url.to_string()))
+            .transpose()
+            .map_err(|error| format!("{}: server-url: {}", config.path.display(), error))?;
+        accounts.push(Account {
+            server_url,
+            organization: setting("organization").map(config::Value::to_string),
+            project: setting("project").map(strings).unwrap_or_default(),
+            user: setting("user").map(config::Value::to_string),
+            token: setting("token").map(config::Value::to_string),
+        });
+    }
 
-    eprintln!("From {} to {}", from, to);
+    let command = Args::command();
+    let mut settings = Vec::new();
+    for (key, value) in &table {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key.replace('_', "-").as_str()))
+            .ok_or_else(|| format!("{}: unknown setting {}", config.path.display(), key))?;
+        if matches!(
+            matches.value_source(arg.get_id().as_str()),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
 
-    let user = args.user;
-    let token = args.token;
-    let organization = args.organization;
-    let project = args.project;
+        let flag = format!("--{}", arg.get_long().unwrap());
+        let values = match value {
+            config::Value::Array(values) => values.clone(),
+            value => vec![value.clone()],
+        };
+        for value in values {
+            match value {
+                // Switches are given or not, rather than taking a value
+                config::Value::Boolean(on) if !arg.get_action().takes_values() => {
+                    if on {
+                        settings.push(flag.clone().into());
+                    }
+                }
+                value => {
+                    settings.push(flag.clone().into());
+                    settings.push(value.to_string().into());
+                }
+            }
+        }
+    }
 
-    let mut map = HashMap::new();
-    map.insert(
-        "query".to_string(), 
-        format!("SELECT [System.Id] FROM workitems WHERE [System.ChangedDate] >= '{from}' AND [System.ChangedDate] <= '{to}' ORDER BY [System.ChangedDate] DESC")
-    );
-    let client = reqwest::Client::new();
-    let query_result: WorkItemQueryResult = client
-        .post(format!(
-            "https://dev.azure.com/{}/{}/_apis/wit/wiql?api-version=5.1",
-            organization, project
-        ))
-        .basic_auth(&user, Some(&token))
-        .json(&map)
-        .send()
-        .await?
-        .json()
-        .await?;
+    // Aliases from the file add to those on the command line
+    for (old, new) in config.tables.get("aliases").into_iter().flatten() {
+        settings.push("--alias".into());
+        settings.push(format!("{}={}", old, new).into());
+    }
 
-    let mut sums: std::collections::BTreeMap<NaiveDate, f64> = std::collections::BTreeMap::new();
-    for work_item in query_result.work_items.into_iter() {
-        let revisions: Revisions = client
-            .get(format!(
-                "https://dev.azure.com/{}/{}/_apis/wit/workItems/{}/revisions?api-version=5.0",
-                organization, project, work_item.id
-            ))
-            .basic_auth(&user, Some(&token))
-            .send()
-            .await?
-            .json()
-            .await?;
+    for (key, ids) in config.tables.get("harvest").into_iter().flatten() {
+        settings.push("--harvest-map".into());
+        settings.push(format!("{}={}", key, ids).into());
+    }
 
-        let mut printed_header = false;
-        let mut last_completed_work: f64 = 0.0;
-        for revision in revisions.value.into_iter() {
-            if let Some(completed_work) = revision.fields.completed_work {
-                let diff = completed_work - last_completed_work;
-                last_completed_work = completed_work;
+    for (email, account) in config.tables.get("tempo-accounts").into_iter().flatten() {
+        settings.push("--tempo-account".into());
+        settings.push(format!("{}={}", email, account).into());
+    }
+    for (key, issue) in config.tables.get("tempo-issues").into_iter().flatten() {
+        settings.push("--tempo-issue".into());
+        settings.push(format!("{}={}", key, issue).into());
+    }
 
-                if diff == 0.0 {
-                    continue;
-                };
+    for (key, client) in config.tables.get("clients").into_iter().flatten() {
+        settings.push("--client".into());
+        settings.push(format!("{}={}", key, client).into());
+    }
 
-                if revision.fields.changed_by.email != user {
-                    continue;
-                }
+    for (key, rate) in config.tables.get("rates").into_iter().flatten() {
+        settings.push("--rate".into());
+        settings.push(format!("{}={}", key, rate).into());
+    }
 
-                let date = revision.fields.changed_date.date_naive();
-                if date < from || date > to {
-                    continue;
+    // Settings go first so a subcommand and its arguments stay last
+    let mut argv = argv.into_iter();
+    let args = argv.next().into_iter().chain(settings).chain(argv);
+    Ok(Args {
+        accounts,
+        ..Args::try_parse_from(args).unwrap_or_else(|error| usage_error(error))
+    })
+}
+
+/// A setting that may be a single or comma-separated string, or an array
+fn strings(value: &config::Value) -> Vec<String> {
+    match value {
+        config::Value::Array(values) => values.iter().flat_map(strings).collect(),
+        value => value
+            .to_string()
+            .split(',')
+            .map(|part| part.trim().to_string())
+            .collect(),
+    }
+}
+
+async fn run(args: Args) -> Result<ExitCode, Error> {
+    if let Some(Command::Completions { shell }) = args.command {
+        let mut command = Args::command();
+        command.build();
+        completions::write(&mut io::stdout().lock(), shell, &command)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    #[cfg(feature = "store")]
+    if let Some(Command::Trend { store, since }) = &args.command {
+        print_trend(&store::read(store, *since)?, &args);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Only talking to Azure DevOps needs these, so they're checked here
+    // rather than by clap for every subcommand
+    let required = |value: Option<String>, flag: &str, env: &str| {
+        value.ok_or_else(|| format!("missing {} (or set {})", flag, env))
+    };
+    if let Some(Command::Auth { action }) = &args.command {
+        let organization = keyring_account(
+            args.server_url.as_ref(),
+            &required(args.organization.clone(), "--organization", "ORG")?,
+        );
+        match action {
+            AuthAction::Login => {
+                let token = read_secret(&format!("Personal access token for {}: ", organization))?;
+                if token.is_empty() {
+                    return Err("no token given".into());
                 }
+                keyring::set(&organization, &token)?;
+                eprintln!("Stored the token for {} in the keyring", organization);
+            }
+            AuthAction::Logout => {
+                if keyring::delete(&organization)? {
+                    eprintln!("Removed the token for {} from the keyring", organization);
+                } else {
+                    eprintln!("No token for {} in the keyring", organization);
+                }
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
 
-                if !printed_header {
-                    println!(
-                        "{} {}",
-                        work_item.id,
-                        revision.fields.title.unwrap_or("".to_string())
-                    );
-                    printed_header = true
+    let user = required(args.user.first().cloned(), "--user", "USERNAME")?;
+    match args.command {
+        Some(Command::Check | Command::Sync | Command::Log { .. } | Command::Correct)
+            if args.offline =>
+        {
+            return Err("check, sync, log and correct need Azure DevOps, not --offline".into());
+        }
+        Some(Command::Sync) if args.cache.is_none() => {
+            return Err("sync needs --cache".into());
+        }
+        Some(Command::Invoice { .. }) if args.rate.is_empty() => {
+            return Err("invoice needs --rate for the time to bill".into());
+        }
+        Some(ref command) if args.watch.is_some() && !matches!(command, Command::Report) => {
+            return Err("--watch only goes with report".into());
+        }
+        _ => {}
+    }
+
+    // Other emails of the user, including those signing in to other profiles
+    let mut aliases: HashMap<String, String> = HashMap::new();
+    for alias in &args.alias {
+        let (old, new) = alias
+            .split_once('=')
+            .ok_or_else(|| format!("expected --alias OLD=NEW, got {:?}", alias))?;
+        aliases.insert(old.trim().to_lowercase(), new.trim().to_string());
+    }
+    let other_emails = args.user[1..].iter().chain(
+        args.accounts
+            .iter()
+            .filter_map(|account| account.user.as_ref()),
+    );
+    for email in other_emails {
+        aliases
+            .entry(email.to_lowercase())
+            .or_insert_with(|| user.clone());
+    }
+    aliases.remove(&user.to_lowercase());
+
+    let primary = Account {
+        server_url: args.server_url,
+        organization: args.organization,
+        project: args.project,
+        user: Some(user.clone()),
+        token: args.token,
+    };
+    let client_options = ClientOptions {
+        proxy: args.proxy,
+        proxy_user: args.proxy_user,
+        proxy_pass: args.proxy_pass,
+        no_proxy: args.no_proxy,
+        ca_cert: args.ca_cert,
+        insecure: args.insecure,
+        max_redirects: args.max_redirects,
+        max_retries: args.max_retries,
+    };
+    // Profiles with a token of their own keep using it
+    let aad_token = match args.auth {
+        Auth::Device if !args.offline => {
+            let cache = args
+                .token_cache
+                .or_else(|| config::default_path().map(|path| path.with_file_name("token.json")))
+                .ok_or("nowhere to keep the tokens, give --token-cache")?;
+            let settings = aad::Settings {
+                tenant: args.tenant,
+                client_id: args
+                    .aad_client_id
+                    .unwrap_or_else(|| aad::DEFAULT_CLIENT_ID.to_string()),
+                cache,
+            };
+            Some(aad::access_token(&client_options, &settings).await?)
+        }
+        Auth::Sp if !args.offline => {
+            if args.tenant == "organizations" {
+                return Err("--auth sp needs --tenant (or set AZURE_TENANT_ID)".into());
+            }
+            let client_id = required(args.aad_client_id, "--aad-client-id", "AZURE_CLIENT_ID")?;
+            let credential = match (args.client_secret, args.federated_token_file) {
+                (Some(secret), _) => aad::Credential::Secret(secret),
+                (None, Some(path)) => aad::Credential::FederatedToken(path),
+                (None, None) => {
+                    return Err("--auth sp needs --client-secret or --federated-token-file".into())
+                }
+            };
+            Some(
+                aad::service_principal_token(
+                    &client_options,
+                    &args.tenant,
+                    &client_id,
+                    &credential,
+                )
+                .await?,
+            )
+        }
+        Auth::ManagedIdentity if !args.offline => {
+            Some(aad::managed_identity_token(&client_options, args.aad_client_id.as_deref()).await?)
+        }
+        _ => None,
+    };
+    let mut accounts = Vec::new();
+    for account in std::iter::once(primary).chain(args.accounts) {
+        if account.project.is_empty() && !args.all_projects {
+            return Err("missing --project (or set PROJECT), or --all-projects".into());
+        }
+        let organization = required(account.organization, "--organization", "ORG")?;
+        let connection = Connection {
+            server: account
+                .server_url
+                .clone()
+                .unwrap_or_else(|| Url::parse(CLOUD).unwrap()),
+            organization: organization.clone(),
+            project: None,
+            user: account.user.unwrap_or_else(|| user.clone()),
+            bearer: account.token.is_none() && aad_token.is_some(),
+            // Offline, an expired token or none at all will do
+            token: match (account.token, &aad_token) {
+                (Some(token), _) => token,
+                (None, Some(token)) => token.clone(),
+                (None, None) if args.offline => String::new(),
+                // A keyring that can't be asked is as good as an empty one
+                (None, None) => {
+                    match keyring::get(&keyring_account(account.server_url.as_ref(), &organization))
+                        .ok()
+                        .flatten()
+                    {
+                        Some(token) => token,
+                        None => {
+                            return Err(
+                                "missing --token (or set ACCESS_TOKEN, or run auth login)".into()
+                            )
+                        }
+                    }
                 }
+            },
+            wiql_date_format: args.wiql_date_format.clone(),
+            api_version: args.api_version.clone(),
+        };
+        accounts.push((connection, account.project));
+    }
+    let client = AzdoClient::new(&client_options)?;
+    let harvest = match &args.command {
+        Some(Command::Push {
+            service: Service::Harvest { .. },
+        }) => Some(Harvest::new(
+            &client_options,
+            &required(
+                args.harvest_account_id,
+                "--harvest-account-id",
+                "HARVEST_ACCOUNT_ID",
+            )?,
+            &required(args.harvest_token, "--harvest-token", "HARVEST_TOKEN")?,
+        )?),
+        _ => None,
+    };
+
+    if !args.offline {
+        // Once per token, before an expired one fails a query confusingly
+        let mut validated = HashSet::new();
+        for (connection, _) in &accounts {
+            if !validated.insert((
+                &connection.server,
+                &connection.organization,
+                &connection.token,
+            )) {
+                continue;
+            }
+            let expires = client.validate_token(connection).await?;
+            if let Some(expires) = expires.filter(|expires| {
+                *expires - Utc::now() < chrono::Duration::days(args.token_expiry_warning)
+            }) {
+                eprintln!(
+                    "Warning: the token for {} expires on {}",
+                    connection.organization,
+                    expires
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M")
+                );
+            }
+        }
+    }
+
+    let mut connections = Vec::new();
+    for (connection, projects) in accounts {
+        if args.all_projects {
+            connections.push(connection);
+            continue;
+        }
+        for project in projects {
+            let project = if args.offline {
+                project
+            } else {
+                client.resolve_project(&connection, &project).await?
+            };
+            connections.push(Connection {
+                project: Some(project),
+                ..connection.clone()
+            });
+        }
+    }
+
+    if let Some(Command::Log {
+        item,
+        hours,
+        date,
+        remaining,
+    }) = args.command
+    {
+        let today = dates::today();
+        // Backdated time goes in at noon, well inside the day in any time zone
+        let at = date
+            .map(|date| date.first_day(today))
+            .filter(|date| *date != today)
+            .map(|date| dates::at_hour(date, 12));
+        let connection = &connections[0];
+        let logged = client
+            .log_time(connection, &args.field, item, hours, at, remaining)
+            .await?;
+        print_logged(&logged, args.duration_format);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Check) = args.command {
+        for connection in &connections {
+            client.check(connection).await?;
+            println!(
+                "{}/{}: ok as {}",
+                connection.organization,
+                connection.project.as_deref().unwrap_or("*"),
+                connection.user
+            );
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Find dates
+    let today = dates::today();
+    let (first_day, last_day) = match args.period {
+        Some(period) => period.range(today, args.week_start),
+        None => default_range(args.week_start),
+    };
+    let from = args.from.map_or(first_day, |from| from.first_day(today));
+    let to = args.to.map_or(last_day, |to| to.last_day(today));
+    if from > to {
+        return Err(format!("--from {} is after --to {}", from, to).into());
+    }
 
-                sums.entry(date)
-                    .and_modify(|sum| *sum += diff)
-                    .or_insert(diff);
+    eprintln!("From {} to {}", from, to);
 
+    let baselines = match &args.baseline_file {
+        Some(path) => {
+            read_baselines(path).map_err(|error| format!("{}: {}", path.display(), error))?
+        }
+        None => HashMap::new(),
+    };
+
+    let users = if args.all_users {
+        None
+    } else if let Some(team) = &args.team {
+        let (connection, project) = connections
+            .iter()
+            .find_map(|connection| Some((connection, connection.project.as_deref()?)))
+            .ok_or("--team needs a --project the team belongs to")?;
+        let members = client.team_members(connection, project, team).await?;
+        eprintln!("{} members in {}", members.len(), team);
+        Some(members)
+    } else {
+        Some(vec![user.clone()])
+    };
+
+    let conditions = [
+        wiql_any("System.AreaPath", "UNDER", &args.area_path),
+        iteration_condition(&args.iteration),
+        wiql_any("System.Tags", "CONTAINS", &args.tag),
+        wiql_in("System.WorkItemType", &args.work_item_type),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let wiql = match &args.wiql_file {
+        Some(path) => Some(
+            std::fs::read_to_string(path)
+                .map_err(|error| format!("{}: {}", path.display(), error))?
+                .trim()
+                .to_string(),
+        ),
+        None => args.wiql,
+    };
+
+    if args.recompute_diffs_from_full_history {
+        eprintln!(
+            "Warning: --recompute-diffs-from-full-history is deprecated and does nothing, \
+             every item's complete history is always read now"
+        );
+    }
+    if args.lazy_titles {
+        eprintln!(
+            "Warning: --lazy-titles is deprecated and does nothing, the titles come with \
+             the histories without a request of their own"
+        );
+    }
+    if args.backend == Backend::Odata && args.attribute_field != CHANGED_BY {
+        return Err("--backend odata can only attribute time to System.ChangedBy".into());
+    }
+    if args.backend == Backend::Odata && !args.track.is_empty() {
+        return Err("--track only goes with --backend rest".into());
+    }
+    if args.backend == Backend::Odata && args.field != COMPLETED_WORK {
+        return Err(format!("--backend odata can only read {}", COMPLETED_WORK).into());
+    }
+    if args.backend == Backend::Odata && args.cache.is_some() {
+        return Err("--cache only goes with --backend rest".into());
+    }
+    if args.backend == Backend::Odata && matches!(args.command, Some(Command::CycleTime { .. })) {
+        return Err("cycle-time needs --backend rest".into());
+    }
+    let query = ReportQuery {
+        user,
+        users,
+        aliases,
+        wiql,
+        conditions,
+        query_id: args.query_id,
+        rollup: args.rollup,
+        from,
+        to,
+        week_start: args.week_start,
+        concurrency: usize::from(args.concurrency),
+        history: args.history_source,
+        backend: args.backend,
+        cache: args.cache,
+        offline: args.offline,
+        since_state: args.since_state_change,
+        attribute_field: args.attribute_field,
+        field: args.field,
+        track: args.track,
+        strict_range_server: args.strict_range_server,
+        baselines,
+        tiebreak: args.tiebreak,
+        sample: args.sample.map(|n| {
+            let seed = args
+                .seed
+                .unwrap_or_else(|| Utc::now().timestamp_nanos_opt().unwrap_or(0) as u64);
+            eprintln!("Sampling {} work items with seed {}", n, seed);
+            (n, seed)
+        }),
+    };
+
+    #[cfg(feature = "serve")]
+    if let Some(Command::Serve {
+        bind,
+        port,
+        cache_ttl,
+    }) = args.command
+    {
+        serve::serve(
+            client,
+            connections,
+            query,
+            bind,
+            port,
+            std::time::Duration::from_secs(cache_ttl),
+        )
+        .await;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    #[cfg(feature = "tui")]
+    if let Some(Command::Tui) = args.command {
+        tui::run(&client, &connections, query, args.duration_format).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::CycleTime { done_state }) = &args.command {
+        let report = client.cycle_times(&connections, &query, done_state).await?;
+        render_flow(&mut io::stdout().lock(), args.format, &report)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(seconds) = args.watch {
+        let interval = std::time::Duration::from_secs(seconds);
+        return watch(&client, &connections, query, interval, args.duration_format).await;
+    }
+
+    if args.plan || args.dry_run {
+        let plan = client.plan(&connections, &query).await?;
+        eprintln!(
+            "{} work items: up to {} history requests, {} at a time, and {} batch requests, \
+             about {}s at {}ms a request",
+            plan.work_items,
+            plan.history_requests,
+            plan.concurrency,
+            plan.batch_requests,
+            plan.estimate().as_secs(),
+            plan.request_time.as_millis()
+        );
+        if args.dry_run || (!args.yes && !confirm("Continue?")?) {
+            return Ok(ExitCode::SUCCESS);
+        }
+    }
+    let mut report = client.report(&connections, &query).await?;
+    if let (Some(Command::Sync), Some(path)) = (&args.command, &query.cache) {
+        eprintln!("Updated {}", path.display());
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(n) = args.buckets {
+        report.buckets = Some(bucket_totals(&report.sums, report.from, report.to, n));
+    }
+    if args.group_by.contains(&GroupBy::Tag) {
+        report.by_tag = Some(tag_totals(&report.items, args.tag_split));
+    }
+    if args.group_by.contains(&GroupBy::Iteration) {
+        report.by_iteration = Some(iteration_totals(&report.items));
+    }
+    if !args.group_by.is_empty() {
+        report.grouped = Some(group_totals(&report.items, &args.group_by, args.tag_split));
+    }
+    if !args.client.is_empty() {
+        for item in &mut report.items {
+            item.client = mapping::lookup(&args.client, item).cloned();
+        }
+        report.by_client = Some(client_totals(&report.items));
+    }
+    if !args.rate.is_empty() {
+        report.billing = Some(rates::bill(&report.items, &args.rate, &args.currency));
+    }
+    if args.target_hours_per_day.is_some() || !args.target_hours.is_empty() {
+        let mut targets = Targets {
+            per_day: args.target_hours_per_day.unwrap_or(0.0),
+            weekdays: args.target_hours.iter().copied().collect(),
+            holidays: BTreeMap::new(),
+        };
+        for (first, last) in &args.holiday {
+            targets.add_holidays(*first, *last, "holiday");
+        }
+        for path in &args.holidays_ics {
+            for (first, last, summary) in targets::read_ics(path)? {
+                targets.add_holidays(first, last, &summary);
+            }
+        }
+        report.target = Some(targets::compare(
+            &targets,
+            &report.sums,
+            report.from,
+            report.to,
+        ));
+    }
+
+    let mut tempo_accounts = HashMap::new();
+    for account in &args.tempo_account {
+        let (email, id) = account.split_once('=').ok_or_else(|| {
+            format!(
+                "expected --tempo-account EMAIL=ACCOUNT_ID, got {:?}",
+                account
+            )
+        })?;
+        tempo_accounts.insert(email.trim().to_lowercase(), id.trim().to_string());
+    }
+    let options = RenderOptions {
+        duration_format: args.duration_format,
+        merge_same_day: args.merge_same_day,
+        top_contributors: args.top_contributors,
+        minor_threshold: args
+            .collapse_users_under_threshold
+            .then_some(args.minor_threshold),
+        flag_ambiguous: args.flag_ambiguous,
+        completion_ratio: args.completion_ratio,
+        estimates: args.estimates,
+        show_tags: args.show_tags,
+        interval: args.bucket,
+        expected_min: args.expected_min,
+        tempo_issues: args.tempo_issue,
+        tempo_accounts,
+    };
+    // Fetch once, render to every requested output
+    let exports = match &args.command {
+        Some(Command::Export { sinks }) => sinks.as_slice(),
+        Some(Command::Push { .. } | Command::Invoice { .. }) => &[],
+        _ => {
+            render(&mut io::stdout().lock(), args.format, &report, &options)?;
+            &[]
+        }
+    };
+    let html = args.html.clone().map(|path| Sink {
+        format: Format::Html,
+        path,
+    });
+    let xlsx = args.xlsx.clone().map(|path| Sink {
+        format: Format::Xlsx,
+        path,
+    });
+    for sink in exports.iter().chain(&args.also).chain(&html).chain(&xlsx) {
+        write_sink(sink, &report, &options)?;
+    }
+    #[cfg(feature = "email")]
+    if !args.email_to.is_empty() {
+        let mut text = Vec::new();
+        render(&mut text, Format::Text, &report, &options)?;
+        let mut html = Vec::new();
+        render(&mut html, Format::Html, &report, &options)?;
+        let smtp = mail::Smtp {
+            host: args.smtp_host.clone().unwrap_or_default(),
+            port: args.smtp_port,
+            security: args.smtp_security,
+            user: args.smtp_user.clone(),
+            password: args.smtp_password.clone(),
+        };
+        let message = mail::Message {
+            from: args
+                .email_from
+                .clone()
+                .unwrap_or_else(|| report.user.clone()),
+            to: args.email_to.clone(),
+            subject: format!(
+                "Time logged by {}, {} to {}",
+                report.user, report.from, report.to
+            ),
+            text: String::from_utf8_lossy(&text).into_owned(),
+            html: String::from_utf8_lossy(&html).into_owned(),
+        };
+        mail::send(&smtp, &message).await?;
+        if VERBOSE.load(Ordering::Relaxed) {
+            eprintln!("Mailed the report to {}", args.email_to.join(", "));
+        }
+    }
+    if let Some(webhook) = &args.slack_webhook {
+        notify::slack(&client_options, webhook, &report, &options).await?;
+    }
+    if let Some(webhook) = &args.teams_webhook {
+        notify::teams(&client_options, webhook, &report, &options).await?;
+    }
+
+    #[cfg(feature = "store")]
+    if let Some(path) = &args.store {
+        let count = store::upsert(path, &report)?;
+        if VERBOSE.load(Ordering::Relaxed) {
+            eprintln!("Stored {} entries in {}", count, path.display());
+        }
+    }
+
+    if let (
+        Some(Command::Invoice {
+            output,
+            number,
+            date,
+        }),
+        Some(billing),
+    ) = (&args.command, &report.billing)
+    {
+        let invoice = Invoice {
+            number: number.clone(),
+            date: date.unwrap_or_else(|| chrono::Local::now().date_naive()),
+            from: report.from,
+            to: report.to,
+            bill_from: args.bill_from.clone(),
+            bill_to: args.bill_to.clone(),
+            currency: billing.currency.clone(),
+            lines: invoice::lines(billing),
+        };
+        if billing.unrated_hours != 0.0 {
+            eprintln!(
+                "Left {} without a rate off the invoice",
+                options.duration_format.format(billing.unrated_hours)
+            );
+        }
+        std::fs::write(output, invoice.pdf())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let (
+        Some(Command::Push {
+            service: Service::Harvest { dry_run },
+        }),
+        Some(harvest),
+    ) = (&args.command, &harvest)
+    {
+        let pushed = harvest.push(&report, &args.harvest_map, *dry_run).await?;
+        let format = options.duration_format;
+        let mut unmapped = false;
+        for pushed in pushed
+            .iter()
+            .filter(|pushed| pushed.action != Action::Unchanged)
+        {
+            println!(
+                "#{} {} {}: {}",
+                pushed.id,
+                pushed.date,
+                format.format(pushed.hours),
+                pushed.action
+            );
+            unmapped |= pushed.action == Action::Unmapped;
+        }
+        if unmapped {
+            eprintln!("Map the remaining items to a Harvest project and task with --harvest-map");
+        }
+        let changed = pushed
+            .iter()
+            .filter(|pushed| !matches!(pushed.action, Action::Unchanged | Action::Unmapped))
+            .count();
+        eprintln!(
+            "{} {} of {} entries in Harvest",
+            if *dry_run { "Would change" } else { "Changed" },
+            changed,
+            pushed.len()
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Correct) = args.command {
+        correct(
+            &client,
+            &connections,
+            &query.field,
+            &report,
+            options.duration_format,
+            args.yes,
+        )
+        .await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if args.exit_code_hours {
+        let (code, warning) = hours_exit_code(report.total);
+        if let Some(warning) = warning {
+            eprintln!("Warning: {}", warning);
+        }
+        return Ok(ExitCode::from(code));
+    }
+    if let Some(minimum) = args.fail_under.filter(|minimum| report.total < *minimum) {
+        eprintln!(
+            "Only {} of {} hours logged from {} to {}",
+            options.duration_format.format(report.total),
+            options.duration_format.format(minimum),
+            report.from,
+            report.to
+        );
+        return Ok(ExitCode::from(EXIT_UNDER));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_logged(logged: &Logged, format: DurationFormat) {
+    print!(
+        "#{} rev {}: {} {} -> {}",
+        logged.id,
+        logged.rev,
+        logged.field.rsplit('.').next().unwrap_or_default(),
+        format.format(logged.completed_work.0),
+        format.format(logged.completed_work.1)
+    );
+    if let Some((before, after)) = logged.remaining_work {
+        print!(
+            ", RemainingWork {} -> {}",
+            format.format(before),
+            format.format(after)
+        );
+    }
+    println!();
+}
+
+/// Ask for `ITEM DATE HOURS` lines and log the difference between HOURS and
+/// what the report has for the item on that day, until an empty line
+async fn correct(
+    client: &AzdoClient,
+    connections: &[Connection],
+    field: &str,
+    report: &Report,
+    duration_format: DurationFormat,
+    yes: bool,
+) -> Result<(), Error> {
+    let mut days: HashMap<(u64, NaiveDate), f64> = HashMap::new();
+    for item in &report.items {
+        for entry in &item.entries {
+            *days.entry((item.id, entry.date)).or_default() += entry.hours;
+        }
+    }
+    let today = dates::today();
+    eprintln!("Correct a day as ITEM DATE HOURS, the hours it should have; an empty line ends");
+    loop {
+        eprint!("> ");
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 || line.trim().is_empty() {
+            return Ok(());
+        }
+        let (id, date, hours) = match parse_correction(&line, today) {
+            Ok(correction) => correction,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                continue;
+            }
+        };
+        if date < report.from || date > report.to {
+            eprintln!(
+                "Error: {} isn't in the report, from {} to {}",
+                date, report.from, report.to
+            );
+            continue;
+        }
+        let logged = days.get(&(id, date)).copied().unwrap_or(0.0);
+        let difference = hours - logged;
+        if difference.abs() < 1e-9 {
+            eprintln!(
+                "#{} already has {} on {}",
+                id,
+                duration_format.format(hours),
+                date
+            );
+            continue;
+        }
+        let question = format!(
+            "#{} has {} on {}, log {:+}?",
+            id,
+            duration_format.format(logged),
+            date,
+            difference
+        );
+        if !yes && !confirm(&question)? {
+            continue;
+        }
+
+        let item = report.items.iter().find(|item| item.id == id);
+        let connection = item
+            .and_then(|item| {
+                connections.iter().find(|connection| {
+                    connection.organization == item.organization
+                        && connection
+                            .project
+                            .as_deref()
+                            .is_none_or(|project| project.eq_ignore_ascii_case(&item.project))
+                })
+            })
+            .unwrap_or(&connections[0]);
+        match client
+            .correct_day(connection, field, id, date, logged, hours)
+            .await
+        {
+            Ok(result) => {
+                print_logged(&result, duration_format);
+                days.insert((id, date), hours);
+            }
+            // The others may still go through
+            Err(error) => eprintln!("Error: {}", error),
+        }
+    }
+}
+
+fn parse_correction(line: &str, today: NaiveDate) -> Result<(u64, NaiveDate, f64), String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let [id, date, hours] = words[..] else {
+        return Err(format!("expected ITEM DATE HOURS, got {:?}", line.trim()));
+    };
+    let id = id
+        .trim_start_matches('#')
+        .parse()
+        .map_err(|_| format!("not a work item id: {}", id))?;
+    let date = date.parse::<DateSpec>()?.first_day(today);
+    let hours: f64 = hours
+        .parse()
+        .map_err(|_| format!("not a number of hours: {}", hours))?;
+    if hours < 0.0 {
+        return Err("a day can't have less than 0 hours".into());
+    }
+    Ok((id, date, hours))
+}
+
+/// Report again every `interval` until interrupted, printing the hours so far
+/// today and the daily totals on the first round and whenever they change
+async fn watch(
+    client: &AzdoClient,
+    connections: &[Connection],
+    mut query: ReportQuery,
+    interval: std::time::Duration,
+    duration_format: DurationFormat,
+) -> Result<ExitCode, Error> {
+    // Without --cache the histories go in a temporary one, so a round only
+    // fetches the items changed since the one before
+    let temporary = (query.cache.is_none() && query.backend == Backend::Rest).then(|| {
+        std::env::temp_dir().join(format!(
+            "azure-devops-time-used-{}.json",
+            std::process::id()
+        ))
+    });
+    if temporary.is_some() {
+        query.cache = temporary.clone();
+    }
+
+    let mut shown: Option<BTreeMap<NaiveDate, f64>> = None;
+    loop {
+        let report = tokio::select! {
+            report = client.report(connections, &query) => report,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        match report {
+            Ok(report) if shown.as_ref() != Some(&report.sums) => {
+                let today = report.sums.get(&dates::today()).copied().unwrap_or(0.0);
                 println!(
-                    "\t{} {} {} {}",
-                    date, revision.fields.changed_by, completed_work, diff
+                    "{} today {}, total {}",
+                    chrono::Local::now().format("%H:%M"),
+                    duration_format.format(today),
+                    duration_format.format(report.total)
                 );
+                for (date, hours) in &report.sums {
+                    println!("\t{} {}", date, duration_format.format(*hours));
+                }
+                shown = Some(report.sums);
             }
+            Ok(_) => {}
+            // Keep watching through a network hiccup, the next round may work
+            Err(error) => eprintln!("Error: {}", error),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = tokio::signal::ctrl_c() => break,
         }
     }
-    println!("{:#?}", sums);
 
-    Ok(())
+    if let Some(path) = temporary {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(ExitCode::SUCCESS)
 }
 
 #[allow(dead_code)]
@@ -237,3 +2077,192 @@ fn print_work_logs(v: Value) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code_hours_are_clamped_below_the_failures() {
+        assert_eq!(hours_exit_code(7.4), (7, None));
+        assert_eq!(hours_exit_code(7.5), (8, None));
+        assert_eq!(hours_exit_code(0.0), (0, None));
+        assert_eq!(hours_exit_code(249.4), (249, None));
+
+        let (code, warning) = hours_exit_code(312.5);
+        assert_eq!(code, MAX_EXIT_HOURS);
+        assert_eq!(
+            warning.unwrap(),
+            "312.5 hours don't fit in an exit code, exiting with 249"
+        );
+        // Corrections can take more off than was logged
+        let (code, warning) = hours_exit_code(-3.0);
+        assert_eq!(code, 0);
+        assert!(warning.is_some());
+    }
+
+    #[test]
+    fn exit_code_hours_help_tells_the_reserved_codes() {
+        let help = Args::command()
+            .get_arguments()
+            .find(|arg| arg.get_id() == "exit_code_hours")
+            .and_then(|arg| arg.get_long_help())
+            .unwrap()
+            .to_string();
+        assert!(help.contains("250 to 255"), "{}", help);
+    }
+
+    #[test]
+    fn fail_under_exits_apart_from_usage_errors() {
+        let usage = Args::try_parse_from(["azure-devops-time-used", "--fail-under", "many"])
+            .unwrap_err()
+            .exit_code();
+        assert_ne!(i32::from(EXIT_UNDER), usage);
+        assert_ne!(EXIT_UNDER, 1);
+        assert!(!(EXIT_USAGE..=EXIT_FAILURE).contains(&EXIT_UNDER));
+        let help = Args::command()
+            .get_arguments()
+            .find(|arg| arg.get_id() == "fail_under")
+            .and_then(|arg| arg.get_long_help())
+            .unwrap()
+            .to_string();
+        assert!(help.starts_with("Exit with code 3 "), "{}", help);
+    }
+
+    #[test]
+    fn baseline_file_lines() {
+        let path = std::env::temp_dir().join(format!("baselines-{}.csv", std::process::id()));
+        std::fs::write(&path, "id,completed_work\n# corrected\n12, 4.5\n\n34,0\n").unwrap();
+        let baselines = read_baselines(&path);
+        std::fs::write(&path, "12,4.5\n34\n").unwrap();
+        let broken = read_baselines(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(baselines.unwrap(), HashMap::from([(12, 4.5), (34, 0.0)]));
+        assert_eq!(broken.unwrap_err(), "line 2: expected id,completed_work");
+    }
+
+    #[tokio::test]
+    async fn completions_need_no_connection() {
+        // No --organization, --project nor --user, which only the report needs
+        let args = Args::try_parse_from(["azure-devops-time-used", "completions", "bash"]).unwrap();
+        assert_eq!(run(args).await.unwrap(), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn also_takes_several_sinks() {
+        let args = Args::try_parse_from([
+            "azure-devops-time-used",
+            "--also",
+            "json:report.json",
+            "--also",
+            "csv:report.csv",
+        ])
+        .unwrap();
+        let sinks: Vec<(Format, &str)> = args
+            .also
+            .iter()
+            .map(|sink| (sink.format, sink.path.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            sinks,
+            [(Format::Json, "report.json"), (Format::Csv, "report.csv")]
+        );
+    }
+
+    /// What the command line comes to with this config file
+    fn with_config(name: &str, contents: &str, argv: &[&str]) -> Result<Args, String> {
+        let path = std::env::temp_dir().join(format!("{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let config = ["azure-devops-time-used", "--config", path.to_str().unwrap()];
+        let args = parse_args(config.iter().chain(argv).map(OsString::from).collect());
+        let _ = std::fs::remove_file(&path);
+        args.map_err(|error| error.replace(path.to_str().unwrap(), "PATH"))
+    }
+
+    const PROFILES: &str = r#"
+format = "csv"
+user = "me@adapt.dk"
+profile = "client-a"
+
+[profiles.client-a]
+organization = "a-org"
+project = ["A1", "A2"]
+
+[profiles.client-b]
+organization = "b-org"
+token = "b-token"
+server-url = "https://tfs.example.com/tfs"
+"#;
+
+    #[test]
+    fn profiles_fill_in_for_flags_not_given() {
+        // The default profile, over the top-level settings
+        let args = with_config("default-profile", PROFILES, &[]).unwrap();
+        assert_eq!(args.organization.as_deref(), Some("a-org"));
+        assert_eq!(args.project, ["A1", "A2"]);
+        assert_eq!(args.user, ["me@adapt.dk"]);
+        assert_eq!(args.format, Format::Csv);
+        assert!(args.accounts.is_empty());
+
+        // The first profile given instead, with the command line over it
+        let args = with_config(
+            "profiles",
+            PROFILES,
+            &[
+                "--profile",
+                "client-b,client-a",
+                "--organization",
+                "cli-org",
+            ],
+        )
+        .unwrap();
+        assert_eq!(args.organization.as_deref(), Some("cli-org"));
+        assert_eq!(args.token.as_deref(), Some("b-token"));
+        assert_eq!(
+            args.server_url.as_ref().map(Url::as_str),
+            Some("https://tfs.example.com/tfs/")
+        );
+        assert!(args.project.is_empty());
+        // The others are connections of their own, falling back on the top level
+        let [account] = &args.accounts[..] else {
+            panic!("{:?}", args.accounts);
+        };
+        assert_eq!(account.organization.as_deref(), Some("a-org"));
+        assert_eq!(account.project, ["A1", "A2"]);
+        assert_eq!(account.user.as_deref(), Some("me@adapt.dk"));
+        assert_eq!(account.token, None);
+        assert_eq!(account.server_url, None);
+    }
+
+    #[test]
+    fn unknown_profiles_and_settings_are_errors() {
+        assert_eq!(
+            with_config("unknown-profile", PROFILES, &["--profile", "nope"]).unwrap_err(),
+            "PATH: no [profiles.nope]"
+        );
+        assert_eq!(
+            with_config("unknown-setting", "colour = \"red\"\n", &[]).unwrap_err(),
+            "PATH: unknown setting colour"
+        );
+        assert_eq!(
+            with_config(
+                "bad-server",
+                "[profiles.a]\n[profiles.b]\nserver-url = \"ftp://x\"\n",
+                &["--profile", "a,b"]
+            )
+            .unwrap_err(),
+            "PATH: server-url: not an http(s) URL: ftp://x/"
+        );
+    }
+
+    #[cfg(feature = "serve")]
+    #[test]
+    fn serve_listens_on_localhost_by_default() {
+        let args = Args::try_parse_from(["azure-devops-time-used", "serve"]).unwrap();
+        let Some(Command::Serve { bind, .. }) = args.command else {
+            panic!("not serve: {:?}", args.command);
+        };
+        assert!(bind.is_loopback());
+    }
+}
@@ -0,0 +1,99 @@
+//! Settings that differ per work item, such as where its time goes in another
+//! tool
+//!
+//! Each is given as `KEY=VALUE`, where the key is a work item (`#1234`), a tag
+//! (`tag:Support`), an area path (`area:Website\Shop`, which takes in the
+//! areas under it), an Azure DevOps project or `*` for everything else.
+//! [`lookup`] tries them in that order.
+
+use crate::ReportItem;
+use std::{fmt::Display, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping<T> {
+    pub key: Key,
+    pub value: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Key {
+    WorkItem(u64),
+    /// Matched ignoring case, as are areas and projects
+    Tag(String),
+    Area(String),
+    Project(String),
+    Any,
+}
+
+impl<T: FromStr> FromStr for Mapping<T>
+where
+    T::Err: Display,
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, got {:?}", s))?;
+        let key = key.trim();
+        let key = if key == "*" {
+            Key::Any
+        } else if let Some(id) = key.strip_prefix('#') {
+            Key::WorkItem(
+                id.parse()
+                    .map_err(|_| format!("not a work item id: {}", key))?,
+            )
+        } else if let Some(tag) = key.strip_prefix("tag:") {
+            Key::Tag(tag.trim().to_string())
+        } else if let Some(area) = key.strip_prefix("area:") {
+            Key::Area(area.trim().trim_end_matches('\\').to_string())
+        } else {
+            Key::Project(key.to_string())
+        };
+        Ok(Mapping {
+            key,
+            value: value
+                .trim()
+                .parse()
+                .map_err(|error: T::Err| error.to_string())?,
+        })
+    }
+}
+
+/// The value for a work item: the one naming it, else the one for one of its
+/// tags, its area (the deepest that matches), its project, else the one for `*`
+pub fn lookup<'a, T>(mappings: &'a [Mapping<T>], item: &ReportItem) -> Option<&'a T> {
+    let find = |matches: &dyn Fn(&Key) -> bool| {
+        mappings
+            .iter()
+            .find(|mapping| matches(&mapping.key))
+            .map(|mapping| &mapping.value)
+    };
+    let area = item.area.as_deref().unwrap_or("");
+    let under = |path: &str| {
+        area.eq_ignore_ascii_case(path)
+            || area.len() > path.len()
+                && area.as_bytes()[path.len()] == b'\\'
+                && area[..path.len()].eq_ignore_ascii_case(path)
+    };
+    let deepest_area = mappings
+        .iter()
+        .filter_map(|mapping| match &mapping.key {
+            Key::Area(path) if under(path) => Some((path.len(), &mapping.value)),
+            _ => None,
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, value)| value);
+    find(&|key| *key == Key::WorkItem(item.id))
+        .or_else(|| {
+            find(&|key| {
+                matches!(key, Key::Tag(tag)
+                    if item.tags.iter().any(|item_tag| item_tag.eq_ignore_ascii_case(tag)))
+            })
+        })
+        .or(deepest_area)
+        .or_else(|| {
+            find(&|key| matches!(key, Key::Project(project) if project.eq_ignore_ascii_case(&item.project)))
+        })
+        .or_else(|| find(&|key| *key == Key::Any))
+}
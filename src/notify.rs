@@ -0,0 +1,288 @@
+//! Posting a short summary of the report to Slack and Teams webhooks
+//!
+//! The summary is what someone glancing at a channel wants to know: the total,
+//! the hours of each day and which days fell short of `--target-hours-per-day`.
+
+use crate::{
+    client::{self, SendRetrying},
+    output::{difference, RenderOptions},
+    ClientOptions, Error, Report,
+};
+use chrono::{Datelike, NaiveDate, Weekday};
+use serde_json::{json, Value};
+
+/// A day as the summary shows it
+struct Day {
+    date: NaiveDate,
+    logged: f64,
+    /// How far off the target, with targets
+    difference: Option<f64>,
+    holiday: Option<String>,
+}
+
+impl Day {
+    fn under_target(&self) -> bool {
+        self.difference
+            .is_some_and(|difference| difference < -0.005)
+    }
+}
+
+/// Every day of the range that had time logged or some expected, so a
+/// workday without any time still shows up when there's a target
+fn days(report: &Report) -> Vec<Day> {
+    if let Some(target) = &report.target {
+        return target
+            .days
+            .iter()
+            .filter(|day| day.logged != 0.0 || day.expected != 0.0)
+            .map(|day| Day {
+                date: day.date,
+                logged: day.logged,
+                difference: Some(day.difference),
+                holiday: day.holiday.clone(),
+            })
+            .collect();
+    }
+    report
+        .from
+        .iter_days()
+        .take_while(|date| *date <= report.to)
+        .filter_map(|date| {
+            let logged = report.sums.get(&date).copied().unwrap_or(0.0);
+            let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+            (logged != 0.0 || !weekend).then_some(Day {
+                date,
+                logged,
+                difference: None,
+                holiday: None,
+            })
+        })
+        .collect()
+}
+
+/// Whether the report covers a team or everyone rather than one user
+fn several_users(report: &Report) -> bool {
+    report.contributors.len() > 1
+}
+
+fn title(report: &Report) -> String {
+    let who = if several_users(report) {
+        format!("{} users", report.contributors.len())
+    } else {
+        report.user.clone()
+    };
+    format!("Time logged by {}, {} to {}", who, report.from, report.to)
+}
+
+/// The total, next to the target when there is one
+fn total(report: &Report, options: &RenderOptions) -> String {
+    let hours = |hours| options.duration_format.format(hours);
+    match &report.target {
+        Some(target) => format!(
+            "Total {} of {}{}",
+            hours(target.logged),
+            hours(target.expected),
+            difference(target.difference, options)
+        ),
+        None => format!("Total {}", hours(report.total)),
+    }
+}
+
+/// A Slack message, in Block Kit with plain text for notifications
+pub fn slack_message(report: &Report, options: &RenderOptions) -> Value {
+    let days = days(report);
+    let lines: Vec<String> = days
+        .iter()
+        .map(|day| {
+            let marker = if day.under_target() { " ⚠" } else { "" };
+            format!(
+                "{} {} {:>6}{}{}{}",
+                day.date.format("%a"),
+                day.date,
+                options.duration_format.format(day.logged),
+                day.difference
+                    .map(|hours| difference(hours, options))
+                    .unwrap_or_default(),
+                day.holiday
+                    .as_ref()
+                    .map(|name| format!(" [{}]", name))
+                    .unwrap_or_default(),
+                marker
+            )
+        })
+        .collect();
+    let under: Vec<String> = days
+        .iter()
+        .filter(|day| day.under_target())
+        .map(|day| format!("{} {}", day.date.format("%a"), day.date))
+        .collect();
+
+    let mut blocks = vec![
+        json!({
+            "type": "header",
+            "text": { "type": "plain_text", "text": title(report) },
+        }),
+        json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("*{}*", total(report, options)) },
+        }),
+    ];
+    if !lines.is_empty() {
+        blocks.push(json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("```{}```", lines.join("\n")) },
+        }));
+    }
+    if !under.is_empty() {
+        blocks.push(json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": format!(":warning: Under target on {}", under.join(", ")),
+            },
+        }));
+    }
+    json!({
+        "text": format!("{}: {}", title(report), total(report, options)),
+        "blocks": blocks,
+    })
+}
+
+/// A Teams message with an Adaptive Card, adding the hours of each user when
+/// there are several
+pub fn teams_message(report: &Report, options: &RenderOptions) -> Value {
+    let hours = |hours| options.duration_format.format(hours);
+    let days = days(report);
+    let mut body = vec![
+        json!({
+            "type": "TextBlock",
+            "text": title(report),
+            "size": "Large",
+            "weight": "Bolder",
+            "wrap": true,
+        }),
+        json!({
+            "type": "TextBlock",
+            "text": total(report, options),
+            "weight": "Bolder",
+        }),
+    ];
+    if !days.is_empty() {
+        let facts: Vec<Value> = days
+            .iter()
+            .map(|day| {
+                let mut value = format!(
+                    "{}{}",
+                    hours(day.logged),
+                    day.difference
+                        .map(|hours| difference(hours, options))
+                        .unwrap_or_default()
+                );
+                if let Some(name) = &day.holiday {
+                    value.push_str(&format!(" [{}]", name));
+                }
+                if day.under_target() {
+                    value.push_str(" ⚠");
+                }
+                json!({ "title": format!("{} {}", day.date.format("%a"), day.date), "value": value })
+            })
+            .collect();
+        body.push(json!({ "type": "FactSet", "facts": facts }));
+    }
+    let under: Vec<String> = days
+        .iter()
+        .filter(|day| day.under_target())
+        .map(|day| format!("{} {}", day.date.format("%a"), day.date))
+        .collect();
+    if !under.is_empty() {
+        body.push(json!({
+            "type": "TextBlock",
+            "text": format!("Under target on {}", under.join(", ")),
+            "color": "Attention",
+            "wrap": true,
+        }));
+    }
+    if several_users(report) {
+        let facts: Vec<Value> = report
+            .contributors
+            .iter()
+            .map(|contributor| {
+                json!({ "title": contributor.display_name, "value": hours(contributor.hours) })
+            })
+            .collect();
+        body.push(json!({
+            "type": "TextBlock",
+            "text": "By user",
+            "weight": "Bolder",
+            "separator": true,
+        }));
+        body.push(json!({ "type": "FactSet", "facts": facts }));
+    }
+
+    json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "contentUrl": null,
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": body,
+            },
+        }],
+    })
+}
+
+/// Post the summary to a Slack incoming webhook
+pub async fn slack(
+    client_options: &ClientOptions,
+    webhook: &str,
+    report: &Report,
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    post(
+        client_options,
+        webhook,
+        &slack_message(report, options),
+        "Slack",
+    )
+    .await
+}
+
+/// Post the summary to a Teams incoming webhook, or a Workflows one taking
+/// the same messages
+pub async fn teams(
+    client_options: &ClientOptions,
+    webhook: &str,
+    report: &Report,
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    post(
+        client_options,
+        webhook,
+        &teams_message(report, options),
+        "Teams",
+    )
+    .await
+}
+
+async fn post(
+    client_options: &ClientOptions,
+    webhook: &str,
+    message: &Value,
+    service: &str,
+) -> Result<(), Error> {
+    // Webhook URLs are secrets, so errors don't show them
+    let failed =
+        |error: reqwest::Error| format!("{} webhook failed: {}", service, error.without_url());
+    client::build(client_options)?
+        .post(webhook)
+        .json(message)
+        .send_retrying()
+        .await
+        .map_err(failed)?
+        .error_for_status()
+        .map_err(failed)?;
+    Ok(())
+}
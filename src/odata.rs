@@ -0,0 +1,356 @@
+//! Work item history from the Analytics OData service
+//!
+//! One query finds the items changed in the range, and a request per 50 of
+//! those fetches their revisions, instead of a WIQL query
+//! and a revisions request per item.
+
+use crate::{
+    client::{Authorize, SendRetrying},
+    Connection, Fields, ReportQuery, Revision, User, WorkItem, CHANGED_BY,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::{BTreeSet, HashMap};
+use uuid::Uuid;
+
+/// Work items per history request, keeping the filter within URL limits
+pub(crate) const IDS_PER_REQUEST: usize = 50;
+
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    value: Vec<T>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+}
+
+/// Every row of a query, following the server's paging links
+async fn get_all<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    connection: &Connection,
+    url: Url,
+) -> Result<Vec<T>, reqwest::Error> {
+    let mut rows = Vec::new();
+    let mut next = Some(url.to_string());
+    while let Some(url) = next {
+        let page: Page<T> = client
+            .get(url)
+            .authorize(connection)
+            .send_retrying()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        rows.extend(page.value);
+        next = page.next_link;
+    }
+    Ok(rows)
+}
+
+/// Midnight UTC starting the day, as an OData date-time literal
+fn instant(day: NaiveDate) -> String {
+    day.format("%Y-%m-%dT00:00:00Z").to_string()
+}
+
+fn string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Changed {
+    work_item_id: u64,
+}
+
+/// Work items with a revision in the range, by the users reported on when
+/// time is attributed to whoever changed it
+///
+/// Not only those with completed work on it, as work cleared in the range
+/// leaves none on the revision that takes the hours back.
+pub async fn work_items(
+    client: &reqwest::Client,
+    connection: &Connection,
+    query: &ReportQuery,
+) -> Result<Vec<WorkItem>, reqwest::Error> {
+    // A day more either side, as days are counted in the local time zone
+    let mut filter = format!(
+        "ChangedDate ge {} and ChangedDate lt {}",
+        instant(query.from.pred_opt().unwrap()),
+        instant(query.to + chrono::Duration::days(2))
+    );
+    if let Some(users) = query
+        .users
+        .as_ref()
+        .filter(|_| query.attribute_field == CHANGED_BY)
+    {
+        let emails: BTreeSet<String> = users
+            .iter()
+            .map(|user| user.to_lowercase())
+            .chain(query.aliases.keys().cloned())
+            .map(|email| string(&email))
+            .collect();
+        let emails: Vec<String> = emails.into_iter().collect();
+        filter.push_str(&format!(
+            " and ChangedBy/UserEmail in ({})",
+            emails.join(", ")
+        ));
+    }
+
+    let mut url = connection.analytics_url("WorkItemRevisions");
+    url.query_pairs_mut().append_pair(
+        "$apply",
+        &format!("filter({})/groupby((WorkItemId))", filter),
+    );
+    let rows: Vec<Changed> = get_all(client, connection, url).await?;
+    let ids: BTreeSet<u64> = rows.into_iter().map(|row| row.work_item_id).collect();
+    Ok(ids
+        .into_iter()
+        .map(|id| WorkItem {
+            id,
+            changed_date: None,
+        })
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Person {
+    #[serde(rename = "UserSK")]
+    user_sk: Uuid,
+    user_name: Option<String>,
+    user_email: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProjectName {
+    project_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct IterationPath {
+    iteration_path: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AreaPath {
+    area_path: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Row {
+    work_item_id: u64,
+    revision: u32,
+    changed_date: DateTime<Utc>,
+    changed_by: Option<Person>,
+    completed_work: Option<f64>,
+    remaining_work: Option<f64>,
+    original_estimate: Option<f64>,
+    title: Option<String>,
+    state: Option<String>,
+    reason: Option<String>,
+    tag_names: Option<String>,
+    project: Option<ProjectName>,
+    iteration: Option<IterationPath>,
+    area: Option<AreaPath>,
+}
+
+impl Row {
+    fn into_revision(self) -> Revision {
+        let changed_by = match self.changed_by {
+            Some(person) => User {
+                id: person.user_sk,
+                display_name: person.user_name.unwrap_or_default(),
+                email: person.user_email.unwrap_or_default(),
+            },
+            None => User {
+                id: Uuid::nil(),
+                display_name: String::new(),
+                email: String::new(),
+            },
+        };
+        Revision {
+            rev: self.revision,
+            fields: Fields {
+                changed_date: self.changed_date,
+                changed_by,
+                completed_work: self.completed_work,
+                remaining_work: self.remaining_work,
+                original_estimate: self.original_estimate,
+                title: self.title,
+                state: self.state,
+                reason: self.reason,
+                tags: self.tag_names,
+                team_project: self.project.map(|project| project.project_name),
+                iteration_path: self.iteration.map(|iteration| iteration.iteration_path),
+                area_path: self.area.map(|area| area.area_path),
+                other: HashMap::new(),
+            },
+        }
+    }
+}
+
+/// Every revision of the given work items, in the order of `ids`
+pub async fn histories(
+    client: &reqwest::Client,
+    connection: &Connection,
+    ids: &[u64],
+) -> Result<Vec<Vec<Revision>>, reqwest::Error> {
+    let mut by_item: HashMap<u64, Vec<Revision>> = HashMap::new();
+    for chunk in ids.chunks(IDS_PER_REQUEST) {
+        let ids: Vec<String> = chunk.iter().map(u64::to_string).collect();
+        let mut url = connection.analytics_url("WorkItemRevisions");
+        url.query_pairs_mut()
+            .append_pair("$filter", &format!("WorkItemId in ({})", ids.join(", ")))
+            .append_pair(
+                "$select",
+                "WorkItemId,Revision,ChangedDate,CompletedWork,RemainingWork,OriginalEstimate,Title,State,Reason,TagNames",
+            )
+            .append_pair(
+                "$expand",
+                "ChangedBy($select=UserSK,UserName,UserEmail),Project($select=ProjectName),Iteration($select=IterationPath),Area($select=AreaPath)",
+            )
+            .append_pair("$orderby", "WorkItemId,Revision");
+        let rows: Vec<Row> = get_all(client, connection, url).await?;
+        for row in rows {
+            by_item
+                .entry(row.work_item_id)
+                .or_default()
+                .push(row.into_revision());
+        }
+    }
+    Ok(ids
+        .iter()
+        .map(|id| by_item.remove(id).unwrap_or_default())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{connection, mock_server, query, response};
+
+    /// The path and decoded query pairs of a request's first line
+    fn target(request: &str) -> (String, HashMap<String, String>) {
+        let target = request.split(' ').nth(1).unwrap();
+        let url = Url::parse(&format!("http://localhost{}", target)).unwrap();
+        (
+            url.path().to_string(),
+            url.query_pairs().into_owned().collect(),
+        )
+    }
+
+    fn served_from(server: std::net::SocketAddr) -> Connection {
+        Connection {
+            server: Url::parse(&format!("http://{}/", server)).unwrap(),
+            ..connection()
+        }
+    }
+
+    #[tokio::test]
+    async fn items_changed_in_the_range_are_found_across_pages() {
+        // The second page is served from elsewhere, to know where to link to
+        let (second, second_requests) = mock_server(vec![response(
+            "200 OK",
+            &[],
+            r#"{"value": [{"WorkItemId": 3}, {"WorkItemId": 2}]}"#,
+        )])
+        .await;
+        let first_page = serde_json::json!({
+            "value": [{ "WorkItemId": 2 }, { "WorkItemId": 1 }],
+            "@odata.nextLink": format!("http://{}/next?page=2", second),
+        });
+        let (first, first_requests) =
+            mock_server(vec![response("200 OK", &[], &first_page.to_string())]).await;
+        let query = ReportQuery {
+            users: Some(vec!["A@b.c".to_string(), "o'brien@b.c".to_string()]),
+            ..query()
+        };
+
+        let items = work_items(&reqwest::Client::new(), &served_from(first), &query)
+            .await
+            .unwrap();
+        assert_eq!(
+            items.iter().map(|item| item.id).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+
+        let (path, pairs) = target(&first_requests.await.unwrap()[0]);
+        assert_eq!(path, "/org/Proj/_odata/v3.0-preview/WorkItemRevisions");
+        // A day either side, and items whose work was cleared count too
+        assert_eq!(
+            pairs["$apply"],
+            "filter(ChangedDate ge 2024-02-29T00:00:00Z and ChangedDate lt 2024-04-02T00:00:00Z \
+             and ChangedBy/UserEmail in ('a@b.c', 'o''brien@b.c'))/groupby((WorkItemId))"
+        );
+        let (path, pairs) = target(&second_requests.await.unwrap()[0]);
+        assert_eq!(path, "/next");
+        assert_eq!(pairs["page"], "2");
+    }
+
+    #[tokio::test]
+    async fn histories_are_asked_for_50_items_at_a_time() {
+        let row = |id: u64, revision: u32| {
+            serde_json::json!({
+                "WorkItemId": id,
+                "Revision": revision,
+                "ChangedDate": "2024-03-04T12:00:00Z",
+                "ChangedBy": { "UserSK": Uuid::nil(), "UserName": "A", "UserEmail": "a@b.c" },
+                "CompletedWork": f64::from(revision),
+                "Project": { "ProjectName": "Proj" },
+            })
+        };
+        let page = |rows: Vec<serde_json::Value>| {
+            response(
+                "200 OK",
+                &[],
+                &serde_json::json!({ "value": rows }).to_string(),
+            )
+        };
+        let (server, requests) = mock_server(vec![
+            page(vec![row(3, 1), row(3, 2), row(7, 1), row(51, 1)]),
+            page(vec![row(1, 1)]),
+        ])
+        .await;
+        let ids: Vec<u64> = (1..=51).rev().collect();
+
+        let histories = histories(&reqwest::Client::new(), &served_from(server), &ids)
+            .await
+            .unwrap();
+        assert_eq!(histories.len(), 51);
+        // In the order asked for, whatever order the rows came in
+        let history = |id: u64| &histories[ids.iter().position(|&other| other == id).unwrap()];
+        let revisions = |id: u64| -> Vec<(u32, Option<f64>)> {
+            history(id)
+                .iter()
+                .map(|revision| (revision.rev, revision.fields.completed_work))
+                .collect()
+        };
+        assert_eq!(revisions(3), [(1, Some(1.0)), (2, Some(2.0))]);
+        assert_eq!(revisions(7), [(1, Some(1.0))]);
+        assert_eq!(revisions(51), [(1, Some(1.0))]);
+        assert_eq!(revisions(1), [(1, Some(1.0))]);
+        assert_eq!(revisions(2), []);
+        let fields = &history(3)[0].fields;
+        assert_eq!(fields.changed_by.email, "a@b.c");
+        assert_eq!(fields.team_project.as_deref(), Some("Proj"));
+
+        let filters: Vec<String> = requests
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| target(request).1["$filter"].clone())
+            .collect();
+        let first: Vec<String> = (2..=51).rev().map(|id| id.to_string()).collect();
+        assert_eq!(
+            filters,
+            [
+                format!("WorkItemId in ({})", first.join(", ")),
+                "WorkItemId in (1)".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1139 @@
+//! Rendering a [`Report`] in the supported formats
+
+use crate::{
+    flow::{FlowReport, StateTime},
+    mapping::Mapping,
+    rates,
+    targets::DayTarget,
+    Contributor, Estimate, Group, GroupBy, Report, ReportItem, TimeEntry,
+};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use clap::ValueEnum;
+use serde_json::Value;
+mod csv;
+mod html;
+mod ics;
+mod junit;
+mod markdown;
+mod pivot;
+mod tempo;
+mod toggl;
+mod xlsx;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    /// Entries and daily sums, for spreadsheets
+    Csv,
+    /// Table of work items by day with totals
+    Markdown,
+    /// Work items by every day of the range, with row and column totals
+    Pivot,
+    /// Self-contained HTML page with per-day and per-item tables
+    Html,
+    /// Excel workbook with an entries sheet and a summary sheet
+    Xlsx,
+    /// JUnit XML with a test case per user and workday
+    Junit,
+    /// iCalendar with an event per work item and day, laid out from 9:00
+    Ics,
+    /// CSV for importing into Toggl Track or Clockify, laid out like ics
+    Toggl,
+    /// JSON worklogs for the Tempo API, laid out like ics
+    Tempo,
+}
+
+/// What the summary sums the hours by
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    #[default]
+    Day,
+    /// Week from --week-start, named after the ISO week of its Monday, e.g. 2023-W12
+    Week,
+    /// Calendar month, e.g. 2023-03
+    Month,
+}
+
+impl Interval {
+    /// The report's sums for the interval, keyed by day, week or month
+    pub fn sums(self, report: &Report) -> BTreeMap<String, f64> {
+        match self {
+            Interval::Day => report
+                .sums
+                .iter()
+                .map(|(date, hours)| (date.to_string(), *hours))
+                .collect(),
+            Interval::Week => report.weekly.clone(),
+            Interval::Month => report.monthly.clone(),
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DurationFormat {
+    /// Decimal hours, e.g. 4.5
+    #[default]
+    Decimal,
+    /// ISO 8601 durations, e.g. PT4H30M
+    Iso8601,
+}
+
+impl DurationFormat {
+    pub fn format(self, hours: f64) -> String {
+        match self {
+            DurationFormat::Decimal => hours.to_string(),
+            DurationFormat::Iso8601 => iso8601_duration(hours),
+        }
+    }
+}
+
+/// Hours as an ISO 8601 duration rounded to whole minutes
+///
+/// Negative corrections get a leading minus, which most parsers accept.
+fn iso8601_duration(hours: f64) -> String {
+    let minutes = (hours.abs() * 60.0).round() as u64;
+    let sign = if hours < 0.0 && minutes > 0 { "-" } else { "" };
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{sign}PT{m}M"),
+        (h, 0) => format!("{sign}PT{h}H"),
+        (h, m) => format!("{sign}PT{h}H{m}M"),
+    }
+}
+
+/// When the first block of time of a day starts
+const DAY_START: NaiveTime = match NaiveTime::from_hms_opt(9, 0, 0) {
+    Some(time) => time,
+    None => unreachable!(),
+};
+
+/// The time a user logged on an item on a day, for formats that need a start
+/// and an end
+struct Block<'a> {
+    item: &'a ReportItem,
+    email: &'a str,
+    start: NaiveDateTime,
+    hours: f64,
+}
+
+/// The report's time as blocks, one per item, user and day
+///
+/// Revisions only tell the day time was logged on, not when it was spent, so
+/// a day's blocks follow each other from [`DAY_START`]. Several saves of an
+/// item on a day make one block, corrections taking time off included, and
+/// what nets out to nothing or less is left out.
+fn blocks(report: &Report) -> Vec<Block<'_>> {
+    // Items by their place in the report, as ids repeat across organizations
+    let mut days: BTreeMap<NaiveDate, BTreeMap<(usize, &str), f64>> = BTreeMap::new();
+    for (index, item) in report.items.iter().enumerate() {
+        for entry in &item.entries {
+            *days
+                .entry(entry.date)
+                .or_default()
+                .entry((index, &entry.changed_by.email))
+                .or_default() += entry.hours;
+        }
+    }
+
+    let mut blocks = Vec::new();
+    for (date, logged) in days {
+        let mut start = NaiveDateTime::new(date, DAY_START);
+        for ((index, email), hours) in logged {
+            let minutes = (hours * 60.0).round() as i64;
+            if minutes <= 0 {
+                continue;
+            }
+            blocks.push(Block {
+                item: &report.items[index],
+                email,
+                start,
+                hours,
+            });
+            start += Duration::minutes(minutes);
+        }
+    }
+    blocks
+}
+
+/// Replace the hour values in a JSON report with formatted durations
+fn format_json_hours(value: &mut Value, format: DurationFormat) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match (key.as_str(), value.as_f64()) {
+                    (
+                        "hours" | "completed_work" | "total" | "expected" | "logged" | "difference"
+                        | "original_estimate" | "remaining_work" | "variance",
+                        Some(hours),
+                    ) => *value = Value::String(format.format(hours)),
+                    (
+                        "sums" | "weekly" | "monthly" | "by_organization" | "by_project"
+                        | "by_iteration" | "by_client" | "totals",
+                        _,
+                    ) => format_sums(value, format),
+                    // Tracked fields needn't hold hours
+                    ("tracked", _) => {}
+                    // Amounts of money stay numbers, only the hours change
+                    ("billing", _) => {
+                        if let Some(items) = value.get_mut("items").and_then(Value::as_array_mut) {
+                            for item in items {
+                                format_json_hours(item, format);
+                            }
+                        }
+                        if let Some(hours) = value.get("unrated_hours").and_then(Value::as_f64) {
+                            value["unrated_hours"] = Value::String(format.format(hours));
+                        }
+                    }
+                    ("by_user", _) => {
+                        if let Value::Object(users) = value {
+                            users
+                                .values_mut()
+                                .for_each(|sums| format_sums(sums, format));
+                        }
+                    }
+                    _ => format_json_hours(value, format),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                format_json_hours(value, format);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Format the hours in a map of dates, weeks or groups to hours
+fn format_sums(value: &mut Value, format: DurationFormat) {
+    if let Value::Object(sums) = value {
+        for sum in sums.values_mut() {
+            if let Some(hours) = sum.as_f64() {
+                *sum = Value::String(format.format(hours));
+            }
+        }
+    }
+}
+
+/// An extra output written alongside the main one, given as `FORMAT:PATH`
+#[derive(Debug, Clone)]
+pub struct Sink {
+    pub format: Format,
+    pub path: PathBuf,
+}
+
+impl FromStr for Sink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected FORMAT:PATH, got {:?}", s))?;
+        Ok(Sink {
+            format: Format::from_str(format, true)?,
+            path: path.into(),
+        })
+    }
+}
+
+pub fn render(
+    out: &mut dyn Write,
+    format: Format,
+    report: &Report,
+    options: &RenderOptions,
+) -> io::Result<()> {
+    match format {
+        Format::Text => write_text(out, report, options),
+        Format::Json if options.duration_format == DurationFormat::Decimal => {
+            serde_json::to_writer_pretty(&mut *out, report)?;
+            writeln!(out)
+        }
+        Format::Json => {
+            let mut value = serde_json::to_value(report)?;
+            format_json_hours(&mut value, options.duration_format);
+            serde_json::to_writer_pretty(&mut *out, &value)?;
+            writeln!(out)
+        }
+        Format::Csv => csv::write(out, report, options),
+        Format::Markdown => markdown::write(out, report, options),
+        Format::Pivot => pivot::write(out, report, options),
+        Format::Html => html::write(out, report, options),
+        Format::Xlsx => xlsx::write(out, report),
+        Format::Junit => junit::write(out, report, options),
+        Format::Ics => ics::write(out, report),
+        Format::Toggl => toggl::write(out, report),
+        Format::Tempo => tempo::write(out, report, options),
+    }
+}
+
+/// Escape text for use in XML (and HTML) content and attribute values
+///
+/// Control characters other than tab and newlines can't appear in XML at all,
+/// not even as references, so they're left out.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\u{0}'..='\u{8}'
+            | '\u{B}'
+            | '\u{C}'
+            | '\u{E}'..='\u{1F}'
+            | '\u{FFFE}'
+            | '\u{FFFF}' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn write_sink(sink: &Sink, report: &Report, options: &RenderOptions) -> io::Result<()> {
+    let mut file = File::create(&sink.path)?;
+    render(&mut file, sink.format, report, options)
+}
+
+/// Render the `cycle-time` report, which only has text, JSON and CSV
+pub fn render_flow(out: &mut dyn Write, format: Format, report: &FlowReport) -> io::Result<()> {
+    let days = |days: Option<f64>| days.map_or(String::new(), |days| format!("{:.1}", days));
+    match format {
+        Format::Text => {
+            for item in &report.items {
+                writeln!(
+                    out,
+                    "{} {} ({})",
+                    item.id,
+                    item.title,
+                    item.state.as_deref().unwrap_or("no state")
+                )?;
+                if !item.time_in_state.is_empty() {
+                    writeln!(out, "    {}", describe_states(&item.time_in_state))?;
+                }
+                if let Some(lead_time) = item.lead_time {
+                    write!(out, "    Lead time {:.1}d", lead_time)?;
+                    if let Some(cycle_time) = item.cycle_time {
+                        write!(out, ", cycle time {:.1}d", cycle_time)?;
+                    }
+                    writeln!(out)?;
+                }
+            }
+            if !report.items.is_empty() {
+                writeln!(out)?;
+            }
+            writeln!(
+                out,
+                "Time in state: {}",
+                describe_states(&report.time_in_state)
+            )?;
+            write!(out, "Finished: {}", report.finished)?;
+            if let Some(lead_time) = report.lead_time {
+                write!(out, ", lead time {:.1}d on average", lead_time)?;
+            }
+            if let Some(cycle_time) = report.cycle_time {
+                write!(out, ", cycle time {:.1}d on average", cycle_time)?;
+            }
+            writeln!(out)
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, report)?;
+            writeln!(out)
+        }
+        Format::Csv => {
+            let mut header = vec!["work_item", "title", "state", "lead_time", "cycle_time"];
+            header.extend(report.time_in_state.iter().map(|time| time.state.as_str()));
+            csv::write_row(out, &header)?;
+            for item in &report.items {
+                let mut row = vec![
+                    item.id.to_string(),
+                    item.title.clone(),
+                    item.state.clone().unwrap_or_default(),
+                    days(item.lead_time),
+                    days(item.cycle_time),
+                ];
+                row.extend(report.time_in_state.iter().map(|total| {
+                    days(
+                        item.time_in_state
+                            .iter()
+                            .find(|time| time.state == total.state)
+                            .map(|time| time.days),
+                    )
+                }));
+                let row: Vec<&str> = row.iter().map(String::as_str).collect();
+                csv::write_row(out, &row)?;
+            }
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "cycle-time only prints text, json or csv, not {}",
+                value_name(&format)
+            ),
+        )),
+    }
+}
+
+/// E.g. "New 1.5d, Active 3.0d"
+fn describe_states(times: &[StateTime]) -> String {
+    if times.is_empty() {
+        return "none".to_string();
+    }
+    let times: Vec<String> = times
+        .iter()
+        .map(|time| format!("{} {:.1}d", time.state, time.days))
+        .collect();
+    times.join(", ")
+}
+
+/// How the report is laid out
+#[derive(Debug, Default)]
+pub struct RenderOptions {
+    pub duration_format: DurationFormat,
+    pub merge_same_day: bool,
+    pub top_contributors: Option<usize>,
+    /// Users with fewer hours are summed up as "Others"
+    pub minor_threshold: Option<f64>,
+    pub flag_ambiguous: bool,
+    pub completion_ratio: bool,
+    /// Estimates next to the time, in the text and CSV output
+    pub estimates: bool,
+    pub show_tags: bool,
+    /// What the summary of the text and CSV output sums by
+    pub interval: Interval,
+    /// Hours a workday needs to pass in the JUnit output
+    pub expected_min: f64,
+    /// The Jira issue of each work item in the Tempo output
+    pub tempo_issues: Vec<Mapping<String>>,
+    /// Atlassian account ids by lowercased email, for the Tempo output
+    pub tempo_accounts: HashMap<String, String>,
+}
+
+/// One printed entry line, covering several revisions when same-day saves are merged
+struct Line<'a> {
+    /// The last entry in the line, for date, user and completed work
+    entry: &'a TimeEntry,
+    hours: f64,
+    revs: Vec<u32>,
+}
+
+fn lines(entries: &[TimeEntry], merge_same_day: bool) -> Vec<Line<'_>> {
+    let mut lines: Vec<Line> = Vec::new();
+    for entry in entries {
+        match lines.last_mut() {
+            Some(line)
+                if merge_same_day
+                    && line.entry.date == entry.date
+                    && line.entry.changed_by.email == entry.changed_by.email =>
+            {
+                line.entry = entry;
+                line.hours += entry.hours;
+                line.revs.push(entry.rev);
+            }
+            _ => lines.push(Line {
+                entry,
+                hours: entry.hours,
+                revs: vec![entry.rev],
+            }),
+        }
+    }
+    lines
+}
+
+fn write_text(out: &mut dyn Write, report: &Report, options: &RenderOptions) -> io::Result<()> {
+    for item in &report.items {
+        write!(out, "{} {}", item.id, item.title)?;
+        if let Some(reason) = &item.state_reason {
+            write!(out, " [{}]", reason)?;
+        }
+        if options.flag_ambiguous && item.ambiguous {
+            write!(out, " (approximate)")?;
+        }
+        if let Some(percent) = item.percent_complete.filter(|_| options.completion_ratio) {
+            write!(out, " {:.0}% complete", percent)?;
+        }
+        if options.show_tags && !item.tags.is_empty() {
+            write!(out, " (tags: {})", item.tags.join(", "))?;
+        }
+        writeln!(out)?;
+        for line in lines(&item.entries, options.merge_same_day) {
+            let revs: Vec<String> = line.revs.iter().map(u32::to_string).collect();
+            writeln!(
+                out,
+                "\t{} {} {} {} #{}@rev{}",
+                line.entry.date,
+                line.entry.changed_by,
+                options.duration_format.format(line.entry.completed_work),
+                options.duration_format.format(line.hours),
+                item.id,
+                revs.join(",")
+            )?;
+        }
+        writeln!(
+            out,
+            "\tTotal {}",
+            options.duration_format.format(item.total)
+        )?;
+        if let Some(estimate) = item.estimate.as_ref().filter(|_| options.estimates) {
+            writeln!(out, "\t{}", describe_estimate(estimate, options))?;
+        }
+        if let Some(n) = options.top_contributors {
+            let top = top_contributors(&item.contributors, n, options);
+            writeln!(out, "\t{}", top)?;
+        }
+    }
+    match (options.duration_format, options.interval) {
+        (DurationFormat::Decimal, Interval::Day) => writeln!(out, "{:#?}", report.sums)?,
+        (DurationFormat::Decimal, interval) => writeln!(out, "{:#?}", interval.sums(report))?,
+        (format, interval) => {
+            let sums: BTreeMap<_, _> = interval
+                .sums(report)
+                .into_iter()
+                .map(|(key, hours)| (key, format.format(hours)))
+                .collect();
+            writeln!(out, "{:#?}", sums)?
+        }
+    }
+    writeln!(
+        out,
+        "Total {}",
+        options.duration_format.format(report.total)
+    )?;
+
+    if let Some(estimates) = report.estimates.as_ref().filter(|_| options.estimates) {
+        writeln!(out, "All items: {}", describe_estimate(estimates, options))?;
+    }
+
+    if let Some(target) = &report.target {
+        let hours = |hours: f64| options.duration_format.format(hours);
+        writeln!(out, "Target:")?;
+        for day in &target.days {
+            writeln!(
+                out,
+                "\t{} {} {} of {}{}{}",
+                day.date.format("%a"),
+                day.date,
+                hours(day.logged),
+                hours(day.expected),
+                difference(day.difference, options),
+                holiday(day)
+            )?;
+        }
+        writeln!(
+            out,
+            "\tTotal {} of {}{}",
+            hours(target.logged),
+            hours(target.expected),
+            difference(target.difference, options)
+        )?;
+    }
+
+    if let Some(billing) = &report.billing {
+        let amount = |amount: f64| rates::format_amount(amount, &billing.currency);
+        writeln!(out, "Billing:")?;
+        for item in &billing.items {
+            writeln!(
+                out,
+                "\t{} {} {} {}",
+                item.id,
+                item.title,
+                options.duration_format.format(item.hours),
+                amount(item.amount)
+            )?;
+        }
+        if billing.by_user.len() > 1 {
+            for (user, sum) in &billing.by_user {
+                writeln!(out, "\t{} {}", user, amount(*sum))?;
+            }
+        }
+        writeln!(out, "\tTotal {}", amount(billing.total))?;
+        if billing.unrated_hours != 0.0 {
+            writeln!(
+                out,
+                "\t{} without a rate, not billed",
+                options.duration_format.format(billing.unrated_hours)
+            )?;
+        }
+    }
+
+    if report.by_user.len() > 1 {
+        writeln!(out, "By user:")?;
+        let (major, minor) = collapse_minor(
+            &report.by_user,
+            |(_, weekly)| weekly.values().sum(),
+            options,
+        );
+        let mut listed: Vec<(String, BTreeMap<String, f64>)> = major
+            .into_iter()
+            .map(|(user, weekly)| (user.clone(), weekly.clone()))
+            .collect();
+        if let Some(minor) = minor {
+            let mut weekly = BTreeMap::new();
+            for (_, weeks) in &minor.users {
+                for (week, hours) in *weeks {
+                    *weekly.entry(week.clone()).or_default() += hours;
+                }
+            }
+            listed.push((minor.label(), weekly));
+        }
+        for (user, weekly) in &listed {
+            writeln!(out, "\t{}", user)?;
+            for (week, hours) in weekly {
+                writeln!(
+                    out,
+                    "\t\t{} {}",
+                    week,
+                    options.duration_format.format(*hours)
+                )?;
+            }
+        }
+    }
+
+    if report.by_organization.len() > 1 {
+        writeln!(out, "By organization:")?;
+        for (organization, hours) in &report.by_organization {
+            let hours = options.duration_format.format(*hours);
+            writeln!(out, "\t{} {}", organization, hours)?;
+        }
+    }
+
+    if report.by_project.len() > 1 {
+        writeln!(out, "By project:")?;
+        for (project, hours) in &report.by_project {
+            writeln!(
+                out,
+                "\t{} {}",
+                project,
+                options.duration_format.format(*hours)
+            )?;
+        }
+    }
+
+    if let Some(by_client) = &report.by_client {
+        writeln!(out, "By client:")?;
+        for (client, hours) in by_client {
+            let hours = options.duration_format.format(*hours);
+            writeln!(out, "\t{} {}", client, hours)?;
+        }
+    }
+
+    for (field, changes) in &report.tracked {
+        writeln!(out, "{}:", field)?;
+        for (date, change) in changes {
+            writeln!(out, "\t{} {}", date, signed(*change))?;
+        }
+        writeln!(out, "\tTotal {}", signed(changes.values().sum()))?;
+    }
+
+    if let Some(grouped) = &report.grouped {
+        let axes: Vec<String> = grouped.by.iter().map(value_name).collect();
+        write!(out, "By {}", axes.join(", "))?;
+        if let Some(by_tag) = &report.by_tag {
+            write!(out, " ({} split)", value_name(&by_tag.split))?;
+        }
+        writeln!(out, ":")?;
+        write_groups(out, &grouped.groups, &grouped.by, 1, options)?;
+    }
+
+    if let Some(rolled_up) = &report.rolled_up {
+        writeln!(out, "By {}:", value_name(&rolled_up.to))?;
+        for total in &rolled_up.totals {
+            let hours = options.duration_format.format(total.hours);
+            match &total.ancestor {
+                Some(ancestor) => writeln!(out, "\t{} {} {}", ancestor.id, ancestor.title, hours)?,
+                None => writeln!(out, "\t(none) {}", hours)?,
+            }
+        }
+    }
+
+    if let Some(sampled) = &report.sampled {
+        writeln!(out, "sampled: {} of {} items", sampled.items, sampled.of)?;
+    }
+
+    if options.flag_ambiguous {
+        let ambiguous: Vec<_> = report.items.iter().filter(|item| item.ambiguous).collect();
+        if !ambiguous.is_empty() {
+            writeln!(out, "Approximate, several users logged time on:")?;
+            for item in ambiguous {
+                let hours = item.total;
+                let hours = options.duration_format.format(hours);
+                writeln!(out, "\t{} {} {}", item.id, item.title, hours)?;
+            }
+        }
+    }
+
+    if let Some(n) = options.top_contributors {
+        let top = top_contributors(&report.contributors, n, options);
+        writeln!(out, "{}", top)?;
+    }
+
+    if let Some(buckets) = &report.buckets {
+        for bucket in buckets {
+            let hours = options.duration_format.format(bucket.hours);
+            writeln!(out, "{} - {} {}", bucket.from, bucket.to, hours)?;
+        }
+    }
+    Ok(())
+}
+
+/// A day off as ` [Christmas Eve]`, nothing on other days
+pub(crate) fn holiday(day: &DayTarget) -> String {
+    match &day.holiday {
+        Some(name) => format!(" [{}]", name),
+        None => String::new(),
+    }
+}
+
+/// A difference from the target as ` (+1.5)` or ` (-2)`, nothing when on target
+pub(crate) fn difference(hours: f64, options: &RenderOptions) -> String {
+    // Sums of fractional hours are rarely exactly equal
+    if hours.abs() < 0.005 {
+        return String::new();
+    }
+    let sign = if hours > 0.0 { "+" } else { "-" };
+    format!(" ({}{})", sign, options.duration_format.format(hours.abs()))
+}
+
+/// `Estimate 8, remaining 2, completed 7, 1 over`
+fn describe_estimate(estimate: &Estimate, options: &RenderOptions) -> String {
+    let hours = |hours: f64| options.duration_format.format(hours);
+    // Sums of fractional hours are rarely exactly equal
+    let variance = match estimate.variance {
+        variance if variance.abs() < 0.005 => "on estimate".to_string(),
+        variance if variance > 0.0 => format!("{} over", hours(variance)),
+        variance => format!("{} under", hours(-variance)),
+    };
+    format!(
+        "Estimate {}, remaining {}, completed {}, {}",
+        hours(estimate.original_estimate),
+        hours(estimate.remaining_work),
+        hours(estimate.completed_work),
+        variance
+    )
+}
+
+/// A change in a tracked field, as `+1.5` or `-2`
+pub(crate) fn signed(change: f64) -> String {
+    format!("{:+}", (change * 100.0).round() / 100.0)
+}
+
+/// Hours per client and day, for the formats laid out by day
+fn client_day_sums(report: &Report) -> BTreeMap<&str, BTreeMap<NaiveDate, f64>> {
+    let mut sums: BTreeMap<&str, BTreeMap<NaiveDate, f64>> = BTreeMap::new();
+    for item in &report.items {
+        let client = item.client.as_deref().unwrap_or(crate::NO_CLIENT);
+        for entry in &item.entries {
+            *sums
+                .entry(client)
+                .or_default()
+                .entry(entry.date)
+                .or_default() += entry.hours;
+        }
+    }
+    sums
+}
+
+/// How a value is spelled on the command line
+fn value_name(value: &impl ValueEnum) -> String {
+    value.to_possible_value().unwrap().get_name().to_string()
+}
+
+/// `groups` by the first of the `axes`, their subgroups by the next and so on
+fn write_groups(
+    out: &mut dyn Write,
+    groups: &[Group],
+    axes: &[GroupBy],
+    depth: usize,
+    options: &RenderOptions,
+) -> io::Result<()> {
+    let indent = "\t".repeat(depth);
+    let (groups, minor) = match axes.first() {
+        Some(GroupBy::User) => collapse_minor(groups, |group| group.hours, options),
+        _ => (groups.iter().collect(), None),
+    };
+    for group in groups {
+        let hours = options.duration_format.format(group.hours);
+        writeln!(out, "{}{} {}", indent, group.key, hours)?;
+        write_groups(
+            out,
+            &group.groups,
+            axes.get(1..).unwrap_or_default(),
+            depth + 1,
+            options,
+        )?;
+    }
+    if let Some(minor) = minor {
+        let hours = options.duration_format.format(minor.hours);
+        writeln!(out, "{}{} {}", indent, minor.label(), hours)?;
+    }
+    Ok(())
+}
+
+/// The users under --minor-threshold hours, summed up as one "Others" entry
+struct Minor<T> {
+    users: Vec<T>,
+    hours: f64,
+}
+
+impl<T> Minor<T> {
+    fn label(&self) -> String {
+        format!("Others ({} users)", self.users.len())
+    }
+}
+
+/// Split the users into those listed one by one, in their order, and those
+/// with too few hours to, when they're collapsed
+fn collapse_minor<T>(
+    users: impl IntoIterator<Item = T>,
+    hours: impl Fn(&T) -> f64,
+    options: &RenderOptions,
+) -> (Vec<T>, Option<Minor<T>>) {
+    let Some(threshold) = options.minor_threshold else {
+        return (users.into_iter().collect(), None);
+    };
+    let (major, minor): (Vec<T>, Vec<T>) =
+        users.into_iter().partition(|user| hours(user) >= threshold);
+    let minor = (!minor.is_empty()).then(|| Minor {
+        hours: minor.iter().map(&hours).sum(),
+        users: minor,
+    });
+    (major, minor)
+}
+
+fn top_contributors(contributors: &[Contributor], n: usize, options: &RenderOptions) -> String {
+    let hours = |hours: f64| match options.duration_format {
+        DurationFormat::Decimal => format!("{}h", hours),
+        format => format.format(hours),
+    };
+
+    let (major, minor) = collapse_minor(contributors, |contributor| contributor.hours, options);
+    let mut top: Vec<String> = major
+        .iter()
+        .take(n)
+        .map(|contributor| format!("{} {}", contributor.display_name, hours(contributor.hours)))
+        .collect();
+    if let Some(minor) = minor {
+        top.push(format!("{} {}", minor.label(), hours(minor.hours)));
+    }
+    format!("Top contributors: {}", top.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{rendered, report, revision};
+    use serde_json::json;
+
+    #[test]
+    fn iso8601_durations() {
+        let iso = |hours| DurationFormat::Iso8601.format(hours);
+        assert_eq!(iso(4.5), "PT4H30M");
+        assert_eq!(iso(0.25), "PT15M");
+        assert_eq!(iso(8.0), "PT8H");
+        assert_eq!(iso(0.0), "PT0M");
+        // To the nearest minute, 1.333... being 1h 20m
+        assert_eq!(iso(4.0 / 3.0), "PT1H20M");
+        assert_eq!(iso(-1.5), "-PT1H30M");
+        assert_eq!(DurationFormat::Decimal.format(4.5), "4.5");
+    }
+
+    #[test]
+    fn iso8601_durations_in_json() {
+        let report = report(vec![vec![revision(
+            1,
+            "2024-03-04",
+            "a@b.c",
+            json!({ crate::COMPLETED_WORK: 4.5 }),
+        )]]);
+        let options = RenderOptions {
+            duration_format: DurationFormat::Iso8601,
+            ..RenderOptions::default()
+        };
+        let json: Value = serde_json::from_str(&rendered(Format::Json, &report, &options)).unwrap();
+        assert_eq!(json["total"], json!("PT4H30M"));
+        assert_eq!(json["items"][0]["entries"][0]["hours"], json!("PT4H30M"));
+        // Not everything numeric is hours
+        assert_eq!(json["items"][0]["entries"][0]["rev"], json!(1));
+    }
+
+    #[test]
+    fn merge_same_day_sums_the_revisions_of_a_day() {
+        let report = report(vec![vec![
+            revision(
+                1,
+                "2024-03-04",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 1.0 }),
+            ),
+            revision(
+                2,
+                "2024-03-04",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 1.5 }),
+            ),
+            revision(
+                3,
+                "2024-03-04",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 3.0 }),
+            ),
+            revision(
+                4,
+                "2024-03-05",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 4.0 }),
+            ),
+        ]]);
+        let options = RenderOptions {
+            merge_same_day: true,
+            ..RenderOptions::default()
+        };
+
+        let text = rendered(Format::Text, &report, &options);
+        let lines: Vec<&str> = text.lines().filter(|line| line.contains("@rev")).collect();
+        assert_eq!(lines.len(), 2, "{}", text);
+        assert!(lines[0].starts_with("\t2024-03-04 "), "{}", lines[0]);
+        assert!(lines[0].ends_with(" 3 3 #1@rev1,2,3"), "{}", lines[0]);
+        assert!(lines[1].ends_with(" 4 1 #1@rev4"), "{}", lines[1]);
+
+        // The JSON keeps every revision
+        let json: Value = serde_json::from_str(&rendered(Format::Json, &report, &options)).unwrap();
+        assert_eq!(json["items"][0]["entries"].as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn minor_users_are_collapsed_everywhere() {
+        let hours = [
+            ("major@b.c", 5.0),
+            ("minor1@b.c", 0.5),
+            ("second@b.c", 3.0),
+            ("minor2@b.c", 0.25),
+            ("minor3@b.c", 0.5),
+        ];
+        let mut report = report(
+            hours
+                .iter()
+                .map(|(email, hours)| {
+                    vec![revision(
+                        1,
+                        "2024-03-04",
+                        email,
+                        json!({ crate::COMPLETED_WORK: hours }),
+                    )]
+                })
+                .collect(),
+        );
+        report.grouped = Some(crate::group_totals(
+            &report.items,
+            &[GroupBy::User, GroupBy::Date],
+            crate::TagSplit::Duplicate,
+        ));
+        let options = RenderOptions {
+            top_contributors: Some(5),
+            minor_threshold: Some(1.0),
+            ..RenderOptions::default()
+        };
+
+        let text = rendered(Format::Text, &report, &options);
+        let others: Vec<&str> = text
+            .lines()
+            .filter(|line| line.contains("Others") && !line.starts_with("\tTop"))
+            .collect();
+        assert_eq!(
+            others,
+            [
+                "\tOthers (3 users)",
+                "\tOthers (3 users) 1.25",
+                "Top contributors: major@b.c 5h, second@b.c 3h, Others (3 users) 1.25h",
+            ],
+            "{}",
+            text
+        );
+        for major in ["major@b.c", "second@b.c"] {
+            // In the by-user summary and the groups, with its days under it
+            assert_eq!(
+                text.lines()
+                    .filter(|line| line.starts_with(&format!("\t{}", major)))
+                    .count(),
+                2,
+                "{}",
+                text
+            );
+        }
+        // The items still say who did them, the summaries don't
+        assert!(!text.contains("\tminor1@b.c"), "{}", text);
+        // The minors' weeks are summed up under "Others"
+        assert!(
+            text.contains("\tOthers (3 users)\n\t\t2024-W10 1.25\n"),
+            "{}",
+            text
+        );
+
+        // Without the threshold nobody is collapsed
+        let text = rendered(Format::Text, &report, &RenderOptions::default());
+        assert!(!text.contains("Others"), "{}", text);
+        assert!(text.contains("minor2@b.c"), "{}", text);
+    }
+
+    #[test]
+    fn every_sink_gets_the_same_report() {
+        let report = report(vec![vec![
+            revision(
+                1,
+                "2024-03-04",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 2.0 }),
+            ),
+            revision(
+                2,
+                "2024-03-05",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 3.5 }),
+            ),
+        ]]);
+        let dir = std::env::temp_dir().join(format!("sinks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let json_path = dir.join("report.json");
+        let csv_path = dir.join("report.csv");
+        let sinks: Vec<Sink> = [
+            format!("json:{}", json_path.display()),
+            format!("csv:{}", csv_path.display()),
+        ]
+        .iter()
+        .map(|sink| sink.parse().unwrap())
+        .collect();
+        for sink in &sinks {
+            write_sink(sink, &report, &RenderOptions::default()).unwrap();
+        }
+
+        let json: Value =
+            serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        let csv = std::fs::read_to_string(&csv_path).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(json["total"], json!(3.5));
+        let entries: Vec<(String, f64)> = json["items"][0]["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                (
+                    entry["date"].as_str().unwrap().to_string(),
+                    entry["hours"].as_f64().unwrap(),
+                )
+            })
+            .collect();
+        let rows: Vec<(String, f64)> = csv
+            .lines()
+            .skip(1)
+            .take_while(|row| !row.is_empty())
+            .map(|row| {
+                let columns: Vec<&str> = row.split(',').collect();
+                (columns[0].to_string(), columns[6].parse().unwrap())
+            })
+            .collect();
+        assert_eq!(entries, rows);
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[test]
+    fn reasons_reach_the_json_and_csv() {
+        let revisions: crate::Revisions =
+            serde_json::from_str(include_str!("../tests/fixtures/state-reason.json")).unwrap();
+        let query = crate::ReportQuery {
+            since_state: Some("Resolved".to_string()),
+            ..crate::testing::query()
+        };
+        let items = crate::collect_items(
+            &crate::testing::connection(),
+            &query,
+            vec![crate::testing::work_item(7)],
+            vec![revisions.value],
+        );
+        let report = Report::new(&query, items, None);
+        let options = RenderOptions::default();
+
+        let text = rendered(Format::Text, &report, &options);
+        assert!(text.starts_with("7 Reasons [Work finished]\n"), "{}", text);
+
+        let json: Value = serde_json::from_str(&rendered(Format::Json, &report, &options)).unwrap();
+        assert_eq!(json["items"][0]["state_reason"], json!("Work finished"));
+        assert_eq!(
+            json["items"][0]["entries"][0]["reason"],
+            json!("Work finished")
+        );
+
+        let csv = rendered(Format::Csv, &report, &options);
+        let rows: Vec<&str> = csv.lines().take(3).collect();
+        assert!(rows[0].ends_with(",rev,reason"), "{}", rows[0]);
+        assert!(rows[1].ends_with(",2,Work finished"), "{}", rows[1]);
+        assert!(rows[2].ends_with(",3,Work finished"), "{}", rows[2]);
+    }
+
+    #[test]
+    fn missing_reasons_are_left_empty() {
+        let report = report(vec![vec![revision(
+            1,
+            "2024-03-04",
+            "a@b.c",
+            json!({ crate::COMPLETED_WORK: 1.0 }),
+        )]]);
+        let options = RenderOptions::default();
+        let json: Value = serde_json::from_str(&rendered(Format::Json, &report, &options)).unwrap();
+        assert_eq!(json["items"][0]["entries"][0]["reason"], Value::Null);
+        let csv = rendered(Format::Csv, &report, &options);
+        assert!(csv.lines().nth(1).unwrap().ends_with(",1,"), "{}", csv);
+    }
+
+    #[test]
+    fn detailed_output_has_the_revision_numbers() {
+        let report = report(vec![vec![
+            revision(1, "2024-03-04", "a@b.c", json!({ "System.State": "New" })),
+            revision(
+                2,
+                "2024-03-04",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 1.5 }),
+            ),
+            revision(
+                3,
+                "2024-03-05",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 2.0 }),
+            ),
+        ]]);
+        let options = RenderOptions::default();
+
+        let text = rendered(Format::Text, &report, &options);
+        assert!(text.contains("#1@rev2"), "{}", text);
+        assert!(text.contains("#1@rev3"), "{}", text);
+
+        let json: Value = serde_json::from_str(&rendered(Format::Json, &report, &options)).unwrap();
+        let revs: Vec<&Value> = json["items"][0]["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| &entry["rev"])
+            .collect();
+        assert_eq!(revs, [&json!(2), &json!(3)]);
+
+        let csv = rendered(Format::Csv, &report, &options);
+        let mut rows = csv.lines();
+        let header: Vec<&str> = rows.next().unwrap().split(',').collect();
+        let rev = header.iter().position(|column| *column == "rev").unwrap();
+        let revs: Vec<&str> = rows
+            .take(2)
+            .map(|row| row.split(',').nth(rev).unwrap())
+            .collect();
+        assert_eq!(revs, ["2", "3"]);
+    }
+}
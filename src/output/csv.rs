@@ -0,0 +1,192 @@
+//! CSV for spreadsheets: one row per entry, then the daily sums, then the
+//! totals per client with `--client`, the daily changes of the `--track`
+//! fields, then per work item, with `--estimates` too, and overall
+
+use super::{value_name, Interval, RenderOptions};
+use crate::Report;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+};
+
+pub fn write(out: &mut dyn Write, report: &Report, options: &RenderOptions) -> io::Result<()> {
+    let hours = |hours: f64| options.duration_format.format(hours);
+
+    writeln!(
+        out,
+        "date,organization,project,work_item,title,user,hours,completed_work,rev,reason"
+    )?;
+    for item in &report.items {
+        for entry in &item.entries {
+            write_row(
+                out,
+                &[
+                    &entry.date.to_string(),
+                    &item.organization,
+                    &item.project,
+                    &item.id.to_string(),
+                    &item.title,
+                    &entry.changed_by.email,
+                    &hours(entry.hours),
+                    &hours(entry.completed_work),
+                    &entry.rev.to_string(),
+                    entry.reason.as_deref().unwrap_or(""),
+                ],
+            )?;
+        }
+    }
+
+    writeln!(out)?;
+    let key = match options.interval {
+        Interval::Day => "date".to_string(),
+        interval => value_name(&interval),
+    };
+    writeln!(out, "{},hours", key)?;
+    for (key, sum) in options.interval.sums(report) {
+        write_row(out, &[&key, &hours(sum)])?;
+    }
+
+    if let Some(by_client) = &report.by_client {
+        writeln!(out)?;
+        writeln!(out, "client,hours")?;
+        for (client, sum) in by_client {
+            write_row(out, &[client, &hours(*sum)])?;
+        }
+    }
+
+    // A column per tracked field
+    let fields: Vec<&str> = report.tracked.keys().map(String::as_str).collect();
+    if !fields.is_empty() {
+        writeln!(out)?;
+        let dates: BTreeSet<_> = report.tracked.values().flat_map(BTreeMap::keys).collect();
+        write_row(out, &[&["date"], &fields[..]].concat())?;
+        for date in dates {
+            let changes = report.tracked.values().map(|changes| changes.get(date));
+            let row: Vec<String> = std::iter::once(date.to_string())
+                .chain(changes.map(|change| change.copied().unwrap_or(0.0).to_string()))
+                .collect();
+            write_row(out, &row.iter().map(String::as_str).collect::<Vec<_>>())?;
+        }
+    }
+
+    writeln!(out)?;
+    let estimates: &[&str] = if options.estimates {
+        &[
+            "original_estimate",
+            "remaining_work",
+            "completed_work",
+            "variance",
+        ]
+    } else {
+        &[]
+    };
+    write_row(
+        out,
+        &[&["work_item", "title", "hours"], estimates, &fields[..]].concat(),
+    )?;
+    for item in &report.items {
+        let estimate = item.estimate.clone().unwrap_or_default();
+        let estimate = [
+            estimate.original_estimate,
+            estimate.remaining_work,
+            estimate.completed_work,
+            estimate.variance,
+        ];
+        let changes = fields.iter().map(|field| {
+            let changes = item.tracked.get(*field);
+            changes.map_or(0.0, |changes| changes.values().sum::<f64>())
+        });
+        let row: Vec<String> = [item.id.to_string(), item.title.clone(), hours(item.total)]
+            .into_iter()
+            .chain(
+                estimate[..estimates.len()]
+                    .iter()
+                    .map(|estimated| hours(*estimated)),
+            )
+            .chain(changes.map(|change| change.to_string()))
+            .collect();
+        write_row(out, &row.iter().map(String::as_str).collect::<Vec<_>>())?;
+    }
+    write_row(out, &["total", "", &hours(report.total)])
+}
+
+pub(super) fn write_row(out: &mut dyn Write, fields: &[&str]) -> io::Result<()> {
+    let fields: Vec<String> = fields.iter().map(|field| escape(field)).collect();
+    writeln!(out, "{}", fields.join(","))
+}
+
+/// Quote a field if it holds a separator, quote or line break
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::Format,
+        testing::{rendered, report, revision},
+    };
+    use serde_json::json;
+
+    fn two_items() -> Report {
+        report(vec![
+            vec![
+                revision(
+                    1,
+                    "2024-03-04",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 1.5, "System.Title": "Fix \"login\", again" }),
+                ),
+                revision(
+                    2,
+                    "2024-03-05",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 4.0, "System.Title": "Fix \"login\", again" }),
+                ),
+            ],
+            vec![revision(
+                1,
+                "2024-03-05",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 2.0, "System.Reason": "New\nline" }),
+            )],
+        ])
+    }
+
+    #[test]
+    fn entries_then_sums_then_items() {
+        assert_eq!(
+            rendered(Format::Csv, &two_items(), &RenderOptions::default()),
+            r#"date,organization,project,work_item,title,user,hours,completed_work,rev,reason
+2024-03-04,org,Proj,1,"Fix ""login"", again",a@b.c,1.5,1.5,1,
+2024-03-05,org,Proj,1,"Fix ""login"", again",a@b.c,2.5,4,2,
+2024-03-05,org,Proj,2,Item,a@b.c,2,2,1,"New
+line"
+
+date,hours
+2024-03-04,1.5
+2024-03-05,4.5
+
+work_item,title,hours
+1,"Fix ""login"", again",4
+2,Item,2
+total,,6
+"#
+        );
+    }
+
+    #[test]
+    fn sums_follow_the_interval() {
+        let options = RenderOptions {
+            interval: Interval::Week,
+            ..RenderOptions::default()
+        };
+        let csv = rendered(Format::Csv, &two_items(), &options);
+        assert!(csv.contains("\n\nweek,hours\n2024-W10,6\n\n"), "{}", csv);
+    }
+}
@@ -0,0 +1,210 @@
+//! Self-contained HTML report, for people who'd rather not read a terminal
+
+use super::{escape_xml, RenderOptions};
+use crate::{rates, Report};
+use std::io::{self, Write};
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:.3em .6em;text-align:left}\
+td.hours,th.hours{text-align:right}\
+thead,tfoot{background:#f0f0f0;font-weight:bold}";
+
+pub fn write(out: &mut dyn Write, report: &Report, options: &RenderOptions) -> io::Result<()> {
+    let hours = |hours: f64| options.duration_format.format(hours);
+    let title = format!(
+        "Time for {} from {} to {}",
+        report.user, report.from, report.to
+    );
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html><head><meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>{}</title>", escape_xml(&title))?;
+    writeln!(out, "<style>{}</style></head><body>", STYLE)?;
+    writeln!(out, "<h1>{}</h1>", escape_xml(&title))?;
+
+    writeln!(out, "<h2>Per day</h2>")?;
+    writeln!(
+        out,
+        "<table><thead><tr><th>Day</th><th class=\"hours\">Hours</th></tr></thead><tbody>"
+    )?;
+    for (day, sum) in &report.sums {
+        writeln!(
+            out,
+            "<tr><td>{}</td><td class=\"hours\">{}</td></tr>",
+            day.format("%a %Y-%m-%d"),
+            hours(*sum)
+        )?;
+    }
+    writeln!(
+        out,
+        "</tbody><tfoot><tr><td>Total</td><td class=\"hours\">{}</td></tr></tfoot></table>",
+        hours(report.total)
+    )?;
+
+    writeln!(out, "<h2>Per work item</h2>")?;
+    writeln!(
+        out,
+        "<table><thead><tr><th>Work item</th><th>Title</th><th class=\"hours\">Hours</th></tr></thead><tbody>"
+    )?;
+    for item in &report.items {
+        let total = item.total;
+        writeln!(
+            out,
+            "<tr><td><a href=\"{}\">#{}</a></td><td>{}</td><td class=\"hours\">{}</td></tr>",
+            escape_xml(&item.url),
+            item.id,
+            escape_xml(&item.title),
+            hours(total)
+        )?;
+    }
+    writeln!(
+        out,
+        "</tbody><tfoot><tr><td colspan=\"2\">Total</td><td class=\"hours\">{}</td></tr></tfoot></table>",
+        hours(report.total)
+    )?;
+
+    if let Some(by_client) = &report.by_client {
+        writeln!(out, "<h2>Per client</h2>")?;
+        writeln!(
+            out,
+            "<table><thead><tr><th>Client</th><th class=\"hours\">Hours</th></tr></thead><tbody>"
+        )?;
+        for (client, sum) in by_client {
+            writeln!(
+                out,
+                "<tr><td>{}</td><td class=\"hours\">{}</td></tr>",
+                escape_xml(client),
+                hours(*sum)
+            )?;
+        }
+        writeln!(
+            out,
+            "</tbody><tfoot><tr><td>Total</td><td class=\"hours\">{}</td></tr></tfoot></table>",
+            hours(report.total)
+        )?;
+    }
+
+    if let Some(billing) = &report.billing {
+        let amount = |amount: f64| rates::format_amount(amount, &billing.currency);
+        writeln!(out, "<h2>Billing</h2>")?;
+        writeln!(
+            out,
+            "<table><thead><tr><th>Work item</th><th>Title</th><th class=\"hours\">Hours</th><th class=\"hours\">Amount</th></tr></thead><tbody>"
+        )?;
+        for item in &billing.items {
+            writeln!(
+                out,
+                "<tr><td>#{}</td><td>{}</td><td class=\"hours\">{}</td><td class=\"hours\">{}</td></tr>",
+                item.id,
+                escape_xml(&item.title),
+                hours(item.hours),
+                amount(item.amount)
+            )?;
+        }
+        writeln!(
+            out,
+            "</tbody><tfoot><tr><td colspan=\"3\">Total</td><td class=\"hours\">{}</td></tr></tfoot></table>",
+            amount(billing.total)
+        )?;
+        if billing.unrated_hours != 0.0 {
+            writeln!(
+                out,
+                "<p>{} without a rate aren't billed.</p>",
+                hours(billing.unrated_hours)
+            )?;
+        }
+    }
+
+    writeln!(out, "</body></html>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::Format,
+        testing::{rendered, report, revision},
+    };
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn days_items_and_clients_in_tables() {
+        let mut report = report(vec![
+            vec![
+                revision(
+                    1,
+                    "2024-03-04",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 1.5, "System.Title": "<script>alert('hi')</script>" }),
+                ),
+                revision(
+                    2,
+                    "2024-03-05",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 4.0, "System.Title": "<script>alert('hi')</script>" }),
+                ),
+            ],
+            vec![revision(
+                1,
+                "2024-03-05",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 2.0 }),
+            )],
+        ]);
+        report.by_client = Some(BTreeMap::from([("Fish & \"chips\"".to_string(), 6.0)]));
+
+        assert_eq!(
+            rendered(Format::Html, &report, &RenderOptions::default()),
+            format!(
+                r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8">
+<title>Time for a@b.c from 2024-03-01 to 2024-03-31</title>
+<style>{}</style></head><body>
+<h1>Time for a@b.c from 2024-03-01 to 2024-03-31</h1>
+<h2>Per day</h2>
+<table><thead><tr><th>Day</th><th class="hours">Hours</th></tr></thead><tbody>
+<tr><td>Mon 2024-03-04</td><td class="hours">1.5</td></tr>
+<tr><td>Tue 2024-03-05</td><td class="hours">4.5</td></tr>
+</tbody><tfoot><tr><td>Total</td><td class="hours">6</td></tr></tfoot></table>
+<h2>Per work item</h2>
+<table><thead><tr><th>Work item</th><th>Title</th><th class="hours">Hours</th></tr></thead><tbody>
+<tr><td><a href="https://dev.azure.com/org/Proj/_workitems/edit/1">#1</a></td><td>&lt;script&gt;alert(&apos;hi&apos;)&lt;/script&gt;</td><td class="hours">4</td></tr>
+<tr><td><a href="https://dev.azure.com/org/Proj/_workitems/edit/2">#2</a></td><td>Item</td><td class="hours">2</td></tr>
+</tbody><tfoot><tr><td colspan="2">Total</td><td class="hours">6</td></tr></tfoot></table>
+<h2>Per client</h2>
+<table><thead><tr><th>Client</th><th class="hours">Hours</th></tr></thead><tbody>
+<tr><td>Fish &amp; &quot;chips&quot;</td><td class="hours">6</td></tr>
+</tbody><tfoot><tr><td>Total</td><td class="hours">6</td></tr></tfoot></table>
+</body></html>
+"#,
+                STYLE
+            )
+        );
+    }
+
+    #[test]
+    fn billing_has_a_table_of_its_own() {
+        let mut report = report(vec![vec![revision(
+            1,
+            "2024-03-04",
+            "a@b.c",
+            json!({ crate::COMPLETED_WORK: 1.5, "System.Title": "<b>Bold</b>" }),
+        )]]);
+        let rates = vec!["*=1000".parse().unwrap()];
+        report.billing = Some(rates::bill(&report.items, &rates, "EUR"));
+
+        let html = rendered(Format::Html, &report, &RenderOptions::default());
+        assert!(
+            html.contains(
+                "<tr><td>#1</td><td>&lt;b&gt;Bold&lt;/b&gt;</td><td class=\"hours\">1.5</td><td class=\"hours\">1,500.00 EUR</td></tr>\n\
+                 </tbody><tfoot><tr><td colspan=\"3\">Total</td><td class=\"hours\">1,500.00 EUR</td></tr></tfoot></table>\n\
+                 </body>"
+            ),
+            "{}",
+            html
+        );
+        assert!(!html.contains("<b>"), "{}", html);
+    }
+}
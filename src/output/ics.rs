@@ -0,0 +1,179 @@
+//! iCalendar with an event per work item, user and day, to put logged time
+//! next to meetings in a calendar
+//!
+//! The events of a day follow each other from 9:00, see [`blocks`]. The gaps
+//! in the calendar are what matters; the times themselves mean nothing.
+
+use super::{blocks, iso8601_duration};
+use crate::Report;
+use chrono::Utc;
+use std::io::{self, Write};
+
+pub fn write(out: &mut dyn Write, report: &Report) -> io::Result<()> {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+    line(out, "BEGIN:VCALENDAR")?;
+    line(out, "VERSION:2.0")?;
+    line(out, "PRODID:-//adaptdk//azure-devops-time-used//EN")?;
+    line(out, "CALSCALE:GREGORIAN")?;
+    for block in blocks(report) {
+        let item = block.item;
+        let mut summary = format!("#{} {}", item.id, item.title);
+        if !block.email.eq_ignore_ascii_case(&report.user) {
+            summary.push_str(&format!(" ({})", block.email));
+        }
+        line(out, "BEGIN:VEVENT")?;
+        // The same item, user and day keep their UID, so importing the range
+        // again updates the events rather than doubling them
+        line(
+            out,
+            &format!(
+                "UID:{}-{}-{}-{}@azure-devops-time-used",
+                item.organization,
+                item.id,
+                block.start.format("%Y%m%d"),
+                block.email.to_lowercase()
+            ),
+        )?;
+        line(out, &format!("DTSTAMP:{}", stamp))?;
+        line(
+            out,
+            &format!("DTSTART:{}", block.start.format("%Y%m%dT%H%M%S")),
+        )?;
+        line(out, &format!("DURATION:{}", iso8601_duration(block.hours)))?;
+        line(out, &format!("SUMMARY:{}", escape(&summary)))?;
+        line(out, &format!("DESCRIPTION:{}", escape(&item.url)))?;
+        line(out, &format!("URL:{}", item.url))?;
+        if let Some(client) = &item.client {
+            line(out, &format!("CATEGORIES:{}", escape(client)))?;
+        }
+        line(out, "TRANSP:TRANSPARENT")?;
+        line(out, "END:VEVENT")?;
+    }
+    line(out, "END:VCALENDAR")
+}
+
+/// Write a content line, folded at 75 octets as RFC 5545 asks
+fn line(out: &mut dyn Write, text: &str) -> io::Result<()> {
+    let mut width = 0;
+    for c in text.chars() {
+        if width + c.len_utf8() > 75 {
+            out.write_all(b"\r\n ")?;
+            width = 1;
+        }
+        write!(out, "{}", c)?;
+        width += c.len_utf8();
+    }
+    out.write_all(b"\r\n")
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::{Format, RenderOptions},
+        testing::{rendered, report, revision},
+    };
+    use serde_json::json;
+
+    #[test]
+    fn a_days_events_follow_each_other() {
+        let title = "Meetings; planning, review and a title long enough to be folded over";
+        let mut report = report(vec![
+            vec![
+                revision(
+                    1,
+                    "2024-03-04",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 1.5, "System.Title": title }),
+                ),
+                // Taken back the next day, which is no event
+                revision(
+                    2,
+                    "2024-03-05",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 1.0, "System.Title": title }),
+                ),
+            ],
+            vec![revision(
+                1,
+                "2024-03-04",
+                "O@b.c",
+                json!({ crate::COMPLETED_WORK: 0.5 }),
+            )],
+        ]);
+        report.items[1].client = Some("Client A".to_string());
+
+        let ics = rendered(Format::Ics, &report, &RenderOptions::default());
+        for line in ics.split_terminator("\r\n") {
+            assert!(line.len() <= 75, "{}", line);
+        }
+        let unfolded = ics.replace("\r\n ", "");
+        let lines: Vec<&str> = unfolded
+            .split_terminator("\r\n")
+            .filter(|line| !line.starts_with("DTSTAMP:"))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "BEGIN:VCALENDAR",
+                "VERSION:2.0",
+                "PRODID:-//adaptdk//azure-devops-time-used//EN",
+                "CALSCALE:GREGORIAN",
+                "BEGIN:VEVENT",
+                "UID:org-1-20240304-a@b.c@azure-devops-time-used",
+                "DTSTART:20240304T090000",
+                "DURATION:PT1H30M",
+                r"SUMMARY:#1 Meetings\; planning\, review and a title long enough to be folded over",
+                "DESCRIPTION:https://dev.azure.com/org/Proj/_workitems/edit/1",
+                "URL:https://dev.azure.com/org/Proj/_workitems/edit/1",
+                "TRANSP:TRANSPARENT",
+                "END:VEVENT",
+                "BEGIN:VEVENT",
+                "UID:org-2-20240304-o@b.c@azure-devops-time-used",
+                "DTSTART:20240304T103000",
+                "DURATION:PT30M",
+                "SUMMARY:#2 Item (O@b.c)",
+                "DESCRIPTION:https://dev.azure.com/org/Proj/_workitems/edit/2",
+                "URL:https://dev.azure.com/org/Proj/_workitems/edit/2",
+                "CATEGORIES:Client A",
+                "TRANSP:TRANSPARENT",
+                "END:VEVENT",
+                "END:VCALENDAR",
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_are_folded_between_characters() {
+        let mut out = Vec::new();
+        line(&mut out, &format!("SUMMARY:{}", "æ".repeat(100))).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.split_terminator("\r\n").collect();
+        // 8 bytes and 33 æ, then a space and 37 æ, and the rest
+        assert_eq!(
+            lines.iter().map(|line| line.len()).collect::<Vec<_>>(),
+            [74, 75, 61]
+        );
+        assert_eq!(
+            out.replace("\r\n ", ""),
+            format!("SUMMARY:{}\r\n", "æ".repeat(100))
+        );
+        assert_eq!(escape("a\\b;c,d\r\ne"), r"a\\b\;c\,d\ne");
+    }
+}
@@ -0,0 +1,168 @@
+//! JUnit XML where every workday of every user is a test case
+//!
+//! A day passes when at least `--expected-min` hours were logged, so timesheet
+//! gaps show up as failing tests in CI dashboards.
+
+use super::{escape_xml, RenderOptions};
+use crate::Report;
+use chrono::{Datelike, NaiveDate, Weekday};
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+pub fn write(out: &mut dyn Write, report: &Report, options: &RenderOptions) -> io::Result<()> {
+    // Hours per user per day, starting from the queried user so a week
+    // without any logged time still shows up as failures
+    let mut users: BTreeMap<&str, BTreeMap<NaiveDate, f64>> = BTreeMap::new();
+    users.entry(&report.user).or_default();
+    for entry in report.items.iter().flat_map(|item| &item.entries) {
+        *users
+            .entry(&entry.changed_by.email)
+            .or_default()
+            .entry(entry.date)
+            .or_default() += entry.hours;
+    }
+
+    let workdays: Vec<NaiveDate> = report
+        .from
+        .iter_days()
+        .take_while(|day| *day <= report.to)
+        .filter(|day| !matches!(day.weekday(), Weekday::Sat | Weekday::Sun))
+        .collect();
+
+    let failures = |days: &BTreeMap<NaiveDate, f64>| {
+        workdays
+            .iter()
+            .filter(|day| days.get(day).copied().unwrap_or(0.0) < options.expected_min)
+            .count()
+    };
+    let total_failures: usize = users.values().map(failures).sum();
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<testsuites name="timesheet" tests="{}" failures="{}">"#,
+        workdays.len() * users.len(),
+        total_failures
+    )?;
+    for (user, days) in &users {
+        let user = escape_xml(user);
+        writeln!(
+            out,
+            r#"  <testsuite name="{}" tests="{}" failures="{}">"#,
+            user,
+            workdays.len(),
+            failures(days)
+        )?;
+        // Totals ride along as properties, which CI dashboards tend to show
+        writeln!(out, "    <properties>")?;
+        writeln!(
+            out,
+            r#"      <property name="hours" value="{}"/>"#,
+            options.duration_format.format(days.values().sum())
+        )?;
+        writeln!(
+            out,
+            r#"      <property name="report_total" value="{}"/>"#,
+            options.duration_format.format(report.total)
+        )?;
+        writeln!(out, "    </properties>")?;
+        for day in &workdays {
+            let hours = days.get(day).copied().unwrap_or(0.0);
+            if hours >= options.expected_min {
+                writeln!(
+                    out,
+                    r#"    <testcase classname="{}" name="{}"/>"#,
+                    user, day
+                )?;
+            } else {
+                let message = format!(
+                    "logged {} on {}, expected at least {}",
+                    options.duration_format.format(hours),
+                    day,
+                    options.duration_format.format(options.expected_min)
+                );
+                writeln!(out, r#"    <testcase classname="{}" name="{}">"#, user, day)?;
+                writeln!(
+                    out,
+                    r#"      <failure message="{}"/>"#,
+                    escape_xml(&message)
+                )?;
+                writeln!(out, "    </testcase>")?;
+            }
+        }
+        writeln!(out, "  </testsuite>")?;
+    }
+    writeln!(out, "</testsuites>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{report, revision};
+    use serde_json::json;
+
+    #[test]
+    fn short_days_fail_with_the_hours_logged() {
+        let mut report = report(vec![
+            vec![
+                revision(
+                    1,
+                    "2024-03-04",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 8.0 }),
+                ),
+                revision(
+                    2,
+                    "2024-03-05",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 11.0 }),
+                ),
+            ],
+            vec![revision(
+                1,
+                "2024-03-04",
+                "o'brien&co@b.c",
+                json!({ crate::COMPLETED_WORK: 8.0 }),
+            )],
+        ]);
+        // A Monday and a Tuesday
+        report.from = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        report.to = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        let options = RenderOptions {
+            expected_min: 8.0,
+            ..RenderOptions::default()
+        };
+
+        let mut out = Vec::new();
+        write(&mut out, &report, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="timesheet" tests="4" failures="2">
+  <testsuite name="a@b.c" tests="2" failures="1">
+    <properties>
+      <property name="hours" value="11"/>
+      <property name="report_total" value="19"/>
+    </properties>
+    <testcase classname="a@b.c" name="2024-03-04"/>
+    <testcase classname="a@b.c" name="2024-03-05">
+      <failure message="logged 3 on 2024-03-05, expected at least 8"/>
+    </testcase>
+  </testsuite>
+  <testsuite name="o&apos;brien&amp;co@b.c" tests="2" failures="1">
+    <properties>
+      <property name="hours" value="8"/>
+      <property name="report_total" value="19"/>
+    </properties>
+    <testcase classname="o&apos;brien&amp;co@b.c" name="2024-03-04"/>
+    <testcase classname="o&apos;brien&amp;co@b.c" name="2024-03-05">
+      <failure message="logged 0 on 2024-03-05, expected at least 8"/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Markdown table of work items by day, for pasting into wikis
+
+use super::{difference, holiday, RenderOptions};
+use crate::{rates, Report};
+use std::io::{self, Write};
+
+pub fn write(out: &mut dyn Write, report: &Report, options: &RenderOptions) -> io::Result<()> {
+    let hours = |hours: f64| options.duration_format.format(hours);
+    // Only days with logged time get a column, to keep the table narrow
+    let days: Vec<_> = report.sums.keys().collect();
+
+    writeln!(out, "# Time from {} to {}", report.from, report.to)?;
+    writeln!(out)?;
+
+    let mut header = vec!["Work item".to_string()];
+    header.extend(days.iter().map(|day| day.format("%a %Y-%m-%d").to_string()));
+    header.push("Total".to_string());
+    writeln!(out, "| {} |", header.join(" | "))?;
+    writeln!(out, "|{}", "---|".repeat(header.len()))?;
+
+    for item in &report.items {
+        let mut row = vec![format!(
+            "[#{}]({}) {}",
+            item.id,
+            item.url,
+            escape(&item.title)
+        )];
+        let mut total = 0.0;
+        for day in &days {
+            let sum: f64 = item
+                .entries
+                .iter()
+                .filter(|entry| entry.date == **day)
+                .map(|entry| entry.hours)
+                .sum();
+            total += sum;
+            row.push(if sum == 0.0 {
+                String::new()
+            } else {
+                hours(sum)
+            });
+        }
+        row.push(hours(total));
+        writeln!(out, "| {} |", row.join(" | "))?;
+    }
+
+    let mut footer = vec!["**Total**".to_string()];
+    footer.extend(
+        report
+            .sums
+            .values()
+            .map(|sum| format!("**{}**", hours(*sum))),
+    );
+    footer.push(format!("**{}**", hours(report.total)));
+    writeln!(out, "| {} |", footer.join(" | "))?;
+
+    if let Some(by_client) = &report.by_client {
+        writeln!(out)?;
+        writeln!(out, "| Client | Hours |")?;
+        writeln!(out, "|---|---|")?;
+        for (client, sum) in by_client {
+            writeln!(out, "| {} | {} |", escape(client), hours(*sum))?;
+        }
+    }
+
+    if let Some(target) = &report.target {
+        writeln!(out)?;
+        writeln!(out, "| Day | Logged | Expected | Difference |")?;
+        writeln!(out, "|---|---|---|---|")?;
+        for day in &target.days {
+            writeln!(
+                out,
+                "| {}{} | {} | {} | {} |",
+                day.date.format("%a %Y-%m-%d"),
+                escape(&holiday(day)),
+                hours(day.logged),
+                hours(day.expected),
+                difference(day.difference, options).trim()
+            )?;
+        }
+        writeln!(
+            out,
+            "| **Total** | **{}** | **{}** | **{}** |",
+            hours(target.logged),
+            hours(target.expected),
+            difference(target.difference, options).trim()
+        )?;
+    }
+
+    if let Some(billing) = &report.billing {
+        let amount = |amount: f64| rates::format_amount(amount, &billing.currency);
+        writeln!(out)?;
+        writeln!(out, "| Work item | Hours | Amount |")?;
+        writeln!(out, "|---|---|---|")?;
+        for item in &billing.items {
+            writeln!(
+                out,
+                "| #{} {} | {} | {} |",
+                item.id,
+                escape(&item.title),
+                hours(item.hours),
+                amount(item.amount)
+            )?;
+        }
+        writeln!(out, "| **Total** | | **{}** |", amount(billing.total))?;
+        if billing.unrated_hours != 0.0 {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{} without a rate aren't billed.",
+                hours(billing.unrated_hours)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Keep titles from breaking the table or turning into markup
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '|' | '\\' | '*' | '_' | '[' | ']' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::Format,
+        testing::{rendered, report, revision},
+    };
+    use serde_json::json;
+
+    #[test]
+    fn items_by_the_days_with_time() {
+        let title = "Fix | pipes *bold* [link]";
+        let mut report = report(vec![
+            vec![
+                revision(
+                    1,
+                    "2024-03-04",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 1.5, "System.Title": title }),
+                ),
+                revision(
+                    2,
+                    "2024-03-05",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 4.0, "System.Title": title }),
+                ),
+            ],
+            vec![revision(
+                1,
+                "2024-03-05",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 2.0 }),
+            )],
+        ]);
+        let rates = vec!["#1=100".parse().unwrap()];
+        report.billing = Some(rates::bill(&report.items, &rates, "DKK"));
+
+        assert_eq!(
+            rendered(Format::Markdown, &report, &RenderOptions::default()),
+            r"# Time from 2024-03-01 to 2024-03-31
+
+| Work item | Mon 2024-03-04 | Tue 2024-03-05 | Total |
+|---|---|---|---|
+| [#1](https://dev.azure.com/org/Proj/_workitems/edit/1) Fix \| pipes \*bold\* \[link\] | 1.5 | 2.5 | 4 |
+| [#2](https://dev.azure.com/org/Proj/_workitems/edit/2) Item |  | 2 | 2 |
+| **Total** | **1.5** | **4.5** | **6** |
+
+| Work item | Hours | Amount |
+|---|---|---|
+| #1 Fix \| pipes \*bold\* \[link\] | 4 | 400.00 DKK |
+| **Total** | | **400.00 DKK** |
+
+2 without a rate aren't billed.
+"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Timesheet layout: a row per work item, a column per day of the range
+
+use super::{client_day_sums, RenderOptions};
+use crate::Report;
+use std::io::{self, Write};
+
+/// Longest work item label before it's cut off
+const LABEL_WIDTH: usize = 40;
+
+pub fn write(out: &mut dyn Write, report: &Report, options: &RenderOptions) -> io::Result<()> {
+    let hours = |hours: f64| options.duration_format.format(hours);
+    // Every day of the range, so the columns line up week after week
+    let days: Vec<_> = report
+        .from
+        .iter_days()
+        .take_while(|day| *day <= report.to)
+        .collect();
+
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut header = vec!["Work item".to_string()];
+    header.extend(days.iter().map(|day| day.format("%a %m-%d").to_string()));
+    header.push("Total".to_string());
+    rows.push(header);
+
+    for item in &report.items {
+        let mut label = format!("{} {}", item.id, item.title);
+        if label.chars().count() > LABEL_WIDTH {
+            label = label.chars().take(LABEL_WIDTH - 1).collect::<String>() + "…";
+        }
+        let mut row = vec![label];
+        for day in &days {
+            let sum: f64 = item
+                .entries
+                .iter()
+                .filter(|entry| entry.date == *day)
+                .map(|entry| entry.hours)
+                .sum();
+            row.push(if sum == 0.0 {
+                String::new()
+            } else {
+                hours(sum)
+            });
+        }
+        row.push(hours(item.total));
+        rows.push(row);
+    }
+
+    let mut footer = vec!["Total".to_string()];
+    footer.extend(days.iter().map(|day| {
+        let sum = report.sums.get(day).copied().unwrap_or(0.0);
+        if sum == 0.0 {
+            String::new()
+        } else {
+            hours(sum)
+        }
+    }));
+    footer.push(hours(report.total));
+    rows.push(footer);
+    // Subtotals per client go under the totals, after a blank line
+    let clients_from = rows.len();
+    if report.by_client.is_some() {
+        for (client, sums) in client_day_sums(report) {
+            let mut row = vec![client.to_string()];
+            row.extend(days.iter().map(|day| match sums.get(day) {
+                Some(sum) if *sum != 0.0 => hours(*sum),
+                _ => String::new(),
+            }));
+            row.push(hours(sums.values().sum()));
+            rows.push(row);
+        }
+    }
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    for (index, row) in rows.iter().enumerate() {
+        if index == clients_from {
+            writeln!(out)?;
+        }
+        let mut cells = Vec::new();
+        for (column, cell) in row.iter().enumerate() {
+            // Labels read left to right, numbers line up on the right
+            cells.push(match column {
+                0 => format!("{:<width$}", cell, width = widths[column]),
+                _ => format!("{:>width$}", cell, width = widths[column]),
+            });
+        }
+        writeln!(out, "{}", cells.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::Format,
+        testing::{rendered, report, revision},
+    };
+    use chrono::NaiveDate;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn every_day_of_the_range_is_a_column() {
+        let mut report = report(vec![
+            vec![revision(
+                1,
+                "2024-03-04",
+                "a@b.c",
+                json!({
+                    crate::COMPLETED_WORK: 1.5,
+                    "System.Title": "A title that goes on and on, well past the width",
+                }),
+            )],
+            vec![revision(
+                1,
+                "2024-03-06",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 10.25 }),
+            )],
+        ]);
+        report.from = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        report.to = NaiveDate::from_ymd_opt(2024, 3, 6).unwrap();
+
+        assert_eq!(
+            rendered(Format::Pivot, &report, &RenderOptions::default()),
+            "\
+Work item                                 Mon 03-04  Tue 03-05  Wed 03-06  Total
+1 A title that goes on and on, well pas…        1.5                          1.5
+2 Item                                                              10.25  10.25
+Total                                           1.5                 10.25  11.75
+"
+        );
+
+        // Then each client's, under a blank line
+        report.items[0].client = Some("Client A".to_string());
+        report.by_client = Some(BTreeMap::new());
+        let pivot = rendered(Format::Pivot, &report, &RenderOptions::default());
+        assert!(
+            pivot.ends_with(
+                "\
+Total                                           1.5                 10.25  11.75
+
+(no client)                                                         10.25  10.25
+Client A                                        1.5                          1.5
+"
+            ),
+            "{}",
+            pivot
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Tempo worklogs as JSON, to carry time over to Jira
+//!
+//! Each worklog is the body of a `POST /4/worklogs` request to the Tempo API,
+//! one per work item, user and day, laid out by [`blocks`]. Work items need
+//! mapping to a Jira issue and users to an Atlassian account; those that
+//! aren't mapped are left out.
+
+use super::{blocks, RenderOptions};
+use crate::{mapping, Report};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+};
+
+pub fn write(out: &mut dyn Write, report: &Report, options: &RenderOptions) -> io::Result<()> {
+    let mut worklogs = Vec::new();
+    let mut unmapped_items = BTreeSet::new();
+    let mut unmapped_users = BTreeSet::new();
+    for block in blocks(report) {
+        let item = block.item;
+        let Some(issue) = mapping::lookup(&options.tempo_issues, item) else {
+            unmapped_items.insert(item.id);
+            continue;
+        };
+        let Some(account) = options.tempo_accounts.get(&block.email.to_lowercase()) else {
+            unmapped_users.insert(block.email);
+            continue;
+        };
+        let mut worklog = Map::new();
+        // The current API takes the issue's id, older ones its key
+        match issue.parse::<u64>() {
+            Ok(id) => worklog.insert("issueId".to_string(), json!(id)),
+            Err(_) => worklog.insert("issueKey".to_string(), json!(issue)),
+        };
+        worklog.insert("authorAccountId".to_string(), json!(account));
+        worklog.insert(
+            "startDate".to_string(),
+            json!(block.start.format("%Y-%m-%d").to_string()),
+        );
+        worklog.insert(
+            "startTime".to_string(),
+            json!(block.start.format("%H:%M:%S").to_string()),
+        );
+        worklog.insert(
+            "timeSpentSeconds".to_string(),
+            json!((block.hours * 3600.0).round() as u64),
+        );
+        worklog.insert(
+            "description".to_string(),
+            json!(format!("#{} {}", item.id, item.title)),
+        );
+        worklogs.push(Value::Object(worklog));
+    }
+
+    if !unmapped_items.is_empty() {
+        let ids: Vec<String> = unmapped_items.iter().map(|id| format!("#{}", id)).collect();
+        eprintln!(
+            "Warning: left out {}, not mapped to a Jira issue with --tempo-issue",
+            ids.join(", ")
+        );
+    }
+    if !unmapped_users.is_empty() {
+        let emails: Vec<&str> = unmapped_users.into_iter().collect();
+        eprintln!(
+            "Warning: left out the time of {}, not mapped to an account with --tempo-account",
+            emails.join(", ")
+        );
+    }
+    serde_json::to_writer_pretty(&mut *out, &worklogs)?;
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::Format,
+        testing::{rendered, report, revision},
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn worklogs_of_mapped_items_and_users() {
+        let mut report = report(vec![
+            vec![
+                revision(
+                    1,
+                    "2024-03-04",
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: 1.5 }),
+                ),
+                revision(
+                    2,
+                    "2024-03-04",
+                    "o@b.c",
+                    json!({ crate::COMPLETED_WORK: 2.0 }),
+                ),
+            ],
+            vec![revision(
+                1,
+                "2024-03-04",
+                "A@b.c",
+                json!({ crate::COMPLETED_WORK: 0.25 }),
+            )],
+            vec![revision(
+                1,
+                "2024-03-05",
+                "a@b.c",
+                json!({ crate::COMPLETED_WORK: 1.0 }),
+            )],
+        ]);
+        report.items[2].project = "Other".to_string();
+        let options = RenderOptions {
+            tempo_issues: vec!["#1=10042".parse().unwrap(), "Proj=OPS-7".parse().unwrap()],
+            tempo_accounts: HashMap::from([("a@b.c".to_string(), "557058:a".to_string())]),
+            ..RenderOptions::default()
+        };
+
+        let worklogs: Value =
+            serde_json::from_str(&rendered(Format::Tempo, &report, &options)).unwrap();
+        assert_eq!(
+            worklogs,
+            json!([
+                {
+                    "issueId": 10042,
+                    "authorAccountId": "557058:a",
+                    "startDate": "2024-03-04",
+                    "startTime": "09:00:00",
+                    "timeSpentSeconds": 5400,
+                    "description": "#1 Item",
+                },
+                // After o@b.c's half hour, which isn't mapped to an account
+                {
+                    "issueKey": "OPS-7",
+                    "authorAccountId": "557058:a",
+                    "startDate": "2024-03-04",
+                    "startTime": "11:00:00",
+                    "timeSpentSeconds": 900,
+                    "description": "#2 Item",
+                },
+            ])
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! CSV in the columns Toggl Track imports, which Clockify's import can be
+//! pointed at too
+//!
+//! Entries start and end as laid out by [`blocks`], one after the other from
+//! 9:00, as revisions only tell the day the time was logged on.
+
+use super::{blocks, csv::write_row};
+use crate::Report;
+use std::io::{self, Write};
+
+pub fn write(out: &mut dyn Write, report: &Report) -> io::Result<()> {
+    writeln!(
+        out,
+        "Email,Client,Project,Description,Start date,Start time,Duration,Tags"
+    )?;
+    for block in blocks(report) {
+        let item = block.item;
+        // Both tools want hh:mm:ss, whatever --duration-format says
+        let minutes = (block.hours * 60.0).round() as u64;
+        write_row(
+            out,
+            &[
+                block.email,
+                item.client.as_deref().unwrap_or(""),
+                &item.project,
+                &format!("#{} {}", item.id, item.title),
+                &block.start.format("%Y-%m-%d").to_string(),
+                &block.start.format("%H:%M:%S").to_string(),
+                &format!("{:02}:{:02}:00", minutes / 60, minutes % 60),
+                &item.tags.join(", "),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        output::{Format, RenderOptions},
+        testing::{rendered, report, revision},
+    };
+    use serde_json::json;
+
+    #[test]
+    fn entries_take_turns_from_nine() {
+        let fields = |hours: f64| {
+            json!({
+                crate::COMPLETED_WORK: hours,
+                "System.Title": "Fix, again",
+                "System.Tags": "Support; Backend",
+            })
+        };
+        let mut report = report(vec![
+            vec![
+                revision(1, "2024-03-04", "a@b.c", fields(1.5)),
+                revision(2, "2024-03-05", "a@b.c", fields(9.75)),
+            ],
+            vec![revision(
+                1,
+                "2024-03-04",
+                "o@b.c",
+                json!({ crate::COMPLETED_WORK: 1.0 / 3.0 }),
+            )],
+        ]);
+        report.items[1].client = Some("Client A".to_string());
+
+        assert_eq!(
+            rendered(Format::Toggl, &report, &RenderOptions::default()),
+            r##"Email,Client,Project,Description,Start date,Start time,Duration,Tags
+a@b.c,,Proj,"#1 Fix, again",2024-03-04,09:00:00,01:30:00,"Support, Backend"
+o@b.c,Client A,Proj,#2 Item,2024-03-04,10:30:00,00:20:00,
+a@b.c,,Proj,"#1 Fix, again",2024-03-05,09:00:00,08:15:00,"Support, Backend"
+"##
+        );
+    }
+}
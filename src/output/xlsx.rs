@@ -0,0 +1,334 @@
+//! Excel workbook with the raw entries and a day by work item summary
+//!
+//! An XLSX file is a zip of a handful of XML parts. Cells use inline strings,
+//! so no shared string table or styles are needed, and the parts are stored
+//! uncompressed, so the zip container is simple enough to write by hand.
+
+use super::{client_day_sums, escape_xml};
+use crate::Report;
+use std::io::{self, Write};
+
+enum Cell {
+    Text(String),
+    Number(f64),
+    Empty,
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::Text(text.to_string())
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::Text(text)
+    }
+}
+
+impl From<f64> for Cell {
+    fn from(number: f64) -> Self {
+        Cell::Number(number)
+    }
+}
+
+/// Hours are written as numbers regardless of `--duration-format`, so they
+/// can be summed in Excel
+pub fn write(out: &mut dyn Write, report: &Report) -> io::Result<()> {
+    let mut entries: Vec<Vec<Cell>> = vec![[
+        "Date",
+        "Work item",
+        "Title",
+        "User",
+        "Hours",
+        "Completed work",
+        "Rev",
+        "Reason",
+    ]
+    .map(Cell::from)
+    .into()];
+    for item in &report.items {
+        for entry in &item.entries {
+            entries.push(vec![
+                entry.date.to_string().into(),
+                (item.id as f64).into(),
+                item.title.as_str().into(),
+                entry.changed_by.email.as_str().into(),
+                entry.hours.into(),
+                entry.completed_work.into(),
+                f64::from(entry.rev).into(),
+                entry.reason.as_deref().unwrap_or("").into(),
+            ]);
+        }
+    }
+
+    let days: Vec<_> = report.sums.keys().collect();
+    let mut header: Vec<Cell> = vec!["Work item".into(), "Title".into()];
+    header.extend(days.iter().map(|day| Cell::from(day.to_string())));
+    header.push("Total".into());
+    let mut summary = vec![header];
+    for item in &report.items {
+        let mut row: Vec<Cell> = vec![(item.id as f64).into(), item.title.as_str().into()];
+        let mut total = 0.0;
+        for day in &days {
+            let sum: f64 = item
+                .entries
+                .iter()
+                .filter(|entry| entry.date == **day)
+                .map(|entry| entry.hours)
+                .sum();
+            total += sum;
+            row.push(if sum == 0.0 { Cell::Empty } else { sum.into() });
+        }
+        row.push(total.into());
+        summary.push(row);
+    }
+    let mut totals: Vec<Cell> = vec!["Total".into(), Cell::Empty];
+    totals.extend(report.sums.values().map(|sum| Cell::from(*sum)));
+    totals.push(report.total.into());
+    summary.push(totals);
+    if report.by_client.is_some() {
+        summary.push(Vec::new());
+        for (client, sums) in client_day_sums(report) {
+            let mut row: Vec<Cell> = vec!["Client".into(), client.into()];
+            for day in &days {
+                row.push(match sums.get(*day) {
+                    Some(sum) if *sum != 0.0 => (*sum).into(),
+                    _ => Cell::Empty,
+                });
+            }
+            row.push(sums.values().sum::<f64>().into());
+            summary.push(row);
+        }
+    }
+
+    let mut zip = Zip::default();
+    zip.add("[Content_Types].xml", CONTENT_TYPES.as_bytes());
+    zip.add("_rels/.rels", ROOT_RELS.as_bytes());
+    zip.add("xl/workbook.xml", WORKBOOK.as_bytes());
+    zip.add("xl/_rels/workbook.xml.rels", WORKBOOK_RELS.as_bytes());
+    zip.add("xl/worksheets/sheet1.xml", sheet(&entries).as_bytes());
+    zip.add("xl/worksheets/sheet2.xml", sheet(&summary).as_bytes());
+    out.write_all(&zip.finish())
+}
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/worksheets/sheet2.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/></Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+const WORKBOOK: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Entries" sheetId="1" r:id="rId1"/><sheet name="Summary" sheetId="2" r:id="rId2"/></sheets></workbook>"#;
+
+const WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet2.xml"/></Relationships>"#;
+
+fn sheet(rows: &[Vec<Cell>]) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#,
+    );
+    for (row, cells) in rows.iter().enumerate() {
+        xml.push_str(&format!("<row r=\"{}\">", row + 1));
+        for (column, cell) in cells.iter().enumerate() {
+            let reference = format!("{}{}", column_name(column), row + 1);
+            match cell {
+                Cell::Text(text) => xml.push_str(&format!(
+                    "<c r=\"{}\" t=\"inlineStr\"><is><t>{}</t></is></c>",
+                    reference,
+                    escape_xml(text)
+                )),
+                Cell::Number(number) => {
+                    xml.push_str(&format!("<c r=\"{}\"><v>{}</v></c>", reference, number))
+                }
+                Cell::Empty => {}
+            }
+        }
+        xml.push_str("</row>");
+    }
+    xml.push_str("</sheetData></worksheet>");
+    xml
+}
+
+/// Spreadsheet column letters for a zero-based index: A, B, ..., Z, AA, ...
+fn column_name(mut index: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+/// Just enough of the zip format to store files without compression
+#[derive(Default)]
+struct Zip {
+    data: Vec<u8>,
+    directory: Vec<u8>,
+    count: u16,
+}
+
+/// 1980-01-01 in MS-DOS date format, the earliest representable
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+impl Zip {
+    fn add(&mut self, name: &str, contents: &[u8]) {
+        let offset = self.data.len() as u32;
+        let crc = crc32(contents);
+        let size = contents.len() as u32;
+
+        let mut common = Vec::new();
+        common.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        common.extend_from_slice(&0u16.to_le_bytes()); // flags
+        common.extend_from_slice(&0u16.to_le_bytes()); // stored
+        common.extend_from_slice(&0u16.to_le_bytes()); // time
+        common.extend_from_slice(&DOS_DATE.to_le_bytes());
+        common.extend_from_slice(&crc.to_le_bytes());
+        common.extend_from_slice(&size.to_le_bytes()); // compressed
+        common.extend_from_slice(&size.to_le_bytes()); // uncompressed
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+
+        self.data.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        self.data.extend_from_slice(&common);
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(contents);
+
+        self.directory
+            .extend_from_slice(&0x02014b50u32.to_le_bytes());
+        self.directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        self.directory.extend_from_slice(&common);
+        self.directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        self.directory.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        self.directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        self.directory.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        self.directory.extend_from_slice(&offset.to_le_bytes());
+        self.directory.extend_from_slice(name.as_bytes());
+        self.count += 1;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let offset = self.data.len() as u32;
+        let size = self.directory.len() as u32;
+        self.data.append(&mut self.directory);
+        self.data.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        self.data.extend_from_slice(&0u16.to_le_bytes()); // this disk
+        self.data.extend_from_slice(&0u16.to_le_bytes()); // directory disk
+        self.data.extend_from_slice(&self.count.to_le_bytes());
+        self.data.extend_from_slice(&self.count.to_le_bytes());
+        self.data.extend_from_slice(&size.to_le_bytes());
+        self.data.extend_from_slice(&offset.to_le_bytes());
+        self.data.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        self.data
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{report, revision};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    /// The files of a zip, from its local headers, checking their CRCs
+    fn unzip(zip: &[u8]) -> BTreeMap<String, String> {
+        let u16_at = |at: usize| usize::from(u16::from_le_bytes([zip[at], zip[at + 1]]));
+        let u32_at = |at: usize| u32::from_le_bytes(zip[at..at + 4].try_into().unwrap());
+        let mut files = BTreeMap::new();
+        let mut at = 0;
+        while u32_at(at) == 0x04034b50 {
+            let (crc, size) = (u32_at(at + 14), u32_at(at + 18) as usize);
+            assert_eq!(size, u32_at(at + 22) as usize, "stored, so not compressed");
+            let name_start = at + 30;
+            let start = name_start + u16_at(at + 26) + u16_at(at + 28);
+            let name = String::from_utf8(zip[name_start..name_start + u16_at(at + 26)].to_vec());
+            let contents = &zip[start..start + size];
+            assert_eq!(crc32(contents), crc, "{:?}", name);
+            files.insert(name.unwrap(), String::from_utf8(contents.to_vec()).unwrap());
+            at = start + size;
+        }
+        // Then the central directory, with as many entries
+        assert_eq!(u32_at(at), 0x02014b50);
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(end), 0x06054b50);
+        assert_eq!(u16_at(end + 10), files.len());
+        files
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn workbook_strings_are_valid_xml() {
+        let report = report(vec![vec![revision(
+            1,
+            "2024-03-04",
+            "a@b.c",
+            json!({
+                "System.Title": "\u{1}Bell\u{7} & <Co>\tline\u{1F}",
+                crate::COMPLETED_WORK: 2.5,
+            }),
+        )]]);
+        let mut out = Vec::new();
+        write(&mut out, &report).unwrap();
+
+        let files = unzip(&out);
+        assert_eq!(
+            files.keys().map(String::as_str).collect::<Vec<_>>(),
+            [
+                "[Content_Types].xml",
+                "_rels/.rels",
+                "xl/_rels/workbook.xml.rels",
+                "xl/workbook.xml",
+                "xl/worksheets/sheet1.xml",
+                "xl/worksheets/sheet2.xml",
+            ]
+        );
+        // Inline strings, there's no sharedStrings part to look in
+        let entries = &files["xl/worksheets/sheet1.xml"];
+        assert!(
+            entries.contains(
+                r#"<c r="C2" t="inlineStr"><is><t>Bell &amp; &lt;Co&gt;	line</t></is></c>"#
+            ),
+            "{}",
+            entries
+        );
+        assert!(
+            entries.contains(r#"<c r="E2"><v>2.5</v></c>"#),
+            "{}",
+            entries
+        );
+        for (name, xml) in &files {
+            assert!(
+                !xml.chars()
+                    .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r')),
+                "{}: {:?}",
+                name,
+                xml
+            );
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Billable amounts, from hourly rates per work item, tag, project or user
+
+use crate::{Ancestor, ReportItem};
+use serde::Serialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// What an hourly rate applies to
+#[derive(Debug, Clone, PartialEq)]
+pub enum RateKey {
+    /// `#1234`
+    WorkItem(u64),
+    /// `tag:NAME`
+    Tag(String),
+    /// An email
+    User(String),
+    /// `*`
+    Any,
+    /// Anything else is an Azure DevOps project
+    Project(String),
+}
+
+/// A `--rate` such as `tag:Support=80` or `me@adapt.dk=95`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rate {
+    pub key: RateKey,
+    pub hourly: f64,
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, hourly) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected KEY=RATE, got {:?}", s))?;
+        let key = key.trim();
+        let key = if key == "*" {
+            RateKey::Any
+        } else if let Some(id) = key.strip_prefix('#') {
+            RateKey::WorkItem(
+                id.parse()
+                    .map_err(|_| format!("not a work item id: {}", key))?,
+            )
+        } else if let Some(tag) = key.strip_prefix("tag:") {
+            RateKey::Tag(tag.trim().to_string())
+        } else if key.contains('@') {
+            RateKey::User(key.to_string())
+        } else {
+            RateKey::Project(key.to_string())
+        };
+        let hourly = hourly
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|hourly| *hourly >= 0.0)
+            .ok_or_else(|| format!("invalid rate {}", hourly))?;
+        Ok(Rate { key, hourly })
+    }
+}
+
+/// The rate for time a user logged on an item, the most specific one given:
+/// for the item, one of its tags, its project, the user, then `*`
+pub fn rate_for(rates: &[Rate], item: &ReportItem, user: &str) -> Option<f64> {
+    let find = |matches: &dyn Fn(&RateKey) -> bool| {
+        rates
+            .iter()
+            .find(|rate| matches(&rate.key))
+            .map(|rate| rate.hourly)
+    };
+    find(&|key| *key == RateKey::WorkItem(item.id))
+        .or_else(|| {
+            find(&|key| {
+                matches!(key, RateKey::Tag(tag)
+                    if item.tags.iter().any(|item_tag| item_tag.eq_ignore_ascii_case(tag)))
+            })
+        })
+        .or_else(|| {
+            find(&|key| matches!(key, RateKey::Project(project) if project.eq_ignore_ascii_case(&item.project)))
+        })
+        .or_else(|| find(&|key| matches!(key, RateKey::User(email) if email.eq_ignore_ascii_case(user))))
+        .or_else(|| find(&|key| *key == RateKey::Any))
+}
+
+#[derive(Debug, Serialize)]
+pub struct BilledItem {
+    pub id: u64,
+    pub title: String,
+    pub project: String,
+    /// With `--rollup`, what the item's time rolls up to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rolls_up_to: Option<Ancestor>,
+    /// Hours with a rate
+    pub hours: f64,
+    pub amount: f64,
+}
+
+/// Billable amounts of the report, in `currency`
+#[derive(Debug, Serialize)]
+pub struct Billing {
+    pub currency: String,
+    /// Items with billable time, in report order
+    pub items: Vec<BilledItem>,
+    pub by_user: BTreeMap<String, f64>,
+    pub by_project: BTreeMap<String, f64>,
+    pub total: f64,
+    /// Hours no rate applies to, which aren't billed
+    pub unrated_hours: f64,
+}
+
+pub fn bill(items: &[ReportItem], rates: &[Rate], currency: &str) -> Billing {
+    let mut billing = Billing {
+        currency: currency.to_string(),
+        items: Vec::new(),
+        by_user: BTreeMap::new(),
+        by_project: BTreeMap::new(),
+        total: 0.0,
+        unrated_hours: 0.0,
+    };
+    for item in items {
+        let mut billed = BilledItem {
+            id: item.id,
+            title: item.title.clone(),
+            project: item.project.clone(),
+            rolls_up_to: item.rolls_up_to.clone(),
+            hours: 0.0,
+            amount: 0.0,
+        };
+        for entry in &item.entries {
+            let Some(hourly) = rate_for(rates, item, &entry.changed_by.email) else {
+                billing.unrated_hours += entry.hours;
+                continue;
+            };
+            let amount = entry.hours * hourly;
+            billed.hours += entry.hours;
+            billed.amount += amount;
+            *billing
+                .by_user
+                .entry(entry.changed_by.email.clone())
+                .or_default() += amount;
+        }
+        if billed.hours != 0.0 {
+            *billing.by_project.entry(item.project.clone()).or_default() += billed.amount;
+            billing.total += billed.amount;
+            billing.items.push(billed);
+        }
+    }
+    billing
+}
+
+/// An amount with two decimals, thousands separated, and the currency
+pub fn format_amount(amount: f64, currency: &str) -> String {
+    format!("{} {}", format_number(amount), currency)
+}
+
+/// An amount with two decimals and thousands separated
+pub fn format_number(amount: f64) -> String {
+    let cents = (amount.abs() * 100.0).round() as u64;
+    let units = (cents / 100).to_string();
+    let mut grouped = String::new();
+    for (i, digit) in units.chars().enumerate() {
+        if i > 0 && (units.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    let sign = if amount < 0.0 && cents > 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, grouped, cents % 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{report, revision},
+        COMPLETED_WORK,
+    };
+    use serde_json::json;
+
+    fn rates(rates: &[&str]) -> Vec<Rate> {
+        rates.iter().map(|rate| rate.parse().unwrap()).collect()
+    }
+
+    /// Items with a revision a day on each of `work`'s cumulative values
+    fn items(work: &[&[f64]]) -> Vec<ReportItem> {
+        let histories = work
+            .iter()
+            .map(|values| {
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| {
+                        let date = format!("2024-03-{:02}", i + 4);
+                        revision(
+                            i as u32 + 1,
+                            &date,
+                            "a@b.c",
+                            json!({ COMPLETED_WORK: value }),
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+        report(histories).items
+    }
+
+    #[test]
+    fn rates_name_what_they_apply_to() {
+        assert_eq!(
+            rates(&[
+                "#12=95",
+                " tag: Support =80.5",
+                "me@adapt.dk=95",
+                "*=0",
+                "Client A=10"
+            ])
+            .into_iter()
+            .map(|rate| (rate.key, rate.hourly))
+            .collect::<Vec<_>>(),
+            [
+                (RateKey::WorkItem(12), 95.0),
+                (RateKey::Tag("Support".to_string()), 80.5),
+                (RateKey::User("me@adapt.dk".to_string()), 95.0),
+                (RateKey::Any, 0.0),
+                (RateKey::Project("Client A".to_string()), 10.0),
+            ]
+        );
+        for (rate, error) in [
+            ("95", "expected KEY=RATE, got \"95\""),
+            ("#x=95", "not a work item id: #x"),
+            ("*=-1", "invalid rate -1"),
+            ("*=lots", "invalid rate lots"),
+        ] {
+            assert_eq!(rate.parse::<Rate>().unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    fn the_most_specific_rate_applies() {
+        let mut item = items(&[&[1.0]]).remove(0);
+        item.tags = vec!["Support".to_string()];
+        // Least specific first, so the order given doesn't decide
+        let all = rates(&["*=10", "A@B.C=20", "proj=30", "tag:support=40", "#1=50"]);
+        for (given, expected) in [
+            (5, Some(50.0)),
+            (4, Some(40.0)),
+            (3, Some(30.0)),
+            (2, Some(20.0)),
+            (1, Some(10.0)),
+            (0, None),
+        ] {
+            assert_eq!(
+                rate_for(&all[..given], &item, "a@b.c"),
+                expected,
+                "{:?}",
+                &all[..given]
+            );
+        }
+        // Rates for other items, tags, projects and users don't apply
+        let others = rates(&["#2=50", "tag:Bug=40", "Other=30", "o@b.c=20"]);
+        assert_eq!(rate_for(&others, &item, "a@b.c"), None);
+        // Of two rates for the same thing, the first
+        assert_eq!(rate_for(&rates(&["*=1", "*=2"]), &item, "a@b.c"), Some(1.0));
+    }
+
+    #[test]
+    fn billing_sums_rated_hours_and_counts_the_rest() {
+        let mut items = items(&[&[1.0, 3.5], &[2.0], &[1.5, 2.0], &[1.0]]);
+        items[0].tags = vec!["Support".to_string()];
+        items[0].entries[1].changed_by.email = "o@b.c".to_string();
+        items[1].project = "Other".to_string();
+        items[2].entries[1].changed_by.email = "o@b.c".to_string();
+        let billing = bill(
+            &items,
+            &rates(&["tag:Support=100", "Other=80", "o@b.c=50"]),
+            "DKK",
+        );
+
+        assert_eq!(
+            billing
+                .items
+                .iter()
+                .map(|item| (item.id, item.hours, item.amount))
+                .collect::<Vec<_>>(),
+            // Item 4 has no rated hours, and isn't billed
+            [(1, 3.5, 350.0), (2, 2.0, 160.0), (3, 0.5, 25.0)]
+        );
+        assert_eq!(
+            billing.by_user,
+            BTreeMap::from([("a@b.c".to_string(), 260.0), ("o@b.c".to_string(), 275.0)])
+        );
+        assert_eq!(
+            billing.by_project,
+            BTreeMap::from([("Other".to_string(), 160.0), ("Proj".to_string(), 375.0)])
+        );
+        assert_eq!(billing.total, 535.0);
+        assert_eq!(billing.unrated_hours, 2.5);
+        assert_eq!(billing.currency, "DKK");
+    }
+
+    #[test]
+    fn amounts_are_rounded_to_cents_and_grouped() {
+        for (amount, formatted) in [
+            (0.0, "0.00"),
+            (0.004, "0.00"),
+            (-0.004, "0.00"),
+            (0.125, "0.13"),
+            (999.999, "1,000.00"),
+            (123.4, "123.40"),
+            (1234.5, "1,234.50"),
+            (-1234.5, "-1,234.50"),
+            (123456.0, "123,456.00"),
+            (1234567.891, "1,234,567.89"),
+            (-0.5, "-0.50"),
+        ] {
+            assert_eq!(format_number(amount), formatted, "{}", amount);
+        }
+        assert_eq!(format_amount(1500.0, "EUR"), "1,500.00 EUR");
+    }
+}
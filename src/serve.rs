@@ -0,0 +1,259 @@
+//! Tiny HTTP front end for dashboards
+//!
+//! `GET /report?user=<email>&from=<date>&to=<date>` runs the same aggregation as
+//! the CLI and answers with the report as JSON. `GET /health` answers `ok`.
+//!
+//! There's no authentication, and the reports are fetched with the server's
+//! token, so it only listens on localhost unless told otherwise.
+
+use crate::{dates::default_range, AzdoClient, Connection, ReportQuery};
+use chrono::NaiveDate;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+#[derive(Debug, Deserialize)]
+struct Params {
+    user: Option<String>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+type CacheKey = (String, NaiveDate, NaiveDate);
+
+/// The report last fetched for a key, locked while it's being fetched again
+type Slot = Arc<Mutex<Option<(Instant, String)>>>;
+
+struct State {
+    client: AzdoClient,
+    connections: Vec<Connection>,
+    /// Settings from the command line that requests can't override
+    defaults: ReportQuery,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<CacheKey, Slot>>,
+}
+
+pub async fn serve(
+    client: AzdoClient,
+    connections: Vec<Connection>,
+    defaults: ReportQuery,
+    bind: IpAddr,
+    port: u16,
+    cache_ttl: Duration,
+) {
+    let state = Arc::new(State {
+        client,
+        connections,
+        defaults,
+        cache_ttl,
+        cache: Mutex::new(HashMap::new()),
+    });
+
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(&state, request).await) }
+            }))
+        }
+    });
+
+    let address = SocketAddr::from((bind, port));
+    eprintln!("Listening on http://{}", address);
+    if let Err(error) = Server::bind(&address).serve(make_service).await {
+        eprintln!("Server error: {}", error);
+    }
+}
+
+async fn handle(state: &State, request: Request<Body>) -> Response<Body> {
+    if request.method() != Method::GET {
+        return respond(StatusCode::METHOD_NOT_ALLOWED, "text/plain", "GET only");
+    }
+
+    match request.uri().path() {
+        "/health" => respond(StatusCode::OK, "text/plain", "ok"),
+        "/report" => {
+            let params: Params =
+                match serde_urlencoded::from_str(request.uri().query().unwrap_or("")) {
+                    Ok(params) => params,
+                    Err(error) => {
+                        return respond(StatusCode::BAD_REQUEST, "text/plain", error.to_string())
+                    }
+                };
+            report(state, params).await
+        }
+        _ => respond(StatusCode::NOT_FOUND, "text/plain", "not found"),
+    }
+}
+
+async fn report(state: &State, params: Params) -> Response<Body> {
+    let (first_day, last_day) = default_range(state.defaults.week_start);
+    // Asking for a user narrows the report down to them
+    let users = match &params.user {
+        Some(user) => Some(vec![user.clone()]),
+        None => state.defaults.users.clone(),
+    };
+    let query = ReportQuery {
+        user: params.user.unwrap_or_else(|| state.defaults.user.clone()),
+        users,
+        from: params.from.unwrap_or(first_day),
+        to: params.to.unwrap_or(last_day),
+        ..state.defaults.clone()
+    };
+    let key = (query.user.clone(), query.from, query.to);
+
+    // Requests for the same report wait for the one fetching it, so a burst of
+    // dashboard refreshes costs one round of Azure DevOps calls rather than
+    // many, while other reports are fetched alongside
+    let slot = state.cache.lock().await.entry(key).or_default().clone();
+    let mut cached = slot.lock().await;
+    if let Some((fetched, body)) = &*cached {
+        if fetched.elapsed() < state.cache_ttl {
+            return respond(StatusCode::OK, "application/json", body.clone());
+        }
+    }
+
+    match state.client.report(&state.connections, &query).await {
+        Ok(report) => {
+            let body = serde_json::to_string(&report).unwrap();
+            *cached = Some((Instant::now(), body.clone()));
+            respond(StatusCode::OK, "application/json", body)
+        }
+        Err(error) => respond(StatusCode::BAD_GATEWAY, "text/plain", error.to_string()),
+    }
+}
+
+fn respond(status: StatusCode, content_type: &str, body: impl Into<Body>) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", content_type)
+        .body(body.into())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{connection, mock_server, query, response},
+        ClientOptions,
+    };
+    use reqwest::Url;
+
+    fn state(server: SocketAddr) -> State {
+        State {
+            client: AzdoClient::new(&ClientOptions::default()).unwrap(),
+            connections: vec![Connection {
+                server: Url::parse(&format!("http://{}/", server)).unwrap(),
+                ..connection()
+            }],
+            defaults: query(),
+            cache_ttl: Duration::from_secs(60),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The status and body of the answer to a request
+    async fn get(state: &State, method: Method, uri: &str) -> (StatusCode, String) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = handle(state, request).await;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn only_gets_of_the_two_paths_are_answered() {
+        let (server, _) = mock_server(Vec::new()).await;
+        let state = state(server);
+        assert_eq!(
+            get(&state, Method::GET, "/health").await,
+            (StatusCode::OK, "ok".to_string())
+        );
+        assert_eq!(
+            get(&state, Method::POST, "/report").await,
+            (StatusCode::METHOD_NOT_ALLOWED, "GET only".to_string())
+        );
+        assert_eq!(
+            get(&state, Method::GET, "/reports").await,
+            (StatusCode::NOT_FOUND, "not found".to_string())
+        );
+        let (status, body) = get(&state, Method::GET, "/report?from=March").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(
+            body.contains("input contains invalid characters"),
+            "{}",
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_are_fetched_once_while_fresh() {
+        let no_items = || {
+            response(
+                "200 OK",
+                &[("Content-Type", "application/json")],
+                r#"{"workItems": []}"#,
+            )
+        };
+        let (server, requests) = mock_server(vec![
+            no_items(),
+            no_items(),
+            response("401 Unauthorized", &[], ""),
+        ])
+        .await;
+        let state = state(server);
+        let march = "/report?user=x%40y.z&from=2024-03-01&to=2024-03-31";
+
+        let (status, fetched) = get(&state, Method::GET, march).await;
+        assert_eq!(status, StatusCode::OK);
+        let report: serde_json::Value = serde_json::from_str(&fetched).unwrap();
+        assert_eq!(
+            (&report["user"], &report["from"], &report["to"]),
+            (&"x@y.z".into(), &"2024-03-01".into(), &"2024-03-31".into())
+        );
+        assert_eq!(report["total"], 0.0);
+        assert_eq!(
+            get(&state, Method::GET, march).await,
+            (StatusCode::OK, fetched)
+        );
+        // Another user is another report, the server's user without one
+        let (_, default_user) =
+            get(&state, Method::GET, "/report?from=2024-03-01&to=2024-03-31").await;
+        assert!(
+            default_user.contains(r#""user":"a@b.c""#),
+            "{}",
+            default_user
+        );
+        let (status, _) = get(&state, Method::GET, "/report?from=2024-02-01&to=2024-02-29").await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+        let requests = requests.await.unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(
+            requests[0].contains("2024-02-29T00:00:00Z"),
+            "{}",
+            requests[0]
+        );
+        assert!(
+            requests[2].contains("2024-01-31T00:00:00Z"),
+            "{}",
+            requests[2]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! Local history of reported time, for trends across many runs
+//!
+//! The store is a SQLite database holding one record per revision that logged
+//! time. Runs with `--store` upsert into it, `trend` reads it back without
+//! talking to Azure DevOps.
+
+mod sqlite;
+
+use crate::Report;
+use chrono::NaiveDate;
+use sqlite::{Database, Value};
+use std::{io, path::Path};
+
+/// The schema changes, each taking the store from the version before it, its
+/// index in the list, to the next; the version is kept in `user_version`
+const MIGRATIONS: &[&str] = &["CREATE TABLE records (
+        organization TEXT NOT NULL,
+        project TEXT NOT NULL,
+        item INTEGER NOT NULL,
+        rev INTEGER NOT NULL,
+        user TEXT NOT NULL,
+        display_name TEXT NOT NULL,
+        date TEXT NOT NULL,
+        hours REAL NOT NULL,
+        title TEXT NOT NULL,
+        PRIMARY KEY (organization, project, item, rev, user)
+    );
+    CREATE INDEX records_by_date ON records (date);"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub organization: String,
+    pub project: String,
+    pub item: u64,
+    pub rev: u32,
+    pub user: String,
+    pub display_name: String,
+    pub date: NaiveDate,
+    pub hours: f64,
+    pub title: String,
+}
+
+/// Open the store, creating it or bringing its schema up to date
+fn open(path: &Path) -> io::Result<Database> {
+    let database = Database::open(path)?;
+    let mut statement = database.prepare("PRAGMA user_version")?;
+    statement.step()?;
+    let version = statement.integer(0) as usize;
+    drop(statement);
+    if version > MIGRATIONS.len() {
+        return Err(io::Error::other(format!(
+            "store version {} is newer than this build understands",
+            version
+        )));
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        database.execute(&format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            migration,
+            from + 1
+        ))?;
+    }
+    Ok(database)
+}
+
+/// Add the report's entries to the store, replacing records seen before
+pub fn upsert(path: &Path, report: &Report) -> io::Result<usize> {
+    let database = open(path)?;
+    // One transaction, so a failed run leaves the store as it was
+    database.execute("BEGIN")?;
+    let mut insert = database.prepare(
+        "INSERT OR REPLACE INTO records
+            (organization, project, item, rev, user, display_name, date, hours, title)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    )?;
+    let mut count = 0;
+    for item in &report.items {
+        for entry in &item.entries {
+            let date = entry.date.to_string();
+            insert.bind(&[
+                Value::Text(&item.organization),
+                Value::Text(&item.project),
+                Value::Integer(item.id as i64),
+                Value::Integer(entry.rev.into()),
+                Value::Text(&entry.changed_by.email),
+                Value::Text(&entry.changed_by.display_name),
+                Value::Text(&date),
+                Value::Real(entry.hours),
+                Value::Text(&item.title),
+            ])?;
+            insert.step()?;
+            count += 1;
+        }
+    }
+    drop(insert);
+    database.execute("COMMIT")?;
+    Ok(count)
+}
+
+/// Stored records on or after `since`, oldest first
+pub fn read(path: &Path, since: Option<NaiveDate>) -> io::Result<Vec<Record>> {
+    let database = open(path)?;
+    let mut select = database.prepare(
+        "SELECT organization, project, item, rev, user, display_name, date, hours, title
+            FROM records
+            WHERE ?1 IS NULL OR date >= ?1
+            ORDER BY date, organization, project, item, rev, user",
+    )?;
+    let since = since.map(|since| since.to_string());
+    select.bind(&[since.as_deref().map_or(Value::Null, Value::Text)])?;
+    let mut records = Vec::new();
+    while select.step()? {
+        let date = select.text(6);
+        records.push(Record {
+            organization: select.text(0),
+            project: select.text(1),
+            item: select.integer(2) as u64,
+            rev: select.integer(3) as u32,
+            user: select.text(4),
+            display_name: select.text(5),
+            date: date
+                .parse()
+                .map_err(|error| io::Error::other(format!("stored date {:?}: {}", date, error)))?,
+            hours: select.real(7),
+            title: select.text(8),
+        });
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{report, revision};
+    use serde_json::json;
+
+    /// A store of its own for each test, as they run at once
+    fn temporary(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.sqlite", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn hours(history: &[f64]) -> Vec<crate::Revision> {
+        history
+            .iter()
+            .enumerate()
+            .map(|(i, hours)| {
+                revision(
+                    i as u32 + 1,
+                    &format!("2024-03-{:02}", i + 4),
+                    "a@b.c",
+                    json!({ crate::COMPLETED_WORK: hours }),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn records_are_read_back_deduplicated() {
+        let path = temporary("store-records");
+        let first = upsert(&path, &report(vec![hours(&[1.0, 3.0]), hours(&[2.0])]));
+        // The same revisions again, one of them corrected since
+        let second = upsert(&path, &report(vec![hours(&[1.0, 3.5])]));
+        let all = read(&path, None);
+        let since = read(&path, NaiveDate::from_ymd_opt(2024, 3, 5));
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(first.unwrap(), 3);
+        assert_eq!(second.unwrap(), 2);
+        let all = all.unwrap();
+        let rows: Vec<(u64, u32, String, f64)> = all
+            .iter()
+            .map(|record| {
+                (
+                    record.item,
+                    record.rev,
+                    record.date.to_string(),
+                    record.hours,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (1, 1, "2024-03-04".to_string(), 1.0),
+                (2, 1, "2024-03-04".to_string(), 2.0),
+                (1, 2, "2024-03-05".to_string(), 2.5),
+            ]
+        );
+        assert_eq!(
+            all[0],
+            Record {
+                organization: "org".to_string(),
+                project: "Proj".to_string(),
+                item: 1,
+                rev: 1,
+                user: "a@b.c".to_string(),
+                display_name: "a@b.c".to_string(),
+                date: NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(),
+                hours: 1.0,
+                title: "Item".to_string(),
+            }
+        );
+        assert_eq!(since.unwrap(), all[2..]);
+    }
+
+    #[test]
+    fn stores_are_created_at_the_latest_version() {
+        let path = temporary("store-version");
+        let created = read(&path, None);
+        let version = Database::open(&path).and_then(|database| {
+            let mut statement = database.prepare("PRAGMA user_version")?;
+            statement.step()?;
+            Ok(statement.integer(0))
+        });
+        // From a later build
+        Database::open(&path)
+            .and_then(|database| database.execute("PRAGMA user_version = 99"))
+            .unwrap();
+        let newer = read(&path, None);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(created.unwrap(), []);
+        assert_eq!(version.unwrap(), MIGRATIONS.len() as i64);
+        assert_eq!(
+            newer.unwrap_err().to_string(),
+            "store version 99 is newer than this build understands"
+        );
+    }
+}
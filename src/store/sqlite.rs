@@ -0,0 +1,250 @@
+//! Just enough of the SQLite C API for the store, linked from the system's
+//! libsqlite3
+
+use libc::{c_char, c_int, c_void};
+use std::{
+    ffi::{CStr, CString},
+    io,
+    path::Path,
+    ptr,
+};
+
+#[repr(C)]
+struct Sqlite3 {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct Sqlite3Stmt {
+    _private: [u8; 0],
+}
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_OPEN_READWRITE: c_int = 0x02;
+const SQLITE_OPEN_CREATE: c_int = 0x04;
+/// Has SQLite copy bound text before the call returns
+const SQLITE_TRANSIENT: isize = -1;
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_open_v2(
+        filename: *const c_char,
+        db: *mut *mut Sqlite3,
+        flags: c_int,
+        vfs: *const c_char,
+    ) -> c_int;
+    fn sqlite3_close(db: *mut Sqlite3) -> c_int;
+    fn sqlite3_errmsg(db: *mut Sqlite3) -> *const c_char;
+    fn sqlite3_exec(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        callback: *const c_void,
+        argument: *mut c_void,
+        error: *mut *mut c_char,
+    ) -> c_int;
+    fn sqlite3_free(memory: *mut c_void);
+    fn sqlite3_prepare_v2(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        length: c_int,
+        statement: *mut *mut Sqlite3Stmt,
+        tail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_finalize(statement: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_reset(statement: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_clear_bindings(statement: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_bind_text(
+        statement: *mut Sqlite3Stmt,
+        index: c_int,
+        text: *const c_char,
+        length: c_int,
+        destructor: isize,
+    ) -> c_int;
+    fn sqlite3_bind_int64(statement: *mut Sqlite3Stmt, index: c_int, value: i64) -> c_int;
+    fn sqlite3_bind_double(statement: *mut Sqlite3Stmt, index: c_int, value: f64) -> c_int;
+    fn sqlite3_bind_null(statement: *mut Sqlite3Stmt, index: c_int) -> c_int;
+    fn sqlite3_step(statement: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_column_text(statement: *mut Sqlite3Stmt, column: c_int) -> *const c_char;
+    fn sqlite3_column_int64(statement: *mut Sqlite3Stmt, column: c_int) -> i64;
+    fn sqlite3_column_double(statement: *mut Sqlite3Stmt, column: c_int) -> f64;
+}
+
+/// A parameter bound to a statement
+pub enum Value<'a> {
+    Text(&'a str),
+    Integer(i64),
+    Real(f64),
+    Null,
+}
+
+pub struct Database {
+    raw: *mut Sqlite3,
+}
+
+impl Database {
+    /// Open the database, creating it when there's none
+    pub fn open(path: &Path) -> io::Result<Database> {
+        let path = path
+            .to_str()
+            .and_then(|path| CString::new(path).ok())
+            .ok_or_else(|| io::Error::other("the store's path isn't valid UTF-8"))?;
+        let mut raw = ptr::null_mut();
+        // SAFETY: path is NUL-terminated and raw is written whether or not
+        // the open succeeds
+        let code = unsafe {
+            sqlite3_open_v2(
+                path.as_ptr(),
+                &mut raw,
+                SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE,
+                ptr::null(),
+            )
+        };
+        // Closed on drop even when the open failed, as SQLite asks
+        let database = Database { raw };
+        if code != SQLITE_OK {
+            return Err(database.error());
+        }
+        Ok(database)
+    }
+
+    /// The last error on the connection
+    fn error(&self) -> io::Error {
+        if self.raw.is_null() {
+            return io::Error::other("out of memory opening the store");
+        }
+        // SAFETY: the connection is open, and the message is NUL-terminated
+        // and copied before anything else can change it
+        let message = unsafe { CStr::from_ptr(sqlite3_errmsg(self.raw)) };
+        io::Error::other(message.to_string_lossy().into_owned())
+    }
+
+    fn check(&self, code: c_int) -> io::Result<()> {
+        match code {
+            SQLITE_OK => Ok(()),
+            _ => Err(self.error()),
+        }
+    }
+
+    /// Run statements that take no parameters and return no rows
+    pub fn execute(&self, sql: &str) -> io::Result<()> {
+        let sql = CString::new(sql).map_err(io::Error::other)?;
+        let mut error = ptr::null_mut();
+        // SAFETY: sql is NUL-terminated, and a message SQLite allocates into
+        // error is freed with sqlite3_free
+        unsafe {
+            let code = sqlite3_exec(
+                self.raw,
+                sql.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                &mut error,
+            );
+            if !error.is_null() {
+                let message = CStr::from_ptr(error).to_string_lossy().into_owned();
+                sqlite3_free(error.cast());
+                return Err(io::Error::other(message));
+            }
+            self.check(code)
+        }
+    }
+
+    pub fn prepare(&self, sql: &str) -> io::Result<Statement<'_>> {
+        let sql = CString::new(sql).map_err(io::Error::other)?;
+        let mut raw = ptr::null_mut();
+        // SAFETY: sql is NUL-terminated, of which SQLite reads the first
+        // statement
+        let code =
+            unsafe { sqlite3_prepare_v2(self.raw, sql.as_ptr(), -1, &mut raw, ptr::null_mut()) };
+        self.check(code)?;
+        Ok(Statement {
+            raw,
+            database: self,
+        })
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        // SAFETY: the statements borrow the database, so they're finalized by now
+        unsafe {
+            sqlite3_close(self.raw);
+        }
+    }
+}
+
+pub struct Statement<'a> {
+    raw: *mut Sqlite3Stmt,
+    database: &'a Database,
+}
+
+impl Statement<'_> {
+    /// Bind the parameters, `?1` onwards, for the next run of the statement
+    pub fn bind(&mut self, values: &[Value]) -> io::Result<()> {
+        // SAFETY: the statement is prepared; text is copied in as it's bound
+        unsafe {
+            sqlite3_reset(self.raw);
+            sqlite3_clear_bindings(self.raw);
+            for (i, value) in values.iter().enumerate() {
+                let index = i as c_int + 1;
+                let code = match value {
+                    Value::Text(text) => sqlite3_bind_text(
+                        self.raw,
+                        index,
+                        text.as_ptr().cast(),
+                        c_int::try_from(text.len()).map_err(io::Error::other)?,
+                        SQLITE_TRANSIENT,
+                    ),
+                    Value::Integer(value) => sqlite3_bind_int64(self.raw, index, *value),
+                    Value::Real(value) => sqlite3_bind_double(self.raw, index, *value),
+                    Value::Null => sqlite3_bind_null(self.raw, index),
+                };
+                self.database.check(code)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Go to the next row, `false` once there are no more
+    pub fn step(&mut self) -> io::Result<bool> {
+        // SAFETY: the statement is prepared, its parameters bound and copied
+        match unsafe { sqlite3_step(self.raw) } {
+            SQLITE_ROW => Ok(true),
+            SQLITE_DONE => Ok(false),
+            _ => Err(self.database.error()),
+        }
+    }
+
+    /// A column of the current row as text, empty for NULL
+    pub fn text(&self, column: c_int) -> String {
+        // SAFETY: on a row, the text stays valid until the next step, and is
+        // copied before that
+        unsafe {
+            let text = sqlite3_column_text(self.raw, column);
+            if text.is_null() {
+                return String::new();
+            }
+            CStr::from_ptr(text).to_string_lossy().into_owned()
+        }
+    }
+
+    pub fn integer(&self, column: c_int) -> i64 {
+        // SAFETY: on a row, SQLite converts whatever the column holds
+        unsafe { sqlite3_column_int64(self.raw, column) }
+    }
+
+    pub fn real(&self, column: c_int) -> f64 {
+        // SAFETY: on a row, SQLite converts whatever the column holds
+        unsafe { sqlite3_column_double(self.raw, column) }
+    }
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the statement was prepared and isn't used after this
+        unsafe {
+            sqlite3_finalize(self.raw);
+        }
+    }
+}
@@ -0,0 +1,412 @@
+//! Comparing the hours logged each day with the hours expected
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+/// Hours expected per day, from `--target-hours-per-day` and `--target-hours`
+#[derive(Debug, Clone, Default)]
+pub struct Targets {
+    pub per_day: f64,
+    /// Overrides per weekday; Saturday and Sunday expect nothing unless given
+    pub weekdays: HashMap<Weekday, f64>,
+    /// Days off and what they are, expecting no hours
+    pub holidays: BTreeMap<NaiveDate, String>,
+}
+
+impl Targets {
+    /// Mark `first..=last` as days off
+    pub fn add_holidays(&mut self, first: NaiveDate, last: NaiveDate, name: &str) {
+        for day in first.iter_days().take_while(|day| *day <= last) {
+            self.holidays.entry(day).or_insert_with(|| name.to_string());
+        }
+    }
+
+    pub fn hours(&self, day: NaiveDate) -> f64 {
+        if self.holidays.contains_key(&day) {
+            return 0.0;
+        }
+        match self.weekdays.get(&day.weekday()) {
+            Some(hours) => *hours,
+            None if matches!(day.weekday(), Weekday::Sat | Weekday::Sun) => 0.0,
+            None => self.per_day,
+        }
+    }
+}
+
+/// Parse a `--target-hours` value such as `fri=6`
+pub fn parse_weekday_hours(value: &str) -> Result<(Weekday, f64), String> {
+    let (day, hours) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected DAY=HOURS, got {}", value))?;
+    let day = day
+        .trim()
+        .parse::<Weekday>()
+        .map_err(|_| format!("unknown weekday {}", day))?;
+    let hours = hours
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| format!("invalid hours {}", hours))?;
+    Ok((day, hours))
+}
+
+/// Parse a `--holiday` value, a date or a `FIRST..LAST` span such as a vacation
+pub fn parse_days_off(value: &str) -> Result<(NaiveDate, NaiveDate), String> {
+    let date = |text: &str| {
+        NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("invalid date {}, expected YYYY-MM-DD", text))
+    };
+    let (first, last) = match value.split_once("..") {
+        Some((first, last)) => (date(first)?, date(last)?),
+        None => (date(value)?, date(value)?),
+    };
+    if last < first {
+        return Err(format!("{} ends before it starts", value));
+    }
+    Ok((first, last))
+}
+
+/// The all-day events of an iCalendar file, as first and last day and summary
+///
+/// Recurrence rules aren't expanded, so yearly holidays need an event per year,
+/// as exported public holiday calendars have.
+pub fn read_ics(path: &Path) -> Result<Vec<(NaiveDate, NaiveDate, String)>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|error| format!("Can't read {}: {}", path.display(), error))?;
+    let error = |message: &str| format!("{}: {}", path.display(), message);
+
+    // Lines starting with whitespace continue the one before
+    let mut lines: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = Vec::new();
+    let (mut start, mut end, mut summary) = (None, None, String::new());
+    for line in &lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = name.split(';').next().unwrap_or_default();
+        match name.to_ascii_uppercase().as_str() {
+            "BEGIN" if value.eq_ignore_ascii_case("VEVENT") => {
+                (start, end, summary) = (None, None, String::new());
+            }
+            "DTSTART" => start = Some(ics_date(value).ok_or_else(|| error("invalid DTSTART"))?),
+            "DTEND" => end = Some(ics_date(value).ok_or_else(|| error("invalid DTEND"))?),
+            "SUMMARY" => {
+                summary = unescape(value);
+            }
+            "END" if value.eq_ignore_ascii_case("VEVENT") => {
+                let Some((first, _)) = start else {
+                    return Err(error("event without DTSTART"));
+                };
+                // All-day events end the day before DTEND, timed ones on it
+                let last = match end {
+                    Some((last, true)) => last,
+                    Some((after, false)) => after - Duration::days(1),
+                    None => first,
+                };
+                events.push((first, last.max(first), summary.clone()));
+            }
+            _ => {}
+        }
+    }
+    Ok(events)
+}
+
+/// Undo iCalendar text escapes; line breaks become spaces
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push(' '),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// An iCalendar `DATE` or `DATE-TIME`, and whether it's a time within its day
+fn ics_date(value: &str) -> Option<(NaiveDate, bool)> {
+    let date = NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()?;
+    let within = value
+        .get(8..)
+        .is_some_and(|time| time.starts_with('T') && !time.starts_with("T000000"));
+    Some((date, within))
+}
+
+#[derive(Debug, Serialize)]
+pub struct DayTarget {
+    pub date: NaiveDate,
+    /// What the day off is, when it is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub holiday: Option<String>,
+    pub expected: f64,
+    pub logged: f64,
+    /// Logged minus expected, negative when under-logged
+    pub difference: f64,
+}
+
+/// Logged against expected hours for every day of the range
+#[derive(Debug, Serialize)]
+pub struct TargetComparison {
+    pub days: Vec<DayTarget>,
+    pub expected: f64,
+    pub logged: f64,
+    pub difference: f64,
+}
+
+pub fn compare(
+    targets: &Targets,
+    sums: &BTreeMap<NaiveDate, f64>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> TargetComparison {
+    let days: Vec<DayTarget> = from
+        .iter_days()
+        .take_while(|day| *day <= to)
+        .map(|date| {
+            let expected = targets.hours(date);
+            let logged = sums.get(&date).copied().unwrap_or(0.0);
+            DayTarget {
+                date,
+                holiday: targets.holidays.get(&date).cloned(),
+                expected,
+                logged,
+                difference: logged - expected,
+            }
+        })
+        // Weekends without any time logged tell nothing, holidays are shown
+        .filter(|day| day.expected != 0.0 || day.logged != 0.0 || day.holiday.is_some())
+        .collect();
+    let expected: f64 = days.iter().map(|day| day.expected).sum();
+    let logged: f64 = days.iter().map(|day| day.logged).sum();
+    TargetComparison {
+        days,
+        expected,
+        logged,
+        difference: logged - expected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn weekends_expect_nothing_unless_given() {
+        let targets = Targets {
+            per_day: 7.5,
+            weekdays: HashMap::from([(Weekday::Fri, 6.0), (Weekday::Sat, 2.0)]),
+            ..Targets::default()
+        };
+        // Monday 18 March 2024 onwards
+        let week: Vec<f64> = (18..=24)
+            .map(|day| targets.hours(date(&format!("2024-03-{}", day))))
+            .collect();
+        assert_eq!(week, [7.5, 7.5, 7.5, 7.5, 6.0, 2.0, 0.0]);
+
+        assert_eq!(parse_weekday_hours("fri=6").unwrap(), (Weekday::Fri, 6.0));
+        assert_eq!(
+            parse_weekday_hours(" Monday = 4.5 ").unwrap(),
+            (Weekday::Mon, 4.5)
+        );
+        assert_eq!(
+            parse_weekday_hours("fri").unwrap_err(),
+            "expected DAY=HOURS, got fri"
+        );
+        assert_eq!(
+            parse_weekday_hours("fry=6").unwrap_err(),
+            "unknown weekday fry"
+        );
+        assert_eq!(
+            parse_weekday_hours("fri=six").unwrap_err(),
+            "invalid hours six"
+        );
+    }
+
+    #[test]
+    fn days_are_compared_with_what_they_expect() {
+        let targets = Targets {
+            per_day: 7.5,
+            ..Targets::default()
+        };
+        let sums = BTreeMap::from([
+            (date("2024-03-22"), 8.0),
+            (date("2024-03-23"), 1.0),
+            // Outside the range
+            (date("2024-03-26"), 7.5),
+        ]);
+        let comparison = compare(&targets, &sums, date("2024-03-21"), date("2024-03-25"));
+
+        let days: Vec<(String, f64, f64, f64)> = comparison
+            .days
+            .iter()
+            .map(|day| {
+                (
+                    day.date.to_string(),
+                    day.expected,
+                    day.logged,
+                    day.difference,
+                )
+            })
+            .collect();
+        // The empty Sunday is left out, the Saturday with time isn't
+        assert_eq!(
+            days,
+            [
+                ("2024-03-21".to_string(), 7.5, 0.0, -7.5),
+                ("2024-03-22".to_string(), 7.5, 8.0, 0.5),
+                ("2024-03-23".to_string(), 0.0, 1.0, 1.0),
+                ("2024-03-25".to_string(), 7.5, 0.0, -7.5),
+            ]
+        );
+        assert_eq!(
+            (
+                comparison.expected,
+                comparison.logged,
+                comparison.difference
+            ),
+            (22.5, 9.0, -13.5)
+        );
+    }
+
+    #[test]
+    fn days_off_are_a_day_or_a_span() {
+        assert_eq!(
+            parse_days_off("2024-03-28").unwrap(),
+            (date("2024-03-28"), date("2024-03-28"))
+        );
+        assert_eq!(
+            parse_days_off("2024-07-08..2024-07-26").unwrap(),
+            (date("2024-07-08"), date("2024-07-26"))
+        );
+        assert_eq!(
+            parse_days_off("2024-07-26..2024-07-08").unwrap_err(),
+            "2024-07-26..2024-07-08 ends before it starts"
+        );
+        assert_eq!(
+            parse_days_off("2024-07-08..").unwrap_err(),
+            "invalid date , expected YYYY-MM-DD"
+        );
+        assert_eq!(
+            parse_days_off("8/7/2024").unwrap_err(),
+            "invalid date 8/7/2024, expected YYYY-MM-DD"
+        );
+    }
+
+    #[test]
+    fn holidays_from_a_calendar_expect_nothing() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/holidays.ics");
+        let events = read_ics(&path).unwrap();
+        let events: Vec<(String, String, &str)> = events
+            .iter()
+            .map(|(first, last, summary)| (first.to_string(), last.to_string(), summary.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                // All-day events end before DTEND
+                (
+                    "2024-03-28".to_string(),
+                    "2024-03-28".to_string(),
+                    "Skærtorsdag"
+                ),
+                (
+                    "2024-03-29".to_string(),
+                    "2024-04-01".to_string(),
+                    // Folded onto a second line, with escapes
+                    "Easter, from Good Friday to Easter Monday; the long way round"
+                ),
+                // Timed ones on it, unless at midnight
+                (
+                    "2024-04-05".to_string(),
+                    "2024-04-05".to_string(),
+                    "Dentist then home"
+                ),
+                ("2024-04-08".to_string(), "2024-04-09".to_string(), "Course"),
+                (
+                    "2024-05-01".to_string(),
+                    "2024-05-01".to_string(),
+                    "Labour Day"
+                ),
+            ]
+        );
+
+        let mut targets = Targets {
+            per_day: 7.5,
+            ..Targets::default()
+        };
+        for (first, last, summary) in read_ics(&path).unwrap() {
+            targets.add_holidays(first, last, &summary);
+        }
+        // A vacation given on the command line doesn't rename the calendar's
+        targets.add_holidays(date("2024-04-01"), date("2024-04-03"), "Vacation");
+        let sums = BTreeMap::from([(date("2024-03-28"), 1.0)]);
+        let comparison = compare(&targets, &sums, date("2024-03-27"), date("2024-04-03"));
+        let days: Vec<(String, Option<&str>, f64, f64)> = comparison
+            .days
+            .iter()
+            .map(|day| {
+                (
+                    day.date.to_string(),
+                    day.holiday.as_deref(),
+                    day.expected,
+                    day.difference,
+                )
+            })
+            .collect();
+        let easter = Some("Easter, from Good Friday to Easter Monday; the long way round");
+        assert_eq!(
+            days,
+            [
+                ("2024-03-27".to_string(), None, 7.5, -7.5),
+                ("2024-03-28".to_string(), Some("Skærtorsdag"), 0.0, 1.0),
+                ("2024-03-29".to_string(), easter, 0.0, 0.0),
+                ("2024-03-30".to_string(), easter, 0.0, 0.0),
+                ("2024-03-31".to_string(), easter, 0.0, 0.0),
+                ("2024-04-01".to_string(), easter, 0.0, 0.0),
+                ("2024-04-02".to_string(), Some("Vacation"), 0.0, 0.0),
+                ("2024-04-03".to_string(), Some("Vacation"), 0.0, 0.0),
+            ]
+        );
+        assert_eq!(comparison.expected, 7.5);
+    }
+
+    #[test]
+    fn events_need_a_start() {
+        let path = std::env::temp_dir().join(format!("holidays-{}.ics", std::process::id()));
+        fs::write(&path, "BEGIN:VEVENT\nSUMMARY:Whenever\nEND:VEVENT\n").unwrap();
+        let missing = read_ics(&path);
+        fs::write(&path, "BEGIN:VEVENT\nDTSTART:2024-03-28\nEND:VEVENT\n").unwrap();
+        let invalid = read_ics(&path);
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(
+            missing.unwrap_err(),
+            format!("{}: event without DTSTART", path.display())
+        );
+        assert_eq!(
+            invalid.unwrap_err(),
+            format!("{}: invalid DTSTART", path.display())
+        );
+    }
+}
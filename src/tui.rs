@@ -0,0 +1,814 @@
+//! Interactive dashboard of the report in the terminal
+//!
+//! The days of the range are columns and the work items, or the users, rows.
+//! Enter drills into the revisions behind a row, and the range can be moved
+//! and switched between week and month without restarting. The terminal is
+//! driven directly, in raw mode and with ANSI escapes.
+
+use azure_devops_time_used::{
+    dates::{self, Period, WeekStart},
+    output::DurationFormat,
+    AzdoClient, Connection, Error, Report, ReportQuery,
+};
+use chrono::{Duration, NaiveDate};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, Read, Write},
+    mem::MaybeUninit,
+};
+
+/// Width of the row labels
+const LABEL: usize = 32;
+/// Width of a day or total column
+const COLUMN: usize = 8;
+
+#[derive(Debug, PartialEq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Back,
+    Char(char),
+}
+
+/// Puts the terminal in raw mode on the alternate screen until dropped
+struct Terminal(libc::termios);
+
+impl Terminal {
+    fn enter() -> io::Result<Terminal> {
+        let mut original = MaybeUninit::uninit();
+        // SAFETY: tcgetattr fills the termios in when it succeeds
+        let original = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, original.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            original.assume_init()
+        };
+        let mut raw = original;
+        // SAFETY: raw is a valid termios, copied from the terminal's own
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Terminal(original))
+    }
+
+    /// Columns and rows, 80 by 24 when the terminal won't say
+    fn size() -> (usize, usize) {
+        let mut size = MaybeUninit::<libc::winsize>::zeroed();
+        // SAFETY: TIOCGWINSZ writes a winsize, which zeroed is valid for too
+        let size = unsafe {
+            libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr());
+            size.assume_init()
+        };
+        match (size.ws_col, size.ws_row) {
+            (0, _) | (_, 0) => (80, 24),
+            (columns, rows) => (usize::from(columns), usize::from(rows)),
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // SAFETY: restores the termios read in enter
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0);
+        }
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Block until keys are pressed, and read all that came; `None` when stdin closed
+fn read_keys() -> io::Result<Option<Vec<Key>>> {
+    let mut buffer = [0u8; 64];
+    let read = io::stdin().read(&mut buffer)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    Ok(Some(keys(&buffer[..read])))
+}
+
+/// The keys pressed, from what the terminal sent for them
+fn keys(mut bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    while !bytes.is_empty() {
+        let (key, length) = match bytes {
+            [0x1b, b'[' | b'O', b'A', ..] => (Some(Key::Up), 3),
+            [0x1b, b'[' | b'O', b'B', ..] => (Some(Key::Down), 3),
+            [0x1b, b'[' | b'O', b'C', ..] => (Some(Key::Right), 3),
+            [0x1b, b'[' | b'O', b'D', ..] => (Some(Key::Left), 3),
+            [b'\r' | b'\n', ..] => (Some(Key::Enter), 1),
+            [0x1b | 0x7f | 0x08, ..] => (Some(Key::Back), 1),
+            // Ctrl-C, as raw mode doesn't turn it into a signal
+            [0x03, ..] => (Some(Key::Char('q')), 1),
+            _ => {
+                let text = String::from_utf8_lossy(&bytes[..bytes.len().min(4)]);
+                let c = text.chars().next().unwrap_or(char::REPLACEMENT_CHARACTER);
+                // A byte that isn't UTF-8 is skipped on its own
+                let length = match c {
+                    char::REPLACEMENT_CHARACTER => 1,
+                    c => c.len_utf8(),
+                };
+                (Some(Key::Char(c)), length)
+            }
+        };
+        keys.extend(key);
+        bytes = &bytes[length..];
+    }
+    keys
+}
+
+/// What the rows of the table are
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Rows {
+    Items,
+    Users,
+}
+
+struct Row {
+    label: String,
+    /// Index of the work item, for the item rows
+    item: Option<usize>,
+    /// Email of the user, for the user rows
+    user: Option<String>,
+    days: BTreeMap<NaiveDate, f64>,
+    total: f64,
+}
+
+fn rows(report: &Report, kind: Rows) -> Vec<Row> {
+    match kind {
+        Rows::Items => report
+            .items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let mut days = BTreeMap::new();
+                for entry in &item.entries {
+                    *days.entry(entry.date).or_insert(0.0) += entry.hours;
+                }
+                Row {
+                    label: format!("{} {}", item.id, item.title),
+                    item: Some(index),
+                    user: None,
+                    days,
+                    total: item.total,
+                }
+            })
+            .collect(),
+        Rows::Users => {
+            let mut users: BTreeMap<String, Row> = BTreeMap::new();
+            for entry in report.items.iter().flat_map(|item| &item.entries) {
+                let row = users
+                    .entry(entry.changed_by.email.clone())
+                    .or_insert_with(|| Row {
+                        label: entry.changed_by.display_name.clone(),
+                        item: None,
+                        user: Some(entry.changed_by.email.clone()),
+                        days: BTreeMap::new(),
+                        total: 0.0,
+                    });
+                *row.days.entry(entry.date).or_insert(0.0) += entry.hours;
+                row.total += entry.hours;
+            }
+            users.into_values().collect()
+        }
+    }
+}
+
+/// `text` cut or padded to exactly `width` characters
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text.chars().take(width).collect();
+    let length = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - length));
+    fitted
+}
+
+struct Dashboard<'a> {
+    client: &'a AzdoClient,
+    connections: &'a [Connection],
+    query: ReportQuery,
+    duration_format: DurationFormat,
+    report: Option<Report>,
+    kind: Rows,
+    rows: Vec<Row>,
+    row: usize,
+    column: usize,
+    /// First day column shown, when they don't all fit
+    offset: usize,
+    /// Lines scrolled down in the revisions of the selected row, when open
+    detail: Option<usize>,
+    message: String,
+}
+
+impl Dashboard<'_> {
+    fn days(&self) -> Vec<NaiveDate> {
+        self.query
+            .from
+            .iter_days()
+            .take_while(|day| *day <= self.query.to)
+            .collect()
+    }
+
+    async fn load(&mut self) {
+        self.message = format!("Loading {} to {}...", self.query.from, self.query.to);
+        let _ = self.draw();
+        match self.client.report(self.connections, &self.query).await {
+            Ok(report) => {
+                self.rows = rows(&report, self.kind);
+                self.report = Some(report);
+                self.message.clear();
+            }
+            Err(error) => {
+                self.rows.clear();
+                self.report = None;
+                self.message = format!("Error: {}", error);
+            }
+        }
+        self.row = self.row.min(self.rows.len().saturating_sub(1));
+        self.column = self.column.min(self.days().len().saturating_sub(1));
+        self.offset = 0;
+        self.detail = None;
+    }
+
+    fn show(&mut self, (from, to): (NaiveDate, NaiveDate)) -> bool {
+        let changed = (from, to) != (self.query.from, self.query.to);
+        self.query.from = from;
+        self.query.to = to;
+        changed
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let (width, height) = Terminal::size();
+        let screen = self.screen(width, height);
+        let mut out = io::stdout().lock();
+        out.write_all(screen.as_bytes())?;
+        out.flush()
+    }
+
+    /// What the terminal shows, at its size
+    fn screen(&mut self, width: usize, height: usize) -> String {
+        let days = self.days();
+        let shown = width.saturating_sub(LABEL + COLUMN) / COLUMN;
+        let shown = shown.max(1);
+        if self.column < self.offset {
+            self.offset = self.column;
+        } else if self.column >= self.offset + shown {
+            self.offset = self.column + 1 - shown;
+        }
+        let hours = |hours: f64| {
+            if hours == 0.0 {
+                String::new()
+            } else {
+                self.duration_format.format((hours * 100.0).round() / 100.0)
+            }
+        };
+
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        let total = self.report.as_ref().map_or(0.0, |report| report.total);
+        let _ = write!(
+            screen,
+            "\x1b[1m{} to {}\x1b[0m  total {}  [{}]\r\n",
+            self.query.from,
+            self.query.to,
+            hours(total),
+            match self.kind {
+                Rows::Items => "work items",
+                Rows::Users => "users",
+            }
+        );
+
+        let visible = &days[self.offset.min(days.len())..days.len().min(self.offset + shown)];
+        let mut header = fit("", LABEL);
+        for (index, day) in visible.iter().enumerate() {
+            let text = fit(&day.format("%a %d").to_string(), COLUMN);
+            if self.offset + index == self.column {
+                let _ = write!(header, "\x1b[4m{}\x1b[24m", text);
+            } else {
+                header.push_str(&text);
+            }
+        }
+        header.push_str(&fit("Total", COLUMN));
+        let _ = write!(screen, "\x1b[1m{}\x1b[0m\r\n", header);
+
+        // Title, header, footer and the message line
+        let body = height.saturating_sub(4);
+        match self.detail {
+            None => {
+                let first = self
+                    .row
+                    .saturating_sub(body.saturating_sub(1))
+                    .min(self.rows.len());
+                for (index, row) in self.rows.iter().enumerate().skip(first).take(body) {
+                    let mut line = fit(&row.label, LABEL - 1) + " ";
+                    for day in visible {
+                        line.push_str(&fit(
+                            &hours(row.days.get(day).copied().unwrap_or(0.0)),
+                            COLUMN,
+                        ));
+                    }
+                    line.push_str(&fit(&hours(row.total), COLUMN));
+                    if index == self.row {
+                        let _ = write!(screen, "\x1b[7m{}\x1b[0m\r\n", line);
+                    } else {
+                        let _ = write!(screen, "{}\r\n", line);
+                    }
+                }
+                if self.rows.is_empty() && self.message.is_empty() {
+                    screen.push_str("No time logged\r\n");
+                }
+            }
+            Some(scroll) => {
+                let lines = self.detail_lines(days.get(self.column).copied());
+                // The first line names the row
+                for (index, line) in lines.iter().enumerate().skip(scroll).take(body) {
+                    let line = fit(line, width);
+                    if index == 0 {
+                        let _ = write!(screen, "\x1b[1m{}\x1b[0m\r\n", line);
+                    } else {
+                        let _ = write!(screen, "{}\r\n", line);
+                    }
+                }
+            }
+        }
+
+        let _ = write!(
+            screen,
+            "\x1b[{};1H{}\r\n\x1b[2m{}\x1b[0m",
+            height.saturating_sub(1),
+            fit(&self.message, width),
+            fit(
+                "arrows move  enter revisions  [ ] previous/next  w week  m month  t today  u rows  r reload  q quit",
+                width
+            )
+        );
+        screen
+    }
+
+    /// The revisions behind the selected row, those on `day` marked
+    fn detail_lines(&self, day: Option<NaiveDate>) -> Vec<String> {
+        let (Some(report), Some(row)) = (&self.report, self.rows.get(self.row)) else {
+            return Vec::new();
+        };
+        let items = match row.item {
+            Some(index) => std::slice::from_ref(&report.items[index]),
+            None => report.items.as_slice(),
+        };
+        let mut lines = vec![row.label.clone()];
+        for item in items {
+            for entry in &item.entries {
+                if row
+                    .user
+                    .as_ref()
+                    .is_some_and(|user| *user != entry.changed_by.email)
+                {
+                    continue;
+                }
+                let marker = if Some(entry.date) == day { '>' } else { ' ' };
+                let what = match row.item {
+                    Some(_) => entry.changed_by.to_string(),
+                    None => format!("#{} {}", item.id, item.title),
+                };
+                lines.push(format!(
+                    "{} {} rev {:<4} {:>7} -> {:<7} {}",
+                    marker,
+                    entry.date,
+                    entry.rev,
+                    self.duration_format.format(entry.hours),
+                    self.duration_format.format(entry.completed_work),
+                    what
+                ));
+            }
+        }
+        lines
+    }
+
+    /// Act on a key; `false` to quit
+    async fn press(&mut self, key: Key) -> bool {
+        match (key, self.detail) {
+            (Key::Char('q'), _) => return false,
+            (Key::Back, Some(_)) => self.detail = None,
+            (Key::Up | Key::Char('k'), Some(scroll)) => {
+                self.detail = Some(scroll.saturating_sub(1))
+            }
+            (Key::Down | Key::Char('j'), Some(scroll)) => self.detail = Some(scroll + 1),
+            (Key::Enter, None) if !self.rows.is_empty() => self.detail = Some(0),
+            (Key::Up | Key::Char('k'), None) => self.row = self.row.saturating_sub(1),
+            (Key::Down | Key::Char('j'), None) => {
+                self.row = (self.row + 1).min(self.rows.len().saturating_sub(1))
+            }
+            (Key::Left | Key::Char('h'), _) => self.column = self.column.saturating_sub(1),
+            (Key::Right | Key::Char('l'), _) => {
+                self.column = (self.column + 1).min(self.days().len().saturating_sub(1))
+            }
+            (Key::Char('u'), _) => {
+                self.kind = match self.kind {
+                    Rows::Items => Rows::Users,
+                    Rows::Users => Rows::Items,
+                };
+                if let Some(report) = &self.report {
+                    self.rows = rows(report, self.kind);
+                }
+                self.row = 0;
+                self.detail = None;
+            }
+            (Key::Char('r'), _) => self.load().await,
+            (Key::Char(key @ ('[' | ']' | 'w' | 'm' | 't')), _) => {
+                let range = moved(
+                    key,
+                    (self.query.from, self.query.to),
+                    self.query.week_start,
+                    dates::today(),
+                );
+                if self.show(range) {
+                    self.load().await;
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+/// The range a key switches to from `from` to `to`: the one before or after
+/// it, the week or month it starts in, or this week
+fn moved(
+    key: char,
+    (from, to): (NaiveDate, NaiveDate),
+    week_start: WeekStart,
+    today: NaiveDate,
+) -> (NaiveDate, NaiveDate) {
+    let length = to - from + Duration::days(1);
+    // Months differ in length, so a month steps to the one before or after
+    let month = Period::ThisMonth.range(from, week_start) == (from, to);
+    match key {
+        '[' if month => Period::ThisMonth.range(from - Duration::days(1), week_start),
+        ']' if month => Period::ThisMonth.range(to + Duration::days(1), week_start),
+        '[' => (from - length, from - Duration::days(1)),
+        ']' => (from + length, to + length),
+        'w' => Period::ThisWeek.range(from, week_start),
+        'm' => Period::ThisMonth.range(from, week_start),
+        _ => Period::ThisWeek.range(today, week_start),
+    }
+}
+
+/// Run the dashboard until `q` is pressed
+pub async fn run(
+    client: &AzdoClient,
+    connections: &[Connection],
+    query: ReportQuery,
+    duration_format: DurationFormat,
+) -> Result<(), Error> {
+    let _terminal = Terminal::enter()?;
+    let mut dashboard = Dashboard {
+        client,
+        connections,
+        query,
+        duration_format,
+        report: None,
+        kind: Rows::Items,
+        rows: Vec::new(),
+        row: 0,
+        column: 0,
+        offset: 0,
+        detail: None,
+        message: String::new(),
+    };
+    dashboard.load().await;
+
+    loop {
+        dashboard.draw()?;
+        let Some(keys) = tokio::task::spawn_blocking(read_keys)
+            .await
+            .map_err(|error| error.to_string())??
+        else {
+            return Ok(());
+        };
+        for key in keys {
+            if !dashboard.press(key).await {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use azure_devops_time_used::{
+        Backend, ClientOptions, HistorySource, ReportItem, Tiebreak, TimeEntry, User, CHANGED_BY,
+        COMPLETED_WORK,
+    };
+    use std::collections::HashMap;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    /// The week of 2024-03-18, for everyone
+    fn query() -> ReportQuery {
+        ReportQuery {
+            user: "a@b.c".to_string(),
+            users: None,
+            aliases: HashMap::new(),
+            from: date("2024-03-18"),
+            to: date("2024-03-24"),
+            since_state: None,
+            attribute_field: CHANGED_BY.to_string(),
+            field: COMPLETED_WORK.to_string(),
+            track: Vec::new(),
+            strict_range_server: false,
+            wiql: None,
+            conditions: Vec::new(),
+            query_id: None,
+            rollup: None,
+            baselines: HashMap::new(),
+            tiebreak: Tiebreak::Rev,
+            sample: None,
+            week_start: WeekStart::Mon,
+            concurrency: 1,
+            history: HistorySource::Revisions,
+            backend: Backend::Rest,
+            cache: None,
+            offline: true,
+        }
+    }
+
+    /// An item with these revisions: number, day, whose and hours
+    fn item(id: u64, entries: &[(u32, &str, &str, f64)]) -> ReportItem {
+        let entries: Vec<TimeEntry> = entries
+            .iter()
+            .map(|(rev, day, email, hours)| TimeEntry {
+                rev: *rev,
+                date: date(day),
+                changed_by: User {
+                    id: uuid::Uuid::nil(),
+                    display_name: email.split('@').next().unwrap().to_string(),
+                    email: email.to_string(),
+                },
+                completed_work: *hours,
+                hours: *hours,
+                reason: None,
+            })
+            .collect();
+        ReportItem {
+            id,
+            title: format!("Item {}", id),
+            organization: "org".to_string(),
+            project: "Proj".to_string(),
+            url: String::new(),
+            tags: Vec::new(),
+            iteration: None,
+            area: None,
+            client: None,
+            rolls_up_to: None,
+            total: entries.iter().map(|entry| entry.hours).sum(),
+            entries,
+            contributors: Vec::new(),
+            state_reason: None,
+            ambiguous: false,
+            percent_complete: None,
+            tracked: BTreeMap::new(),
+            estimate: None,
+        }
+    }
+
+    fn report() -> Report {
+        let mut report = Report::combine(&query(), Vec::new());
+        report.items = vec![
+            item(
+                1,
+                &[
+                    (2, "2024-03-18", "ann@b.c", 2.0),
+                    (3, "2024-03-19", "bob@b.c", 1.5),
+                ],
+            ),
+            item(2, &[(5, "2024-03-18", "ann@b.c", 0.5)]),
+        ];
+        report.total = 4.0;
+        report
+    }
+
+    fn showing<'a>(client: &'a AzdoClient, report: Report) -> Dashboard<'a> {
+        Dashboard {
+            client,
+            connections: &[],
+            query: query(),
+            duration_format: DurationFormat::Decimal,
+            rows: rows(&report, Rows::Items),
+            report: Some(report),
+            kind: Rows::Items,
+            row: 0,
+            column: 0,
+            offset: 0,
+            detail: None,
+            message: String::new(),
+        }
+    }
+
+    /// Lines of the screen without the escapes, or the trailing spaces
+    fn text(screen: &str) -> Vec<String> {
+        let mut text = String::new();
+        let mut chars = screen.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => {
+                    for c in chars.by_ref() {
+                        if c.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                }
+                '\r' => {}
+                c => text.push(c),
+            }
+        }
+        text.lines()
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn keys_are_read_from_what_the_terminal_sends() {
+        assert_eq!(
+            keys(b"\x1b[A\x1bOB\x1b[C\x1b[D\r\n\x7f\x08\x1b\x03j\xc3\xa6\xffq"),
+            [
+                Key::Up,
+                Key::Down,
+                Key::Right,
+                Key::Left,
+                Key::Enter,
+                Key::Enter,
+                Key::Back,
+                Key::Back,
+                Key::Back,
+                Key::Char('q'),
+                Key::Char('j'),
+                Key::Char('æ'),
+                Key::Char(char::REPLACEMENT_CHARACTER),
+                Key::Char('q'),
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_are_cut_or_padded_to_the_column() {
+        assert_eq!(fit("æøå", 5), "æøå  ");
+        assert_eq!(fit("1234 A long title", 6), "1234 A");
+        assert_eq!(fit("", 2), "  ");
+    }
+
+    #[test]
+    fn rows_are_items_or_users() {
+        let report = report();
+        let summed = |kind| {
+            rows(&report, kind)
+                .into_iter()
+                .map(|row| {
+                    (
+                        row.label,
+                        row.days.into_iter().collect::<Vec<_>>(),
+                        row.total,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summed(Rows::Items),
+            [
+                (
+                    "1 Item 1".to_string(),
+                    vec![(date("2024-03-18"), 2.0), (date("2024-03-19"), 1.5)],
+                    3.5
+                ),
+                ("2 Item 2".to_string(), vec![(date("2024-03-18"), 0.5)], 0.5),
+            ]
+        );
+        // Across the items
+        assert_eq!(
+            summed(Rows::Users),
+            [
+                ("ann".to_string(), vec![(date("2024-03-18"), 2.5)], 2.5),
+                ("bob".to_string(), vec![(date("2024-03-19"), 1.5)], 1.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges_step_and_switch() {
+        let week = (date("2024-03-18"), date("2024-03-24"));
+        let february = (date("2024-02-01"), date("2024-02-29"));
+        let today = date("2024-05-08");
+        let moved = |key, range| moved(key, range, WeekStart::Mon, today);
+        assert_eq!(moved('[', week), (date("2024-03-11"), date("2024-03-17")));
+        assert_eq!(moved(']', week), (date("2024-03-25"), date("2024-03-31")));
+        // Months step by their own length
+        assert_eq!(
+            moved('[', february),
+            (date("2024-01-01"), date("2024-01-31"))
+        );
+        assert_eq!(
+            moved(']', february),
+            (date("2024-03-01"), date("2024-03-31"))
+        );
+        assert_eq!(moved('m', week), (date("2024-03-01"), date("2024-03-31")));
+        assert_eq!(
+            moved('w', february),
+            (date("2024-01-29"), date("2024-02-04"))
+        );
+        assert_eq!(
+            moved('t', february),
+            (date("2024-05-06"), date("2024-05-12"))
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_move_around_and_into_the_revisions() {
+        let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+        let mut dashboard = showing(&client, report());
+
+        for key in [Key::Down, Key::Down, Key::Right, Key::Char('l')] {
+            assert!(dashboard.press(key).await);
+        }
+        // Only as far as there are rows and days
+        assert_eq!((dashboard.row, dashboard.column), (1, 2));
+        for _ in 0..10 {
+            dashboard.press(Key::Right).await;
+        }
+        assert_eq!(dashboard.column, 6);
+        dashboard.press(Key::Up).await;
+        dashboard.press(Key::Char('h')).await;
+        assert_eq!((dashboard.row, dashboard.column), (0, 5));
+
+        dashboard.press(Key::Enter).await;
+        assert_eq!(dashboard.detail, Some(0));
+        // Up and down scroll the revisions now
+        dashboard.press(Key::Down).await;
+        assert_eq!((dashboard.row, dashboard.detail), (0, Some(1)));
+        dashboard.press(Key::Back).await;
+        assert_eq!(dashboard.detail, None);
+
+        dashboard.press(Key::Char('u')).await;
+        assert!(dashboard.kind == Rows::Users);
+        let labels: Vec<&str> = dashboard
+            .rows
+            .iter()
+            .map(|row| row.label.as_str())
+            .collect();
+        assert_eq!(labels, ["ann", "bob"]);
+        assert!(!dashboard.press(Key::Char('q')).await);
+
+        // Nothing to open without rows
+        let mut empty = showing(&client, Report::combine(&query(), Vec::new()));
+        empty.press(Key::Enter).await;
+        assert_eq!(empty.detail, None);
+        assert_eq!(empty.row, 0);
+    }
+
+    #[tokio::test]
+    async fn the_screen_shows_the_days_that_fit() {
+        let client = AzdoClient::new(&ClientOptions::default()).unwrap();
+        let mut dashboard = showing(&client, report());
+        // Room for the labels, the total and three days
+        let screen = text(&dashboard.screen(LABEL + 4 * COLUMN, 8));
+        assert_eq!(
+            screen[..4],
+            [
+                "2024-03-18 to 2024-03-24  total 4  [work items]",
+                "                                Mon 18  Tue 19  Wed 20  Total",
+                "1 Item 1                        2       1.5             3.5",
+                "2 Item 2                        0.5                     0.5",
+            ]
+        );
+        assert!(screen[5].starts_with("arrows move"), "{:?}", screen);
+
+        // Scrolled along to the selected day
+        dashboard.column = 4;
+        let screen = text(&dashboard.screen(LABEL + 4 * COLUMN, 8));
+        assert_eq!(
+            screen[1],
+            "                                Wed 20  Thu 21  Fri 22  Total"
+        );
+
+        // The revisions of the row, those of the selected day marked
+        dashboard.column = 0;
+        dashboard.row = 0;
+        dashboard.detail = Some(0);
+        let screen = text(&dashboard.screen(80, 8));
+        assert_eq!(
+            screen[2..5],
+            [
+                "1 Item 1",
+                "> 2024-03-18 rev 2          2 -> 2       ann <ann@b.c>",
+                "  2024-03-19 rev 3        1.5 -> 1.5     bob <bob@b.c>",
+            ]
+        );
+    }
+}
@@ -0,0 +1,437 @@
+//! Recording time on work items, by moving their CompletedWork on
+//!
+//! Reading hours relies on the difference between revisions, so logging time
+//! is a revision adding the hours to CompletedWork, or the field given instead,
+//! optionally taking them off RemainingWork too.
+
+use crate::{
+    client::{Authorize, SendRetrying},
+    dates, deserialize_hours, Connection, Error, REMAINING_WORK,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize)]
+struct WorkItem {
+    rev: u32,
+    fields: Fields,
+}
+
+#[derive(Debug, Deserialize)]
+struct Fields {
+    #[serde(rename = "System.ChangedDate")]
+    changed_date: DateTime<Utc>,
+    #[serde(
+        rename = "Microsoft.VSTS.Scheduling.RemainingWork",
+        default,
+        deserialize_with = "deserialize_hours"
+    )]
+    remaining_work: Option<f64>,
+    /// The field time is tracked in, under its own name
+    #[serde(flatten)]
+    other: HashMap<String, Value>,
+}
+
+/// What logging time on a work item changed, as before and after
+#[derive(Debug, Serialize)]
+pub struct Logged {
+    pub id: u64,
+    /// The revision the time was logged in
+    pub rev: u32,
+    /// The field time is tracked in, CompletedWork by default
+    pub field: String,
+    pub completed_work: (f64, f64),
+    /// Only when asked to take the hours off RemainingWork
+    pub remaining_work: Option<(f64, f64)>,
+}
+
+/// Add `hours` to the `field` of a work item, negative to take them off
+///
+/// `at` backdates the revision, which Azure DevOps only allows bypassing its
+/// rules, and only after the item's last change.
+pub(crate) async fn log_time(
+    client: &reqwest::Client,
+    connection: &Connection,
+    field: &str,
+    id: u64,
+    hours: f64,
+    at: Option<DateTime<Utc>>,
+    decrement_remaining: bool,
+) -> Result<Logged, Error> {
+    let url = connection.project_url(&["_apis", "wit", "workitems", &id.to_string()]);
+    let current: WorkItem = client
+        .get(url.clone())
+        .query(&[("api-version", connection.api_version())])
+        .query(&[(
+            "fields",
+            [field, REMAINING_WORK, "System.ChangedDate"].join(","),
+        )])
+        .authorize(connection)
+        .send_retrying()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let completed = match current.fields.other.get(field) {
+        Some(value) => deserialize_hours(value)
+            .map_err(|error| format!("#{} {}: {}", id, field, error))?
+            .unwrap_or(0.0),
+        None => 0.0,
+    };
+    let completed_work = (completed, completed + hours);
+    if completed_work.1 < 0.0 {
+        return Err(format!(
+            "#{} has {} hours of {}, can't take {} off",
+            id, completed, field, -hours
+        )
+        .into());
+    }
+    let remaining_work = decrement_remaining.then(|| {
+        let remaining = current.fields.remaining_work.unwrap_or(0.0);
+        (remaining, (remaining - hours).max(0.0))
+    });
+    if let Some(at) = at {
+        if at <= current.fields.changed_date {
+            return Err(format!(
+                "#{} last changed {}, time can only be logged after that",
+                id, current.fields.changed_date
+            )
+            .into());
+        }
+        if at > Utc::now() {
+            return Err(format!("can't log time in the future, at {}", at).into());
+        }
+    }
+
+    // The test makes the update fail rather than overwrite a change made in
+    // between, and keeps a retried request from logging the hours twice
+    let mut operations = vec![
+        json!({ "op": "test", "path": "/rev", "value": current.rev }),
+        json!({
+            "op": "add",
+            "path": format!("/fields/{}", field),
+            "value": completed_work.1,
+        }),
+    ];
+    if let Some((_, remaining)) = remaining_work {
+        operations.push(json!({
+            "op": "add",
+            "path": format!("/fields/{}", REMAINING_WORK),
+            "value": remaining,
+        }));
+    }
+    let mut request = client
+        .patch(url)
+        .query(&[("api-version", connection.api_version())]);
+    if let Some(at) = at {
+        operations.push(json!({
+            "op": "add",
+            "path": "/fields/System.ChangedDate",
+            "value": at.to_rfc3339(),
+        }));
+        request = request.query(&[("bypassRules", "true")]);
+    }
+    let updated: WorkItem = request
+        .authorize(connection)
+        .header(CONTENT_TYPE, "application/json-patch+json")
+        .body(serde_json::Value::Array(operations).to_string())
+        .send_retrying()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(Logged {
+        id,
+        rev: updated.rev,
+        field: field.to_string(),
+        completed_work,
+        remaining_work,
+    })
+}
+
+/// Log the difference between the `hours` item `id` should have on `date`
+/// and the `logged` ones the report has for it
+///
+/// A past day is corrected late that day, so the revision comes after the
+/// ones being corrected.
+pub(crate) async fn correct_day(
+    client: &reqwest::Client,
+    connection: &Connection,
+    field: &str,
+    id: u64,
+    date: NaiveDate,
+    logged: f64,
+    hours: f64,
+) -> Result<Logged, Error> {
+    if hours < 0.0 {
+        return Err("a day can't have less than 0 hours".into());
+    }
+    let at = (date != dates::today()).then(|| dates::at_hour(date, 23));
+    log_time(client, connection, field, id, hours - logged, at, false).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{connection, mock_server, response},
+        COMPLETED_WORK,
+    };
+    use reqwest::Url;
+
+    fn work_item(rev: u32, completed: f64, remaining: f64) -> String {
+        json!({
+            "rev": rev,
+            "fields": {
+                "System.ChangedDate": "2024-03-04T10:00:00Z",
+                COMPLETED_WORK: completed,
+                REMAINING_WORK: remaining,
+            },
+        })
+        .to_string()
+    }
+
+    fn served_from(server: std::net::SocketAddr) -> Connection {
+        Connection {
+            server: Url::parse(&format!("http://{}/", server)).unwrap(),
+            ..connection()
+        }
+    }
+
+    /// The JSON patch a request sends
+    fn patch(request: &str) -> Value {
+        serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap()
+    }
+
+    #[tokio::test]
+    async fn time_is_added_on_the_revision_read() {
+        let (server, requests) = mock_server(vec![
+            response("200 OK", &[], &work_item(5, 3.0, 1.0)),
+            response("200 OK", &[], &work_item(6, 5.0, 0.0)),
+        ])
+        .await;
+        let at = "2024-03-05T23:00:00Z".parse().unwrap();
+        let logged = log_time(
+            &reqwest::Client::new(),
+            &served_from(server),
+            COMPLETED_WORK,
+            1,
+            2.0,
+            Some(at),
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(logged.rev, 6);
+        assert_eq!(logged.completed_work, (3.0, 5.0));
+        // Never below nothing left
+        assert_eq!(logged.remaining_work, Some((1.0, 0.0)));
+
+        let requests = requests.await.unwrap();
+        assert!(
+            requests[0].starts_with("GET /org/Proj/_apis/wit/workitems/1?api-version=7.1&fields=")
+        );
+        // Backdating needs the rules bypassed
+        assert!(
+            requests[1].starts_with(
+                "PATCH /org/Proj/_apis/wit/workitems/1?api-version=7.1&bypassRules=true "
+            ),
+            "{}",
+            requests[1]
+        );
+        assert!(requests[1].contains("content-type: application/json-patch+json\r\n"));
+        assert_eq!(
+            patch(&requests[1]),
+            json!([
+                { "op": "test", "path": "/rev", "value": 5 },
+                { "op": "add", "path": format!("/fields/{}", COMPLETED_WORK), "value": 5.0 },
+                { "op": "add", "path": format!("/fields/{}", REMAINING_WORK), "value": 0.0 },
+                { "op": "add", "path": "/fields/System.ChangedDate", "value": "2024-03-05T23:00:00+00:00" },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn time_logged_now_leaves_the_rules_and_remaining_work_alone() {
+        let (server, requests) = mock_server(vec![
+            response("200 OK", &[], &work_item(5, 3.0, 1.0)),
+            response("200 OK", &[], &work_item(6, 2.5, 1.0)),
+        ])
+        .await;
+        let logged = log_time(
+            &reqwest::Client::new(),
+            &served_from(server),
+            COMPLETED_WORK,
+            1,
+            -0.5,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(logged.completed_work, (3.0, 2.5));
+        assert_eq!(logged.remaining_work, None);
+
+        let requests = requests.await.unwrap();
+        assert!(requests[1].starts_with("PATCH /org/Proj/_apis/wit/workitems/1?api-version=7.1 "));
+        assert_eq!(
+            patch(&requests[1]),
+            json!([
+                { "op": "test", "path": "/rev", "value": 5 },
+                { "op": "add", "path": format!("/fields/{}", COMPLETED_WORK), "value": 2.5 },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn impossible_changes_are_refused_before_writing() {
+        let before_last_change = "2024-03-04T09:00:00Z".parse().unwrap();
+        let future = Utc::now() + chrono::Duration::days(1);
+        for (hours, at, error) in [
+            (
+                -4.0,
+                None,
+                "#1 has 3 hours of Microsoft.VSTS.Scheduling.CompletedWork, can't take 4 off"
+                    .to_string(),
+            ),
+            (
+                1.0,
+                Some(before_last_change),
+                "#1 last changed 2024-03-04 10:00:00 UTC, time can only be logged after that"
+                    .to_string(),
+            ),
+            (
+                1.0,
+                Some(future),
+                format!("can't log time in the future, at {}", future),
+            ),
+        ] {
+            // Only the item is read
+            let (server, requests) =
+                mock_server(vec![response("200 OK", &[], &work_item(5, 3.0, 1.0))]).await;
+            let result = log_time(
+                &reqwest::Client::new(),
+                &served_from(server),
+                COMPLETED_WORK,
+                1,
+                hours,
+                at,
+                false,
+            )
+            .await;
+            assert_eq!(result.unwrap_err().to_string(), error);
+            assert_eq!(requests.await.unwrap().len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_past_day_is_corrected_late_that_day() {
+        let (server, requests) = mock_server(vec![
+            response("200 OK", &[], &work_item(5, 3.0, 1.0)),
+            response("200 OK", &[], &work_item(6, 4.5, 1.0)),
+        ])
+        .await;
+        let date = "2024-03-05".parse().unwrap();
+        let logged = correct_day(
+            &reqwest::Client::new(),
+            &served_from(server),
+            COMPLETED_WORK,
+            1,
+            date,
+            1.0,
+            2.5,
+        )
+        .await
+        .unwrap();
+        assert_eq!(logged.completed_work, (3.0, 4.5));
+
+        let requests = requests.await.unwrap();
+        assert!(
+            requests[1].contains("&bypassRules=true "),
+            "{}",
+            requests[1]
+        );
+        assert_eq!(
+            patch(&requests[1]),
+            json!([
+                { "op": "test", "path": "/rev", "value": 5 },
+                { "op": "add", "path": format!("/fields/{}", COMPLETED_WORK), "value": 4.5 },
+                {
+                    "op": "add",
+                    "path": "/fields/System.ChangedDate",
+                    "value": dates::at_hour(date, 23).to_rfc3339(),
+                },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn corrections_never_leave_less_than_nothing() {
+        // A day can't be set below 0 hours, nor take off more than the item has
+        let (server, requests) = mock_server(Vec::new()).await;
+        let date = "2024-03-05".parse().unwrap();
+        let client = reqwest::Client::new();
+        let result = correct_day(
+            &client,
+            &served_from(server),
+            COMPLETED_WORK,
+            1,
+            date,
+            1.0,
+            -1.0,
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "a day can't have less than 0 hours"
+        );
+        assert!(requests.await.unwrap().is_empty());
+
+        let (server, requests) =
+            mock_server(vec![response("200 OK", &[], &work_item(5, 3.0, 1.0))]).await;
+        let result = correct_day(
+            &client,
+            &served_from(server),
+            COMPLETED_WORK,
+            1,
+            date,
+            4.0,
+            0.0,
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "#1 has 3 hours of Microsoft.VSTS.Scheduling.CompletedWork, can't take 4 off"
+        );
+        assert_eq!(requests.await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn today_is_corrected_now() {
+        let (server, requests) = mock_server(vec![
+            response("200 OK", &[], &work_item(5, 3.0, 1.0)),
+            response("200 OK", &[], &work_item(6, 2.0, 1.0)),
+        ])
+        .await;
+        correct_day(
+            &reqwest::Client::new(),
+            &served_from(server),
+            COMPLETED_WORK,
+            1,
+            dates::today(),
+            2.0,
+            1.0,
+        )
+        .await
+        .unwrap();
+
+        let requests = requests.await.unwrap();
+        assert!(!requests[1].contains("bypassRules"));
+        assert_eq!(patch(&requests[1]).as_array().unwrap().len(), 2);
+    }
+}